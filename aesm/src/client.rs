@@ -0,0 +1,404 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! A client of the AESM service socket
+
+use crate::{
+    messages::{
+        GetQuoteExRequest, GetQuoteSizeExRequest, InitQuoteExRequest, Request, Response,
+        SelectAttKeyIdRequest,
+    },
+    read_message, write_message, Error, PlainData,
+};
+use core::{mem, time::Duration};
+use mc_sgx_core_sys_types::{sgx_att_key_id_ext_t, sgx_report_t, sgx_target_info_t};
+use mc_sgx_core_types::{ExtendedAttestationKeyId, QuoteNonce, Report, TargetInfo};
+use mc_sgx_dcap_sys_types::sgx_ql_qe_report_info_t;
+use mc_sgx_dcap_types::{Quote3, ReportInfo};
+use std::{
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+};
+
+/// The size of `sgx_ql_att_key_id_list_header_t`, which precedes the
+/// attestation key IDs in a `SelectAttKeyIDRequest`
+pub(crate) const KEY_ID_LIST_HEADER_SIZE: usize = 8;
+
+/// The AESM error code for success, `AESM_SUCCESS`
+const AESM_SUCCESS: u32 = 0;
+
+/// The target info and public key ID of an attestation key initialized by
+/// the AESM service
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct InitializedKey {
+    target_info: TargetInfo,
+    public_key_id: Vec<u8>,
+}
+
+impl InitializedKey {
+    /// The target info of the quoting enclave owning the key
+    pub fn target_info(&self) -> &TargetInfo {
+        &self.target_info
+    }
+
+    /// The public identifier of the attestation key
+    pub fn public_key_id(&self) -> &[u8] {
+        &self.public_key_id
+    }
+}
+
+/// Quotes through the AESM (Architectural Enclave Service Manager) service
+///
+/// The AESM service loads the quoting enclaves, so a process only needs
+/// access to the service's socket to quote, not to the DCAP libraries and
+/// enclaves. Each call connects to the socket anew.
+///
+/// ```no_run
+/// # use mc_sgx_aesm::AesmClient;
+/// # use mc_sgx_core_types::Report;
+/// let client = AesmClient::new();
+/// let key = client.select_attestation_key(&[])?;
+/// let initialized = client.init_quote(&key)?;
+/// // The application enclave creates a report for `initialized.target_info()`
+/// # let report = Report::default();
+/// let quote = client.quote(&key, &report)?;
+/// # Ok::<(), mc_sgx_aesm::Error>(())
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct AesmClient {
+    socket: PathBuf,
+    timeout: Duration,
+}
+
+impl Default for AesmClient {
+    fn default() -> Self {
+        Self {
+            socket: PathBuf::from(Self::DEFAULT_SOCKET),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl AesmClient {
+    /// Where the AESM service listens by default
+    pub const DEFAULT_SOCKET: &'static str = "/var/run/aesmd/aesm.socket";
+
+    /// A client of the AESM service at [`AesmClient::DEFAULT_SOCKET`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connect to the AESM service at `path` instead
+    ///
+    /// # Arguments
+    /// * `path` - The path of the AESM service's socket
+    #[must_use]
+    pub fn socket<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.socket = path.as_ref().to_owned();
+        self
+    }
+
+    /// How long the AESM service has to respond to each request
+    ///
+    /// Defaults to 10 seconds.
+    ///
+    /// # Arguments
+    /// * `timeout` - The time to wait for each response
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The path of the AESM service's socket
+    pub fn socket_path(&self) -> &Path {
+        &self.socket
+    }
+
+    /// Select the attestation key to quote with
+    ///
+    /// # Arguments
+    /// * `candidates` - The attestation keys the quote verifier supports. When
+    ///   empty the AESM service picks the platform's default attestation key.
+    ///
+    /// # Errors
+    /// * [`Error::Aesm`] if the AESM service supports none of the
+    ///   `candidates`.
+    /// * [`Error::Io`] if the AESM service can't be reached.
+    pub fn select_attestation_key(
+        &self,
+        candidates: &[ExtendedAttestationKeyId],
+    ) -> Result<ExtendedAttestationKeyId, Error> {
+        let request = Request {
+            select_att_key_id_req: Some(SelectAttKeyIdRequest {
+                att_key_id_list: (!candidates.is_empty()).then(|| key_id_list(candidates)),
+                timeout: Some(self.timeout_ms()),
+            }),
+            ..Default::default()
+        };
+        let response = self
+            .call(&request)?
+            .select_att_key_id_res
+            .ok_or(Error::MissingField("the select attestation key response"))?;
+        check(response.error_code)?;
+
+        let key = response
+            .selected_att_key_id
+            .ok_or(Error::MissingField("the selected attestation key"))?;
+        Ok(sgx_att_key_id_ext_t::from_bytes("selected attestation key", &key)?.into())
+    }
+
+    /// Initialize quoting with an attestation key
+    ///
+    /// # Arguments
+    /// * `key` - The attestation key to quote with, usually from
+    ///   [`AesmClient::select_attestation_key()`].
+    ///
+    /// # Errors
+    /// * [`Error::Aesm`] if the AESM service fails to initialize the key.
+    /// * [`Error::Io`] if the AESM service can't be reached.
+    pub fn init_quote(&self, key: &ExtendedAttestationKeyId) -> Result<InitializedKey, Error> {
+        // Without asking for the public key ID only its size is returned
+        let size = self
+            .init_quote_ex(key, None)?
+            .pub_key_id_size
+            .ok_or(Error::MissingField("the public key ID size"))?;
+
+        let response = self.init_quote_ex(key, Some(size))?;
+        let target_info = response
+            .target_info
+            .ok_or(Error::MissingField("the target info"))?;
+        let target_info = sgx_target_info_t::from_bytes("target info", &target_info)?;
+        let public_key_id = response
+            .pub_key_id
+            .ok_or(Error::MissingField("the public key ID"))?;
+
+        Ok(InitializedKey {
+            target_info: target_info.into(),
+            public_key_id,
+        })
+    }
+
+    /// The size, in bytes, of the quotes `key` produces
+    ///
+    /// # Arguments
+    /// * `key` - The attestation key to quote with
+    ///
+    /// # Errors
+    /// * [`Error::Aesm`] if the AESM service fails to size the quote, for
+    ///   instance when `key` hasn't been initialized.
+    /// * [`Error::Io`] if the AESM service can't be reached.
+    pub fn quote_size(&self, key: &ExtendedAttestationKeyId) -> Result<usize, Error> {
+        let request = Request {
+            get_quote_size_ex_req: Some(GetQuoteSizeExRequest {
+                att_key_id: Some(key_id_bytes(key)),
+                timeout: Some(self.timeout_ms()),
+            }),
+            ..Default::default()
+        };
+        let response = self
+            .call(&request)?
+            .get_quote_size_ex_res
+            .ok_or(Error::MissingField("the quote size response"))?;
+        check(response.error_code)?;
+
+        let size = response
+            .quote_size
+            .ok_or(Error::MissingField("the quote size"))?;
+        Ok(size as usize)
+    }
+
+    /// Quote an application enclave's report
+    ///
+    /// # Arguments
+    /// * `key` - The ECDSA attestation key to quote with
+    /// * `report` - The report to quote, it must target the quoting enclave,
+    ///   [`InitializedKey::target_info()`].
+    ///
+    /// # Errors
+    /// * [`Error::Aesm`] if the AESM service fails to quote the report.
+    /// * [`Error::Quote3`] if the quote isn't a version 3 quote.
+    /// * [`Error::Io`] if the AESM service can't be reached.
+    pub fn quote(
+        &self,
+        key: &ExtendedAttestationKeyId,
+        report: &Report,
+    ) -> Result<Quote3<Vec<u8>>, Error> {
+        let (quote, _) = self.get_quote_ex(key, report, None)?;
+        Ok(quote)
+    }
+
+    /// Quote an application enclave's report, also getting a report of the
+    /// quoting enclave for the application enclave
+    ///
+    /// The report data of the quoting enclave report is
+    /// `SHA256(nonce || quote)` followed by 32 zero bytes.
+    ///
+    /// # Arguments
+    /// * `key` - The ECDSA attestation key to quote with
+    /// * `report` - The report to quote, it must target the quoting enclave,
+    ///   [`InitializedKey::target_info()`].
+    /// * `app_enclave_target_info` - The target info of the application
+    ///   enclave, the quoting enclave report targets it.
+    /// * `nonce` - A nonce from the application enclave.
+    ///
+    /// # Errors
+    /// * [`Error::Aesm`] if the AESM service fails to quote the report.
+    /// * [`Error::Quote3`] if the quote isn't a version 3 quote.
+    /// * [`Error::Io`] if the AESM service can't be reached.
+    pub fn quote_with_qe_report(
+        &self,
+        key: &ExtendedAttestationKeyId,
+        report: &Report,
+        app_enclave_target_info: &TargetInfo,
+        nonce: &QuoteNonce,
+    ) -> Result<(Quote3<Vec<u8>>, ReportInfo), Error> {
+        let report_info = sgx_ql_qe_report_info_t {
+            nonce: nonce.clone().into(),
+            app_enclave_target_info: app_enclave_target_info.clone().into(),
+            qe_report: sgx_report_t::default(),
+        };
+        let (quote, report_info) = self.get_quote_ex(key, report, Some(report_info))?;
+        let report_info = report_info.ok_or(Error::MissingField("the QE report info"))?;
+        Ok((quote, report_info))
+    }
+
+    fn get_quote_ex(
+        &self,
+        key: &ExtendedAttestationKeyId,
+        report: &Report,
+        report_info: Option<sgx_ql_qe_report_info_t>,
+    ) -> Result<(Quote3<Vec<u8>>, Option<ReportInfo>), Error> {
+        let size = self.quote_size(key)?;
+        let request = Request {
+            get_quote_ex_req: Some(GetQuoteExRequest {
+                report: report.as_ref().to_bytes(),
+                att_key_id: Some(key_id_bytes(key)),
+                qe_report_info: report_info.as_ref().map(PlainData::to_bytes),
+                buf_size: u32::try_from(size).map_err(|_| Error::MessageTooLarge(size))?,
+                timeout: Some(self.timeout_ms()),
+            }),
+            ..Default::default()
+        };
+        let response = self
+            .call(&request)?
+            .get_quote_ex_res
+            .ok_or(Error::MissingField("the quote response"))?;
+        check(response.error_code)?;
+
+        let quote = response.quote.ok_or(Error::MissingField("the quote"))?;
+        let report_info = match (report_info, response.qe_report_info) {
+            (Some(_), Some(info)) => {
+                Some(sgx_ql_qe_report_info_t::from_bytes("QE report info", &info)?.into())
+            }
+            (Some(_), None) => return Err(Error::MissingField("the QE report info")),
+            (None, _) => None,
+        };
+        Ok((quote.try_into()?, report_info))
+    }
+
+    fn init_quote_ex(
+        &self,
+        key: &ExtendedAttestationKeyId,
+        public_key_id_size: Option<u64>,
+    ) -> Result<crate::messages::InitQuoteExResponse, Error> {
+        let request = Request {
+            init_quote_ex_req: Some(InitQuoteExRequest {
+                att_key_id: Some(key_id_bytes(key)),
+                b_pub_key_id: public_key_id_size.is_some(),
+                buf_size: public_key_id_size,
+                timeout: Some(self.timeout_ms()),
+            }),
+            ..Default::default()
+        };
+        let response = self
+            .call(&request)?
+            .init_quote_ex_res
+            .ok_or(Error::MissingField("the init quote response"))?;
+        check(response.error_code)?;
+        Ok(response)
+    }
+
+    /// Send `request` and wait for the response
+    fn call(&self, request: &Request) -> Result<Response, Error> {
+        let mut stream = UnixStream::connect(&self.socket)?;
+        // Leave the AESM service time to report its own timeout
+        let timeout = self.timeout + Duration::from_secs(1);
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        write_message(&mut stream, request)?;
+        read_message(&mut stream)
+    }
+
+    fn timeout_ms(&self) -> u32 {
+        u32::try_from(self.timeout.as_millis()).unwrap_or(u32::MAX)
+    }
+}
+
+/// Fail with [`Error::Aesm`] unless `error_code` is success
+fn check(error_code: u32) -> Result<(), Error> {
+    match error_code {
+        AESM_SUCCESS => Ok(()),
+        code => Err(Error::Aesm(code)),
+    }
+}
+
+/// The bytes of `key` as a `sgx_att_key_id_t`, which has the size of
+/// `sgx_att_key_id_ext_t`
+fn key_id_bytes(key: &ExtendedAttestationKeyId) -> Vec<u8> {
+    key.as_ref().to_bytes()
+}
+
+/// The `sgx_ql_att_key_id_list_t` for `candidates`
+pub(crate) fn key_id_list(candidates: &[ExtendedAttestationKeyId]) -> Vec<u8> {
+    let mut list = Vec::with_capacity(
+        KEY_ID_LIST_HEADER_SIZE + candidates.len() * mem::size_of::<sgx_att_key_id_ext_t>(),
+    );
+    // The header's structure ID and version, both 0
+    list.extend_from_slice(&[0; 4]);
+    list.extend_from_slice(&(candidates.len() as u32).to_le_bytes());
+    for candidate in candidates {
+        list.extend_from_slice(&key_id_bytes(candidate));
+    }
+    list
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mc_sgx_core_sys_types::sgx_ql_att_key_id_t;
+
+    fn key(algorithm_id: u32) -> ExtendedAttestationKeyId {
+        sgx_att_key_id_ext_t {
+            base: sgx_ql_att_key_id_t {
+                algorithm_id,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn key_id_list_starts_with_the_count() {
+        let list = key_id_list(&[key(2), key(0), key(2)]);
+        assert_eq!(list[..KEY_ID_LIST_HEADER_SIZE], [0, 0, 0, 0, 3, 0, 0, 0]);
+        assert_eq!(list.len(), KEY_ID_LIST_HEADER_SIZE + 3 * 256);
+    }
+
+    #[test]
+    fn unreachable_service_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = AesmClient::new().socket(dir.path().join("aesm.socket"));
+        assert!(matches!(
+            client.select_attestation_key(&[]),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn timeout_saturates_in_requests() {
+        let client = AesmClient::new().timeout(Duration::from_secs(u64::MAX / 1000));
+        assert_eq!(client.timeout_ms(), u32::MAX);
+    }
+}
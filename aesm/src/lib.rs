@@ -0,0 +1,183 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+#![doc = include_str!("../README.md")]
+#![deny(missing_docs, missing_debug_implementations)]
+
+mod client;
+mod messages;
+#[cfg(feature = "mock")]
+mod mock;
+
+pub use crate::client::{AesmClient, InitializedKey};
+#[cfg(feature = "mock")]
+pub use crate::mock::{MockAesm, MockAesmBuilder};
+
+use core::{mem, ptr, slice};
+use mc_sgx_core_sys_types::{sgx_att_key_id_ext_t, sgx_report_t, sgx_target_info_t};
+use mc_sgx_dcap_sys_types::sgx_ql_qe_report_info_t;
+use mc_sgx_dcap_types::Quote3Error;
+use prost::Message;
+use std::io::{self, Read, Write};
+
+/// The largest message accepted from, or sent to, the AESM service
+const MAX_MESSAGE_SIZE: u32 = 1 << 20;
+
+/// Errors communicating with the AESM service
+#[derive(Clone, Debug, displaydoc::Display, Eq, Hash, PartialEq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum Error {
+    /// Failed to communicate with the AESM service: {0}
+    Io(String),
+    /// Failed to decode a message from the AESM service: {0}
+    Decode(String),
+    /// The message is {0} bytes, larger than the 1MiB allowed
+    MessageTooLarge(usize),
+    /// The AESM service responded without {0}
+    MissingField(&'static str),
+    /// The {0} is {1} bytes, it should be {2} bytes
+    InvalidSize(&'static str, usize, usize),
+    /// The AESM service failed with error code {0}
+    Aesm(u32),
+    /// Error from Quote3 interface
+    Quote3(Quote3Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(src: io::Error) -> Self {
+        Self::Io(src.to_string())
+    }
+}
+
+impl From<prost::DecodeError> for Error {
+    fn from(src: prost::DecodeError) -> Self {
+        Self::Decode(src.to_string())
+    }
+}
+
+impl From<Quote3Error> for Error {
+    fn from(src: Quote3Error) -> Self {
+        Self::Quote3(src)
+    }
+}
+
+/// Write `message` to `stream`, preceded by its length
+///
+/// The AESM service frames each message with its length as a little endian
+/// `u32`.
+pub(crate) fn write_message<M: Message>(stream: &mut impl Write, message: &M) -> Result<(), Error> {
+    let bytes = message.encode_to_vec();
+    let length = u32::try_from(bytes.len())
+        .ok()
+        .filter(|length| *length <= MAX_MESSAGE_SIZE)
+        .ok_or(Error::MessageTooLarge(bytes.len()))?;
+    stream.write_all(&length.to_le_bytes())?;
+    stream.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Read a message, preceded by its length, from `stream`
+pub(crate) fn read_message<M: Message + Default>(stream: &mut impl Read) -> Result<M, Error> {
+    let mut length = [0; mem::size_of::<u32>()];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_le_bytes(length);
+    if length > MAX_MESSAGE_SIZE {
+        return Err(Error::MessageTooLarge(length as usize));
+    }
+    let mut bytes = vec![0; length as usize];
+    stream.read_exact(&mut bytes)?;
+    Ok(M::decode(bytes.as_slice())?)
+}
+
+/// The FFI structures sent to, and received from, the AESM service as bytes
+///
+/// # Safety
+/// Implementors must be plain data, valid for any bit pattern.
+pub(crate) unsafe trait PlainData: Copy {
+    /// The bytes of this structure
+    fn to_bytes(&self) -> Vec<u8> {
+        // SAFETY: `PlainData` has no padding or pointers to expose
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, mem::size_of::<Self>()) }
+            .to_vec()
+    }
+
+    /// The structure in `bytes`
+    ///
+    /// # Arguments
+    /// * `name` - What `bytes` hold, for the error
+    /// * `bytes` - The bytes of the structure
+    ///
+    /// # Errors
+    /// [`Error::InvalidSize`] if `bytes` isn't the size of the structure.
+    fn from_bytes(name: &'static str, bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != mem::size_of::<Self>() {
+            return Err(Error::InvalidSize(
+                name,
+                bytes.len(),
+                mem::size_of::<Self>(),
+            ));
+        }
+        // SAFETY: The size was checked and `PlainData` is valid for any bytes
+        Ok(unsafe { ptr::read_unaligned(bytes.as_ptr() as *const Self) })
+    }
+}
+
+// SAFETY: These are C structures of integers and byte arrays
+unsafe impl PlainData for sgx_att_key_id_ext_t {}
+unsafe impl PlainData for sgx_report_t {}
+unsafe impl PlainData for sgx_target_info_t {}
+unsafe impl PlainData for sgx_ql_qe_report_info_t {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mc_sgx_core_sys_types::sgx_key_id_t;
+
+    #[test]
+    fn message_round_trip() {
+        let message = messages::Request {
+            select_att_key_id_req: Some(messages::SelectAttKeyIdRequest {
+                att_key_id_list: Some(vec![1, 2, 3]),
+                timeout: Some(100),
+            }),
+            ..Default::default()
+        };
+        let mut bytes = vec![];
+        write_message(&mut bytes, &message).unwrap();
+        assert_eq!(bytes[..4], (bytes.len() as u32 - 4).to_le_bytes());
+
+        let decoded: messages::Request = read_message(&mut bytes.as_slice()).unwrap();
+        assert_eq!(decoded, message);
+    }
+
+    #[test]
+    fn oversized_message_is_rejected() {
+        let bytes = (MAX_MESSAGE_SIZE + 1).to_le_bytes();
+        assert_eq!(
+            read_message::<messages::Request>(&mut bytes.as_slice()),
+            Err(Error::MessageTooLarge(MAX_MESSAGE_SIZE as usize + 1))
+        );
+    }
+
+    #[test]
+    fn plain_data_round_trip() {
+        let report = sgx_report_t {
+            key_id: sgx_key_id_t { id: [9; 32] },
+            ..Default::default()
+        };
+        let bytes = report.to_bytes();
+        assert_eq!(bytes.len(), mem::size_of::<sgx_report_t>());
+
+        let decoded = sgx_report_t::from_bytes("report", &bytes).unwrap();
+        assert_eq!(decoded.key_id.id, [9; 32]);
+    }
+
+    #[test]
+    fn plain_data_of_the_wrong_size_fails() {
+        let bytes = sgx_report_t::default().to_bytes();
+        assert!(matches!(
+            sgx_report_t::from_bytes("report", &bytes[1..]),
+            Err(Error::InvalidSize("report", size, expected))
+                if size == bytes.len() - 1 && expected == bytes.len()
+        ));
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! The AESM protobuf messages
+//!
+//! These mirror the quoting messages of
+//! `psw/ae/aesm_service/source/core/ipc/messages.proto` in the Intel SGX
+//! platform software, as of the `sgx_2.19` tag of
+//! <https://github.com/intel/linux-sgx>. Only the requests used by this crate
+//! are present, the other fields of [`Request`] and [`Response`] are skipped
+//! when decoding.
+
+#![allow(missing_docs)]
+
+/// A request to the AESM service, exactly one of the fields is set
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct Request {
+    #[prost(message, optional, tag = "15")]
+    pub init_quote_ex_req: Option<InitQuoteExRequest>,
+    #[prost(message, optional, tag = "16")]
+    pub get_quote_size_ex_req: Option<GetQuoteSizeExRequest>,
+    #[prost(message, optional, tag = "17")]
+    pub get_quote_ex_req: Option<GetQuoteExRequest>,
+    #[prost(message, optional, tag = "20")]
+    pub select_att_key_id_req: Option<SelectAttKeyIdRequest>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct InitQuoteExRequest {
+    #[prost(bytes = "vec", optional, tag = "1")]
+    pub att_key_id: Option<Vec<u8>>,
+    #[prost(bool, required, tag = "3")]
+    pub b_pub_key_id: bool,
+    #[prost(uint64, optional, tag = "4")]
+    pub buf_size: Option<u64>,
+    #[prost(uint32, optional, tag = "9")]
+    pub timeout: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct GetQuoteSizeExRequest {
+    #[prost(bytes = "vec", optional, tag = "1")]
+    pub att_key_id: Option<Vec<u8>>,
+    #[prost(uint32, optional, tag = "9")]
+    pub timeout: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct GetQuoteExRequest {
+    #[prost(bytes = "vec", required, tag = "1")]
+    pub report: Vec<u8>,
+    #[prost(bytes = "vec", optional, tag = "2")]
+    pub att_key_id: Option<Vec<u8>>,
+    #[prost(bytes = "vec", optional, tag = "3")]
+    pub qe_report_info: Option<Vec<u8>>,
+    #[prost(uint32, required, tag = "4")]
+    pub buf_size: u32,
+    #[prost(uint32, optional, tag = "9")]
+    pub timeout: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct SelectAttKeyIdRequest {
+    #[prost(bytes = "vec", optional, tag = "1")]
+    pub att_key_id_list: Option<Vec<u8>>,
+    #[prost(uint32, optional, tag = "9")]
+    pub timeout: Option<u32>,
+}
+
+/// A response from the AESM service, the field matching the request is set
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct Response {
+    #[prost(message, optional, tag = "15")]
+    pub init_quote_ex_res: Option<InitQuoteExResponse>,
+    #[prost(message, optional, tag = "16")]
+    pub get_quote_size_ex_res: Option<GetQuoteSizeExResponse>,
+    #[prost(message, optional, tag = "17")]
+    pub get_quote_ex_res: Option<GetQuoteExResponse>,
+    #[prost(message, optional, tag = "20")]
+    pub select_att_key_id_res: Option<SelectAttKeyIdResponse>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct InitQuoteExResponse {
+    #[prost(uint32, required, tag = "1", default = "1")]
+    pub error_code: u32,
+    #[prost(bytes = "vec", optional, tag = "2")]
+    pub target_info: Option<Vec<u8>>,
+    #[prost(uint64, optional, tag = "3")]
+    pub pub_key_id_size: Option<u64>,
+    #[prost(bytes = "vec", optional, tag = "4")]
+    pub pub_key_id: Option<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct GetQuoteSizeExResponse {
+    #[prost(uint32, required, tag = "1", default = "1")]
+    pub error_code: u32,
+    #[prost(uint32, optional, tag = "2")]
+    pub quote_size: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct GetQuoteExResponse {
+    #[prost(uint32, required, tag = "1", default = "1")]
+    pub error_code: u32,
+    #[prost(bytes = "vec", optional, tag = "2")]
+    pub quote: Option<Vec<u8>>,
+    #[prost(bytes = "vec", optional, tag = "3")]
+    pub qe_report_info: Option<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct SelectAttKeyIdResponse {
+    #[prost(uint32, required, tag = "1", default = "1")]
+    pub error_code: u32,
+    #[prost(bytes = "vec", optional, tag = "2")]
+    pub selected_att_key_id: Option<Vec<u8>>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use prost::Message;
+    use yare::parameterized;
+
+    // The fixtures are encoded from the field numbers and wire types of
+    // `messages.proto`, independently of the prost attributes above.
+
+    #[parameterized(
+    select_att_key_id = {
+        &[
+            0xA2, 0x01, 0x09, 0x0A, 0x04, 0xAA, 0xAA, 0xAA, 0xAA, 0x48, 0x90, 0x4E,
+        ],
+        Request {
+            select_att_key_id_req: Some(SelectAttKeyIdRequest {
+                att_key_id_list: Some(vec![0xAA; 4]),
+                timeout: Some(10_000),
+            }),
+            ..Default::default()
+        },
+    },
+    init_quote_ex = {
+        &[
+            0x7A, 0x0E, 0x0A, 0x04, 0x01, 0x02, 0x03, 0x04, 0x18, 0x01, 0x20, 0x80, 0x03, 0x48,
+            0x90, 0x4E,
+        ],
+        Request {
+            init_quote_ex_req: Some(InitQuoteExRequest {
+                att_key_id: Some(vec![1, 2, 3, 4]),
+                b_pub_key_id: true,
+                buf_size: Some(384),
+                timeout: Some(10_000),
+            }),
+            ..Default::default()
+        },
+    },
+    get_quote_size_ex = {
+        &[
+            0x82, 0x01, 0x09, 0x0A, 0x04, 0x01, 0x02, 0x03, 0x04, 0x48, 0x90, 0x4E,
+        ],
+        Request {
+            get_quote_size_ex_req: Some(GetQuoteSizeExRequest {
+                att_key_id: Some(vec![1, 2, 3, 4]),
+                timeout: Some(10_000),
+            }),
+            ..Default::default()
+        },
+    },
+    get_quote_ex = {
+        &[
+            0x8A, 0x01, 0x12, 0x0A, 0x04, 0x55, 0x55, 0x55, 0x55, 0x12, 0x04, 0x01, 0x02, 0x03,
+            0x04, 0x20, 0xD2, 0x09, 0x48, 0x90, 0x4E,
+        ],
+        Request {
+            get_quote_ex_req: Some(GetQuoteExRequest {
+                report: vec![0x55; 4],
+                att_key_id: Some(vec![1, 2, 3, 4]),
+                qe_report_info: None,
+                buf_size: 1234,
+                timeout: Some(10_000),
+            }),
+            ..Default::default()
+        },
+    },
+    )]
+    fn request_wire_format(bytes: &[u8], request: Request) {
+        assert_eq!(request.encode_to_vec(), bytes);
+        assert_eq!(Request::decode(bytes).unwrap(), request);
+    }
+
+    #[parameterized(
+    select_att_key_id = {
+        &[0xA2, 0x01, 0x08, 0x08, 0x00, 0x12, 0x04, 0x01, 0x02, 0x03, 0x04],
+        Response {
+            select_att_key_id_res: Some(SelectAttKeyIdResponse {
+                error_code: 0,
+                selected_att_key_id: Some(vec![1, 2, 3, 4]),
+            }),
+            ..Default::default()
+        },
+    },
+    init_quote_ex = {
+        &[
+            0x7A, 0x0B, 0x08, 0x00, 0x12, 0x04, 0x66, 0x66, 0x66, 0x66, 0x18, 0x80, 0x03,
+        ],
+        Response {
+            init_quote_ex_res: Some(InitQuoteExResponse {
+                error_code: 0,
+                target_info: Some(vec![0x66; 4]),
+                pub_key_id_size: Some(384),
+                pub_key_id: None,
+            }),
+            ..Default::default()
+        },
+    },
+    get_quote_size_ex = {
+        &[0x82, 0x01, 0x05, 0x08, 0x00, 0x10, 0xE2, 0x23],
+        Response {
+            get_quote_size_ex_res: Some(GetQuoteSizeExResponse {
+                error_code: 0,
+                quote_size: Some(4578),
+            }),
+            ..Default::default()
+        },
+    },
+    get_quote_ex = {
+        &[0x8A, 0x01, 0x08, 0x08, 0x00, 0x12, 0x04, 0x77, 0x77, 0x77, 0x77],
+        Response {
+            get_quote_ex_res: Some(GetQuoteExResponse {
+                error_code: 0,
+                quote: Some(vec![0x77; 4]),
+                qe_report_info: None,
+            }),
+            ..Default::default()
+        },
+    },
+    get_quote_ex_failure = {
+        &[0x8A, 0x01, 0x02, 0x08, 0x2A],
+        Response {
+            get_quote_ex_res: Some(GetQuoteExResponse {
+                error_code: 42,
+                quote: None,
+                qe_report_info: None,
+            }),
+            ..Default::default()
+        },
+    },
+    )]
+    fn response_wire_format(bytes: &[u8], response: Response) {
+        assert_eq!(response.encode_to_vec(), bytes);
+        assert_eq!(Response::decode(bytes).unwrap(), response);
+    }
+
+    #[test]
+    fn other_responses_are_skipped() {
+        // `getSupportedAttKeyIDNumRes`, field 18, with a count of 1
+        let bytes = [0x92, 0x01, 0x04, 0x08, 0x00, 0x10, 0x01];
+        assert_eq!(Response::decode(&bytes[..]).unwrap(), Response::default());
+    }
+}
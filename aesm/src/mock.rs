@@ -0,0 +1,440 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! A stand-in AESM service for testing clients without SGX hardware
+
+use crate::{
+    client::AesmClient,
+    messages::{
+        GetQuoteExRequest, GetQuoteExResponse, GetQuoteSizeExResponse, InitQuoteExRequest,
+        InitQuoteExResponse, Request, Response, SelectAttKeyIdRequest, SelectAttKeyIdResponse,
+    },
+    read_message, write_message, Error, PlainData,
+};
+use core::{mem, time::Duration};
+use mc_sgx_core_sys_types::{sgx_att_key_id_ext_t, sgx_target_info_t};
+use mc_sgx_core_types::{ExtendedAttestationKeyId, TargetInfo};
+use mc_sgx_dcap_sys_types::sgx_ql_qe_report_info_t;
+use sha2::{Digest, Sha256};
+use std::{
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// The AESM error code for an unexpected failure, `AESM_UNEXPECTED_ERROR`
+const AESM_UNEXPECTED_ERROR: u32 = 1;
+
+/// The AESM error code for invalid parameters, `AESM_PARAMETER_ERROR`
+const AESM_PARAMETER_ERROR: u32 = 2;
+
+/// Builds a [`MockAesm`]
+///
+/// The mock answers every request with the configured values, regardless of
+/// the attestation key requested. It does no cryptography, the quote
+/// returned is whatever was configured.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct MockAesmBuilder {
+    key: ExtendedAttestationKeyId,
+    target_info: TargetInfo,
+    public_key_id: Vec<u8>,
+    quote: Vec<u8>,
+    error_code: u32,
+}
+
+impl MockAesmBuilder {
+    /// The attestation key selected when the client offers no candidates
+    ///
+    /// When the client offers candidates the first one is selected.
+    ///
+    /// # Arguments
+    /// * `key` - The default attestation key
+    #[must_use]
+    pub fn key(mut self, key: ExtendedAttestationKeyId) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// The target info of the quoting enclave
+    ///
+    /// # Arguments
+    /// * `target_info` - The target info returned when initializing quoting
+    #[must_use]
+    pub fn target_info(mut self, target_info: TargetInfo) -> Self {
+        self.target_info = target_info;
+        self
+    }
+
+    /// The public identifier of the attestation key
+    ///
+    /// # Arguments
+    /// * `public_key_id` - The ID returned when initializing quoting
+    #[must_use]
+    pub fn public_key_id(mut self, public_key_id: impl Into<Vec<u8>>) -> Self {
+        self.public_key_id = public_key_id.into();
+        self
+    }
+
+    /// The quote returned for every report
+    ///
+    /// # Arguments
+    /// * `quote` - The bytes of the quote
+    #[must_use]
+    pub fn quote(mut self, quote: impl Into<Vec<u8>>) -> Self {
+        self.quote = quote.into();
+        self
+    }
+
+    /// Fail every request with an AESM error code
+    ///
+    /// # Arguments
+    /// * `error_code` - The AESM error code, 0 succeeds
+    #[must_use]
+    pub fn error_code(mut self, error_code: u32) -> Self {
+        self.error_code = error_code;
+        self
+    }
+
+    /// Start serving on the socket at `path`
+    ///
+    /// # Arguments
+    /// * `path` - Where to create the socket, the file must not exist
+    ///
+    /// # Errors
+    /// [`Error::Io`] if the socket can't be created.
+    pub fn serve<P: AsRef<Path>>(self, path: P) -> Result<MockAesm, Error> {
+        let path = path.as_ref().to_owned();
+        let listener = UnixListener::bind(&path)?;
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let thread_shutdown = shutdown.clone();
+        let thread = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if thread_shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+                // A misbehaving client shouldn't take down the service
+                if let Ok(stream) = stream {
+                    let _ = self.handle(stream);
+                }
+            }
+        });
+
+        Ok(MockAesm {
+            path,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    fn handle(&self, mut stream: UnixStream) -> Result<(), Error> {
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let request: Request = read_message(&mut stream)?;
+        let response = self.respond(request);
+        write_message(&mut stream, &response)
+    }
+
+    fn respond(&self, request: Request) -> Response {
+        let mut response = Response::default();
+        if let Some(request) = request.select_att_key_id_req {
+            response.select_att_key_id_res = Some(self.select_att_key_id(request));
+        }
+        if let Some(request) = request.init_quote_ex_req {
+            response.init_quote_ex_res = Some(self.init_quote_ex(request));
+        }
+        if request.get_quote_size_ex_req.is_some() {
+            response.get_quote_size_ex_res = Some(self.get_quote_size_ex());
+        }
+        if let Some(request) = request.get_quote_ex_req {
+            response.get_quote_ex_res = Some(self.get_quote_ex(request));
+        }
+        response
+    }
+
+    fn select_att_key_id(&self, request: SelectAttKeyIdRequest) -> SelectAttKeyIdResponse {
+        if self.error_code != 0 {
+            return SelectAttKeyIdResponse {
+                error_code: self.error_code,
+                ..Default::default()
+            };
+        }
+        let key = match request.att_key_id_list {
+            None => self.key.as_ref().to_bytes(),
+            Some(list) => {
+                let start = crate::client::KEY_ID_LIST_HEADER_SIZE;
+                let end = start + mem::size_of::<sgx_att_key_id_ext_t>();
+                match list.get(start..end) {
+                    Some(first) => first.to_vec(),
+                    None => {
+                        return SelectAttKeyIdResponse {
+                            error_code: AESM_PARAMETER_ERROR,
+                            ..Default::default()
+                        }
+                    }
+                }
+            }
+        };
+        SelectAttKeyIdResponse {
+            error_code: 0,
+            selected_att_key_id: Some(key),
+        }
+    }
+
+    fn init_quote_ex(&self, request: InitQuoteExRequest) -> InitQuoteExResponse {
+        if self.error_code != 0 {
+            return InitQuoteExResponse {
+                error_code: self.error_code,
+                ..Default::default()
+            };
+        }
+        let size = self.public_key_id.len() as u64;
+        if !request.b_pub_key_id {
+            return InitQuoteExResponse {
+                error_code: 0,
+                pub_key_id_size: Some(size),
+                ..Default::default()
+            };
+        }
+        if request.buf_size != Some(size) {
+            return InitQuoteExResponse {
+                error_code: AESM_PARAMETER_ERROR,
+                ..Default::default()
+            };
+        }
+        let target_info: sgx_target_info_t = self.target_info.clone().into();
+        InitQuoteExResponse {
+            error_code: 0,
+            target_info: Some(target_info.to_bytes()),
+            pub_key_id_size: Some(size),
+            pub_key_id: Some(self.public_key_id.clone()),
+        }
+    }
+
+    fn get_quote_size_ex(&self) -> GetQuoteSizeExResponse {
+        if self.error_code != 0 {
+            return GetQuoteSizeExResponse {
+                error_code: self.error_code,
+                ..Default::default()
+            };
+        }
+        GetQuoteSizeExResponse {
+            error_code: 0,
+            quote_size: Some(self.quote.len() as u32),
+        }
+    }
+
+    fn get_quote_ex(&self, request: GetQuoteExRequest) -> GetQuoteExResponse {
+        if self.error_code != 0 {
+            return GetQuoteExResponse {
+                error_code: self.error_code,
+                ..Default::default()
+            };
+        }
+        if request.buf_size as usize != self.quote.len() {
+            return GetQuoteExResponse {
+                error_code: AESM_PARAMETER_ERROR,
+                ..Default::default()
+            };
+        }
+        let qe_report_info = match request.qe_report_info {
+            None => None,
+            Some(info) => match sgx_ql_qe_report_info_t::from_bytes("QE report info", &info) {
+                Ok(info) => Some(self.qe_report_info(info).to_bytes()),
+                Err(_) => {
+                    return GetQuoteExResponse {
+                        error_code: AESM_UNEXPECTED_ERROR,
+                        ..Default::default()
+                    }
+                }
+            },
+        };
+        GetQuoteExResponse {
+            error_code: 0,
+            quote: Some(self.quote.clone()),
+            qe_report_info,
+        }
+    }
+
+    /// Fill in the QE report the way the quoting enclave does, without the
+    /// MAC
+    fn qe_report_info(&self, mut info: sgx_ql_qe_report_info_t) -> sgx_ql_qe_report_info_t {
+        let mut hasher = Sha256::new();
+        hasher.update(info.nonce.rand);
+        hasher.update(&self.quote);
+        let hash = hasher.finalize();
+
+        let report_data = &mut info.qe_report.body.report_data.d;
+        *report_data = [0; 64];
+        report_data[..hash.len()].copy_from_slice(&hash);
+        info
+    }
+}
+
+/// A stand-in AESM service, serving until dropped
+///
+/// ```
+/// # use mc_sgx_aesm::MockAesmBuilder;
+/// let dir = tempfile::tempdir().unwrap();
+/// let aesm = MockAesmBuilder::default()
+///     .public_key_id(vec![1; 16])
+///     .serve(dir.path().join("aesm.socket"))?;
+/// let client = aesm.client();
+/// # Ok::<(), mc_sgx_aesm::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct MockAesm {
+    path: PathBuf,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockAesm {
+    /// The path of the socket being served
+    pub fn socket_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// A client of this service
+    pub fn client(&self) -> AesmClient {
+        AesmClient::new().socket(&self.path)
+    }
+}
+
+impl Drop for MockAesm {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake the server waiting for a connection so it sees the shutdown
+        let _ = UnixStream::connect(&self.path);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mc_sgx_core_sys_types::sgx_ql_att_key_id_t;
+    use mc_sgx_core_types::{QuoteNonce, Report};
+    use yare::parameterized;
+
+    const QUOTE: &[u8] = include_bytes!("../../dcap/types/data/tests/hw_quote.dat");
+
+    fn key(algorithm_id: u32) -> ExtendedAttestationKeyId {
+        sgx_att_key_id_ext_t {
+            base: sgx_ql_att_key_id_t {
+                algorithm_id,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .into()
+    }
+
+    fn target_info() -> TargetInfo {
+        sgx_target_info_t {
+            config_svn: 3,
+            ..Default::default()
+        }
+        .into()
+    }
+
+    fn serve(builder: MockAesmBuilder) -> (tempfile::TempDir, MockAesm) {
+        let dir = tempfile::tempdir().unwrap();
+        let aesm = builder.serve(dir.path().join("aesm.socket")).unwrap();
+        (dir, aesm)
+    }
+
+    #[test]
+    fn select_default_key() {
+        let (_dir, aesm) = serve(MockAesmBuilder::default().key(key(2)));
+        assert_eq!(aesm.client().select_attestation_key(&[]), Ok(key(2)));
+    }
+
+    #[test]
+    fn select_candidate_key() {
+        let (_dir, aesm) = serve(MockAesmBuilder::default().key(key(2)));
+        assert_eq!(
+            aesm.client().select_attestation_key(&[key(3), key(2)]),
+            Ok(key(3))
+        );
+    }
+
+    #[parameterized(
+        empty = { 0 },
+        sixteen = { 16 },
+        large = { 384 },
+    )]
+    fn init_quote(public_key_id_size: usize) {
+        let public_key_id = vec![5; public_key_id_size];
+        let (_dir, aesm) = serve(
+            MockAesmBuilder::default()
+                .target_info(target_info())
+                .public_key_id(public_key_id.clone()),
+        );
+        let initialized = aesm.client().init_quote(&key(2)).unwrap();
+        assert_eq!(initialized.target_info(), &target_info());
+        assert_eq!(initialized.public_key_id(), public_key_id);
+    }
+
+    #[test]
+    fn quote_size() {
+        let (_dir, aesm) = serve(MockAesmBuilder::default().quote(QUOTE));
+        assert_eq!(aesm.client().quote_size(&key(2)), Ok(QUOTE.len()));
+    }
+
+    #[test]
+    fn quote() {
+        let (_dir, aesm) = serve(MockAesmBuilder::default().quote(QUOTE));
+        let quote = aesm.client().quote(&key(2), &Report::default()).unwrap();
+        assert_eq!(quote.as_ref(), QUOTE);
+    }
+
+    #[test]
+    fn quote_with_qe_report() {
+        let (_dir, aesm) = serve(MockAesmBuilder::default().quote(QUOTE));
+        let nonce = QuoteNonce::from([7; 16]);
+        let (quote, report_info) = aesm
+            .client()
+            .quote_with_qe_report(&key(2), &Report::default(), &target_info(), &nonce)
+            .unwrap();
+
+        assert_eq!(report_info.nonce(), nonce);
+        assert_eq!(report_info.target_info(), target_info());
+        let report_data = report_info.report().body().report_data();
+        assert!(quote.verify_nonce(&nonce, &report_data));
+    }
+
+    #[test]
+    fn invalid_quote_fails() {
+        let (_dir, aesm) = serve(MockAesmBuilder::default().quote(vec![1; 32]));
+        assert!(matches!(
+            aesm.client().quote(&key(2), &Report::default()),
+            Err(Error::Quote3(_))
+        ));
+    }
+
+    #[parameterized(
+        select = { |client: &AesmClient| client.select_attestation_key(&[]).map(|_| ()) },
+        init = { |client: &AesmClient| client.init_quote(&key(2)).map(|_| ()) },
+        size = { |client: &AesmClient| client.quote_size(&key(2)).map(|_| ()) },
+        quote = { |client: &AesmClient| client.quote(&key(2), &Report::default()).map(|_| ()) },
+    )]
+    fn aesm_errors_are_returned(call: fn(&AesmClient) -> Result<(), Error>) {
+        let (_dir, aesm) = serve(MockAesmBuilder::default().error_code(42));
+        assert_eq!(call(&aesm.client()), Err(Error::Aesm(42)));
+    }
+
+    #[test]
+    fn dropping_removes_the_socket() {
+        let (_dir, aesm) = serve(MockAesmBuilder::default());
+        let path = aesm.socket_path().to_owned();
+        assert!(path.exists());
+        drop(aesm);
+        assert!(!path.exists());
+    }
+}
@@ -2,12 +2,18 @@
 
 #![doc = include_str!("../README.md")]
 
+mod sdk;
+
+pub use crate::sdk::{
+    check_sgx_sdk, dcap_version, emit_dcap_cfgs, emit_sgx_sdk_cfgs, sgx_sdk_dir, sgx_sdk_version,
+    Version, MINIMUM_SGX_SDK_VERSION,
+};
 use bindgen::callbacks::DeriveInfo;
 use bindgen::{
     callbacks::{IntKind, ParseCallbacks},
     Builder, EnumVariation,
 };
-use std::{env, path::PathBuf};
+use std::{env, fs, path::PathBuf};
 
 static DEFAULT_SGX_SDK_PATH: &str = "/opt/intel/sgxsdk";
 
@@ -99,10 +105,136 @@ fn env_c_flags() -> Vec<String> {
     })
 }
 
+/// The SGX SDK release the pre-generated bindings were generated from
+pub const PREGENERATED_BINDINGS_VERSION: &str = "2.19";
+
+/// When set, the pre-generated bindings are used even if the SGX SDK is
+/// installed.
+const PREGENERATED_BINDINGS_ENV: &str = "SGX_PREGENERATED_BINDINGS";
+
+/// When set, the pre-generated bindings are overwritten with freshly
+/// generated ones.
+const UPDATE_BINDINGS_ENV: &str = "SGX_UPDATE_BINDINGS";
+
+/// When set, the build fails if the pre-generated bindings differ from freshly
+/// generated ones.
+const CHECK_BINDINGS_ENV: &str = "SGX_CHECK_BINDINGS";
+
+/// Write the bindings for the current crate to `OUT_DIR/bindings.rs`
+///
+/// The bindings are generated from `builder` unless the pre-generated bindings
+/// should be used, see [`use_pregenerated_bindings()`]. The pre-generated
+/// bindings are committed to the crate at [`pregenerated_bindings_path()`].
+///
+/// When the bindings are generated they are compared against the
+/// pre-generated ones:
+/// - `SGX_UPDATE_BINDINGS` set: the pre-generated bindings are overwritten.
+/// - `SGX_CHECK_BINDINGS` set: the build fails if they differ.
+/// - Otherwise: a warning is emitted if they differ.
+///
+/// When there are no pre-generated bindings to compare against the build fails
+/// if `SGX_CHECK_BINDINGS` is set.
+///
+/// # Arguments
+/// * `builder` - The builder for the crate's bindings, usually from
+///   [`sgx_builder()`].
+pub fn write_bindings(builder: Builder) {
+    let out_file = build_output_dir().join("bindings.rs");
+    let pregenerated = pregenerated_bindings_path();
+    for var in [
+        PREGENERATED_BINDINGS_ENV,
+        UPDATE_BINDINGS_ENV,
+        CHECK_BINDINGS_ENV,
+        "SGX_SDK",
+        "DOCS_RS",
+    ] {
+        println!("cargo:rerun-if-env-changed={var}");
+    }
+
+    if use_pregenerated_bindings() {
+        cargo_emit::rerun_if_changed!(pregenerated.display());
+        fs::copy(&pregenerated, &out_file).unwrap_or_else(|e| {
+            panic!(
+                "Couldn't copy the pre-generated bindings {}: {e}",
+                pregenerated.display()
+            )
+        });
+        return;
+    }
+
+    let bindings = builder
+        .generate()
+        .expect("Unable to generate bindings")
+        .to_string();
+    fs::write(&out_file, &bindings).expect("Couldn't write bindings!");
+
+    if env::var_os(UPDATE_BINDINGS_ENV).is_some() {
+        let dir = pregenerated
+            .parent()
+            .expect("Pre-generated bindings should be in a directory");
+        fs::create_dir_all(dir).expect("Couldn't create pre-generated bindings directory");
+        fs::write(&pregenerated, &bindings).expect("Couldn't write pre-generated bindings!");
+        return;
+    }
+
+    match fs::read_to_string(&pregenerated) {
+        Ok(existing) if existing == bindings => {}
+        Ok(_) => {
+            let message = format!(
+                "The pre-generated bindings {} are out of date, rebuild with {UPDATE_BINDINGS_ENV}=1",
+                pregenerated.display()
+            );
+            if env::var_os(CHECK_BINDINGS_ENV).is_some() {
+                panic!("{message}");
+            }
+            cargo_emit::warning!("{}", message);
+        }
+        Err(_) if env::var_os(CHECK_BINDINGS_ENV).is_some() => panic!(
+            "There are no pre-generated bindings at {}, create them with {UPDATE_BINDINGS_ENV}=1",
+            pregenerated.display()
+        ),
+        Err(_) => {}
+    }
+}
+
+/// Whether the pre-generated bindings should be used instead of generating
+/// them
+///
+/// The pre-generated bindings are used when the crate has them, see
+/// [`pregenerated_bindings_path()`], and any of the following are true:
+/// - `SGX_PREGENERATED_BINDINGS` is set.
+/// - The build is for docs.rs, `DOCS_RS` is set.
+/// - The SGX SDK libraries are not installed, see [`sgx_library_dir()`].
+///
+/// Crates without pre-generated bindings always generate them, from the
+/// vendored headers when the SGX SDK isn't installed.
+pub fn use_pregenerated_bindings() -> bool {
+    pregenerated_bindings_requested() && pregenerated_bindings_path().exists()
+}
+
+/// Whether the environment asks for the pre-generated bindings, regardless of
+/// whether the crate has them
+fn pregenerated_bindings_requested() -> bool {
+    env::var_os(PREGENERATED_BINDINGS_ENV).is_some()
+        || env::var_os("DOCS_RS").is_some()
+        || !sgx_library_dir().exists()
+}
+
+/// The path to the pre-generated bindings of the crate being built
+///
+/// This is `bindings/sgx-<version>.rs` in the crate's directory, where
+/// `<version>` is [`PREGENERATED_BINDINGS_VERSION`].
+pub fn pregenerated_bindings_path() -> PathBuf {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").expect("Missing env.CARGO_MANIFEST_DIR");
+    PathBuf::from(manifest_dir)
+        .join("bindings")
+        .join(format!("sgx-{PREGENERATED_BINDINGS_VERSION}.rs"))
+}
+
 /// SGXParseCallbacks to be used with [bindgen::Builder::parse_callbacks]
 ///
 /// This provides a default implementation for most of the SGX libraries
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct SgxParseCallbacks {
     // types that are copyable and thus should derive `Copy`
     //
@@ -239,11 +371,6 @@ impl ParseCallbacks for SgxParseCallbacks {
     }
 }
 
-/// Return the SGX SDK path, if it exists.
-fn sgx_sdk_dir() -> Option<PathBuf> {
-    env::var("SGX_SDK").ok().map(PathBuf::from)
-}
-
 /// This constant contains the manifest dir of crate-build, which will contain
 /// the headers, which allows all the headers to live in one dir, rather than
 /// scattered about the repo.
@@ -257,6 +384,14 @@ pub fn sgx_include_dir() -> PathBuf {
     PathBuf::from(CARGO_MANIFEST_DIR).join("headers")
 }
 
+/// Return the include path for the trusted C library (tlibc)
+///
+/// Code compiled for the trusted side of an enclave must use these headers in
+/// place of the system C headers.
+pub fn sgx_tlibc_include_dir() -> PathBuf {
+    sgx_include_dir().join("tlibc")
+}
+
 /// Return the SGX include path as a string.
 ///
 /// Calls sgx_include_dir() and converts to a string.
@@ -269,8 +404,8 @@ pub fn sgx_include_string() -> String {
 
 /// Return the SGX library path.
 ///
-/// Will first attempt to look at the environment variable `SGX_SDK`, if that
-/// isn't present then `/opt/intel/sgxsdk` will be used.
+/// Uses the SGX SDK found by [`sgx_sdk_dir()`], if the SDK can't be found
+/// then `/opt/intel/sgxsdk` will be used.
 pub fn sgx_library_dir() -> PathBuf {
     // As of INTEL-SA-00615, 32-on-64bit enclaves are insecure, so we don't support
     // them.
@@ -291,8 +426,8 @@ pub fn sgx_library_string() -> String {
 
 /// Return the SGX binary path.
 ///
-/// Will first attempt to look at the environment variable `SGX_SDK`, if that
-/// isn't present then `/opt/intel/sgxsdk` will be used.
+/// Uses the SGX SDK found by [`sgx_sdk_dir()`], if the SDK can't be found
+/// then `/opt/intel/sgxsdk` will be used.
 pub fn sgx_bin_x64_dir() -> PathBuf {
     let mut retval = sgx_sdk_dir().unwrap_or_else(|| PathBuf::from(DEFAULT_SGX_SDK_PATH));
     retval.push("bin");
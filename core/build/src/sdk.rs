@@ -0,0 +1,336 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Discovery of the installed SGX SDK and DCAP libraries and their versions
+
+use once_cell::sync::OnceCell;
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Standard install locations of the SGX SDK, in the order they are searched.
+const STANDARD_SGX_SDK_PATHS: &[&str] = &["/opt/intel/sgxsdk"];
+
+/// The pkg-config package the SGX SDK installs for the untrusted runtime.
+const SGX_SDK_PKG_CONFIG: &str = "libsgx_urts";
+
+/// The pkg-config package of the DCAP quote library.
+const DCAP_PKG_CONFIG: &str = "sgx_dcap_ql";
+
+/// The oldest SGX SDK the vendored headers are compatible with.
+pub const MINIMUM_SGX_SDK_VERSION: Version = Version::new(2, 19);
+
+/// The SGX SDK releases with a `sgx_sdk_<major>_<minor>` cfg.
+const SGX_SDK_VERSIONS: &[Version] = &[
+    Version::new(2, 19),
+    Version::new(2, 20),
+    Version::new(2, 21),
+    Version::new(2, 22),
+    Version::new(2, 23),
+    Version::new(2, 24),
+    Version::new(2, 25),
+];
+
+/// The DCAP releases with a `sgx_dcap_<major>_<minor>` cfg.
+const DCAP_VERSIONS: &[Version] = &[
+    Version::new(1, 16),
+    Version::new(1, 17),
+    Version::new(1, 18),
+    Version::new(1, 19),
+    Version::new(1, 20),
+    Version::new(1, 21),
+    Version::new(1, 22),
+];
+
+/// The version of an SGX SDK or DCAP release, i.e. `2.19.100.3`
+///
+/// Only the major and minor numbers are compared, the remaining components
+/// identify builds of the same release.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    /// The major version
+    pub major: u32,
+    /// The minor version
+    pub minor: u32,
+}
+
+impl Version {
+    /// Create a version from its major and minor numbers
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split('.');
+        let mut number = || {
+            parts
+                .next()
+                .and_then(|p| p.parse::<u32>().ok())
+                .ok_or_else(|| format!("Invalid version: {s:?}"))
+        };
+        Ok(Self::new(number()?, number()?))
+    }
+}
+
+/// Return the SGX SDK path, if it can be found.
+///
+/// The SDK is searched for in order:
+/// 1. The `SGX_SDK` environment variable.
+/// 2. The `prefix` of the `libsgx_urts` pkg-config package, the SDK's
+///    `environment` script adds it to `PKG_CONFIG_PATH`.
+/// 3. The standard install location, `/opt/intel/sgxsdk`.
+///
+/// The search is only done once per build script, later calls return the same
+/// result.
+pub fn sgx_sdk_dir() -> Option<PathBuf> {
+    static SGX_SDK_DIR: OnceCell<Option<PathBuf>> = OnceCell::new();
+    SGX_SDK_DIR
+        .get_or_init(|| {
+            println!("cargo:rerun-if-env-changed=SGX_SDK");
+            println!("cargo:rerun-if-env-changed=PKG_CONFIG_PATH");
+            if let Some(dir) = env::var_os("SGX_SDK") {
+                return Some(PathBuf::from(dir));
+            }
+            if let Some(prefix) = pkg_config_variable(SGX_SDK_PKG_CONFIG, "prefix") {
+                return Some(PathBuf::from(prefix));
+            }
+            STANDARD_SGX_SDK_PATHS
+                .iter()
+                .map(PathBuf::from)
+                .find(|path| path.exists())
+        })
+        .clone()
+}
+
+/// Return the version of the installed SGX SDK, if it can be determined.
+///
+/// The version comes from the `SGX_SDK_VERSION` environment variable, or the
+/// `libsgx_urts.pc` file the SDK ships in its `pkgconfig` directory.
+pub fn sgx_sdk_version() -> Option<Version> {
+    if let Some(version) = env_version("SGX_SDK_VERSION") {
+        return Some(version);
+    }
+    let pc_file = sgx_sdk_dir()?
+        .join("pkgconfig")
+        .join(format!("{SGX_SDK_PKG_CONFIG}.pc"));
+    pc_file_version(&pc_file).or_else(|| pkg_config_version(SGX_SDK_PKG_CONFIG))
+}
+
+/// Return the version of the installed DCAP libraries, if it can be
+/// determined.
+///
+/// The version comes from the `SGX_DCAP_VERSION` environment variable, or the
+/// `sgx_dcap_ql` pkg-config package.
+pub fn dcap_version() -> Option<Version> {
+    env_version("SGX_DCAP_VERSION").or_else(|| pkg_config_version(DCAP_PKG_CONFIG))
+}
+
+/// Check that the SGX SDK the crate links against is installed and recent
+/// enough
+///
+/// The check is skipped when building for docs.rs, `DOCS_RS` is set, or when
+/// `SGX_PREGENERATED_BINDINGS` is set. Neither links the SGX SDK libraries, so
+/// the crate can be built and documented without the SDK.
+///
+/// # Panics
+/// If the SGX SDK libraries can't be found, see [`crate::sgx_library_dir()`],
+/// or the installed SGX SDK is older than [`MINIMUM_SGX_SDK_VERSION`].
+pub fn check_sgx_sdk() {
+    println!("cargo:rerun-if-env-changed=DOCS_RS");
+    println!(
+        "cargo:rerun-if-env-changed={}",
+        crate::PREGENERATED_BINDINGS_ENV
+    );
+    if env::var_os("DOCS_RS").is_some() || env::var_os(crate::PREGENERATED_BINDINGS_ENV).is_some() {
+        return;
+    }
+
+    let library_dir = crate::sgx_library_dir();
+    if !library_dir.exists() {
+        panic!(
+            "The SGX SDK libraries could not be found in {}, set `SGX_SDK` to the SGX SDK install directory",
+            library_dir.display()
+        );
+    }
+
+    match sgx_sdk_version() {
+        Some(sdk) if sdk < MINIMUM_SGX_SDK_VERSION => panic!(
+            "The installed SGX SDK is version {sdk}, at least {MINIMUM_SGX_SDK_VERSION} is required"
+        ),
+        Some(_) => {}
+        None => cargo_emit::warning!(
+            "The version of the SGX SDK in {} could not be determined, set `SGX_SDK_VERSION` to check it is at least {}",
+            library_dir.display(),
+            MINIMUM_SGX_SDK_VERSION
+        ),
+    }
+}
+
+/// Check the installed SGX SDK and emit version `cfg`s for it
+///
+/// The SDK is checked with [`check_sgx_sdk()`]. Then for every known SGX SDK
+/// release up to and including the installed one a
+/// `sgx_sdk_<major>_<minor>` cfg is emitted, i.e. `sgx_sdk_2_20` for an SDK
+/// of 2.20 or newer. This allows wrappers to only expose newer APIs when the
+/// SDK provides them:
+///
+/// ```ignore
+/// #[cfg(sgx_sdk_2_20)]
+/// pub fn newer_api() {}
+/// ```
+///
+/// No cfgs are emitted when the SDK version can not be determined.
+///
+/// # Panics
+/// If [`check_sgx_sdk()`] fails.
+pub fn emit_sgx_sdk_cfgs() {
+    check_sgx_sdk();
+    emit_cfgs("sgx_sdk", SGX_SDK_VERSIONS, sgx_sdk_version());
+}
+
+/// Emit version `cfg`s for the installed DCAP libraries
+///
+/// The same as [`emit_sgx_sdk_cfgs()`] with `sgx_dcap_<major>_<minor>` cfgs,
+/// i.e. `sgx_dcap_1_17`. Nothing is emitted when the DCAP version can not be
+/// determined.
+pub fn emit_dcap_cfgs() {
+    emit_cfgs("sgx_dcap", DCAP_VERSIONS, dcap_version());
+}
+
+fn emit_cfgs(prefix: &str, known: &[Version], installed: Option<Version>) {
+    for version in known {
+        println!("cargo:rustc-check-cfg=cfg({})", cfg_name(prefix, version));
+    }
+    for cfg in cfg_names(prefix, known, installed) {
+        cargo_emit::rustc_cfg!("{}", cfg);
+    }
+}
+
+fn cfg_name(prefix: &str, version: &Version) -> String {
+    format!("{prefix}_{}_{}", version.major, version.minor)
+}
+
+/// The cfgs of the `known` releases up to and including `installed`
+fn cfg_names(prefix: &str, known: &[Version], installed: Option<Version>) -> Vec<String> {
+    let installed = match installed {
+        Some(installed) => installed,
+        None => return vec![],
+    };
+    known
+        .iter()
+        .filter(|version| **version <= installed)
+        .map(|version| cfg_name(prefix, version))
+        .collect()
+}
+
+fn env_version(var: &str) -> Option<Version> {
+    println!("cargo:rerun-if-env-changed={var}");
+    let value = env::var(var).ok()?;
+    let version = value
+        .parse()
+        .unwrap_or_else(|e| panic!("Failed to parse `{var}`: {e}"));
+    Some(version)
+}
+
+/// Read the `Version:` field of a pkg-config `.pc` file
+fn pc_file_version(pc_file: &Path) -> Option<Version> {
+    let contents = fs::read_to_string(pc_file).ok()?;
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("Version:"))
+        .and_then(|version| version.parse().ok())
+}
+
+fn pkg_config_version(package: &str) -> Option<Version> {
+    let library = pkg_config::Config::new()
+        .cargo_metadata(false)
+        .env_metadata(false)
+        .probe(package)
+        .ok()?;
+    library.version.parse().ok()
+}
+
+fn pkg_config_variable(package: &str, variable: &str) -> Option<String> {
+    pkg_config::get_variable(package, variable)
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_full_sdk_version() {
+        assert_eq!("2.19.100.3".parse(), Ok(Version::new(2, 19)));
+    }
+
+    #[test]
+    fn parses_major_minor_version() {
+        assert_eq!(" 1.16\n".parse(), Ok(Version::new(1, 16)));
+    }
+
+    #[test]
+    fn invalid_version_fails() {
+        assert!("2".parse::<Version>().is_err());
+        assert!("two.19".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn versions_order_numerically() {
+        assert!(Version::new(2, 9) < Version::new(2, 19));
+        assert!(Version::new(1, 30) < Version::new(2, 0));
+    }
+
+    #[test]
+    fn reads_pc_file_version() {
+        let dir = env::temp_dir().join(format!("mc-sgx-core-build-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pc_file = dir.join("libsgx_urts.pc");
+        fs::write(
+            &pc_file,
+            "prefix=/opt/intel/sgxsdk\nName: libsgx_urts\nVersion: 2.20.100.4\n",
+        )
+        .unwrap();
+
+        assert_eq!(pc_file_version(&pc_file), Some(Version::new(2, 20)));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cfg_names_up_to_installed_version() {
+        assert_eq!(
+            cfg_names("sgx_sdk", SGX_SDK_VERSIONS, Some(Version::new(2, 20))),
+            ["sgx_sdk_2_19", "sgx_sdk_2_20"]
+        );
+        assert_eq!(
+            cfg_names("sgx_dcap", DCAP_VERSIONS, Some(Version::new(1, 16))),
+            ["sgx_dcap_1_16"]
+        );
+    }
+
+    #[test]
+    fn newer_version_gets_all_cfgs() {
+        let cfgs = cfg_names("sgx_sdk", SGX_SDK_VERSIONS, Some(Version::new(3, 0)));
+        assert_eq!(cfgs.len(), SGX_SDK_VERSIONS.len());
+        assert_eq!(cfgs.last().map(String::as_str), Some("sgx_sdk_2_25"));
+    }
+
+    #[test]
+    fn older_or_unknown_version_gets_no_cfgs() {
+        assert!(cfg_names("sgx_dcap", DCAP_VERSIONS, Some(Version::new(1, 15))).is_empty());
+        assert!(cfg_names("sgx_dcap", DCAP_VERSIONS, None).is_empty());
+    }
+}
@@ -135,10 +135,5 @@ fn main() {
         builder = builder.allowlist_var(c)
     }
 
-    let bindings = builder.generate().expect("Unable to generate bindings");
-
-    let out_path = mc_sgx_core_build::build_output_dir();
-    bindings
-        .write_to_file(out_path.join("bindings.rs"))
-        .expect("Couldn't write bindings!");
+    mc_sgx_core_build::write_bindings(builder);
 }
@@ -14,6 +14,7 @@ pub enum MrSignerKeyHash {
     Sha384([u8; 48]),
 }
 
+/// The algorithm of an attestation key
 #[derive(Default, Debug, Clone, Hash, PartialEq, Eq)]
 #[non_exhaustive]
 #[repr(u16)]
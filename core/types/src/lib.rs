@@ -16,11 +16,12 @@ mod macros;
 mod measurement;
 mod quote;
 mod report;
+mod sigstruct;
 mod svn;
 mod target_info;
 
 pub use crate::{
-    attestation_key::{AttestationKeyId, ExtendedAttestationKeyId},
+    attestation_key::{Algorithm, AttestationKeyId, ExtendedAttestationKeyId},
     attributes::{
         AttributeFlags, Attributes, ExtendedFeatureRequestMask, MiscellaneousAttribute,
         MiscellaneousSelect,
@@ -29,8 +30,9 @@ pub use crate::{
     error::{Error, FfiError},
     key_request::{KeyName, KeyPolicy, KeyRequest, KeyRequestBuilder},
     measurement::{MrEnclave, MrSigner},
-    quote::QuoteNonce,
+    quote::{QuoteNonce, QuotingEnclaveReportInfo},
     report::{ExtendedProductId, FamilyId, IsvProductId, Report, ReportBody, ReportData},
+    sigstruct::Sigstruct,
     svn::{ConfigSvn, CpuSvn, IsvSvn},
     target_info::TargetInfo,
 };
@@ -9,6 +9,7 @@ use mc_sgx_core_sys_types::{
     sgx_basename_t, sgx_epid_group_id_t, sgx_platform_info_t, sgx_qe_report_info_t,
     sgx_quote_nonce_t, sgx_quote_sign_type_t, sgx_update_info_bit_t, SGX_PLATFORM_INFO_SIZE,
 };
+use rand_core::{CryptoRng, RngCore};
 
 /// Quoting Enclave Report Info
 #[derive(Default, Debug, Clone, Hash, PartialEq, Eq)]
@@ -102,6 +103,20 @@ impl_newtype_for_bytestruct! {
     QuoteNonce, sgx_quote_nonce_t, NONCE_SIZE, rand;
 }
 
+impl QuoteNonce {
+    /// Creates a new random [QuoteNonce].
+    ///
+    /// # Arguments
+    ///
+    /// * `csprng` - A cryptographic psuedo random number generator used to
+    ///   generate the nonce.
+    pub fn new<R: CryptoRng + RngCore>(csprng: &mut R) -> Self {
+        let mut nonce = [0u8; NONCE_SIZE];
+        csprng.fill_bytes(&mut nonce);
+        nonce.into()
+    }
+}
+
 /// The raw bytes representing a quote.
 ///
 /// Should not be used directly instead use [`Quote`].
@@ -219,6 +234,7 @@ mod test {
     use crate::{report::Report, TargetInfo};
     use core::{mem, slice};
     use mc_sgx_core_sys_types::{sgx_quote_t, sgx_report_body_t, sgx_report_t, sgx_target_info_t};
+    use rand::{rngs::StdRng, SeedableRng};
 
     #[allow(unsafe_code)]
     fn quote_to_bytes(report: sgx_quote_t) -> [u8; mem::size_of::<sgx_quote_t>()] {
@@ -365,6 +381,16 @@ mod test {
         assert_eq!(info.platform_software_needs_update(), true);
     }
 
+    #[test]
+    fn new_quote_nonces_differ() {
+        let mut csprng = StdRng::from_seed([3; 32]);
+        let first = QuoteNonce::new(&mut csprng);
+        let second = QuoteNonce::new(&mut csprng);
+
+        assert_ne!(first, QuoteNonce::default());
+        assert_ne!(first, second);
+    }
+
     #[test]
     fn default_quoting_enclave_report_info() {
         let info = QuotingEnclaveReportInfo::default();
@@ -0,0 +1,265 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! The enclave signature structure (SIGSTRUCT)
+
+use crate::{Attributes, FfiError, IsvProductId, IsvSvn, MiscellaneousSelect, MrEnclave, MrSigner};
+use core::ops::Range;
+use mc_sgx_core_sys_types::{sgx_attributes_t, SGX_FLAGS_DEBUG};
+use sha2::{Digest, Sha256};
+
+/// The magic number of the `metadata_t` `sgx_sign` adds to a signed enclave
+const METADATA_MAGIC: [u8; 8] = 0x86A8_0294_635D_0E4C_u64.to_le_bytes();
+
+/// The offset of the SIGSTRUCT in the `metadata_t`
+const METADATA_SIGSTRUCT_OFFSET: usize = 64;
+
+/// The fixed HEADER field every SIGSTRUCT starts with
+const HEADER: [u8; 16] = [6, 0, 0, 0, 0xE1, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0];
+
+const MODULUS: Range<usize> = 128..512;
+const MISC_SELECT: Range<usize> = 900..904;
+const ATTRIBUTE_FLAGS: Range<usize> = 928..936;
+const ATTRIBUTE_XFRM: Range<usize> = 936..944;
+const ATTRIBUTE_MASK_FLAGS: Range<usize> = 944..952;
+const ATTRIBUTE_MASK_XFRM: Range<usize> = 952..960;
+const ENCLAVE_HASH: Range<usize> = 960..992;
+const ISV_PROD_ID: Range<usize> = 1024..1026;
+const ISV_SVN: Range<usize> = 1026..1028;
+
+/// The signature structure of a signed enclave, `enclave_css_t`
+///
+/// `sgx_sign` embeds the SIGSTRUCT in the metadata of a signed enclave, see
+/// [`Sigstruct::from_enclave()`]. It can also be written out on its own as the
+/// `-cssfile` of `sgx_sign dump`, see [`Sigstruct::try_from()`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Sigstruct([u8; Sigstruct::SIZE]);
+
+impl Sigstruct {
+    /// Size of a SIGSTRUCT in bytes
+    pub const SIZE: usize = 1808;
+
+    /// Find the SIGSTRUCT in the bytes of a signed enclave
+    ///
+    /// Returns `None` if the enclave isn't signed.
+    ///
+    /// # Arguments
+    /// * `enclave` - The bytes of the signed enclave shared object
+    pub fn from_enclave(enclave: &[u8]) -> Option<Self> {
+        enclave
+            .windows(METADATA_MAGIC.len())
+            .enumerate()
+            .filter(|(_, window)| *window == METADATA_MAGIC)
+            .find_map(|(offset, _)| {
+                let start = offset + METADATA_SIGSTRUCT_OFFSET;
+                let bytes = enclave.get(start..start + Self::SIZE)?;
+                if !bytes.starts_with(&HEADER) {
+                    return None;
+                }
+                Self::try_from(bytes).ok()
+            })
+    }
+
+    /// The MRENCLAVE of the signed enclave
+    pub fn mr_enclave(&self) -> MrEnclave {
+        let mut hash = [0; MrEnclave::SIZE];
+        hash.copy_from_slice(&self.0[ENCLAVE_HASH]);
+        hash.into()
+    }
+
+    /// The MRSIGNER of the signed enclave
+    ///
+    /// This is the SHA256 of the signer's public key modulus.
+    pub fn mr_signer(&self) -> MrSigner {
+        <[u8; MrSigner::SIZE]>::from(Sha256::digest(&self.0[MODULUS])).into()
+    }
+
+    /// The ISV product ID of the enclave
+    pub fn isv_prod_id(&self) -> IsvProductId {
+        self.u16_at(ISV_PROD_ID).into()
+    }
+
+    /// The ISV security version of the enclave
+    pub fn isv_svn(&self) -> IsvSvn {
+        self.u16_at(ISV_SVN).into()
+    }
+
+    /// The MISCSELECT the enclave was signed with
+    pub fn misc_select(&self) -> MiscellaneousSelect {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&self.0[MISC_SELECT]);
+        u32::from_le_bytes(bytes).into()
+    }
+
+    /// The attributes the enclave was signed with
+    pub fn attributes(&self) -> Attributes {
+        sgx_attributes_t {
+            flags: self.u64_at(ATTRIBUTE_FLAGS),
+            xfrm: self.u64_at(ATTRIBUTE_XFRM),
+        }
+        .into()
+    }
+
+    /// The mask of the attributes which must match [`Sigstruct::attributes()`]
+    /// when the enclave is created
+    pub fn attribute_mask(&self) -> Attributes {
+        sgx_attributes_t {
+            flags: self.u64_at(ATTRIBUTE_MASK_FLAGS),
+            xfrm: self.u64_at(ATTRIBUTE_MASK_XFRM),
+        }
+        .into()
+    }
+
+    /// `true` when the enclave was signed with the DEBUG attribute
+    pub fn debug(&self) -> bool {
+        self.u64_at(ATTRIBUTE_FLAGS) & SGX_FLAGS_DEBUG as u64 != 0
+    }
+
+    /// `true` when the enclave can be created in debug mode
+    ///
+    /// The signer allows debug mode by either setting the DEBUG attribute or
+    /// leaving it out of the attribute mask.
+    pub fn debug_capable(&self) -> bool {
+        self.debug() || self.u64_at(ATTRIBUTE_MASK_FLAGS) & SGX_FLAGS_DEBUG as u64 == 0
+    }
+
+    fn u16_at(&self, range: Range<usize>) -> u16 {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(&self.0[range]);
+        u16::from_le_bytes(bytes)
+    }
+
+    fn u64_at(&self, range: Range<usize>) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.0[range]);
+        u64::from_le_bytes(bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for Sigstruct {
+    type Error = FfiError;
+
+    /// A SIGSTRUCT from exactly [`Sigstruct::SIZE`] bytes
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let bytes = bytes.try_into().map_err(|_| FfiError::InvalidInputLength)?;
+        Ok(Self(bytes))
+    }
+}
+
+impl AsRef<[u8]> for Sigstruct {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A SIGSTRUCT which can't be created in debug mode
+    fn sigstruct_bytes() -> [u8; Sigstruct::SIZE] {
+        let mut bytes = [0; Sigstruct::SIZE];
+        bytes[..HEADER.len()].copy_from_slice(&HEADER);
+        bytes[MODULUS].fill(0xA5);
+        bytes[MISC_SELECT].copy_from_slice(&[1, 0, 0, 0]);
+        bytes[ATTRIBUTE_FLAGS].copy_from_slice(&[0x4, 0, 0, 0, 0, 0, 0, 0]);
+        bytes[ATTRIBUTE_XFRM].copy_from_slice(&[0x3, 0, 0, 0, 0, 0, 0, 0]);
+        bytes[ATTRIBUTE_MASK_FLAGS].fill(0xFF);
+        bytes[ATTRIBUTE_MASK_XFRM].fill(0xFF);
+        bytes[ENCLAVE_HASH].fill(0x11);
+        bytes[ISV_PROD_ID].copy_from_slice(&[0x34, 0x12]);
+        bytes[ISV_SVN].copy_from_slice(&[0x05, 0x00]);
+        bytes
+    }
+
+    /// Enclave bytes with the metadata holding `sigstruct` at `offset`
+    fn enclave_bytes(sigstruct: &[u8], offset: usize) -> [u8; 4096] {
+        let mut bytes = [0; 4096];
+        bytes[..4].copy_from_slice(b"\x7FELF");
+        bytes[offset..offset + METADATA_MAGIC.len()].copy_from_slice(&METADATA_MAGIC);
+        let start = offset + METADATA_SIGSTRUCT_OFFSET;
+        bytes[start..start + sigstruct.len()].copy_from_slice(sigstruct);
+        bytes
+    }
+
+    #[test]
+    fn parses_fields() {
+        let sigstruct = Sigstruct::try_from(&sigstruct_bytes()[..]).unwrap();
+
+        assert_eq!(sigstruct.mr_enclave(), MrEnclave::from([0x11; 32]));
+        assert_eq!(sigstruct.isv_prod_id(), IsvProductId::from(0x1234));
+        assert_eq!(sigstruct.isv_svn(), IsvSvn::from(5));
+        assert_eq!(sigstruct.misc_select(), MiscellaneousSelect::from(1));
+        assert_eq!(
+            sigstruct.attributes(),
+            Attributes::from(sgx_attributes_t { flags: 4, xfrm: 3 })
+        );
+        assert!(!sigstruct.debug());
+        assert!(!sigstruct.debug_capable());
+    }
+
+    #[test]
+    fn mr_signer_is_hash_of_modulus() {
+        let sigstruct = Sigstruct(sigstruct_bytes());
+        let expected: [u8; 32] = Sha256::digest([0xA5; 384]).into();
+        assert_eq!(sigstruct.mr_signer(), MrSigner::from(expected));
+    }
+
+    #[test]
+    fn wrong_size_fails() {
+        let bytes = sigstruct_bytes();
+        assert_eq!(
+            Sigstruct::try_from(&bytes[1..]),
+            Err(FfiError::InvalidInputLength)
+        );
+    }
+
+    #[test]
+    fn debug_flag_is_debug_capable() {
+        let mut bytes = sigstruct_bytes();
+        bytes[ATTRIBUTE_FLAGS][0] |= SGX_FLAGS_DEBUG as u8;
+        let sigstruct = Sigstruct(bytes);
+        assert!(sigstruct.debug());
+        assert!(sigstruct.debug_capable());
+    }
+
+    #[test]
+    fn unmasked_debug_flag_is_debug_capable() {
+        let mut bytes = sigstruct_bytes();
+        bytes[ATTRIBUTE_MASK_FLAGS][0] &= !(SGX_FLAGS_DEBUG as u8);
+        let sigstruct = Sigstruct(bytes);
+        assert!(!sigstruct.debug());
+        assert!(sigstruct.debug_capable());
+    }
+
+    #[test]
+    fn finds_sigstruct_in_enclave() {
+        let enclave = enclave_bytes(&sigstruct_bytes(), 300);
+        let sigstruct = Sigstruct::from_enclave(&enclave).expect("SIGSTRUCT should be found");
+        assert_eq!(sigstruct.as_ref(), &sigstruct_bytes()[..]);
+    }
+
+    #[test]
+    fn unsigned_enclave_has_no_sigstruct() {
+        assert_eq!(Sigstruct::from_enclave(b"\x7FELF not signed"), None);
+    }
+
+    #[test]
+    fn magic_without_sigstruct_header_is_skipped() {
+        let mut enclave = enclave_bytes(&[0; Sigstruct::SIZE], 100);
+        assert_eq!(Sigstruct::from_enclave(&enclave), None);
+
+        // A later metadata is still found
+        let later = 2000;
+        enclave[later..later + METADATA_MAGIC.len()].copy_from_slice(&METADATA_MAGIC);
+        let start = later + METADATA_SIGSTRUCT_OFFSET;
+        enclave[start..start + Sigstruct::SIZE].copy_from_slice(&sigstruct_bytes());
+        assert!(Sigstruct::from_enclave(&enclave).is_some());
+    }
+
+    #[test]
+    fn truncated_sigstruct_is_not_found() {
+        let enclave = enclave_bytes(&sigstruct_bytes(), 300);
+        let truncated = &enclave[..300 + METADATA_SIGSTRUCT_OFFSET + Sigstruct::SIZE - 1];
+        assert_eq!(Sigstruct::from_enclave(truncated), None);
+    }
+}
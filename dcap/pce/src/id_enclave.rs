@@ -0,0 +1,232 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! The Intel signed ID enclave, loaded with the uRTS
+
+use crate::{Error, IdEnclave, PCE_ALG_RSA_OAEP_3072, QE_ID_SIZE};
+use mc_sgx_core_sys_types::{sgx_report_t, sgx_target_info_t};
+use mc_sgx_core_types::{Report, TargetInfo};
+use mc_sgx_urts::{Enclave, EnclaveBuilder};
+use mc_sgx_urts_sys::sgx_ecall;
+use std::{ffi::c_void, os::raw::c_int, path::Path};
+
+/// The default location of the ID enclave on an Ubuntu DCAP install
+pub const DEFAULT_ID_ENCLAVE_PATH: &str = "/usr/lib/x86_64-linux-gnu/libsgx_id_enclave.signed.so.1";
+
+/// `PPID_RSA3072_ENCRYPTED`, the certification key type for registration
+const PPID_RSA3072_ENCRYPTED: u16 = 3;
+
+/// The size of the RSA-3072 public key the PCE encrypts the PPID with, the
+/// 384 byte modulus followed by the 4 byte exponent
+const ENCRYPTION_KEY_SIZE: usize = 388;
+
+/// The ECALLs of `id_enclave.edl`, numbered in the order they're declared,
+/// `ide_get_pce_encrypt_key()` then `ide_get_id()`
+const ECALL_IDE_GET_PCE_ENCRYPT_KEY: c_int = 0;
+const ECALL_IDE_GET_ID: c_int = 1;
+
+/// The OCALL table `sgx_edger8r` generates, the ID enclave has no OCALLs
+#[repr(C)]
+struct OcallTable {
+    count: usize,
+}
+
+static OCALL_TABLE: OcallTable = OcallTable { count: 0 };
+
+/// The marshalled arguments of `ide_get_pce_encrypt_key()`, laid out as
+/// `sgx_edger8r` does for the EDL declaration
+///
+/// ```text
+/// public uint32_t ide_get_pce_encrypt_key(
+///     [in] const sgx_target_info_t *pce_target_info,
+///     [out] sgx_report_t *id_enclave_report,
+///     uint8_t crypto_suite,
+///     uint16_t cert_key_type,
+///     uint32_t key_size,
+///     [out, size=key_size] uint8_t *public_key);
+/// ```
+#[repr(C)]
+struct GetPceEncryptKeyArgs {
+    retval: u32,
+    pce_target_info: *const sgx_target_info_t,
+    id_enclave_report: *mut sgx_report_t,
+    crypto_suite: u8,
+    cert_key_type: u16,
+    key_size: u32,
+    public_key: *mut u8,
+}
+
+/// The marshalled arguments of `ide_get_id()`, laid out as `sgx_edger8r`
+/// does for the EDL declaration
+///
+/// ```text
+/// public uint32_t ide_get_id([out, size=16] uint8_t *id);
+/// ```
+#[repr(C)]
+struct GetIdArgs {
+    retval: u32,
+    id: *mut [u8; QE_ID_SIZE],
+}
+
+/// The Intel signed ID enclave, `libsgx_id_enclave.signed.so`
+///
+/// ```no_run
+/// # use mc_sgx_dcap_pce::{IntelIdEnclave, PlatformIdentity};
+/// let id_enclave = IntelIdEnclave::try_default()?;
+/// let identity = PlatformIdentity::retrieve(&id_enclave)?;
+/// # Ok::<(), mc_sgx_dcap_pce::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct IntelIdEnclave {
+    enclave: Enclave,
+}
+
+impl IntelIdEnclave {
+    /// Load the ID enclave from [`DEFAULT_ID_ENCLAVE_PATH`]
+    ///
+    /// # Errors
+    /// [`Error::IdEnclave`] if the ID enclave can't be read or created.
+    pub fn try_default() -> Result<Self, Error> {
+        Self::load(DEFAULT_ID_ENCLAVE_PATH)
+    }
+
+    /// Load the ID enclave
+    ///
+    /// # Arguments
+    /// * `path` - The full file path to the ID enclave
+    ///
+    /// # Errors
+    /// [`Error::IdEnclave`] if the ID enclave can't be read or created.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let enclave = EnclaveBuilder::new(path)
+            .map_err(|e| Error::IdEnclave(format!("{}: {e}", path.display())))?
+            .create()
+            .map_err(|e| Error::IdEnclave(format!("{}: {e}", path.display())))?;
+        Ok(Self { enclave })
+    }
+
+    /// Make the ECALL `index` with the marshalled arguments `args`
+    ///
+    /// The ID enclave functions return 0 on success, any other value is
+    /// reported as [`mc_sgx_core_types::Error::Unexpected`].
+    fn ecall<T>(
+        &self,
+        index: c_int,
+        args: &mut T,
+        retval: impl FnOnce(&T) -> u32,
+    ) -> Result<(), mc_sgx_core_types::Error> {
+        self.enclave.ecall(|eid| unsafe {
+            sgx_ecall(
+                eid,
+                index,
+                &OCALL_TABLE as *const OcallTable as *const c_void,
+                args as *mut T as *mut c_void,
+            )
+        })?;
+        match retval(args) {
+            0 => Ok(()),
+            _ => Err(mc_sgx_core_types::Error::Unexpected),
+        }
+    }
+}
+
+impl IdEnclave for IntelIdEnclave {
+    fn pce_report(
+        &self,
+        pce_target_info: &TargetInfo,
+    ) -> Result<(Report, Vec<u8>), mc_sgx_core_types::Error> {
+        let mut report = sgx_report_t::default();
+        let mut key = vec![0; ENCRYPTION_KEY_SIZE];
+        let mut args = GetPceEncryptKeyArgs {
+            retval: 0,
+            pce_target_info: pce_target_info.as_ref(),
+            id_enclave_report: &mut report,
+            crypto_suite: PCE_ALG_RSA_OAEP_3072,
+            cert_key_type: PPID_RSA3072_ENCRYPTED,
+            key_size: ENCRYPTION_KEY_SIZE as u32,
+            public_key: key.as_mut_ptr(),
+        };
+        self.ecall(ECALL_IDE_GET_PCE_ENCRYPT_KEY, &mut args, |args| args.retval)?;
+        Ok((report.into(), key))
+    }
+
+    fn qe_id(&self) -> Result<[u8; QE_ID_SIZE], mc_sgx_core_types::Error> {
+        let mut id = [0; QE_ID_SIZE];
+        let mut args = GetIdArgs {
+            retval: 0,
+            id: &mut id,
+        };
+        self.ecall(ECALL_IDE_GET_ID, &mut args, |args| args.retval)?;
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::{mem, ptr};
+
+    /// The offset of `field` in `T`, `core::mem::offset_of!()` is newer than
+    /// the MSRV
+    macro_rules! offset_of {
+        ($type:ty, $field:ident) => {{
+            let value = mem::MaybeUninit::<$type>::uninit();
+            let base = value.as_ptr();
+            let field = unsafe { ptr::addr_of!((*base).$field) };
+            field as usize - base as usize
+        }};
+    }
+
+    #[test]
+    fn get_pce_encrypt_key_args_match_the_edger8r_layout() {
+        // The 1 byte crypto suite, 2 byte key type and 4 byte key size share
+        // 8 bytes between the pointers
+        assert_eq!(mem::size_of::<GetPceEncryptKeyArgs>(), 40);
+        assert_eq!(offset_of!(GetPceEncryptKeyArgs, retval), 0);
+        assert_eq!(offset_of!(GetPceEncryptKeyArgs, pce_target_info), 8);
+        assert_eq!(offset_of!(GetPceEncryptKeyArgs, id_enclave_report), 16);
+        assert_eq!(offset_of!(GetPceEncryptKeyArgs, crypto_suite), 24);
+        assert_eq!(offset_of!(GetPceEncryptKeyArgs, cert_key_type), 26);
+        assert_eq!(offset_of!(GetPceEncryptKeyArgs, key_size), 28);
+        assert_eq!(offset_of!(GetPceEncryptKeyArgs, public_key), 32);
+    }
+
+    #[test]
+    fn get_id_args_match_the_edger8r_layout() {
+        assert_eq!(mem::size_of::<GetIdArgs>(), 16);
+        assert_eq!(offset_of!(GetIdArgs, retval), 0);
+        assert_eq!(offset_of!(GetIdArgs, id), 8);
+        assert_eq!(mem::size_of::<[u8; QE_ID_SIZE]>(), 16);
+    }
+
+    #[test]
+    fn ocall_table_matches_the_edger8r_layout() {
+        // `sgx_edger8r` emits `{ size_t nr_ocall; void *table[nr_ocall]; }`,
+        // with no OCALLs only the count remains
+        assert_eq!(mem::size_of::<OcallTable>(), mem::size_of::<usize>());
+        assert_eq!(OCALL_TABLE.count, 0);
+    }
+
+    #[test]
+    fn missing_id_enclave_fails_to_load() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            IntelIdEnclave::load(dir.path().join("missing.signed.so")),
+            Err(Error::IdEnclave(_))
+        ));
+    }
+}
+
+#[cfg(all(test, not(feature = "sim")))]
+mod hw_test {
+    use super::*;
+    use crate::{PlatformIdentity, ENCRYPTED_PPID_SIZE};
+
+    #[test]
+    fn retrieve_the_platform_identity() {
+        let id_enclave = IntelIdEnclave::try_default().unwrap();
+        let identity = PlatformIdentity::retrieve(&id_enclave).unwrap();
+        assert_eq!(identity.encrypted_ppid().len(), ENCRYPTED_PPID_SIZE);
+        assert_ne!(identity.qe_id(), &[0; QE_ID_SIZE]);
+    }
+}
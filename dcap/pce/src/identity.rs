@@ -0,0 +1,286 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! The platform identity registered with the provisioning certification
+//! caching service (PCCS)
+
+use crate::{pce_info, pce_target_info, Error};
+use core::fmt::Write;
+use mc_sgx_core_types::{CpuSvn, IsvSvn, Report, TargetInfo};
+use std::{fs, io, path::Path};
+
+/// The size of the quoting enclave ID
+pub const QE_ID_SIZE: usize = 16;
+
+/// The EFI variable holding the platform manifest of multi-package platforms
+pub const PLATFORM_MANIFEST_EFI_VARIABLE: &str =
+    "/sys/firmware/efi/efivars/SgxRegistrationServerRequest-304e0796-d515-4698-ac6e-e76cb1a71c28";
+
+/// The size of the attributes preceding the contents of an EFI variable
+const EFI_VARIABLE_ATTRIBUTES_SIZE: usize = 4;
+
+/// The ID enclave, which provides the QE ID and a report for the
+/// provisioning certification enclave (PCE)
+///
+/// The ID enclave is an Intel signed enclave, `libsgx_id_enclave.signed.so`,
+/// privileged to get the encrypted PPID from the PCE. With the `id-enclave`
+/// feature `IntelIdEnclave` implements this with its ECALLs,
+/// `ide_get_pce_encrypt_key()` and `ide_get_id()`.
+pub trait IdEnclave {
+    /// A report of the ID enclave targeting the PCE, along with the key the
+    /// PCE encrypts the PPID with
+    ///
+    /// The key is the RSA-3072 public key, the 384 byte modulus followed by
+    /// the 4 byte exponent, which the report data commits to.
+    ///
+    /// # Arguments
+    /// * `pce_target_info` - The target info of the PCE
+    ///
+    /// # Errors
+    /// [`mc_sgx_core_types::Error`] if the ECALL fails.
+    fn pce_report(
+        &self,
+        pce_target_info: &TargetInfo,
+    ) -> Result<(Report, Vec<u8>), mc_sgx_core_types::Error>;
+
+    /// The ID of the quoting enclave on this platform
+    ///
+    /// # Errors
+    /// [`mc_sgx_core_types::Error`] if the ECALL fails.
+    fn qe_id(&self) -> Result<[u8; QE_ID_SIZE], mc_sgx_core_types::Error>;
+}
+
+/// The identity of a platform, as needed to register it with the PCCS and
+/// retrieve its PCK certificates
+///
+/// This is the information Intel's `PCKIDRetrievalTool` collects.
+///
+/// ```no_run
+/// # use mc_sgx_core_types::{Report, TargetInfo};
+/// # use mc_sgx_dcap_pce::{IdEnclave, PlatformIdentity, QE_ID_SIZE};
+/// # struct MyIdEnclave;
+/// # impl IdEnclave for MyIdEnclave {
+/// #     fn pce_report(&self, _: &TargetInfo) -> Result<(Report, Vec<u8>), mc_sgx_core_types::Error> { todo!() }
+/// #     fn qe_id(&self) -> Result<[u8; QE_ID_SIZE], mc_sgx_core_types::Error> { todo!() }
+/// # }
+/// let identity = PlatformIdentity::retrieve(&MyIdEnclave)?;
+/// std::fs::write("pckid_retrieval.csv", identity.to_csv()).expect("Failed to write the CSV");
+/// # Ok::<(), mc_sgx_dcap_pce::Error>(())
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct PlatformIdentity {
+    encrypted_ppid: Vec<u8>,
+    pce_id: u16,
+    cpu_svn: CpuSvn,
+    pce_isv_svn: IsvSvn,
+    qe_id: [u8; QE_ID_SIZE],
+    platform_manifest: Option<Vec<u8>>,
+}
+
+impl PlatformIdentity {
+    /// Retrieve the identity of this platform
+    ///
+    /// The platform manifest is read from
+    /// [`PLATFORM_MANIFEST_EFI_VARIABLE`], only multi-package platforms which
+    /// haven't been registered yet have one.
+    ///
+    /// # Arguments
+    /// * `id_enclave` - The loaded ID enclave
+    ///
+    /// # Errors
+    /// * [`Error::Pce`] if the PCE can't be loaded or rejects the ID
+    ///   enclave's report.
+    /// * [`Error::Sgx`] if the ID enclave fails.
+    /// * [`Error::PlatformManifest`] if the platform manifest exists but
+    ///   can't be read.
+    pub fn retrieve<E: IdEnclave>(id_enclave: &E) -> Result<Self, Error> {
+        let (pce_target_info, _) = pce_target_info()?;
+        let (report, encryption_key) = id_enclave.pce_report(&pce_target_info)?;
+        let pce_info = pce_info(&report, &encryption_key)?;
+        let qe_id = id_enclave.qe_id()?;
+        let platform_manifest = read_platform_manifest(PLATFORM_MANIFEST_EFI_VARIABLE)?;
+
+        Ok(Self {
+            encrypted_ppid: pce_info.encrypted_ppid().to_vec(),
+            pce_id: pce_info.id(),
+            cpu_svn: report.body().cpu_svn(),
+            pce_isv_svn: pce_info.isv_svn(),
+            qe_id,
+            platform_manifest,
+        })
+    }
+
+    /// The platform provisioning ID (PPID), encrypted for Intel's
+    /// provisioning certification service (PCS)
+    pub fn encrypted_ppid(&self) -> &[u8] {
+        &self.encrypted_ppid
+    }
+
+    /// The ID of the provisioning certification enclave
+    pub fn pce_id(&self) -> u16 {
+        self.pce_id
+    }
+
+    /// The CPU SVN of the platform
+    pub fn cpu_svn(&self) -> &CpuSvn {
+        &self.cpu_svn
+    }
+
+    /// The ISV SVN of the provisioning certification enclave
+    pub fn pce_isv_svn(&self) -> IsvSvn {
+        self.pce_isv_svn
+    }
+
+    /// The ID of the quoting enclave
+    pub fn qe_id(&self) -> &[u8; QE_ID_SIZE] {
+        &self.qe_id
+    }
+
+    /// The platform manifest of a multi-package platform
+    pub fn platform_manifest(&self) -> Option<&[u8]> {
+        self.platform_manifest.as_deref()
+    }
+
+    /// The identity as a line of the CSV file `PCKIDRetrievalTool` writes
+    ///
+    /// The columns are the encrypted PPID, PCE ID, CPU SVN, PCE ISV SVN, QE ID
+    /// and, when present, the platform manifest. Each is hex encoded, with
+    /// the PCE ID and PCE ISV SVN as little endian bytes. The PCCS admin tool
+    /// collects these files for registration.
+    pub fn to_csv(&self) -> String {
+        let mut columns = vec![
+            hex::encode_upper(&self.encrypted_ppid),
+            hex::encode_upper(self.pce_id.to_le_bytes()),
+            hex::encode_upper(&self.cpu_svn),
+            hex::encode_upper(u16::from(self.pce_isv_svn).to_le_bytes()),
+            hex::encode_upper(self.qe_id),
+        ];
+        if let Some(platform_manifest) = &self.platform_manifest {
+            columns.push(hex::encode_upper(platform_manifest));
+        }
+        let mut line = columns.join(",");
+        line.push('\n');
+        line
+    }
+
+    /// The identity as the JSON body of the PCCS `platforms` request
+    ///
+    /// The values are encoded as in [`PlatformIdentity::to_csv()`], the
+    /// `platform_manifest` is left out when there isn't one.
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            r#"{{"enc_ppid":"{}","pce_id":"{}","cpu_svn":"{}","pce_svn":"{}","qe_id":"{}""#,
+            hex::encode_upper(&self.encrypted_ppid),
+            hex::encode_upper(self.pce_id.to_le_bytes()),
+            hex::encode_upper(&self.cpu_svn),
+            hex::encode_upper(u16::from(self.pce_isv_svn).to_le_bytes()),
+            hex::encode_upper(self.qe_id),
+        );
+        if let Some(platform_manifest) = &self.platform_manifest {
+            let _ = write!(
+                json,
+                r#","platform_manifest":"{}""#,
+                hex::encode_upper(platform_manifest)
+            );
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// Read the platform manifest from an EFI variable
+///
+/// Returns `None` when the variable doesn't exist, as on single-package
+/// platforms.
+///
+/// # Arguments
+/// * `path` - The path of the EFI variable, usually
+///   [`PLATFORM_MANIFEST_EFI_VARIABLE`].
+///
+/// # Errors
+/// [`Error::PlatformManifest`] if the variable exists but can't be read or is
+/// empty.
+pub fn read_platform_manifest<P: AsRef<Path>>(path: P) -> Result<Option<Vec<u8>>, Error> {
+    let path = path.as_ref();
+    let mut contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::PlatformManifest(format!("{}: {e}", path.display()))),
+    };
+    if contents.len() <= EFI_VARIABLE_ATTRIBUTES_SIZE {
+        return Err(Error::PlatformManifest(format!(
+            "{} is empty",
+            path.display()
+        )));
+    }
+    Ok(Some(contents.split_off(EFI_VARIABLE_ATTRIBUTES_SIZE)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mc_sgx_core_sys_types::sgx_cpu_svn_t;
+
+    fn identity(platform_manifest: Option<Vec<u8>>) -> PlatformIdentity {
+        PlatformIdentity {
+            encrypted_ppid: vec![0xAB; 4],
+            pce_id: 1,
+            cpu_svn: sgx_cpu_svn_t { svn: [2; 16] }.into(),
+            pce_isv_svn: 14.into(),
+            qe_id: [0x0C; QE_ID_SIZE],
+            platform_manifest,
+        }
+    }
+
+    #[test]
+    fn csv_without_platform_manifest() {
+        assert_eq!(
+            identity(None).to_csv(),
+            "ABABABAB,0100,02020202020202020202020202020202,0E00,0C0C0C0C0C0C0C0C0C0C0C0C0C0C0C0C\n"
+        );
+    }
+
+    #[test]
+    fn csv_with_platform_manifest() {
+        let csv = identity(Some(vec![1, 2, 0xFF])).to_csv();
+        assert!(csv.ends_with(",0C0C0C0C0C0C0C0C0C0C0C0C0C0C0C0C,0102FF\n"));
+    }
+
+    #[test]
+    fn json_without_platform_manifest() {
+        assert_eq!(
+            identity(None).to_json(),
+            r#"{"enc_ppid":"ABABABAB","pce_id":"0100","cpu_svn":"02020202020202020202020202020202","pce_svn":"0E00","qe_id":"0C0C0C0C0C0C0C0C0C0C0C0C0C0C0C0C"}"#
+        );
+    }
+
+    #[test]
+    fn json_with_platform_manifest() {
+        let json = identity(Some(vec![1, 2, 0xFF])).to_json();
+        assert!(json.ends_with(r#","platform_manifest":"0102FF"}"#));
+    }
+
+    #[test]
+    fn missing_platform_manifest_is_none() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(read_platform_manifest(dir.path().join("missing")), Ok(None));
+    }
+
+    #[test]
+    fn platform_manifest_skips_the_attributes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("manifest");
+        fs::write(&path, [7, 0, 0, 0, 1, 2, 3]).unwrap();
+        assert_eq!(read_platform_manifest(&path), Ok(Some(vec![1, 2, 3])));
+    }
+
+    #[test]
+    fn empty_platform_manifest_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("manifest");
+        fs::write(&path, [7, 0, 0, 0]).unwrap();
+        assert!(matches!(
+            read_platform_manifest(&path),
+            Err(Error::PlatformManifest(_))
+        ));
+    }
+}
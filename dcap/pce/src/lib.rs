@@ -0,0 +1,154 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+#![doc = include_str!("../README.md")]
+#![deny(missing_docs, missing_debug_implementations)]
+
+#[cfg(feature = "id-enclave")]
+mod id_enclave;
+mod identity;
+
+#[cfg(feature = "id-enclave")]
+pub use id_enclave::{IntelIdEnclave, DEFAULT_ID_ENCLAVE_PATH};
+pub use identity::{
+    read_platform_manifest, IdEnclave, PlatformIdentity, PLATFORM_MANIFEST_EFI_VARIABLE, QE_ID_SIZE,
+};
+
+use mc_sgx_core_sys_types::sgx_target_info_t;
+use mc_sgx_core_types::{IsvSvn, Report, TargetInfo};
+use mc_sgx_dcap_types::PceError;
+use mc_sgx_util::ResultInto;
+
+/// The size of a PPID encrypted with RSA-3072
+pub const ENCRYPTED_PPID_SIZE: usize = 384;
+
+/// `PCE_ALG_RSA_OAEP_3072`, the PPID encryption the PCE uses for
+/// registration
+const PCE_ALG_RSA_OAEP_3072: u8 = 1;
+
+/// Errors retrieving the identity of the platform
+#[derive(Clone, Debug, displaydoc::Display, Eq, Hash, PartialEq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum Error {
+    /// Error from the provisioning certification enclave: {0}
+    Pce(PceError),
+    /// Error from the ID enclave: {0}
+    Sgx(mc_sgx_core_types::Error),
+    /// Failed to read the platform manifest: {0}
+    PlatformManifest(String),
+    /// Failed to load the ID enclave: {0}
+    IdEnclave(String),
+}
+
+impl From<PceError> for Error {
+    fn from(src: PceError) -> Self {
+        Self::Pce(src)
+    }
+}
+
+impl From<mc_sgx_core_types::Error> for Error {
+    fn from(src: mc_sgx_core_types::Error) -> Self {
+        Self::Sgx(src)
+    }
+}
+
+/// The PPID and identity of the provisioning certification enclave (PCE)
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct PceInfo {
+    encrypted_ppid: Vec<u8>,
+    isv_svn: IsvSvn,
+    id: u16,
+}
+
+impl PceInfo {
+    /// The platform provisioning ID (PPID), encrypted with RSA-3072 OAEP for
+    /// the key given to [`pce_info()`]
+    pub fn encrypted_ppid(&self) -> &[u8] {
+        &self.encrypted_ppid
+    }
+
+    /// The ISV SVN of the PCE
+    pub fn isv_svn(&self) -> IsvSvn {
+        self.isv_svn
+    }
+
+    /// The ID of the PCE
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+}
+
+/// The target info and ISV SVN of the provisioning certification enclave
+/// (PCE)
+///
+/// Loads the PCE if it isn't already.
+///
+/// # Errors
+/// [`Error::Pce`] if the PCE can't be loaded.
+pub fn pce_target_info() -> Result<(TargetInfo, IsvSvn), Error> {
+    let mut target_info = sgx_target_info_t::default();
+    let mut isv_svn = 0;
+    unsafe { mc_sgx_dcap_pce_sys::sgx_pce_get_target(&mut target_info, &mut isv_svn) }
+        .into_result()?;
+    Ok((target_info.into(), isv_svn.into()))
+}
+
+/// Get the encrypted PPID and the identity of the PCE
+///
+/// # Arguments
+/// * `report` - A report targeting the PCE, see [`pce_target_info()`], from
+///   an enclave privileged to get the PPID. The report data must commit to
+///   `encryption_key`.
+/// * `encryption_key` - The RSA-3072 public key to encrypt the PPID with,
+///   the 384 byte modulus followed by the 4 byte exponent.
+///
+/// # Errors
+/// [`Error::Pce`] if the report is invalid, the enclave isn't privileged or
+/// the PCE fails to encrypt the PPID.
+pub fn pce_info(report: &Report, encryption_key: &[u8]) -> Result<PceInfo, Error> {
+    let mut encrypted_ppid = vec![0; ENCRYPTED_PPID_SIZE];
+    let mut encrypted_ppid_size = 0;
+    let mut isv_svn = 0;
+    let mut id = 0;
+    let mut signature_scheme = 0;
+    unsafe {
+        mc_sgx_dcap_pce_sys::sgx_get_pce_info(
+            report.as_ref(),
+            encryption_key.as_ptr(),
+            encryption_key.len() as u32,
+            PCE_ALG_RSA_OAEP_3072,
+            encrypted_ppid.as_mut_ptr(),
+            encrypted_ppid.len() as u32,
+            &mut encrypted_ppid_size,
+            &mut isv_svn,
+            &mut id,
+            &mut signature_scheme,
+        )
+    }
+    .into_result()?;
+    encrypted_ppid.truncate(encrypted_ppid_size as usize);
+
+    Ok(PceInfo {
+        encrypted_ppid,
+        isv_svn: isv_svn.into(),
+        id,
+    })
+}
+
+#[cfg(all(test, not(feature = "sim")))]
+mod hw_test {
+    use super::*;
+
+    #[test]
+    fn pce_target_info_is_not_empty() {
+        let (target_info, _) = pce_target_info().unwrap();
+        assert_ne!(target_info, TargetInfo::default());
+    }
+
+    #[test]
+    fn pce_info_for_an_untargeted_report_fails() {
+        assert_eq!(
+            pce_info(&Report::default(), &[0; 388]),
+            Err(Error::Pce(PceError::InvalidReport))
+        );
+    }
+}
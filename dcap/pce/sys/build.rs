@@ -0,0 +1,19 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+//! Builds the FFI function bindings for the PCE library of the Intel SGX DCAP
+
+const PCE_FUNCTIONS: &[&str] = &["sgx_get_pce_info", "sgx_pce_get_target"];
+
+fn main() {
+    cargo_emit::rustc_link_lib!("dylib=sgx_pce_logic");
+    mc_sgx_core_build::emit_dcap_cfgs();
+
+    let mut builder = mc_sgx_core_build::sgx_builder()
+        .header("wrapper.h")
+        .blocklist_type("*");
+
+    for f in PCE_FUNCTIONS {
+        builder = builder.allowlist_function(f);
+    }
+
+    mc_sgx_core_build::write_bindings(builder);
+}
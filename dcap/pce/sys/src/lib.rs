@@ -0,0 +1,9 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+#![doc = include_str!("../README.md")]
+#![allow(non_upper_case_globals, non_camel_case_types, non_snake_case)]
+
+use mc_sgx_core_sys_types::{sgx_isv_svn_t, sgx_report_t, sgx_target_info_t};
+use mc_sgx_dcap_sys_types::sgx_pce_error_t;
+
+include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
@@ -0,0 +1 @@
+#include <sgx_pce.h>
@@ -0,0 +1,489 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! An explicit configuration of the quoting library, owned by the caller
+//! instead of being set once for the process.
+
+use crate::{
+    quote_enclave::{c_path, validate_path},
+    Error,
+};
+use mc_sgx_core_sys_types::sgx_target_info_t;
+use mc_sgx_core_types::{Report, TargetInfo};
+use mc_sgx_dcap_ql_types::PathKind;
+use mc_sgx_dcap_types::{Quote3, RequestPolicy};
+use mc_sgx_util::ResultInto;
+use once_cell::sync::Lazy;
+use std::{
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+/// The default locations of the quoting enclaves on an Ubuntu DCAP install
+const DEFAULT_QUOTING_ENCLAVE: &str = "/usr/lib/x86_64-linux-gnu/libsgx_qe3.signed.so.1";
+const DEFAULT_PROVISIONING_CERTIFICATE_ENCLAVE: &str =
+    "/usr/lib/x86_64-linux-gnu/libsgx_pce.signed.so.1";
+const DEFAULT_ID_ENCLAVE: &str = "/usr/lib/x86_64-linux-gnu/libsgx_id_enclave.signed.so.1";
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct Settings {
+    quoting_enclave: Option<PathBuf>,
+    provisioning_certificate_enclave: Option<PathBuf>,
+    id_enclave: Option<PathBuf>,
+    quote_provider_library: Option<PathBuf>,
+    load_policy: RequestPolicy,
+}
+
+impl Settings {
+    fn paths(&self) -> [(PathKind, &Option<PathBuf>); 4] {
+        [
+            (PathKind::QuotingEnclave, &self.quoting_enclave),
+            (
+                PathKind::ProvisioningCertificateEnclave,
+                &self.provisioning_certificate_enclave,
+            ),
+            (PathKind::IdEnclave, &self.id_enclave),
+            (PathKind::QuoteProviderLibrary, &self.quote_provider_library),
+        ]
+    }
+
+    /// The first path these settings leave unset which `applied` set
+    ///
+    /// The quoting library can't unset a path, so these settings would
+    /// silently use the path of `applied`.
+    fn inherited_path(&self, applied: &Settings) -> Option<PathKind> {
+        self.paths()
+            .into_iter()
+            .zip(applied.paths())
+            .find(|((_, path), (_, applied_path))| path.is_none() && applied_path.is_some())
+            .map(|((kind, _), _)| kind)
+    }
+}
+
+/// The settings last applied to the quoting library
+///
+/// The library keeps its paths and load policy in process global state, so
+/// each context re-applies its settings when another one was used since.
+static APPLIED: Lazy<RwLock<Option<Settings>>> = Lazy::new(|| RwLock::new(None));
+
+/// Builds a [`QuotingContext`]
+///
+/// Paths which aren't set are left to the quoting library to search for. The
+/// library can't forget a path once it's set, so a context leaving a path
+/// unset fails with [`Error::PathInherited`] after another context set it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct QuotingContextBuilder {
+    settings: Settings,
+}
+
+impl QuotingContextBuilder {
+    /// The QE(Quoting Enclave) to use
+    ///
+    /// # Arguments
+    /// * `path` - The full file path to the quoting enclave
+    #[must_use]
+    pub fn quoting_enclave<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.settings.quoting_enclave = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// The PCE(Provisioning Certificate Enclave) to use
+    ///
+    /// # Arguments
+    /// * `path` - The full file path to the provisioning certificate enclave
+    #[must_use]
+    pub fn provisioning_certificate_enclave<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.settings.provisioning_certificate_enclave = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// The IDE(ID Enclave) to use
+    ///
+    /// # Arguments
+    /// * `path` - The full file path to the ID enclave
+    #[must_use]
+    pub fn id_enclave<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.settings.id_enclave = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// The QPL(Quote Provider Library) to use
+    ///
+    /// Without one quote generation is limited to local quote generation.
+    ///
+    /// # Arguments
+    /// * `path` - The full file path to the quote provider library
+    #[must_use]
+    pub fn quote_provider_library<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.settings.quote_provider_library = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// The load policy of the quoting enclaves
+    ///
+    /// Defaults to [`RequestPolicy::Persistent`].
+    ///
+    /// # Arguments
+    /// * `policy` - The policy to use for loading the quoting enclaves
+    #[must_use]
+    pub fn load_policy(mut self, policy: RequestPolicy) -> Self {
+        self.settings.load_policy = policy;
+        self
+    }
+
+    /// Build the context, validating the paths
+    ///
+    /// # Errors
+    /// * [`Error::PathStringConversion`] if one of the paths contains a null
+    ///   (0) byte.
+    /// * [`Error::PathDoesNotExist`] if one of the paths does not point to a
+    ///   file.
+    /// * [`Error::PathLengthTooLong`] if one of the paths is longer than 259
+    ///   (bytes).
+    pub fn build(self) -> Result<QuotingContext, Error> {
+        let settings = self.settings;
+        for (_, path) in settings.paths() {
+            if let Some(path) = path {
+                validate_path(path)?;
+            }
+        }
+        Ok(QuotingContext { settings })
+    }
+}
+
+/// The paths and load policy the quoting library uses
+///
+/// Quotes and QE target info are retrieved through a context, which applies
+/// its settings to the quoting library first. Contexts with different
+/// settings take turns in the same process: switching to another context
+/// unloads the quoting enclaves the previous one kept loaded with
+/// [`RequestPolicy::Persistent`], so they're loaded again from the new paths.
+///
+/// ```no_run
+/// # use mc_sgx_core_types::Report;
+/// # use mc_sgx_dcap_ql::QuotingContext;
+/// # use mc_sgx_dcap_types::RequestPolicy;
+/// let context = QuotingContext::builder()
+///     .quoting_enclave("/opt/dcap/libsgx_qe3.signed.so.1")
+///     .provisioning_certificate_enclave("/opt/dcap/libsgx_pce.signed.so.1")
+///     .id_enclave("/opt/dcap/libsgx_id_enclave.signed.so.1")
+///     .load_policy(RequestPolicy::Ephemeral)
+///     .build()?;
+/// let target_info = context.target_info()?;
+/// # let report = Report::default();
+/// let quote = context.quote(&report)?;
+/// # Ok::<(), mc_sgx_dcap_ql::Error>(())
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QuotingContext {
+    settings: Settings,
+}
+
+impl QuotingContext {
+    /// Start building a context
+    pub fn builder() -> QuotingContextBuilder {
+        QuotingContextBuilder::default()
+    }
+
+    /// A context with the default paths for the system
+    ///
+    /// Currently the defaults assume the default DCAP install on an Ubuntu
+    /// machine.
+    ///
+    /// # Errors
+    /// [`Error::PathDoesNotExist`] if any of the default paths don't exist on
+    /// the system.
+    pub fn try_default() -> Result<Self, Error> {
+        Self::default_builder().build()
+    }
+
+    pub(crate) fn default_builder() -> QuotingContextBuilder {
+        Self::builder()
+            .quoting_enclave(DEFAULT_QUOTING_ENCLAVE)
+            .provisioning_certificate_enclave(DEFAULT_PROVISIONING_CERTIFICATE_ENCLAVE)
+            .id_enclave(DEFAULT_ID_ENCLAVE)
+    }
+
+    /// The path to the QE(Quoting Enclave), if set
+    pub fn quoting_enclave(&self) -> Option<&Path> {
+        self.settings.quoting_enclave.as_deref()
+    }
+
+    /// The path to the PCE(Provisioning Certificate Enclave), if set
+    pub fn provisioning_certificate_enclave(&self) -> Option<&Path> {
+        self.settings.provisioning_certificate_enclave.as_deref()
+    }
+
+    /// The path to the IDE(ID Enclave), if set
+    pub fn id_enclave(&self) -> Option<&Path> {
+        self.settings.id_enclave.as_deref()
+    }
+
+    /// The path to the QPL(Quote Provider Library), if set
+    pub fn quote_provider_library(&self) -> Option<&Path> {
+        self.settings.quote_provider_library.as_deref()
+    }
+
+    /// This context with its load policy replaced by `policy`
+    pub(crate) fn with_load_policy(mut self, policy: RequestPolicy) -> Self {
+        self.settings.load_policy = policy;
+        self
+    }
+
+    /// The load policy of the quoting enclaves
+    pub fn load_policy(&self) -> RequestPolicy {
+        self.settings.load_policy
+    }
+
+    /// The target info of the QE(Quoting Enclave)
+    ///
+    /// # Errors
+    /// [`Error::QuoteLibrary`] if applying the settings or getting the target
+    /// info fails.
+    pub fn target_info(&self) -> Result<TargetInfo, Error> {
+        self.call(|| {
+            let mut info = sgx_target_info_t::default();
+            dcap_ql!(sgx_qe_get_target_info(&mut info))?.into_result()?;
+            Ok(info.into())
+        })
+    }
+
+    /// Create a [`Quote3`] from the provided [`Report`]
+    ///
+    /// # Arguments
+    /// * `report` - The report to build the quote from, targeting the QE
+    ///   from [`QuotingContext::target_info()`]
+    ///
+    /// # Errors
+    /// [`Error::QuoteLibrary`] if applying the settings or getting the quote
+    /// fails.
+    pub fn quote(&self, report: &Report) -> Result<Quote3<Vec<u8>>, Error> {
+        self.call(|| {
+            let mut size = 0;
+            dcap_ql!(sgx_qe_get_quote_size(&mut size))?.into_result()?;
+
+            let mut quote = vec![0; size as usize];
+            dcap_ql!(sgx_qe_get_quote(
+                &report.clone().into(),
+                quote.len() as u32,
+                quote.as_mut_ptr()
+            ))?
+            .into_result()?;
+            Ok(quote.try_into()?)
+        })
+    }
+
+    /// Unload the quoting enclaves loaded with the load policy
+    ///
+    /// With [`RequestPolicy::Persistent`] the enclaves otherwise stay loaded
+    /// until the process exits. They are loaded again by the next call which
+    /// needs them.
+    ///
+    /// # Errors
+    /// [`Error::QuoteLibrary`] if applying the settings or unloading the
+    /// enclaves fails.
+    pub fn cleanup(&self) -> Result<(), Error> {
+        self.call(|| {
+            dcap_ql!(sgx_qe_cleanup_by_policy())?.into_result()?;
+            Ok(())
+        })
+    }
+
+    /// Call `function` with the settings of this context applied
+    ///
+    /// Other contexts may be used concurrently, but only with the same
+    /// settings.
+    ///
+    /// # Errors
+    /// [`Error::PathInherited`] if this context leaves a path unset which the
+    /// previously applied settings set.
+    fn call<T>(&self, function: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        loop {
+            {
+                let applied = APPLIED.read().expect("RwLock has been poisoned");
+                if applied.as_ref() == Some(&self.settings) {
+                    return function();
+                }
+            }
+            let mut applied = APPLIED.write().expect("RwLock has been poisoned");
+            if applied.as_ref() != Some(&self.settings) {
+                if let Some(previous) = applied.as_ref() {
+                    if let Some(kind) = self.settings.inherited_path(previous) {
+                        return Err(Error::PathInherited(format!("{kind:?}")));
+                    }
+                    // The loaded enclaves come from the previous paths, and
+                    // may outlive the previous policy
+                    dcap_ql!(sgx_qe_cleanup_by_policy())?.into_result()?;
+                }
+                // Clear first, a partially applied context matches none
+                *applied = None;
+                self.apply()?;
+                *applied = Some(self.settings.clone());
+            }
+        }
+    }
+
+    fn apply(&self) -> Result<(), Error> {
+        let settings = &self.settings;
+        for (kind, path) in settings.paths() {
+            if let Some(path) = path {
+                let c_path = c_path(path)?;
+                dcap_ql!(sgx_ql_set_path(kind.into(), c_path.as_ptr()))?.into_result()?;
+            }
+        }
+        dcap_ql!(sgx_qe_set_enclave_load_policy(settings.load_policy.into()))?.into_result()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+    use yare::parameterized;
+
+    type Setter = fn(QuotingContextBuilder, PathBuf) -> QuotingContextBuilder;
+
+    #[parameterized(
+    qe = { QuotingContextBuilder::quoting_enclave::<PathBuf> },
+    pce = { QuotingContextBuilder::provisioning_certificate_enclave::<PathBuf> },
+    ide = { QuotingContextBuilder::id_enclave::<PathBuf> },
+    qpl = { QuotingContextBuilder::quote_provider_library::<PathBuf> },
+    )]
+    fn missing_path_fails_to_build(set: Setter) {
+        let dir = tempdir().unwrap();
+        let file_name = dir.path().join("missing.so");
+        assert_eq!(
+            set(QuotingContext::builder(), file_name.clone()).build(),
+            Err(Error::PathDoesNotExist(
+                file_name.to_string_lossy().into_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn context_reports_its_settings() {
+        let dir = tempdir().unwrap();
+        let names = ["qe", "pce", "ide", "qpl"].map(|name| {
+            let file_name = dir.path().join(name);
+            fs::write(&file_name, name).unwrap();
+            file_name
+        });
+
+        let context = QuotingContext::builder()
+            .quoting_enclave(&names[0])
+            .provisioning_certificate_enclave(&names[1])
+            .id_enclave(&names[2])
+            .quote_provider_library(&names[3])
+            .load_policy(RequestPolicy::Ephemeral)
+            .build()
+            .unwrap();
+
+        assert_eq!(context.quoting_enclave(), Some(names[0].as_path()));
+        assert_eq!(
+            context.provisioning_certificate_enclave(),
+            Some(names[1].as_path())
+        );
+        assert_eq!(context.id_enclave(), Some(names[2].as_path()));
+        assert_eq!(context.quote_provider_library(), Some(names[3].as_path()));
+        assert_eq!(context.load_policy(), RequestPolicy::Ephemeral);
+    }
+
+    #[test]
+    fn empty_context_leaves_paths_to_the_library() {
+        let context = QuotingContext::builder().build().unwrap();
+        assert_eq!(context.quoting_enclave(), None);
+        assert_eq!(context.quote_provider_library(), None);
+        assert_eq!(context.load_policy(), RequestPolicy::Persistent);
+    }
+
+    #[test]
+    fn unset_path_after_set_path_is_inherited() {
+        let set = Settings {
+            quote_provider_library: Some("/opt/dcap/libdcap_quoteprov.so.1".into()),
+            ..Default::default()
+        };
+        let unset = Settings::default();
+        assert_eq!(
+            unset.inherited_path(&set),
+            Some(PathKind::QuoteProviderLibrary)
+        );
+    }
+
+    #[test]
+    fn changed_path_is_not_inherited() {
+        let first = Settings {
+            quoting_enclave: Some("/opt/dcap/first/libsgx_qe3.signed.so.1".into()),
+            ..Default::default()
+        };
+        let second = Settings {
+            quoting_enclave: Some("/opt/dcap/second/libsgx_qe3.signed.so.1".into()),
+            load_policy: RequestPolicy::Ephemeral,
+            ..Default::default()
+        };
+        assert_eq!(second.inherited_path(&first), None);
+        assert_eq!(first.inherited_path(&second), None);
+        assert_eq!(first.inherited_path(&Settings::default()), None);
+    }
+}
+
+#[cfg(all(test, not(feature = "sim")))]
+mod hw_test {
+    use super::*;
+
+    #[test]
+    fn contexts_with_different_policies_take_turns() {
+        let persistent = QuotingContext::try_default().unwrap();
+        let ephemeral = QuotingContext::default_builder()
+            .load_policy(RequestPolicy::Ephemeral)
+            .build()
+            .unwrap();
+
+        for _ in 0..2 {
+            assert!(persistent.target_info().is_ok());
+            assert!(ephemeral.target_info().is_ok());
+        }
+    }
+
+    #[test]
+    fn switching_contexts_reloads_the_quoting_enclave() {
+        let default = QuotingContext::try_default().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let copies = [
+            DEFAULT_QUOTING_ENCLAVE,
+            DEFAULT_PROVISIONING_CERTIFICATE_ENCLAVE,
+            DEFAULT_ID_ENCLAVE,
+        ]
+        .map(|path| {
+            let copy = dir.path().join(Path::new(path).file_name().unwrap());
+            std::fs::copy(path, &copy).unwrap();
+            copy
+        });
+        let copied = QuotingContext::builder()
+            .quoting_enclave(&copies[0])
+            .provisioning_certificate_enclave(&copies[1])
+            .id_enclave(&copies[2])
+            .build()
+            .unwrap();
+
+        let target_info = default.target_info().unwrap();
+        assert_eq!(copied.target_info().unwrap(), target_info);
+
+        // The copies are loaded, the default context can't get a QE from them
+        drop(dir);
+        assert_eq!(default.target_info().unwrap(), target_info);
+        assert!(copied.target_info().is_err());
+    }
+
+    #[test]
+    fn unset_path_after_another_context_fails() {
+        let default = QuotingContext::try_default().unwrap();
+        let empty = QuotingContext::builder().build().unwrap();
+
+        assert!(default.target_info().is_ok());
+        assert_eq!(
+            empty.target_info(),
+            Err(Error::PathInherited("QuotingEnclave".into()))
+        );
+    }
+}
@@ -0,0 +1,659 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Discovery of the quoting enclaves and quote provider library installed on
+//! the system.
+
+use crate::{Error, QuotingContext, QuotingContextBuilder};
+use mc_sgx_dcap_ql_types::PathKind;
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// The directories distributions install the DCAP libraries to
+const STANDARD_SEARCH_DIRS: &[&str] = &["/usr/lib/x86_64-linux-gnu", "/usr/lib64"];
+
+/// The SGX SDK directory when `SGX_SDK` isn't set
+const STANDARD_SGX_SDK_DIR: &str = "/opt/intel/sgxsdk";
+
+// The section `sgx_sign` adds to an enclave, holding its SIGSTRUCT
+const SGX_METADATA_SECTION: &[u8] = b".note.sgxmeta";
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_DYN: u16 = 3;
+
+/// A file the quoting library can be pointed at
+struct Component {
+    kind: PathKind,
+    description: &'static str,
+    env_var: &'static str,
+    file_names: &'static [&'static str],
+    enclave: bool,
+}
+
+/// The files to discover, the quote provider library is optional
+const COMPONENTS: &[Component] = &[
+    Component {
+        kind: PathKind::QuotingEnclave,
+        description: "quoting enclave",
+        env_var: "SGX_QL_QE3_PATH",
+        file_names: &["libsgx_qe3.signed.so.1", "libsgx_qe3.signed.so"],
+        enclave: true,
+    },
+    Component {
+        kind: PathKind::ProvisioningCertificateEnclave,
+        description: "provisioning certificate enclave",
+        env_var: "SGX_QL_PCE_PATH",
+        file_names: &["libsgx_pce.signed.so.1", "libsgx_pce.signed.so"],
+        enclave: true,
+    },
+    Component {
+        kind: PathKind::IdEnclave,
+        description: "ID enclave",
+        env_var: "SGX_QL_IDE_PATH",
+        file_names: &[
+            "libsgx_id_enclave.signed.so.1",
+            "libsgx_id_enclave.signed.so",
+        ],
+        enclave: true,
+    },
+    Component {
+        kind: PathKind::QuoteProviderLibrary,
+        description: "quote provider library",
+        env_var: "SGX_QL_QPL_PATH",
+        file_names: &["libdcap_quoteprov.so.1", "libdcap_quoteprov.so"],
+        enclave: false,
+    },
+];
+
+/// Where a discovered path came from
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PathSource {
+    /// The environment variable, like `SGX_QL_QE3_PATH`
+    Environment(&'static str),
+    /// Set with [`PathDiscovery::path()`]
+    Explicit,
+    /// Found in the search directory
+    SearchDir(PathBuf),
+}
+
+impl fmt::Display for PathSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Environment(var) => write!(f, "from `{var}`"),
+            Self::Explicit => write!(f, "set explicitly"),
+            Self::SearchDir(dir) => write!(f, "found in {}", dir.display()),
+        }
+    }
+}
+
+/// A path chosen by [`PathDiscovery`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DiscoveredPath {
+    kind: PathKind,
+    path: PathBuf,
+    source: PathSource,
+}
+
+impl DiscoveredPath {
+    /// Which of the quoting library paths this is
+    pub fn kind(&self) -> PathKind {
+        self.kind
+    }
+
+    /// The full file path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where the path came from
+    pub fn source(&self) -> &PathSource {
+        &self.source
+    }
+}
+
+/// The paths chosen by [`PathDiscovery::discover()`]
+///
+/// The [`fmt::Display`] implementation lists each path and where it came from,
+/// for logging at start up.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DiscoveredPaths {
+    paths: Vec<DiscoveredPath>,
+}
+
+impl DiscoveredPaths {
+    /// The path of `kind`, if one was discovered
+    ///
+    /// The enclaves are always discovered, the quote provider library is
+    /// left to the quoting library when it isn't found.
+    pub fn get(&self, kind: PathKind) -> Option<&DiscoveredPath> {
+        self.paths.iter().find(|path| path.kind == kind)
+    }
+
+    /// Iterate over the discovered paths
+    pub fn iter(&self) -> impl Iterator<Item = &DiscoveredPath> {
+        self.paths.iter()
+    }
+
+    /// A [`QuotingContextBuilder`] using the discovered paths
+    pub fn builder(&self) -> QuotingContextBuilder {
+        self.paths
+            .iter()
+            .fold(QuotingContext::builder(), |builder, discovered| {
+                let path = &discovered.path;
+                match discovered.kind {
+                    PathKind::QuotingEnclave => builder.quoting_enclave(path),
+                    PathKind::ProvisioningCertificateEnclave => {
+                        builder.provisioning_certificate_enclave(path)
+                    }
+                    PathKind::IdEnclave => builder.id_enclave(path),
+                    PathKind::QuoteProviderLibrary => builder.quote_provider_library(path),
+                    _ => builder,
+                }
+            })
+    }
+}
+
+impl fmt::Display for DiscoveredPaths {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for component in COMPONENTS {
+            match self.get(component.kind) {
+                Some(discovered) => writeln!(
+                    f,
+                    "{}: {} ({})",
+                    component.description,
+                    discovered.path.display(),
+                    discovered.source
+                )?,
+                None => writeln!(f, "{}: quoting library default", component.description)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Finds the quoting enclaves and quote provider library
+///
+/// Each path is taken from, in order:
+/// 1. A path set with [`PathDiscovery::path()`].
+/// 2. Its environment variable, with [`PathDiscovery::system()`]:
+///     * `SGX_QL_QE3_PATH` for the quoting enclave
+///     * `SGX_QL_PCE_PATH` for the provisioning certificate enclave
+///     * `SGX_QL_IDE_PATH` for the ID enclave
+///     * `SGX_QL_QPL_PATH` for the quote provider library
+/// 3. The first search directory containing it.
+///
+/// Files are checked to be signed enclaves or shared libraries, files found in
+/// the search directories which aren't are skipped.
+///
+/// ```no_run
+/// # use mc_sgx_dcap_ql::PathDiscovery;
+/// let paths = PathDiscovery::system().discover()?;
+/// println!("{paths}");
+/// let context = paths.builder().build()?;
+/// # Ok::<(), mc_sgx_dcap_ql::Error>(())
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PathDiscovery {
+    search_dirs: Vec<PathBuf>,
+    paths: Vec<DiscoveredPath>,
+}
+
+impl PathDiscovery {
+    /// Discovery from the environment variables and the standard directories
+    ///
+    /// The search directories are `/usr/lib/x86_64-linux-gnu`, `/usr/lib64`
+    /// and the `lib64` directory of the SGX SDK, from the `SGX_SDK`
+    /// environment variable or `/opt/intel/sgxsdk`.
+    pub fn system() -> Self {
+        let sdk = env::var_os("SGX_SDK")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(STANDARD_SGX_SDK_DIR));
+        let mut discovery = STANDARD_SEARCH_DIRS
+            .iter()
+            .map(PathBuf::from)
+            .chain([sdk.join("lib64")])
+            .fold(Self::default(), Self::search_dir);
+        for component in COMPONENTS {
+            if let Some(path) = env::var_os(component.env_var) {
+                discovery.set(
+                    component.kind,
+                    path.into(),
+                    PathSource::Environment(component.env_var),
+                );
+            }
+        }
+        discovery
+    }
+
+    /// Add a directory to search, after those already added
+    ///
+    /// # Arguments
+    /// * `dir` - The directory to look for the files in
+    #[must_use]
+    pub fn search_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.search_dirs.push(dir.as_ref().to_owned());
+        self
+    }
+
+    /// Use `path` for `kind` instead of searching for it
+    ///
+    /// Takes precedence over the environment variable for `kind`.
+    ///
+    /// # Arguments
+    /// * `kind` - Which of the quoting library paths to set
+    /// * `path` - The full file path
+    #[must_use]
+    pub fn path<P: AsRef<Path>>(mut self, kind: PathKind, path: P) -> Self {
+        self.set(kind, path.as_ref().to_owned(), PathSource::Explicit);
+        self
+    }
+
+    fn set(&mut self, kind: PathKind, path: PathBuf, source: PathSource) {
+        self.paths.retain(|discovered| discovered.kind != kind);
+        self.paths.push(DiscoveredPath { kind, path, source });
+    }
+
+    /// Discover the paths
+    ///
+    /// # Errors
+    /// * [`Error::PathNotDiscovered`] if one of the enclaves isn't set and
+    ///   can't be found in the search directories.
+    /// * [`Error::InvalidFile`] if a path which was set isn't a signed
+    ///   enclave or shared library.
+    pub fn discover(&self) -> Result<DiscoveredPaths, Error> {
+        let mut paths = Vec::new();
+        for component in COMPONENTS {
+            if let Some(discovered) = self.paths.iter().find(|p| p.kind == component.kind) {
+                if !component.matches(&discovered.path) {
+                    return Err(Error::InvalidFile(
+                        discovered.path.to_string_lossy().into_owned(),
+                        component.expected().into(),
+                    ));
+                }
+                paths.push(discovered.clone());
+            } else if let Some(discovered) = self.search(component) {
+                paths.push(discovered);
+            } else if component.enclave {
+                return Err(Error::PathNotDiscovered(self.not_found(component)));
+            }
+        }
+        Ok(DiscoveredPaths { paths })
+    }
+
+    fn search(&self, component: &Component) -> Option<DiscoveredPath> {
+        self.search_dirs.iter().find_map(|dir| {
+            component
+                .file_names
+                .iter()
+                .map(|name| dir.join(name))
+                .find(|path| component.matches(path))
+                .map(|path| DiscoveredPath {
+                    kind: component.kind,
+                    path,
+                    source: PathSource::SearchDir(dir.clone()),
+                })
+        })
+    }
+
+    fn not_found(&self, component: &Component) -> String {
+        let dirs = self
+            .search_dirs
+            .iter()
+            .map(|dir| dir.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "the {} ({}) in [{}], set `{}` to its path",
+            component.description, component.file_names[0], dirs, component.env_var
+        )
+    }
+}
+
+impl Component {
+    fn expected(&self) -> &'static str {
+        if self.enclave {
+            "signed enclave"
+        } else {
+            "shared library"
+        }
+    }
+
+    /// Is `path` a 64 bit shared library, which is signed if an enclave
+    fn matches(&self, path: &Path) -> bool {
+        let contents = match fs::read(path) {
+            Ok(contents) => contents,
+            Err(_) => return false,
+        };
+        if !is_shared_library(&contents) {
+            return false;
+        }
+        !self.enclave || has_section(&contents, SGX_METADATA_SECTION).unwrap_or(false)
+    }
+}
+
+fn is_shared_library(elf: &[u8]) -> bool {
+    elf.starts_with(ELF_MAGIC)
+        && elf.get(4) == Some(&ELFCLASS64)
+        && elf.get(5) == Some(&ELFDATA2LSB)
+        && read_u16(elf, 0x10) == Some(ET_DYN)
+}
+
+/// Does the 64 bit little endian ELF `elf` have a section named `name`
+///
+/// Returns `None` when the section headers are malformed.
+fn has_section(elf: &[u8], name: &[u8]) -> Option<bool> {
+    let section_headers = usize::try_from(read_u64(elf, 0x28)?).ok()?;
+    let entry_size = usize::from(read_u16(elf, 0x3a)?);
+    let count = usize::from(read_u16(elf, 0x3c)?);
+    let names_index = usize::from(read_u16(elf, 0x3e)?);
+
+    let header = |index: usize| section_headers.checked_add(index.checked_mul(entry_size)?);
+    let names_header = header(names_index)?;
+    let names = usize::try_from(read_u64(elf, names_header + 0x18)?).ok()?;
+
+    for index in 0..count {
+        let name_offset = read_u32(elf, header(index)?)? as usize;
+        let section_name = elf.get(names.checked_add(name_offset)?..)?;
+        let end = section_name.iter().position(|&b| b == 0)?;
+        if &section_name[..end] == name {
+            return Some(true);
+        }
+    }
+    Some(false)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    let bytes = bytes.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let bytes = bytes.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::{tempdir, TempDir};
+    use yare::parameterized;
+
+    const HEADER_SIZE: usize = 64;
+    const SECTION_HEADER_SIZE: usize = 64;
+
+    /// A minimal 64 bit shared library with the named sections
+    fn elf(sections: &[&str]) -> Vec<u8> {
+        let mut names = vec![0];
+        let mut name_offsets = vec![];
+        for name in sections.iter().chain([&".shstrtab"]) {
+            name_offsets.push(names.len() as u32);
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+
+        let names_offset = HEADER_SIZE;
+        let section_headers = names_offset + names.len();
+        // The null section, the named sections and the section names
+        let count = sections.len() + 2;
+
+        let mut elf = vec![0; section_headers];
+        elf[..4].copy_from_slice(ELF_MAGIC);
+        elf[4] = ELFCLASS64;
+        elf[5] = ELFDATA2LSB;
+        elf[6] = 1;
+        elf[0x10..0x12].copy_from_slice(&ET_DYN.to_le_bytes());
+        elf[0x28..0x30].copy_from_slice(&(section_headers as u64).to_le_bytes());
+        elf[0x3a..0x3c].copy_from_slice(&(SECTION_HEADER_SIZE as u16).to_le_bytes());
+        elf[0x3c..0x3e].copy_from_slice(&(count as u16).to_le_bytes());
+        elf[0x3e..0x40].copy_from_slice(&((count - 1) as u16).to_le_bytes());
+        elf[names_offset..].copy_from_slice(&names);
+
+        elf.extend([0; SECTION_HEADER_SIZE]);
+        for (index, name_offset) in name_offsets.iter().enumerate() {
+            let mut header = [0; SECTION_HEADER_SIZE];
+            header[..4].copy_from_slice(&name_offset.to_le_bytes());
+            if index == count - 2 {
+                header[0x18..0x20].copy_from_slice(&(names_offset as u64).to_le_bytes());
+                header[0x20..0x28].copy_from_slice(&(names.len() as u64).to_le_bytes());
+            }
+            elf.extend(header);
+        }
+        elf
+    }
+
+    fn signed_enclave() -> Vec<u8> {
+        elf(&[".text", ".note.sgxmeta"])
+    }
+
+    fn shared_library() -> Vec<u8> {
+        elf(&[".text"])
+    }
+
+    /// A directory with the enclaves, and the quote provider library when
+    /// `qpl`
+    fn install_dir(qpl: bool) -> TempDir {
+        let dir = tempdir().unwrap();
+        for name in [
+            "libsgx_qe3.signed.so.1",
+            "libsgx_pce.signed.so.1",
+            "libsgx_id_enclave.signed.so.1",
+        ] {
+            fs::write(dir.path().join(name), signed_enclave()).unwrap();
+        }
+        if qpl {
+            fs::write(dir.path().join("libdcap_quoteprov.so.1"), shared_library()).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn finds_all_paths_in_search_dir() {
+        let dir = install_dir(true);
+        let paths = PathDiscovery::default()
+            .search_dir(dir.path())
+            .discover()
+            .unwrap();
+
+        for (kind, name) in [
+            (PathKind::QuotingEnclave, "libsgx_qe3.signed.so.1"),
+            (
+                PathKind::ProvisioningCertificateEnclave,
+                "libsgx_pce.signed.so.1",
+            ),
+            (PathKind::IdEnclave, "libsgx_id_enclave.signed.so.1"),
+            (PathKind::QuoteProviderLibrary, "libdcap_quoteprov.so.1"),
+        ] {
+            let discovered = paths.get(kind).unwrap();
+            assert_eq!(discovered.path(), dir.path().join(name));
+            assert_eq!(
+                discovered.source(),
+                &PathSource::SearchDir(dir.path().to_owned())
+            );
+        }
+    }
+
+    #[test]
+    fn missing_quote_provider_library_is_left_to_the_library() {
+        let dir = install_dir(false);
+        let paths = PathDiscovery::default()
+            .search_dir(dir.path())
+            .discover()
+            .unwrap();
+
+        assert_eq!(paths.get(PathKind::QuoteProviderLibrary), None);
+        assert_eq!(paths.iter().count(), 3);
+        assert!(paths
+            .to_string()
+            .contains("quote provider library: quoting library default"));
+    }
+
+    #[test]
+    fn first_search_dir_wins() {
+        let first = install_dir(false);
+        let second = install_dir(true);
+        let paths = PathDiscovery::default()
+            .search_dir(first.path())
+            .search_dir(second.path())
+            .discover()
+            .unwrap();
+
+        assert_eq!(
+            paths.get(PathKind::QuotingEnclave).unwrap().path(),
+            first.path().join("libsgx_qe3.signed.so.1")
+        );
+        assert_eq!(
+            paths.get(PathKind::QuoteProviderLibrary).unwrap().path(),
+            second.path().join("libdcap_quoteprov.so.1")
+        );
+    }
+
+    #[test]
+    fn unsigned_enclave_is_skipped() {
+        let first = install_dir(false);
+        let second = install_dir(false);
+        fs::write(
+            first.path().join("libsgx_pce.signed.so.1"),
+            shared_library(),
+        )
+        .unwrap();
+
+        let paths = PathDiscovery::default()
+            .search_dir(first.path())
+            .search_dir(second.path())
+            .discover()
+            .unwrap();
+
+        assert_eq!(
+            paths
+                .get(PathKind::ProvisioningCertificateEnclave)
+                .unwrap()
+                .path(),
+            second.path().join("libsgx_pce.signed.so.1")
+        );
+    }
+
+    #[test]
+    fn unversioned_file_name_is_found() {
+        let dir = install_dir(false);
+        let versioned = dir.path().join("libsgx_id_enclave.signed.so.1");
+        let unversioned = dir.path().join("libsgx_id_enclave.signed.so");
+        fs::rename(versioned, &unversioned).unwrap();
+
+        let paths = PathDiscovery::default()
+            .search_dir(dir.path())
+            .discover()
+            .unwrap();
+
+        assert_eq!(paths.get(PathKind::IdEnclave).unwrap().path(), unversioned);
+    }
+
+    #[test]
+    fn explicit_path_takes_precedence() {
+        let dir = install_dir(false);
+        let other = tempdir().unwrap();
+        let qe = other.path().join("qe3.so");
+        fs::write(&qe, signed_enclave()).unwrap();
+
+        let paths = PathDiscovery::default()
+            .search_dir(dir.path())
+            .path(PathKind::QuotingEnclave, &qe)
+            .discover()
+            .unwrap();
+
+        let discovered = paths.get(PathKind::QuotingEnclave).unwrap();
+        assert_eq!(discovered.path(), qe);
+        assert_eq!(discovered.source(), &PathSource::Explicit);
+    }
+
+    #[parameterized(
+    text_as_enclave = { PathKind::QuotingEnclave, b"not an enclave", "signed enclave" },
+    unsigned_enclave = { PathKind::IdEnclave, &shared_library(), "signed enclave" },
+    text_as_library = { PathKind::QuoteProviderLibrary, b"not a library", "shared library" },
+    )]
+    fn invalid_explicit_path_fails(kind: PathKind, contents: &[u8], expected: &str) {
+        let dir = install_dir(true);
+        let file = dir.path().join("invalid");
+        fs::write(&file, contents).unwrap();
+
+        let result = PathDiscovery::default()
+            .search_dir(dir.path())
+            .path(kind, &file)
+            .discover();
+
+        assert_eq!(
+            result,
+            Err(Error::InvalidFile(
+                file.to_string_lossy().into_owned(),
+                expected.into()
+            ))
+        );
+    }
+
+    #[test]
+    fn missing_enclave_names_the_override() {
+        let dir = install_dir(false);
+        fs::remove_file(dir.path().join("libsgx_qe3.signed.so.1")).unwrap();
+
+        let result = PathDiscovery::default().search_dir(dir.path()).discover();
+
+        let message = match result {
+            Err(Error::PathNotDiscovered(message)) => message,
+            other => panic!("Expected the quoting enclave to be missing: {other:?}"),
+        };
+        assert!(message.contains("libsgx_qe3.signed.so.1"));
+        assert!(message.contains(&*dir.path().to_string_lossy()));
+        assert!(message.contains("SGX_QL_QE3_PATH"));
+    }
+
+    #[test]
+    fn discovered_paths_build_a_context() {
+        let dir = install_dir(true);
+        let paths = PathDiscovery::default()
+            .search_dir(dir.path())
+            .discover()
+            .unwrap();
+
+        let context = paths.builder().build().unwrap();
+
+        assert_eq!(
+            context.quoting_enclave(),
+            Some(dir.path().join("libsgx_qe3.signed.so.1").as_path())
+        );
+        assert_eq!(
+            context.quote_provider_library(),
+            Some(dir.path().join("libdcap_quoteprov.so.1").as_path())
+        );
+    }
+
+    #[test]
+    fn report_lists_the_sources() {
+        let dir = install_dir(false);
+        let report = PathDiscovery::default()
+            .search_dir(dir.path())
+            .discover()
+            .unwrap()
+            .to_string();
+
+        let qe = dir.path().join("libsgx_qe3.signed.so.1");
+        assert!(report.contains(&format!(
+            "quoting enclave: {} (found in {})",
+            qe.display(),
+            dir.path().display()
+        )));
+    }
+
+    #[test]
+    fn truncated_section_headers_are_not_an_enclave() {
+        let mut enclave = signed_enclave();
+        enclave.truncate(enclave.len() - SECTION_HEADER_SIZE);
+        assert_eq!(has_section(&enclave, SGX_METADATA_SECTION), None);
+    }
+}
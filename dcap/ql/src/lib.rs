@@ -5,12 +5,39 @@
 
 extern crate alloc;
 
+/// Call a function of the `sgx_dcap_ql` library
+///
+/// With the `dlopen` feature the library is loaded on first use and this
+/// evaluates to [`Error::LibraryUnavailable`] when it can't be loaded.
+macro_rules! dcap_ql {
+    ($function:ident($($arg:expr),* $(,)?)) => {{
+        #[cfg(feature = "dlopen")]
+        let result = mc_sgx_dcap_ql_sys::dlopen::library()
+            .map(|library| unsafe { (library.$function)($($arg),*) })
+            .map_err(|e| $crate::Error::LibraryUnavailable(e.into()));
+        #[cfg(not(feature = "dlopen"))]
+        let result =
+            Ok::<_, $crate::Error>(unsafe { mc_sgx_dcap_ql_sys::$function($($arg),*) });
+        result
+    }};
+}
+
+mod context;
+mod discovery;
+#[cfg(feature = "qe-report")]
+mod qe_report;
 mod quote3;
 mod quote_enclave;
+mod service;
 
+pub use context::{QuotingContext, QuotingContextBuilder};
+pub use discovery::{DiscoveredPath, DiscoveredPaths, PathDiscovery, PathSource};
 use mc_sgx_dcap_types::{QlError, Quote3Error};
+#[cfg(feature = "qe-report")]
+pub use qe_report::QeReportQuoter;
 pub use quote3::TryFromReport;
 pub use quote_enclave::{LoadPolicyInitializer, PathInitializer, QeTargetInfo};
+pub use service::{ErrorClass, QuotingService, RetryPolicy};
 
 /// Errors interacting with quote library functions
 #[derive(Clone, Debug, displaydoc::Display, Eq, Hash, PartialEq, PartialOrd, Ord)]
@@ -28,8 +55,26 @@ pub enum Error {
     PathLengthTooLong(String),
     /// The quoting enclave load policy has already been initialized
     LoadPolicyInitialized,
+    /// The quote library could not be loaded: {0}
+    LibraryUnavailable(String),
     /// Error from Quote3 interface
     Quote3(Quote3Error),
+    /// Could not find {0}
+    PathNotDiscovered(String),
+    /// {0} is not a {1}
+    InvalidFile(String, String),
+    /// The QE target info changed since the report was created for it
+    StaleTargetInfo,
+    /// Error from SGX quoting function: {0}
+    Sgx(mc_sgx_core_types::Error),
+    /// The {0} path is unset, but another context set it
+    PathInherited(String),
+}
+
+impl From<mc_sgx_core_types::Error> for Error {
+    fn from(src: mc_sgx_core_types::Error) -> Self {
+        Self::Sgx(src)
+    }
 }
 
 impl From<Quote3Error> for Error {
@@ -43,3 +88,54 @@ impl From<QlError> for Error {
         Self::QuoteLibrary(src)
     }
 }
+
+impl Error {
+    /// How a [`QuotingService`] handles this error
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Self::QuoteLibrary(QlError::EnclaveLost | QlError::OutOfEpc) => ErrorClass::Reload,
+            Self::QuoteLibrary(
+                QlError::Busy
+                | QlError::ServiceUnavailable
+                | QlError::ServiceTimeout
+                | QlError::NetworkFailure
+                | QlError::Network,
+            ) => ErrorClass::Retryable,
+            _ => ErrorClass::Fatal,
+        }
+    }
+
+    /// Returns `true` if the call which failed may succeed when retried
+    pub fn is_retryable(&self) -> bool {
+        self.class() != ErrorClass::Fatal
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yare::parameterized;
+
+    #[parameterized(
+    enclave_lost = { QlError::EnclaveLost, ErrorClass::Reload },
+    out_of_epc = { QlError::OutOfEpc, ErrorClass::Reload },
+    busy = { QlError::Busy, ErrorClass::Retryable },
+    service_unavailable = { QlError::ServiceUnavailable, ErrorClass::Retryable },
+    service_timeout = { QlError::ServiceTimeout, ErrorClass::Retryable },
+    network_failure = { QlError::NetworkFailure, ErrorClass::Retryable },
+    network = { QlError::Network, ErrorClass::Retryable },
+    invalid_parameter = { QlError::InvalidParameter, ErrorClass::Fatal },
+    enclave_load = { QlError::EnclaveLoad, ErrorClass::Fatal },
+    no_device = { QlError::NoDevice, ErrorClass::Fatal },
+    )]
+    fn quote_library_error_class(error: QlError, class: ErrorClass) {
+        let error = Error::from(error);
+        assert_eq!(error.class(), class);
+        assert_eq!(error.is_retryable(), class != ErrorClass::Fatal);
+    }
+
+    #[test]
+    fn stale_target_info_is_fatal() {
+        assert_eq!(Error::StaleTargetInfo.class(), ErrorClass::Fatal);
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Quotes along with a report of the quoting enclave for the application
+//! enclave
+
+use crate::Error;
+use mc_sgx_core_types::{Algorithm, QuoteNonce, Report, TargetInfo};
+use mc_sgx_dcap_sys_types::sgx_ql_qe_report_info_t;
+use mc_sgx_dcap_types::{Quote3, ReportInfo};
+use mc_sgx_quote_ex::{select_attestation_key_by_algorithm, QuotingKey};
+
+/// Generates version 3 quotes along with a report of the quoting enclave
+/// (QE) which targets the application enclave
+///
+/// `sgx_qe_get_quote()` can't provide the QE report, so the quotes are
+/// generated through the `sgx_quote_ex` library with the platform's
+/// ECDSA-P256 attestation key. The application enclave verifies the QE report
+/// to make sure a genuine QE on the same platform produced the quote, see
+/// `mc_sgx_tservice::VerifyQeReport`.
+///
+/// ```no_run
+/// # use mc_sgx_core_types::{QuoteNonce, Report, TargetInfo};
+/// # use mc_sgx_dcap_ql::QeReportQuoter;
+/// let quoter = QeReportQuoter::new()?;
+/// // The application enclave creates a nonce and a report for
+/// // `quoter.target_info()`
+/// # let (report, app_enclave_target_info, nonce) =
+/// #     (Report::default(), TargetInfo::default(), QuoteNonce::default());
+/// let (quote, report_info) = quoter.quote(&report, &app_enclave_target_info, &nonce)?;
+/// // The quote and report info go back to the application enclave to verify
+/// # Ok::<(), mc_sgx_dcap_ql::Error>(())
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct QeReportQuoter {
+    key: QuotingKey,
+}
+
+impl QeReportQuoter {
+    /// Select and initialize the platform's ECDSA-P256 attestation key
+    ///
+    /// # Errors
+    /// [`Error::Sgx`] if the platform has no ECDSA-P256 attestation key or
+    /// initializing it fails.
+    pub fn new() -> Result<Self, Error> {
+        let id = select_attestation_key_by_algorithm(&[Algorithm::EcdsaP256])?;
+        Ok(Self {
+            key: QuotingKey::init(id)?,
+        })
+    }
+
+    /// The target info of the QE, reports to quote must target it
+    pub fn target_info(&self) -> &TargetInfo {
+        self.key.target_info()
+    }
+
+    /// Quote an application enclave's report along with a QE report for the
+    /// application enclave
+    ///
+    /// The report data of the QE report is `SHA256(nonce || quote)` followed
+    /// by 32 zero bytes, see [`Quote3::verify_nonce()`].
+    ///
+    /// # Arguments
+    /// * `report` - The report to quote, it must target
+    ///   [`QeReportQuoter::target_info()`].
+    /// * `app_enclave_target_info` - The target info of the application
+    ///   enclave, the QE report targets it.
+    /// * `nonce` - A nonce from the application enclave, see
+    ///   [`QuoteNonce::new()`].
+    ///
+    /// # Errors
+    /// * [`Error::Sgx`] if generating the quote fails.
+    /// * [`Error::Quote3`] if the generated quote isn't a valid version 3
+    ///   quote.
+    pub fn quote(
+        &self,
+        report: &Report,
+        app_enclave_target_info: &TargetInfo,
+        nonce: &QuoteNonce,
+    ) -> Result<(Quote3<Vec<u8>>, ReportInfo), Error> {
+        let (quote, report_info) =
+            self.key
+                .quote_with_qe_report(report, app_enclave_target_info, nonce)?;
+        let report_info = sgx_ql_qe_report_info_t {
+            nonce: report_info.nonce().into(),
+            app_enclave_target_info: report_info.app_enclave_target_info().into(),
+            qe_report: report_info.report().into(),
+        };
+        Ok((quote.try_into()?, report_info.into()))
+    }
+}
+
+#[cfg(all(test, not(feature = "sim")))]
+mod hw_test {
+    use super::*;
+
+    #[test]
+    fn quoting_an_untargeted_report_fails() {
+        let quoter = QeReportQuoter::new().unwrap();
+        assert_eq!(
+            quoter.quote(
+                &Report::default(),
+                &TargetInfo::default(),
+                &QuoteNonce::default()
+            ),
+            Err(Error::Sgx(mc_sgx_core_types::Error::MacMismatch))
+        );
+    }
+}
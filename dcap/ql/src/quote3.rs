@@ -5,10 +5,9 @@
 //! This functionality requires HW SGX to work correctly otherwise all
 //! functionality will return errors.
 
-use crate::{Error, LoadPolicyInitializer, PathInitializer};
+use crate::{quote_enclave::default_context, Error};
 use mc_sgx_core_types::Report;
 use mc_sgx_dcap_types::Quote3;
-use mc_sgx_util::ResultInto;
 
 /// Create a Quote3 from a Report
 pub trait TryFromReport {
@@ -20,28 +19,20 @@ pub trait TryFromReport {
     ///   [`LoadPolicyInitializer`] after calling this function will result in
     ///   an error.
     ///
+    /// Use [`QuotingContext::quote()`] to choose the paths and load policy
+    /// instead.
+    ///
     /// # Arguments
     /// * `report` - The report to build the quote from
     ///
     /// # Errors
     /// Will return an [`Error::QuoteLibrary`] if there is a failure from the SGX SDK
+    ///
+    /// [`PathInitializer`]: crate::PathInitializer
+    /// [`LoadPolicyInitializer`]: crate::LoadPolicyInitializer
+    /// [`QuotingContext::quote()`]: crate::QuotingContext::quote
     fn try_from_report(report: Report) -> Result<Quote3<Vec<u8>>, Error> {
-        PathInitializer::ensure_initialized()?;
-        LoadPolicyInitializer::ensure_initialized()?;
-
-        let mut size = 0;
-        unsafe { mc_sgx_dcap_ql_sys::sgx_qe_get_quote_size(&mut size) }.into_result()?;
-
-        let mut quote = vec![0; size as usize];
-        unsafe {
-            mc_sgx_dcap_ql_sys::sgx_qe_get_quote(
-                &report.into(),
-                quote.len() as u32,
-                quote.as_mut_ptr(),
-            )
-        }
-        .into_result()?;
-        Ok(quote.try_into()?)
+        default_context()?.quote(&report)
     }
 }
 
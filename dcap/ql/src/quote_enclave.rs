@@ -8,12 +8,9 @@
 //! has a mix up.  It uses the *verification* description for `sgx_ql_set_path`
 //! and the "generation" description for `sgx_qv_set_path`
 
-use crate::Error;
-use mc_sgx_core_sys_types::sgx_target_info_t;
+use crate::{DiscoveredPaths, Error, PathDiscovery, QuotingContext, QuotingContextBuilder};
 use mc_sgx_core_types::TargetInfo;
-use mc_sgx_dcap_ql_types::PathKind;
 use mc_sgx_dcap_types::RequestPolicy;
-use mc_sgx_util::ResultInto;
 use once_cell::sync::Lazy;
 use std::{ffi::CString, os::unix::ffi::OsStrExt, path::Path, sync::Mutex};
 
@@ -35,32 +32,56 @@ pub type Result<T> = core::result::Result<T, Error>;
 /// If a consumer of this crate does not explicitly initialize the paths, then
 /// they will be defaulted on the first call to an SGX function that needs the
 /// paths set.
+///
+/// These paths, and the [`LoadPolicyInitializer`] policy, make up the default
+/// [`QuotingContext`] used by [`QeTargetInfo`] and
+/// [`TryFromReport`](crate::TryFromReport). Build a [`QuotingContext`] to
+/// choose the paths per call instead.
 #[derive(Debug)]
 pub struct PathInitializer;
 
-static PATH_INITIALIZER: Lazy<Mutex<Option<PathInitializer>>> = Lazy::new(|| Mutex::new(None));
+static PATH_INITIALIZER: Lazy<Mutex<Option<QuotingContext>>> = Lazy::new(|| Mutex::new(None));
 
 impl PathInitializer {
     /// Try to initialize the paths to the default for the system
     ///
     /// Currently the defaults assume the default DCAP install on an Ubuntu
-    /// machine.
+    /// machine, see [`QuotingContext::try_default()`].
     ///
     /// # Errors
     /// * [`Error::PathsInitialized`] if the paths have been previously
     ///   initialized.
-    /// * [`Error::QuoteLibrary`] if any of the default paths don't exist on the system.
+    /// * [`Error::PathDoesNotExist`] if any of the default paths don't exist on
+    ///   the system.
     pub fn try_default() -> Result<()> {
-        Self::with_paths(
-            "/usr/lib/x86_64-linux-gnu/libsgx_qe3.signed.so.1",
-            "/usr/lib/x86_64-linux-gnu/libsgx_pce.signed.so.1",
-            None::<&Path>,
-            "/usr/lib/x86_64-linux-gnu/libsgx_id_enclave.signed.so.1",
-        )
+        Self::initialize(QuotingContext::default_builder())
+    }
+
+    /// Initialize the paths to those found by [`PathDiscovery::system()`]
+    ///
+    /// Unlike [`PathInitializer::try_default()`] this honors the
+    /// `SGX_QL_*_PATH` environment variables and searches the standard
+    /// directories, checking each file is a signed enclave or shared library.
+    ///
+    /// Returns the discovered paths, to report which were chosen.
+    ///
+    /// # Errors
+    /// * [`Error::PathsInitialized`] if the paths have been previously
+    ///   initialized.
+    /// * [`Error::PathNotDiscovered`] if one of the enclaves can't be found.
+    /// * [`Error::InvalidFile`] if an environment variable points to a file
+    ///   which isn't a signed enclave or shared library.
+    pub fn discover() -> Result<DiscoveredPaths> {
+        let paths = PathDiscovery::system().discover()?;
+        Self::initialize(paths.builder())?;
+        Ok(paths)
     }
 
     /// Initialize the DCAP quoting library paths with provided values
     ///
+    /// The paths are validated now and set in the quoting library on the
+    /// first call which needs them.
+    ///
     /// # Arguments
     /// * `quoting_enclave` - The full file path to the quoting enclave
     /// * `provisioning_certificate_enclave` - The full file path to the
@@ -75,10 +96,10 @@ impl PathInitializer {
     ///   initialized.
     /// * [`Error::PathStringConversion`] if one of the paths cannot be
     ///   converted to a [`CString`]
-    /// * [`Error::QuoteLibrary`] if
-    ///     * one of the paths does not point to a file
-    ///     * one of the paths is longer than 259 (bytes)
-    ///     * one of the paths contains a null (0) byte.
+    /// * [`Error::PathDoesNotExist`] if one of the paths does not point to a
+    ///   file
+    /// * [`Error::PathLengthTooLong`] if one of the paths is longer than 259
+    ///   (bytes)
     pub fn with_paths<P1, P2, P3, P4>(
         quoting_enclave: P1,
         provisioning_certificate_enclave: P2,
@@ -91,15 +112,21 @@ impl PathInitializer {
         P3: AsRef<Path>,
         P4: AsRef<Path>,
     {
+        let builder = QuotingContext::builder()
+            .quoting_enclave(quoting_enclave)
+            .provisioning_certificate_enclave(provisioning_certificate_enclave)
+            .id_enclave(id_enclave);
+        let builder = match quote_provider_library {
+            Some(path) => builder.quote_provider_library(path),
+            None => builder,
+        };
+        Self::initialize(builder)
+    }
+
+    fn initialize(builder: QuotingContextBuilder) -> Result<()> {
         let mut value = PATH_INITIALIZER.lock().expect("Mutex has been poisoned");
         if value.is_none() {
-            Self::set_paths(
-                quoting_enclave,
-                provisioning_certificate_enclave,
-                quote_provider_library,
-                id_enclave,
-            )?;
-            *value = Some(PathInitializer);
+            *value = Some(builder.build()?);
             Ok(())
         } else {
             Err(Error::PathsInitialized)
@@ -111,8 +138,8 @@ impl PathInitializer {
     /// If the paths have not been initialized will initialize to the default.
     ///
     /// # Errors
-    /// Will return [`Error::QuoteLibrary`] if the paths have not been initialized and
-    /// the default paths don't exist.
+    /// Will return [`Error::PathDoesNotExist`] if the paths have not been
+    /// initialized and the default paths don't exist.
     ///
     /// Will *not* return an error if the paths were previously initialized.
     pub(crate) fn ensure_initialized() -> Result<()> {
@@ -121,68 +148,56 @@ impl PathInitializer {
             Err(e) => Err(e),
         }
     }
+}
 
-    fn set_paths<P1, P2, P3, P4>(
-        quoting_enclave: P1,
-        provisioning_certificate_enclave: P2,
-        quote_provider_library: Option<P3>,
-        id_enclave: P4,
-    ) -> Result<PathInitializer>
-    where
-        P1: AsRef<Path>,
-        P2: AsRef<Path>,
-        P3: AsRef<Path>,
-        P4: AsRef<Path>,
-    {
-        Self::set_path(PathKind::QuotingEnclave, quoting_enclave)?;
-        Self::set_path(
-            PathKind::ProvisioningCertificateEnclave,
-            provisioning_certificate_enclave,
-        )?;
-        Self::set_path(PathKind::IdEnclave, id_enclave)?;
-        quote_provider_library.map_or(Ok(()), |path| {
-            Self::set_path(PathKind::QuoteProviderLibrary, path)
-        })?;
-        Ok(PathInitializer)
+/// Validate a path for the quoting library
+///
+/// # Errors
+/// * [`Error::PathStringConversion`] if `path` cannot be converted to a
+///   [`CString`]
+/// * [`Error::PathDoesNotExist`] if `path` does not point to a file
+/// * [`Error::PathLengthTooLong`] if `path` is longer than 259 (bytes)
+pub(crate) fn validate_path(path: &Path) -> Result<CString> {
+    let c_path = c_path(path)?;
+
+    path.is_file()
+        .then_some(true)
+        .ok_or_else(|| Error::PathDoesNotExist(path.to_string_lossy().into_owned()))?;
+
+    if c_path.as_bytes_with_nul().len() > MAX_PATH_LENGTH {
+        return Err(Error::PathLengthTooLong(
+            path.to_string_lossy().into_owned(),
+        ));
     }
 
-    /// Set path for QE(Quoting Enclave), PCE(Provisioning Certificate Enclave)
-    /// or QPL(Quote Provider Library)
-    ///
-    /// This allows one to override the path that will be searched for each
-    /// `path_kind`.  When this isn't called then the local path and dlopen
-    /// search path will be utilized.
-    ///
-    /// # Arguments
-    /// * `path_kind` - Which path to set
-    /// * `path` - The path value to use.  This is the full path to a file.
-    ///
-    /// # Errors
-    /// * [`Error::PathStringConversion`] if `path_kind` cannot be converted to
-    ///   a [`CString`]
-    /// * [`Error::QuoteLibrary`] if
-    ///     * `path` does not point to a file
-    ///     * `path` is longer than 259 (bytes)
-    ///     * `path` contains a null (0) byte.
-    fn set_path<P: AsRef<Path>>(path_kind: PathKind, path: P) -> Result<()> {
-        let path = path.as_ref();
-        let c_path = CString::new(path.as_os_str().as_bytes())
-            .map_err(|_| Error::PathStringConversion(path.to_string_lossy().into_owned()))?;
-
-        path.is_file()
-            .then_some(true)
-            .ok_or_else(|| Error::PathDoesNotExist(path.to_string_lossy().into_owned()))?;
-
-        if c_path.as_bytes_with_nul().len() > MAX_PATH_LENGTH {
-            return Err(Error::PathLengthTooLong(
-                path.to_string_lossy().into_owned(),
-            ));
-        }
+    Ok(c_path)
+}
 
-        unsafe { mc_sgx_dcap_ql_sys::sgx_ql_set_path(path_kind.into(), c_path.as_ptr()) }
-            .into_result()?;
-        Ok(())
-    }
+/// Convert a path for the quoting library
+///
+/// # Errors
+/// [`Error::PathStringConversion`] if `path` cannot be converted to a
+/// [`CString`]
+pub(crate) fn c_path(path: &Path) -> Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| Error::PathStringConversion(path.to_string_lossy().into_owned()))
+}
+
+/// The [`QuotingContext`] of the [`PathInitializer`] and
+/// [`LoadPolicyInitializer`], initializing them to the defaults if needed
+pub(crate) fn default_context() -> Result<QuotingContext> {
+    PathInitializer::ensure_initialized()?;
+    LoadPolicyInitializer::ensure_initialized()?;
+    let context = PATH_INITIALIZER
+        .lock()
+        .expect("Mutex has been poisoned")
+        .clone()
+        .expect("Paths were initialized");
+    let policy = LOAD_POLICY_INITIALIZER
+        .lock()
+        .expect("Mutex has been poisoned")
+        .expect("Load policy was initialized");
+    Ok(context.with_load_policy(policy))
 }
 
 /// Target info for quoting enclave
@@ -195,14 +210,13 @@ pub trait QeTargetInfo {
     ///   [`LoadPolicyInitializer`] after calling this function will result in
     ///   an error.
     ///
+    /// Use [`QuotingContext::target_info()`] to choose the paths and load
+    /// policy instead.
+    ///
     /// # Errors
     /// Will return an error if there is a failure from the SGX SDK
     fn for_quoting_enclave() -> Result<TargetInfo> {
-        PathInitializer::ensure_initialized()?;
-        LoadPolicyInitializer::ensure_initialized()?;
-        let mut info = sgx_target_info_t::default();
-        unsafe { mc_sgx_dcap_ql_sys::sgx_qe_get_target_info(&mut info) }.into_result()?;
-        Ok(info.into())
+        default_context()?.target_info()
     }
 }
 
@@ -217,8 +231,7 @@ impl QeTargetInfo for TargetInfo {}
 /// of [`RequestPolicy::Persistent`].
 #[derive(Debug)]
 pub struct LoadPolicyInitializer;
-static LOAD_POLICY_INITIALIZER: Lazy<Mutex<Option<LoadPolicyInitializer>>> =
-    Lazy::new(|| Mutex::new(None));
+static LOAD_POLICY_INITIALIZER: Lazy<Mutex<Option<RequestPolicy>>> = Lazy::new(|| Mutex::new(None));
 
 impl LoadPolicyInitializer {
     /// Try to initialize the quoting enclave load policy to the default
@@ -226,30 +239,29 @@ impl LoadPolicyInitializer {
     /// The default is persistent [`RequestPolicy::Persistent`].
     ///
     /// # Errors
-    /// * [`Error::LoadPolicyInitialized`] if the policy has been previously
-    ///   initialized.
-    /// * [`Error::QuoteLibrary`] for any errors setting the policy in the SGX SDK.
+    /// [`Error::LoadPolicyInitialized`] if the policy has been previously
+    /// initialized.
     pub fn try_default() -> Result<()> {
         Self::policy(RequestPolicy::Persistent)
     }
 
     /// Set the load policy to use for the quoting enclaves
     ///
+    /// The policy is set in the quoting library on the first call which needs
+    /// it.
+    ///
     /// # Arguments
     /// * `policy` - The policy to use for loading the quoting enclaves
     ///
     /// # Errors
-    /// * [`Error::LoadPolicyInitialized`] if the policy has been previously
-    ///   initialized.
-    /// * [`Error::QuoteLibrary`] for any errors setting the policy in the SGX SDK.
+    /// [`Error::LoadPolicyInitialized`] if the policy has been previously
+    /// initialized.
     pub fn policy(policy: RequestPolicy) -> Result<()> {
         let mut value = LOAD_POLICY_INITIALIZER
             .lock()
             .expect("Mutex has been poisoned");
         if value.is_none() {
-            unsafe { mc_sgx_dcap_ql_sys::sgx_qe_set_enclave_load_policy(policy.into()) }
-                .into_result()?;
-            *value = Some(LoadPolicyInitializer);
+            *value = Some(policy);
             Ok(())
         } else {
             Err(Error::LoadPolicyInitialized)
@@ -261,9 +273,6 @@ impl LoadPolicyInitializer {
     /// If the load policy has already been set does nothing
     ///
     /// # Errors
-    /// Will return [`Error::QuoteLibrary`] if the load policy has not been initialized
-    /// and there is an error setting the policy
-    ///
     /// Will *not* return an error if the load policy as previously initialized.
     pub(crate) fn ensure_initialized() -> Result<()> {
         match Self::try_default() {
@@ -276,11 +285,8 @@ impl LoadPolicyInitializer {
 #[cfg(test)]
 mod test {
     use super::*;
-    use mc_sgx_dcap_ql_types::PathKind::{
-        ProvisioningCertificateEnclave, QuoteProviderLibrary, QuotingEnclave,
-    };
     use serial_test::serial;
-    use std::fs;
+    use std::{fs, path::PathBuf};
     use tempfile::tempdir;
     use yare::parameterized;
 
@@ -304,16 +310,18 @@ mod test {
         *value = None;
     }
 
+    type Setter = fn(QuotingContextBuilder, PathBuf) -> QuotingContextBuilder;
+
     #[parameterized(
-    qe = { QuotingEnclave },
-    qpl = { QuoteProviderLibrary },
-    pce = { ProvisioningCertificateEnclave },
+    qe = { QuotingContextBuilder::quoting_enclave::<PathBuf> },
+    qpl = { QuotingContextBuilder::quote_provider_library::<PathBuf> },
+    pce = { QuotingContextBuilder::provisioning_certificate_enclave::<PathBuf> },
     )]
-    fn path_succeeds(path_kind: PathKind) {
+    fn path_succeeds(set: Setter) {
         let dir = tempdir().unwrap();
         let file_name = dir.path().join("fake.txt");
         fs::write(&file_name, "stuff").unwrap();
-        assert_eq!(PathInitializer::set_path(path_kind, file_name), Ok(()));
+        assert!(set(QuotingContext::builder(), file_name).build().is_ok());
     }
 
     #[test]
@@ -321,17 +329,14 @@ mod test {
         let dir = tempdir().unwrap();
         let file_name = dir.path().join("fake.txt");
         fs::write(&file_name, "stuff").unwrap();
-        assert_eq!(
-            PathInitializer::set_path(QuoteProviderLibrary, file_name),
-            Ok(())
-        );
+        assert_eq!(validate_path(&file_name).map(|_| ()), Ok(()));
     }
 
     #[test]
     fn path_as_directory_fails() {
         let dir = tempdir().unwrap();
         assert_eq!(
-            PathInitializer::set_path(QuotingEnclave, dir.path()),
+            validate_path(dir.path()).map(|_| ()),
             Err(Error::PathDoesNotExist(String::from(
                 dir.path().to_str().unwrap()
             )))
@@ -343,9 +348,9 @@ mod test {
         let dir = tempdir().unwrap();
         let file_name = dir.path().join("fake\0.txt");
         // fs::write() will fail to create the file with a null byte in the path
-        // so we pass the path as non existent to `set_path`.
+        // so we pass the path as non existent to `validate_path`.
         assert_eq!(
-            PathInitializer::set_path(ProvisioningCertificateEnclave, &file_name),
+            validate_path(&file_name).map(|_| ()),
             Err(Error::PathStringConversion(String::from(
                 file_name.to_string_lossy()
             )))
@@ -363,7 +368,7 @@ mod test {
         let file_name = dir.path().join(long_name);
         fs::write(&file_name, "stuff").unwrap();
 
-        assert!(PathInitializer::set_path(QuoteProviderLibrary, file_name).is_ok());
+        assert!(validate_path(&file_name).is_ok());
     }
 
     #[test]
@@ -378,7 +383,7 @@ mod test {
         fs::write(&file_name, "stuff").unwrap();
 
         assert_eq!(
-            PathInitializer::set_path(QuoteProviderLibrary, &file_name),
+            validate_path(&file_name).map(|_| ()),
             Err(Error::PathLengthTooLong(String::from(
                 file_name.to_str().unwrap()
             )))
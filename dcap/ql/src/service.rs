@@ -0,0 +1,436 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! A quoting enclave which is explicitly loaded and cleaned up, retrying
+//! transient failures.
+
+use crate::{Error, QuotingContext};
+use core::time::Duration;
+use mc_sgx_core_types::{Report, TargetInfo};
+use mc_sgx_dcap_types::Quote3;
+use std::{sync::Mutex, thread};
+
+/// How a failed quoting library call is handled
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorClass {
+    /// Retrying won't help
+    Fatal,
+    /// The failure is transient, like a busy quoting enclave or an unavailable
+    /// provisioning service, so the call is retried
+    Retryable,
+    /// The quoting enclave was lost or ran out of EPC, so it's cleaned up and
+    /// the call is retried with the target info of the reloaded enclave
+    Reload,
+}
+
+/// How often, and how long apart, a [`QuotingService`] retries
+///
+/// The backoff doubles after each retry, up to the maximum backoff.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 4,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy which makes each call once
+    pub fn never() -> Self {
+        Self::default().attempts(1)
+    }
+
+    /// The number of times a call is made, including the first
+    ///
+    /// Defaults to 4, values below 1 are treated as 1.
+    ///
+    /// # Arguments
+    /// * `attempts` - The maximum number of calls
+    #[must_use]
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// The time to wait before the first retry
+    ///
+    /// Defaults to 50 milliseconds.
+    ///
+    /// # Arguments
+    /// * `backoff` - The time to wait before the first retry
+    #[must_use]
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// The longest time to wait before a retry
+    ///
+    /// Defaults to 1 second.
+    ///
+    /// # Arguments
+    /// * `backoff` - The upper bound of the time between retries
+    #[must_use]
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// The time to wait before the retry numbered `retry`, starting at 0
+    fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+
+    /// Call `attempt` until it succeeds, fails with a [`ErrorClass::Fatal`]
+    /// error or runs out of attempts
+    ///
+    /// `reload` is called before retrying an [`ErrorClass::Reload`] error.
+    fn retry<T, E>(
+        &self,
+        mut attempt: impl FnMut() -> Result<T, E>,
+        class: impl Fn(&E) -> ErrorClass,
+        mut reload: impl FnMut(),
+    ) -> Result<T, E> {
+        let mut retry = 0;
+        loop {
+            let error = match attempt() {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            let error_class = class(&error);
+            if error_class == ErrorClass::Fatal || retry + 1 >= self.attempts {
+                return Err(error);
+            }
+            if error_class == ErrorClass::Reload {
+                reload();
+            }
+            thread::sleep(self.backoff(retry));
+            retry += 1;
+        }
+    }
+}
+
+/// Why a quote attempt of [`QuotingService::quote_with()`] failed
+enum Failure<E> {
+    Quote(Error),
+    Report(E),
+}
+
+/// Quotes with a quoting enclave whose lifetime is managed explicitly
+///
+/// The service caches the target info of the QE(Quoting Enclave) and refreshes
+/// it whenever the QE is reloaded, since reports for a stale target info
+/// can't be quoted. Transient failures are retried per the [`RetryPolicy`],
+/// see [`Error::class()`].
+///
+/// ```no_run
+/// # use mc_sgx_core_types::{Report, TargetInfo};
+/// # use mc_sgx_dcap_ql::{QuotingContext, QuotingService};
+/// # fn create_report(target_info: &TargetInfo) -> Result<Report, mc_sgx_dcap_ql::Error> {
+/// #     Ok(Report::default())
+/// # }
+/// let service = QuotingService::new(QuotingContext::try_default()?);
+/// service.load()?;
+/// // The report is created again for the reloaded QE if it's lost
+/// let quote = service.quote_with(create_report)?;
+/// service.cleanup()?;
+/// # Ok::<(), mc_sgx_dcap_ql::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct QuotingService {
+    context: QuotingContext,
+    retry_policy: RetryPolicy,
+    target_info: Mutex<Option<TargetInfo>>,
+}
+
+impl QuotingService {
+    /// A service quoting with the settings of `context`
+    ///
+    /// The QE isn't loaded until [`QuotingService::load()`] or the first call
+    /// which needs it.
+    ///
+    /// # Arguments
+    /// * `context` - The paths and load policy of the quoting enclaves
+    pub fn new(context: QuotingContext) -> Self {
+        Self {
+            context,
+            retry_policy: RetryPolicy::default(),
+            target_info: Mutex::new(None),
+        }
+    }
+
+    /// Retry transient failures per `policy`
+    ///
+    /// # Arguments
+    /// * `policy` - How often and how long apart to retry
+    #[must_use]
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// The context the service quotes with
+    pub fn context(&self) -> &QuotingContext {
+        &self.context
+    }
+
+    /// Load the QE, returning its target info
+    ///
+    /// # Errors
+    /// The last [`Error`] if loading the QE fails, after retrying transient
+    /// failures.
+    pub fn load(&self) -> Result<TargetInfo, Error> {
+        self.retry_policy
+            .retry(|| self.target_info(), Error::class, || self.unload())
+    }
+
+    /// The target info of the QE, loading it if needed
+    ///
+    /// This is cached until the QE is reloaded or cleaned up.
+    ///
+    /// # Errors
+    /// [`Error::QuoteLibrary`] if loading the QE fails.
+    pub fn target_info(&self) -> Result<TargetInfo, Error> {
+        let mut target_info = self.lock();
+        if let Some(target_info) = target_info.as_ref() {
+            return Ok(target_info.clone());
+        }
+        let loaded = self.context.target_info()?;
+        *target_info = Some(loaded.clone());
+        Ok(loaded)
+    }
+
+    /// Unload the QE
+    ///
+    /// The next call which needs the QE loads it again, with a fresh target
+    /// info.
+    ///
+    /// # Errors
+    /// [`Error::QuoteLibrary`] if unloading the QE fails.
+    pub fn cleanup(&self) -> Result<(), Error> {
+        *self.lock() = None;
+        self.context.cleanup()
+    }
+
+    /// Create a [`Quote3`] from `report`
+    ///
+    /// # Arguments
+    /// * `report` - The report to quote, created for the target info from
+    ///   [`QuotingService::target_info()`]
+    ///
+    /// # Errors
+    /// * [`Error::StaleTargetInfo`] if the QE was reloaded with a different
+    ///   target info, `report` needs to be created again. Use
+    ///   [`QuotingService::quote_with()`] to do so automatically.
+    /// * The last [`Error`] if quoting fails, after retrying transient
+    ///   failures.
+    pub fn quote(&self, report: &Report) -> Result<Quote3<Vec<u8>>, Error> {
+        let target_info = self.target_info()?;
+        self.quote_with(|current: &TargetInfo| {
+            if current == &target_info {
+                Ok(report.clone())
+            } else {
+                Err(Error::StaleTargetInfo)
+            }
+        })
+    }
+
+    /// Create a [`Quote3`] from the report `create_report` returns
+    ///
+    /// `create_report` is called with the current target info of the QE for
+    /// each attempt, so the report is created again after the QE is reloaded.
+    ///
+    /// # Arguments
+    /// * `create_report` - Creates a report for the given QE target info,
+    ///   usually with an ECALL into the enclave being attested
+    ///
+    /// # Errors
+    /// * The error of `create_report`, which isn't retried.
+    /// * The last [`Error`] if quoting fails, after retrying transient
+    ///   failures.
+    pub fn quote_with<F, E>(&self, mut create_report: F) -> Result<Quote3<Vec<u8>>, E>
+    where
+        F: FnMut(&TargetInfo) -> Result<Report, E>,
+        E: From<Error>,
+    {
+        self.retry_policy
+            .retry(
+                || {
+                    let target_info = self.target_info().map_err(Failure::Quote)?;
+                    let report = create_report(&target_info).map_err(Failure::Report)?;
+                    self.context.quote(&report).map_err(Failure::Quote)
+                },
+                |failure| match failure {
+                    Failure::Quote(error) => error.class(),
+                    Failure::Report(_) => ErrorClass::Fatal,
+                },
+                || self.unload(),
+            )
+            .map_err(|failure| match failure {
+                Failure::Quote(error) => error.into(),
+                Failure::Report(error) => error,
+            })
+    }
+
+    /// Forget the target info and clean up the QE before reloading it
+    fn unload(&self) {
+        *self.lock() = None;
+        // A lost QE may fail to clean up, the retry reloads it regardless
+        let _ = self.context.cleanup();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<TargetInfo>> {
+        self.target_info.lock().expect("Mutex has been poisoned")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mc_sgx_dcap_types::QlError;
+
+    fn immediate() -> RetryPolicy {
+        RetryPolicy::default().initial_backoff(Duration::ZERO)
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let policy = RetryPolicy::default()
+            .initial_backoff(Duration::from_millis(10))
+            .max_backoff(Duration::from_millis(50));
+        let backoffs = (0..5)
+            .map(|retry| policy.backoff(retry))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            backoffs,
+            [10, 20, 40, 50, 50].map(Duration::from_millis).to_vec()
+        );
+    }
+
+    #[test]
+    fn backoff_saturates() {
+        let policy = RetryPolicy::default()
+            .initial_backoff(Duration::from_secs(1))
+            .max_backoff(Duration::MAX);
+        assert_eq!(
+            policy.backoff(u32::MAX),
+            Duration::from_secs(u32::MAX.into())
+        );
+    }
+
+    #[test]
+    fn zero_attempts_is_one() {
+        assert_eq!(RetryPolicy::default().attempts(0), RetryPolicy::never());
+    }
+
+    #[test]
+    fn transient_errors_are_retried() {
+        let mut calls = 0;
+        let result = immediate().retry(
+            || {
+                calls += 1;
+                if calls < 3 {
+                    Err(Error::from(QlError::Busy))
+                } else {
+                    Ok(calls)
+                }
+            },
+            Error::class,
+            || panic!("Busy shouldn't reload"),
+        );
+        assert_eq!(result, Ok(3));
+    }
+
+    #[test]
+    fn fatal_errors_are_not_retried() {
+        let mut calls = 0;
+        let result: Result<(), _> = immediate().retry(
+            || {
+                calls += 1;
+                Err(Error::from(QlError::InvalidParameter))
+            },
+            Error::class,
+            || (),
+        );
+        assert_eq!(result, Err(Error::QuoteLibrary(QlError::InvalidParameter)));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn retries_are_bounded() {
+        let mut calls = 0;
+        let result: Result<(), _> = immediate().attempts(3).retry(
+            || {
+                calls += 1;
+                Err(Error::from(QlError::ServiceUnavailable))
+            },
+            Error::class,
+            || (),
+        );
+        assert_eq!(
+            result,
+            Err(Error::QuoteLibrary(QlError::ServiceUnavailable))
+        );
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn lost_enclave_reloads_before_retrying() {
+        let mut events = vec![];
+        let events_cell = core::cell::RefCell::new(&mut events);
+        let result = immediate().retry(
+            || {
+                let mut events = events_cell.borrow_mut();
+                events.push("attempt");
+                if events.len() == 1 {
+                    Err(Error::from(QlError::EnclaveLost))
+                } else {
+                    Ok(())
+                }
+            },
+            Error::class,
+            || events_cell.borrow_mut().push("reload"),
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(events, ["attempt", "reload", "attempt"]);
+    }
+}
+
+#[cfg(all(test, not(feature = "sim")))]
+mod hw_test {
+    use super::*;
+
+    #[test]
+    fn report_errors_are_not_retried() {
+        let service = QuotingService::new(QuotingContext::builder().build().unwrap())
+            .retry_policy(RetryPolicy::never());
+        let mut calls = 0;
+        let result = service.quote_with(|_: &TargetInfo| -> Result<Report, Error> {
+            calls += 1;
+            Err(Error::StaleTargetInfo)
+        });
+        assert_eq!(result, Err(Error::StaleTargetInfo));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn cleanup_forgets_the_target_info() {
+        let service = QuotingService::new(QuotingContext::builder().build().unwrap());
+        let target_info = service.load().unwrap();
+        assert_eq!(service.target_info(), Ok(target_info.clone()));
+
+        service.cleanup().unwrap();
+        assert_eq!(*service.lock(), None);
+        assert_eq!(service.load(), Ok(target_info));
+    }
+}
@@ -1,6 +1,8 @@
 // Copyright (c) 2022-2023 The MobileCoin Foundation
 //! Builds the FFI function bindings for dcap ql library of the Intel SGX SDK
 
+use std::env;
+
 const DCAP_QL_FUNCTIONS: &[&str] = &[
     "sgx_qe_cleanup_by_policy",
     "sgx_qe_get_quote",
@@ -11,7 +13,11 @@ const DCAP_QL_FUNCTIONS: &[&str] = &[
 ];
 
 fn main() {
-    cargo_emit::rustc_link_lib!("dylib=sgx_dcap_ql");
+    // With `dlopen` the library is loaded at runtime instead
+    if env::var_os("CARGO_FEATURE_DLOPEN").is_none() {
+        cargo_emit::rustc_link_lib!("dylib=sgx_dcap_ql");
+    }
+    mc_sgx_core_build::emit_dcap_cfgs();
 
     let mut builder = mc_sgx_core_build::sgx_builder()
         .header("wrapper.h")
@@ -21,10 +27,5 @@ fn main() {
         builder = builder.allowlist_function(f);
     }
 
-    let out_path = mc_sgx_core_build::build_output_dir();
-    builder
-        .generate()
-        .expect("Unable to generate bindings")
-        .write_to_file(out_path.join("bindings.rs"))
-        .expect("Couldn't write bindings!");
+    mc_sgx_core_build::write_bindings(builder);
 }
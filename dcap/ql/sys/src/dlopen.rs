@@ -0,0 +1,137 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Loading of the `sgx_dcap_ql` library at runtime
+
+use libloading::Library;
+use mc_sgx_core_sys_types::{sgx_report_t, sgx_target_info_t};
+use mc_sgx_dcap_ql_sys_types::sgx_ql_path_type_t;
+use mc_sgx_dcap_sys_types::{quote3_error_t, sgx_ql_request_policy_t};
+use once_cell::sync::OnceCell;
+use std::{ffi::OsStr, os::raw::c_char};
+
+/// The name of the library loaded by [`library()`]
+pub const LIBRARY_NAME: &str = "libsgx_dcap_ql.so.1";
+
+/// The functions of the `sgx_dcap_ql` library
+#[derive(Debug)]
+pub struct DcapQl {
+    /// `sgx_qe_cleanup_by_policy()`
+    pub sgx_qe_cleanup_by_policy: unsafe extern "C" fn() -> quote3_error_t,
+    /// `sgx_qe_get_quote()`
+    pub sgx_qe_get_quote: unsafe extern "C" fn(*const sgx_report_t, u32, *mut u8) -> quote3_error_t,
+    /// `sgx_qe_get_quote_size()`
+    pub sgx_qe_get_quote_size: unsafe extern "C" fn(*mut u32) -> quote3_error_t,
+    /// `sgx_qe_get_target_info()`
+    pub sgx_qe_get_target_info: unsafe extern "C" fn(*mut sgx_target_info_t) -> quote3_error_t,
+    /// `sgx_qe_set_enclave_load_policy()`
+    pub sgx_qe_set_enclave_load_policy:
+        unsafe extern "C" fn(sgx_ql_request_policy_t) -> quote3_error_t,
+    /// `sgx_ql_set_path()`
+    pub sgx_ql_set_path: unsafe extern "C" fn(sgx_ql_path_type_t, *const c_char) -> quote3_error_t,
+    // Must outlive the function pointers above
+    _library: Library,
+}
+
+impl DcapQl {
+    /// Load the library and resolve all of its functions
+    ///
+    /// # Arguments
+    /// * `path` - The library to load, either a file name to search for in the
+    ///   standard library locations or a path.
+    ///
+    /// # Safety
+    /// Loading a library runs its initialization code, and the functions are
+    /// assumed to have the signatures of the `sgx_dcap_ql` library.
+    pub unsafe fn load(path: impl AsRef<OsStr>) -> Result<Self, libloading::Error> {
+        let library = Library::new(path.as_ref())?;
+        Ok(Self {
+            sgx_qe_cleanup_by_policy: *library.get(b"sgx_qe_cleanup_by_policy\0")?,
+            sgx_qe_get_quote: *library.get(b"sgx_qe_get_quote\0")?,
+            sgx_qe_get_quote_size: *library.get(b"sgx_qe_get_quote_size\0")?,
+            sgx_qe_get_target_info: *library.get(b"sgx_qe_get_target_info\0")?,
+            sgx_qe_set_enclave_load_policy: *library.get(b"sgx_qe_set_enclave_load_policy\0")?,
+            sgx_ql_set_path: *library.get(b"sgx_ql_set_path\0")?,
+            _library: library,
+        })
+    }
+}
+
+static LIBRARY: OnceCell<Result<DcapQl, String>> = OnceCell::new();
+
+/// The `sgx_dcap_ql` library of the process, loaded on first use
+///
+/// Loading is only attempted once, later calls return the same result.
+///
+/// # Errors
+/// The reason [`LIBRARY_NAME`] could not be loaded, or a function could not be
+/// found in it.
+pub fn library() -> Result<&'static DcapQl, &'static str> {
+    LIBRARY
+        .get_or_init(|| unsafe { DcapQl::load(LIBRARY_NAME) }.map_err(|e| e.to_string()))
+        .as_ref()
+        .map_err(String::as_str)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{fs, path::PathBuf, process::Command};
+    use tempfile::TempDir;
+
+    /// Build a stand in for the library whose functions return `status`.
+    fn stub_library(dir: &TempDir, functions: &[&str], status: u32) -> PathBuf {
+        let source = dir.path().join("stub.c");
+        let library = dir.path().join("libstub.so");
+        let contents = functions
+            .iter()
+            .map(|f| format!("unsigned int {f}() {{ return {status}; }}\n"))
+            .collect::<String>();
+        fs::write(&source, contents).expect("Failed to write stub source");
+        let status = Command::new("cc")
+            .args(["-shared", "-fPIC", "-o"])
+            .arg(&library)
+            .arg(&source)
+            .status()
+            .expect("Failed to run the C compiler");
+        assert!(status.success(), "Failed to compile stub library");
+        library
+    }
+
+    const FUNCTIONS: &[&str] = &[
+        "sgx_qe_cleanup_by_policy",
+        "sgx_qe_get_quote",
+        "sgx_qe_get_quote_size",
+        "sgx_qe_get_target_info",
+        "sgx_qe_set_enclave_load_policy",
+        "sgx_ql_set_path",
+    ];
+
+    #[test]
+    fn calls_into_loaded_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = stub_library(&dir, FUNCTIONS, 0xE011);
+
+        let library = unsafe { DcapQl::load(&path) }.unwrap();
+        let mut size = 0;
+        let result = unsafe { (library.sgx_qe_get_quote_size)(&mut size) };
+
+        assert_eq!(result.0, 0xE011);
+    }
+
+    #[test]
+    fn missing_function_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = stub_library(&dir, &FUNCTIONS[1..], 0);
+
+        let error = unsafe { DcapQl::load(path) }.unwrap_err();
+
+        assert!(error.to_string().contains("sgx_qe_cleanup_by_policy"));
+    }
+
+    #[test]
+    fn missing_library_fails() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert!(unsafe { DcapQl::load(dir.path().join("libmissing.so")) }.is_err());
+    }
+}
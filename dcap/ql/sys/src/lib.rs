@@ -8,3 +8,6 @@ use mc_sgx_dcap_ql_sys_types::sgx_ql_path_type_t;
 use mc_sgx_dcap_sys_types::{quote3_error_t, sgx_ql_request_policy_t};
 
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+#[cfg(feature = "dlopen")]
+pub mod dlopen;
@@ -0,0 +1,497 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! PCK certificates and collateral cached in a local directory
+
+use crate::{Collateral, CollateralSource, EnclaveIdentity, Error, PckCa, PckCertChain, PckCertId};
+use mc_sgx_core_types::CpuSvn;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The size of a TCBm, the CPU SVN followed by the PCE SVN
+const TCBM_SIZE: usize = CpuSvn::SIZE + 2;
+
+/// PCK certificates and collateral cached in a local directory
+///
+/// The files are laid out as:
+///
+/// ```text
+/// pck_certs/<QE ID>-<PCE ID>-<CPU SVN>-<PCE SVN>.pem   PCK certificate and issuer chain
+/// pck_certs/<QE ID>-<PCE ID>-<CPU SVN>-<PCE SVN>.tcbm  TCB of the certificate (optional)
+/// tcb_info/<FMSPC>.json
+/// tcb_info/issuer_chain.pem
+/// pck_crl/processor.der
+/// pck_crl/platform.der
+/// pck_crl/issuer_chain.pem
+/// qe_identity.json
+/// qe_identity_issuer_chain.pem
+/// qve_identity.json
+/// qve_identity_issuer_chain.pem
+/// root_ca_crl.der
+/// ```
+///
+/// Values in file names are upper case hex, with the PCE ID and PCE SVN as
+/// little endian bytes. The `.tcbm` file holds the hex encoded CPU SVN and
+/// PCE SVN the certificate was issued for, like the PCS `SGX-TCBm` header.
+/// Without it the certificate is taken to be for the platform's raw TCB.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct DirectoryCache {
+    root: PathBuf,
+}
+
+impl DirectoryCache {
+    /// A cache in the directory `root`
+    ///
+    /// # Arguments
+    /// * `root` - The directory of the cache, it's created when storing
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_owned(),
+        }
+    }
+
+    /// The directory of the cache
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Store the PCK certificate chain of a platform
+    ///
+    /// # Arguments
+    /// * `id` - The identity of the platform's PCK certificate
+    /// * `chain` - The certificate chain, along with its TCB
+    ///
+    /// # Errors
+    /// [`Error::Io`] if the files can't be written.
+    pub fn store_pck_cert_chain(&self, id: &PckCertId, chain: &PckCertChain) -> Result<(), Error> {
+        let path = self.pck_cert_path(id);
+        let mut tcbm = chain.cpu_svn.as_ref().to_vec();
+        tcbm.extend_from_slice(&u16::from(chain.pce_svn).to_le_bytes());
+
+        write(&path.with_extension("pem"), chain.chain.as_bytes())?;
+        write(
+            &path.with_extension("tcbm"),
+            hex::encode_upper(tcbm).as_bytes(),
+        )
+    }
+
+    /// Store the collateral of platforms with `fmspc`
+    ///
+    /// The QE identity and root CA CRL are shared by all FMSPCs, the latest
+    /// stored is used.
+    ///
+    /// # Arguments
+    /// * `fmspc` - The FMSPC of the platforms
+    /// * `ca` - The CA which issued the PCK certificates
+    /// * `collateral` - The collateral to store
+    ///
+    /// # Errors
+    /// [`Error::Io`] if the files can't be written.
+    pub fn store_collateral(
+        &self,
+        fmspc: &[u8],
+        ca: PckCa,
+        collateral: &Collateral,
+    ) -> Result<(), Error> {
+        let tcb_info = self.root.join("tcb_info");
+        write(
+            &tcb_info.join(format!("{}.json", hex::encode_upper(fmspc))),
+            collateral.tcb_info.as_bytes(),
+        )?;
+        write(
+            &tcb_info.join("issuer_chain.pem"),
+            collateral.tcb_info_issuer_chain.as_bytes(),
+        )?;
+
+        let pck_crl = self.root.join("pck_crl");
+        write(
+            &pck_crl.join(format!("{}.der", ca.name())),
+            &collateral.pck_crl,
+        )?;
+        write(
+            &pck_crl.join("issuer_chain.pem"),
+            collateral.pck_crl_issuer_chain.as_bytes(),
+        )?;
+
+        write(
+            &self.root.join("qe_identity.json"),
+            collateral.qe_identity.as_bytes(),
+        )?;
+        write(
+            &self.root.join("qe_identity_issuer_chain.pem"),
+            collateral.qe_identity_issuer_chain.as_bytes(),
+        )?;
+        self.store_root_ca_crl(&collateral.root_ca_crl)
+    }
+
+    /// Store the identity of the quote verification enclave
+    ///
+    /// # Arguments
+    /// * `identity` - The QvE identity to store
+    ///
+    /// # Errors
+    /// [`Error::Io`] if the files can't be written.
+    pub fn store_qve_identity(&self, identity: &EnclaveIdentity) -> Result<(), Error> {
+        write(
+            &self.root.join("qve_identity.json"),
+            identity.identity.as_bytes(),
+        )?;
+        write(
+            &self.root.join("qve_identity_issuer_chain.pem"),
+            identity.issuer_chain.as_bytes(),
+        )
+    }
+
+    /// Store the CRL of the Intel SGX root CA
+    ///
+    /// # Arguments
+    /// * `crl` - The DER encoded CRL
+    ///
+    /// # Errors
+    /// [`Error::Io`] if the file can't be written.
+    pub fn store_root_ca_crl(&self, crl: &[u8]) -> Result<(), Error> {
+        write(&self.root.join("root_ca_crl.der"), crl)
+    }
+
+    /// The path of a PCK certificate's files, without the extension
+    fn pck_cert_path(&self, id: &PckCertId) -> PathBuf {
+        self.root.join("pck_certs").join(id.hex_fields().join("-"))
+    }
+}
+
+impl CollateralSource for DirectoryCache {
+    fn pck_cert_chain(&self, id: &PckCertId) -> Result<PckCertChain, Error> {
+        let path = self.pck_cert_path(id);
+        let chain = read_string(&path.with_extension("pem"))?;
+
+        let tcbm = match read_string(&path.with_extension("tcbm")) {
+            Ok(tcbm) => tcbm,
+            Err(Error::NotFound(_)) => {
+                return Ok(PckCertChain {
+                    chain,
+                    cpu_svn: id.cpu_svn().clone(),
+                    pce_svn: id.pce_svn(),
+                })
+            }
+            Err(e) => return Err(e),
+        };
+        let tcbm = hex::decode(tcbm.trim())
+            .ok()
+            .filter(|tcbm| tcbm.len() == TCBM_SIZE)
+            .ok_or_else(|| Error::Invalid("TCBm", tcbm.trim().to_owned()))?;
+
+        let mut cpu_svn = [0; CpuSvn::SIZE];
+        cpu_svn.copy_from_slice(&tcbm[..CpuSvn::SIZE]);
+        let pce_svn = u16::from_le_bytes([tcbm[CpuSvn::SIZE], tcbm[CpuSvn::SIZE + 1]]);
+        Ok(PckCertChain {
+            chain,
+            cpu_svn: cpu_svn.into(),
+            pce_svn: pce_svn.into(),
+        })
+    }
+
+    fn collateral(&self, fmspc: &[u8], ca: PckCa) -> Result<Collateral, Error> {
+        let tcb_info = self.root.join("tcb_info");
+        let pck_crl = self.root.join("pck_crl");
+        Ok(Collateral {
+            pck_crl_issuer_chain: read_string(&pck_crl.join("issuer_chain.pem"))?,
+            root_ca_crl: self.root_ca_crl()?,
+            pck_crl: read(&pck_crl.join(format!("{}.der", ca.name())))?,
+            tcb_info_issuer_chain: read_string(&tcb_info.join("issuer_chain.pem"))?,
+            tcb_info: read_string(&tcb_info.join(format!("{}.json", hex::encode_upper(fmspc))))?,
+            qe_identity_issuer_chain: read_string(&self.root.join("qe_identity_issuer_chain.pem"))?,
+            qe_identity: read_string(&self.root.join("qe_identity.json"))?,
+        })
+    }
+
+    fn qve_identity(&self) -> Result<EnclaveIdentity, Error> {
+        Ok(EnclaveIdentity {
+            identity: read_string(&self.root.join("qve_identity.json"))?,
+            issuer_chain: read_string(&self.root.join("qve_identity_issuer_chain.pem"))?,
+        })
+    }
+
+    fn root_ca_crl(&self) -> Result<Vec<u8>, Error> {
+        read(&self.root.join("root_ca_crl.der"))
+    }
+}
+
+/// A [`DirectoryCache`] in front of another source
+///
+/// What's missing from the cache is fetched from the source and stored in
+/// the cache for later requests.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct CachingSource<S> {
+    cache: DirectoryCache,
+    source: S,
+}
+
+impl<S: CollateralSource> CachingSource<S> {
+    /// Cache what's fetched from `source` in `cache`
+    ///
+    /// # Arguments
+    /// * `cache` - The cache to look in first
+    /// * `source` - Where to fetch what's missing from `cache`
+    pub fn new(cache: DirectoryCache, source: S) -> Self {
+        Self { cache, source }
+    }
+
+    /// Look in the cache, then fetch from the source and store on a miss
+    ///
+    /// Failing to store doesn't fail the request, the next request fetches
+    /// again.
+    fn get<T>(
+        &self,
+        cached: impl FnOnce(&DirectoryCache) -> Result<T, Error>,
+        fetch: impl FnOnce(&S) -> Result<T, Error>,
+        store: impl FnOnce(&DirectoryCache, &T) -> Result<(), Error>,
+    ) -> Result<T, Error> {
+        match cached(&self.cache) {
+            Err(Error::NotFound(_)) => {
+                let value = fetch(&self.source)?;
+                let _ = store(&self.cache, &value);
+                Ok(value)
+            }
+            result => result,
+        }
+    }
+}
+
+impl<S: CollateralSource> CollateralSource for CachingSource<S> {
+    fn pck_cert_chain(&self, id: &PckCertId) -> Result<PckCertChain, Error> {
+        self.get(
+            |cache| cache.pck_cert_chain(id),
+            |source| source.pck_cert_chain(id),
+            |cache, chain| cache.store_pck_cert_chain(id, chain),
+        )
+    }
+
+    fn collateral(&self, fmspc: &[u8], ca: PckCa) -> Result<Collateral, Error> {
+        self.get(
+            |cache| cache.collateral(fmspc, ca),
+            |source| source.collateral(fmspc, ca),
+            |cache, collateral| cache.store_collateral(fmspc, ca, collateral),
+        )
+    }
+
+    fn qve_identity(&self) -> Result<EnclaveIdentity, Error> {
+        self.get(
+            DirectoryCache::qve_identity,
+            S::qve_identity,
+            DirectoryCache::store_qve_identity,
+        )
+    }
+
+    fn root_ca_crl(&self) -> Result<Vec<u8>, Error> {
+        self.get(DirectoryCache::root_ca_crl, S::root_ca_crl, |cache, crl| {
+            cache.store_root_ca_crl(crl)
+        })
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, Error> {
+    fs::read(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => Error::NotFound(path.display().to_string()),
+        _ => Error::Io(path.display().to_string(), e.to_string()),
+    })
+}
+
+fn read_string(path: &Path) -> Result<String, Error> {
+    String::from_utf8(read(path)?)
+        .map_err(|_| Error::Invalid("UTF-8 file", path.display().to_string()))
+}
+
+/// Write `contents` to `path` all at once, so concurrent readers never see
+/// part of a file
+fn write(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let io_error = |e: io::Error| Error::Io(path.display().to_string(), e.to_string());
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(io_error)?;
+    }
+    let partial = path.with_extension(format!("partial.{}", std::process::id()));
+    fs::write(&partial, contents).map_err(io_error)?;
+    fs::rename(&partial, path).map_err(io_error)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mc_sgx_core_sys_types::sgx_cpu_svn_t;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn pck_cert_id() -> PckCertId {
+        PckCertId::new(
+            [0xAB; 16],
+            sgx_cpu_svn_t { svn: [3; 16] }.into(),
+            14.into(),
+            0,
+            None,
+        )
+    }
+
+    fn collateral() -> Collateral {
+        Collateral {
+            pck_crl_issuer_chain: "pck crl chain".into(),
+            root_ca_crl: vec![0, 1, 2],
+            pck_crl: vec![3, 0, 4],
+            tcb_info_issuer_chain: "tcb info chain".into(),
+            tcb_info: "{\"tcbInfo\":{}}".into(),
+            qe_identity_issuer_chain: "qe identity chain".into(),
+            qe_identity: "{\"enclaveIdentity\":{}}".into(),
+        }
+    }
+
+    #[test]
+    fn pck_cert_chain_without_tcbm_is_for_the_raw_tcb() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DirectoryCache::new(dir.path());
+        let path = dir.path().join("pck_certs").join(format!(
+            "{}-0000-{}-0E00.pem",
+            "AB".repeat(16),
+            "03".repeat(16)
+        ));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "chain").unwrap();
+
+        let chain = cache.pck_cert_chain(&pck_cert_id()).unwrap();
+        assert_eq!(chain.chain, "chain");
+        assert_eq!(&chain.cpu_svn, pck_cert_id().cpu_svn());
+        assert_eq!(chain.pce_svn, pck_cert_id().pce_svn());
+    }
+
+    #[test]
+    fn pck_cert_chain_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DirectoryCache::new(dir.path());
+        let chain = PckCertChain {
+            chain: "leaf\nintermediate\nroot\n".into(),
+            cpu_svn: sgx_cpu_svn_t { svn: [2; 16] }.into(),
+            pce_svn: 13.into(),
+        };
+
+        cache.store_pck_cert_chain(&pck_cert_id(), &chain).unwrap();
+        assert_eq!(cache.pck_cert_chain(&pck_cert_id()), Ok(chain));
+    }
+
+    #[test]
+    fn invalid_tcbm_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DirectoryCache::new(dir.path());
+        cache
+            .store_pck_cert_chain(&pck_cert_id(), &PckCertChain::default())
+            .unwrap();
+        let tcbm = cache.pck_cert_path(&pck_cert_id()).with_extension("tcbm");
+        fs::write(tcbm, "0102").unwrap();
+
+        assert_eq!(
+            cache.pck_cert_chain(&pck_cert_id()),
+            Err(Error::Invalid("TCBm", "0102".into()))
+        );
+    }
+
+    #[test]
+    fn missing_pck_cert_chain_is_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DirectoryCache::new(dir.path());
+        assert!(matches!(
+            cache.pck_cert_chain(&pck_cert_id()),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn collateral_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DirectoryCache::new(dir.path());
+        let fmspc = [0, 0x90, 0x6E, 0xA1, 0, 0];
+
+        cache
+            .store_collateral(&fmspc, PckCa::Platform, &collateral())
+            .unwrap();
+        assert_eq!(cache.collateral(&fmspc, PckCa::Platform), Ok(collateral()));
+        assert_eq!(cache.root_ca_crl(), Ok(vec![0, 1, 2]));
+        assert!(matches!(
+            cache.collateral(&fmspc, PckCa::Processor),
+            Err(Error::NotFound(_))
+        ));
+        assert!(matches!(
+            cache.collateral(&[1; 6], PckCa::Platform),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn qve_identity_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DirectoryCache::new(dir.path());
+        let identity = EnclaveIdentity {
+            identity: "identity".into(),
+            issuer_chain: "chain".into(),
+        };
+
+        cache.store_qve_identity(&identity).unwrap();
+        assert_eq!(cache.qve_identity(), Ok(identity));
+    }
+
+    /// Serves fixed values, counting the requests
+    #[derive(Debug, Default)]
+    struct CountingSource {
+        requests: AtomicUsize,
+    }
+
+    impl CollateralSource for CountingSource {
+        fn pck_cert_chain(&self, _id: &PckCertId) -> Result<PckCertChain, Error> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            Ok(PckCertChain {
+                chain: "fetched".into(),
+                ..Default::default()
+            })
+        }
+
+        fn collateral(&self, _fmspc: &[u8], _ca: PckCa) -> Result<Collateral, Error> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            Ok(collateral())
+        }
+
+        fn qve_identity(&self) -> Result<EnclaveIdentity, Error> {
+            Err(Error::Network("unreachable".into()))
+        }
+
+        fn root_ca_crl(&self) -> Result<Vec<u8>, Error> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![9])
+        }
+    }
+
+    #[test]
+    fn caching_source_fetches_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = CachingSource::new(DirectoryCache::new(dir.path()), CountingSource::default());
+
+        for _ in 0..3 {
+            assert_eq!(
+                source.pck_cert_chain(&pck_cert_id()).unwrap().chain,
+                "fetched"
+            );
+            assert_eq!(
+                source.collateral(&[1; 6], PckCa::Processor),
+                Ok(collateral())
+            );
+        }
+        assert_eq!(source.source.requests.load(Ordering::SeqCst), 2);
+        // The root CA CRL was cached along with the collateral
+        assert_eq!(source.root_ca_crl(), Ok(vec![0, 1, 2]));
+        assert_eq!(source.source.requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn caching_source_returns_source_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = CachingSource::new(DirectoryCache::new(dir.path()), CountingSource::default());
+        assert_eq!(
+            source.qve_identity(),
+            Err(Error::Network("unreachable".into()))
+        );
+    }
+}
@@ -0,0 +1,577 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! The quote provider library functions DCAP loads from the library
+
+use crate::{source_from_env, CollateralSource, Error, PckCa, PckCertId};
+use core::{mem, slice};
+use mc_sgx_core_types::CpuSvn;
+use mc_sgx_dcap_sys_types::{
+    quote3_error_t, sgx_ql_config_t, sgx_ql_config_version_t, sgx_ql_log_level_t,
+    sgx_ql_logging_callback_t, sgx_ql_pck_cert_id_t, sgx_ql_qve_collateral_t,
+};
+use mc_sgx_dcap_types::QlError;
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    sync::Mutex,
+};
+
+/// The version of the collateral, 3.1 has the CRLs DER encoded
+const COLLATERAL_VERSION: u32 = 3 | (1 << 16);
+
+/// The source of the certificates and collateral, chosen when first needed
+static SOURCE: Lazy<Box<dyn CollateralSource>> = Lazy::new(source_from_env);
+
+/// The logging callback DCAP registered, and the most verbose level to log
+static LOGGER: Lazy<Mutex<(sgx_ql_logging_callback_t, sgx_ql_log_level_t)>> =
+    Lazy::new(|| Mutex::new((None, sgx_ql_log_level_t::SGX_QL_LOG_ERROR)));
+
+/// The buffers handed to DCAP as bare pointers, keyed by address, until DCAP
+/// frees them
+static BUFFERS: Lazy<Mutex<HashMap<usize, Box<[u8]>>>> = Lazy::new(Default::default);
+
+/// A quote config along with the certificate data it points to
+///
+/// The config is first so a pointer to it is a pointer to this.
+#[repr(C)]
+struct QuoteConfig {
+    config: sgx_ql_config_t,
+    _cert_data: Box<[u8]>,
+}
+
+/// Quote collateral along with the data it points to
+///
+/// The collateral is first so a pointer to it is a pointer to this.
+#[repr(C)]
+struct QuoteCollateral {
+    collateral: sgx_ql_qve_collateral_t,
+    _data: Vec<Box<[u8]>>,
+}
+
+/// Get the PCK certificate chain of the platform, to put in quotes
+///
+/// # Arguments
+/// * `p_pck_cert_id` - The identity of the platform's PCK certificate
+/// * `pp_quote_config` - Set to the config, which must be freed with
+///   [`sgx_ql_free_quote_config()`]
+///
+/// # Safety
+/// `p_pck_cert_id` must point to a valid `sgx_ql_pck_cert_id_t` whose
+/// pointers are valid for their sizes, and `pp_quote_config` must be valid
+/// to write to.
+#[no_mangle]
+pub unsafe extern "C" fn sgx_ql_get_quote_config(
+    p_pck_cert_id: *const sgx_ql_pck_cert_id_t,
+    pp_quote_config: *mut *mut sgx_ql_config_t,
+) -> quote3_error_t {
+    if pp_quote_config.is_null() {
+        return QlError::InvalidParameter.into();
+    }
+    let id = match pck_cert_id(p_pck_cert_id) {
+        Some(id) => id,
+        None => return QlError::InvalidParameter.into(),
+    };
+    let chain = match SOURCE.pck_cert_chain(&id) {
+        Ok(chain) => chain,
+        Err(e) => return fail("PCK certificate chain", &e, QlError::NoPlatformCertData),
+    };
+
+    let mut cert_data = chain.chain.into_bytes().into_boxed_slice();
+    let config = sgx_ql_config_t {
+        version: sgx_ql_config_version_t::SGX_QL_CONFIG_VERSION_1,
+        cert_cpu_svn: chain.cpu_svn.into(),
+        cert_pce_isv_svn: chain.pce_svn.into(),
+        cert_data_size: cert_data.len() as u32,
+        p_cert_data: cert_data.as_mut_ptr(),
+    };
+    let config = Box::new(QuoteConfig {
+        config,
+        _cert_data: cert_data,
+    });
+    *pp_quote_config = Box::into_raw(config).cast();
+    quote3_error_t::SGX_QL_SUCCESS
+}
+
+/// Free a config from [`sgx_ql_get_quote_config()`]
+///
+/// # Safety
+/// `p_quote_config` must be null or from [`sgx_ql_get_quote_config()`], and
+/// not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn sgx_ql_free_quote_config(
+    p_quote_config: *mut sgx_ql_config_t,
+) -> quote3_error_t {
+    if !p_quote_config.is_null() {
+        drop(Box::from_raw(p_quote_config.cast::<QuoteConfig>()));
+    }
+    quote3_error_t::SGX_QL_SUCCESS
+}
+
+/// Get the collateral to verify quotes from platforms with `fmspc`
+///
+/// # Arguments
+/// * `fmspc` - The FMSPC of the platforms
+/// * `fmspc_size` - The size of `fmspc`
+/// * `pck_ca` - The CA which issued the PCK certificates, `processor` or
+///   `platform`
+/// * `pp_quote_collateral` - Set to the collateral, which must be freed with
+///   [`sgx_ql_free_quote_verification_collateral()`]
+///
+/// # Safety
+/// `fmspc` must be valid for `fmspc_size` bytes, `pck_ca` must be a NUL
+/// terminated string, and `pp_quote_collateral` must be valid to write to.
+#[no_mangle]
+pub unsafe extern "C" fn sgx_ql_get_quote_verification_collateral(
+    fmspc: *const u8,
+    fmspc_size: u16,
+    pck_ca: *const c_char,
+    pp_quote_collateral: *mut *mut sgx_ql_qve_collateral_t,
+) -> quote3_error_t {
+    if fmspc.is_null() || pck_ca.is_null() || pp_quote_collateral.is_null() {
+        return QlError::InvalidParameter.into();
+    }
+    let fmspc = slice::from_raw_parts(fmspc, fmspc_size.into());
+    let ca = match CStr::from_ptr(pck_ca)
+        .to_str()
+        .map_err(|_| Error::Invalid("PCK CA", "not UTF-8".into()))
+        .and_then(PckCa::try_from)
+    {
+        Ok(ca) => ca,
+        Err(_) => return QlError::InvalidParameter.into(),
+    };
+    let collateral = match SOURCE.collateral(fmspc, ca) {
+        Ok(collateral) => collateral,
+        Err(e) => return fail("collateral", &e, QlError::NoQuoteCollateralData),
+    };
+
+    let mut data = vec![
+        nul_terminated(collateral.pck_crl_issuer_chain),
+        collateral.root_ca_crl.into_boxed_slice(),
+        collateral.pck_crl.into_boxed_slice(),
+        nul_terminated(collateral.tcb_info_issuer_chain),
+        nul_terminated(collateral.tcb_info),
+        nul_terminated(collateral.qe_identity_issuer_chain),
+        nul_terminated(collateral.qe_identity),
+    ];
+    // Zeroed so the TEE type, when the struct has one, is SGX
+    let mut ffi_collateral: sgx_ql_qve_collateral_t = mem::zeroed();
+    ffi_collateral.__bindgen_anon_1.version = COLLATERAL_VERSION;
+    (
+        ffi_collateral.pck_crl_issuer_chain,
+        ffi_collateral.pck_crl_issuer_chain_size,
+    ) = as_ffi(&mut data[0]);
+    (ffi_collateral.root_ca_crl, ffi_collateral.root_ca_crl_size) = as_ffi(&mut data[1]);
+    (ffi_collateral.pck_crl, ffi_collateral.pck_crl_size) = as_ffi(&mut data[2]);
+    (
+        ffi_collateral.tcb_info_issuer_chain,
+        ffi_collateral.tcb_info_issuer_chain_size,
+    ) = as_ffi(&mut data[3]);
+    (ffi_collateral.tcb_info, ffi_collateral.tcb_info_size) = as_ffi(&mut data[4]);
+    (
+        ffi_collateral.qe_identity_issuer_chain,
+        ffi_collateral.qe_identity_issuer_chain_size,
+    ) = as_ffi(&mut data[5]);
+    (ffi_collateral.qe_identity, ffi_collateral.qe_identity_size) = as_ffi(&mut data[6]);
+
+    let collateral = Box::new(QuoteCollateral {
+        collateral: ffi_collateral,
+        _data: data,
+    });
+    *pp_quote_collateral = Box::into_raw(collateral).cast();
+    quote3_error_t::SGX_QL_SUCCESS
+}
+
+/// Free collateral from [`sgx_ql_get_quote_verification_collateral()`]
+///
+/// # Safety
+/// `p_quote_collateral` must be null or from
+/// [`sgx_ql_get_quote_verification_collateral()`], and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn sgx_ql_free_quote_verification_collateral(
+    p_quote_collateral: *mut sgx_ql_qve_collateral_t,
+) -> quote3_error_t {
+    if !p_quote_collateral.is_null() {
+        drop(Box::from_raw(p_quote_collateral.cast::<QuoteCollateral>()));
+    }
+    quote3_error_t::SGX_QL_SUCCESS
+}
+
+/// Get the identity of the quote verification enclave (QvE)
+///
+/// # Arguments
+/// * `pp_qve_identity` - Set to the NUL terminated identity JSON
+/// * `p_qve_identity_size` - Set to the size of the identity, including the
+///   NUL
+/// * `pp_qve_identity_issuer_chain` - Set to the NUL terminated issuer chain
+/// * `p_qve_identity_issuer_chain_size` - Set to the size of the issuer
+///   chain, including the NUL
+///
+/// Both strings must be freed with [`sgx_ql_free_qve_identity()`].
+///
+/// # Safety
+/// All the pointers must be valid to write to.
+#[no_mangle]
+pub unsafe extern "C" fn sgx_ql_get_qve_identity(
+    pp_qve_identity: *mut *mut c_char,
+    p_qve_identity_size: *mut u32,
+    pp_qve_identity_issuer_chain: *mut *mut c_char,
+    p_qve_identity_issuer_chain_size: *mut u32,
+) -> quote3_error_t {
+    if pp_qve_identity.is_null()
+        || p_qve_identity_size.is_null()
+        || pp_qve_identity_issuer_chain.is_null()
+        || p_qve_identity_issuer_chain_size.is_null()
+    {
+        return QlError::InvalidParameter.into();
+    }
+    let identity = match SOURCE.qve_identity() {
+        Ok(identity) => identity,
+        Err(e) => return fail("QvE identity", &e, QlError::NoQuoteCollateralData),
+    };
+
+    let (issuer_chain, issuer_chain_size) = lend(nul_terminated(identity.issuer_chain));
+    let (identity, identity_size) = lend(nul_terminated(identity.identity));
+    *pp_qve_identity = identity.cast();
+    *p_qve_identity_size = identity_size as u32;
+    *pp_qve_identity_issuer_chain = issuer_chain.cast();
+    *p_qve_identity_issuer_chain_size = issuer_chain_size as u32;
+    quote3_error_t::SGX_QL_SUCCESS
+}
+
+/// Free the strings from [`sgx_ql_get_qve_identity()`]
+///
+/// # Safety
+/// The pointers must be null or from [`sgx_ql_get_qve_identity()`], and not
+/// already freed.
+#[no_mangle]
+pub unsafe extern "C" fn sgx_ql_free_qve_identity(
+    p_qve_identity: *mut c_char,
+    p_qve_identity_issuer_chain: *mut c_char,
+) -> quote3_error_t {
+    reclaim(p_qve_identity.cast());
+    reclaim(p_qve_identity_issuer_chain.cast());
+    quote3_error_t::SGX_QL_SUCCESS
+}
+
+/// Get the DER encoded CRL of the Intel SGX root CA
+///
+/// # Arguments
+/// * `pp_root_ca_crl` - Set to the CRL, which must be freed with
+///   [`sgx_ql_free_root_ca_crl()`]
+/// * `p_root_ca_crl_size` - Set to the size of the CRL
+///
+/// # Safety
+/// The pointers must be valid to write to.
+#[no_mangle]
+pub unsafe extern "C" fn sgx_ql_get_root_ca_crl(
+    pp_root_ca_crl: *mut *mut u8,
+    p_root_ca_crl_size: *mut u16,
+) -> quote3_error_t {
+    if pp_root_ca_crl.is_null() || p_root_ca_crl_size.is_null() {
+        return QlError::InvalidParameter.into();
+    }
+    let crl = match SOURCE.root_ca_crl() {
+        Ok(crl) => crl,
+        Err(e) => return fail("root CA CRL", &e, QlError::NoQuoteCollateralData),
+    };
+    let size = match u16::try_from(crl.len()) {
+        Ok(size) => size,
+        Err(_) => {
+            let e = Error::Invalid("root CA CRL", format!("{} bytes is too large", crl.len()));
+            return fail("root CA CRL", &e, QlError::NoQuoteCollateralData);
+        }
+    };
+
+    let (crl, _) = lend(crl.into_boxed_slice());
+    *pp_root_ca_crl = crl;
+    *p_root_ca_crl_size = size;
+    quote3_error_t::SGX_QL_SUCCESS
+}
+
+/// Free the CRL from [`sgx_ql_get_root_ca_crl()`]
+///
+/// # Safety
+/// `p_root_ca_crl` must be null or from [`sgx_ql_get_root_ca_crl()`], and
+/// not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn sgx_ql_free_root_ca_crl(p_root_ca_crl: *mut u8) -> quote3_error_t {
+    reclaim(p_root_ca_crl);
+    quote3_error_t::SGX_QL_SUCCESS
+}
+
+/// Set the callback to log to
+///
+/// # Arguments
+/// * `logger` - The callback, `None` to stop logging
+/// * `loglevel` - The most verbose level to log
+///
+/// # Safety
+/// `logger` must be safe to call from any thread until it's replaced.
+#[no_mangle]
+pub unsafe extern "C" fn sgx_ql_set_logging_callback(
+    logger: sgx_ql_logging_callback_t,
+    loglevel: sgx_ql_log_level_t,
+) -> quote3_error_t {
+    match LOGGER.lock() {
+        Ok(mut current) => {
+            *current = (logger, loglevel);
+            quote3_error_t::SGX_QL_SUCCESS
+        }
+        Err(_) => QlError::Unexpected.into(),
+    }
+}
+
+/// Convert the PCK certificate ID from DCAP
+///
+/// Returns `None` if a required pointer is null.
+///
+/// # Safety
+/// The pointers of `id` must be null or valid for their sizes.
+unsafe fn pck_cert_id(id: *const sgx_ql_pck_cert_id_t) -> Option<PckCertId> {
+    let id = id.as_ref()?;
+    if id.p_qe3_id.is_null() {
+        return None;
+    }
+    let qe_id = slice::from_raw_parts(id.p_qe3_id, id.qe3_id_size as usize);
+    let cpu_svn = id.p_platform_cpu_svn.as_ref()?;
+    let pce_svn = id.p_platform_pce_isv_svn.as_ref()?;
+    let encrypted_ppid = if id.p_encrypted_ppid.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(id.p_encrypted_ppid, id.encrypted_ppid_size as usize).to_vec())
+    };
+    Some(PckCertId::new(
+        qe_id,
+        CpuSvn::from(*cpu_svn),
+        (*pce_svn).into(),
+        id.pce_id,
+        encrypted_ppid,
+    ))
+}
+
+/// Log the failure to get `what`, and return the error for DCAP
+fn fail(what: &str, error: &Error, not_found: QlError) -> quote3_error_t {
+    log(
+        sgx_ql_log_level_t::SGX_QL_LOG_ERROR,
+        &format!("Failed to get the {what}: {error}"),
+    );
+    error.ql_error(not_found).into()
+}
+
+/// Log `message` with the callback DCAP registered
+fn log(level: sgx_ql_log_level_t, message: &str) {
+    let (logger, max_level) = match LOGGER.lock() {
+        Ok(logger) => *logger,
+        Err(_) => return,
+    };
+    if let (Some(logger), true) = (logger, level.0 <= max_level.0) {
+        // Messages are formatted here, and so don't have NULs
+        if let Ok(message) = CString::new(message) {
+            unsafe { logger(level, message.as_ptr()) };
+        }
+    }
+}
+
+/// The pointer and size of `buffer`, as the collateral holds them
+fn as_ffi(buffer: &mut [u8]) -> (*mut c_char, u32) {
+    (buffer.as_mut_ptr().cast(), buffer.len() as u32)
+}
+
+/// `contents` as a NUL terminated string
+fn nul_terminated(contents: String) -> Box<[u8]> {
+    let mut bytes = contents.into_bytes();
+    bytes.push(0);
+    bytes.into_boxed_slice()
+}
+
+/// Hand `buffer` to DCAP, keeping it until [`reclaim()`]
+///
+/// Returns the pointer to the buffer and its size.
+fn lend(mut buffer: Box<[u8]>) -> (*mut u8, usize) {
+    let (pointer, size) = (buffer.as_mut_ptr(), buffer.len());
+    BUFFERS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(pointer as usize, buffer);
+    (pointer, size)
+}
+
+/// Free a buffer from [`lend()`], ignoring null and unknown pointers
+fn reclaim(pointer: *mut u8) {
+    BUFFERS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(&(pointer as usize));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Collateral, DirectoryCache, PckCertChain, CACHE_DIR_VAR};
+    use core::ptr;
+    use mc_sgx_core_sys_types::sgx_cpu_svn_t;
+    use std::env;
+
+    #[test]
+    fn pck_cert_id_from_ffi() {
+        let qe_id = [7u8; 16];
+        let cpu_svn = sgx_cpu_svn_t { svn: [2; 16] };
+        let pce_svn = 11;
+        let ffi_id = sgx_ql_pck_cert_id_t {
+            p_qe3_id: qe_id.as_ptr() as *mut u8,
+            qe3_id_size: qe_id.len() as u32,
+            p_platform_cpu_svn: &cpu_svn as *const _ as *mut _,
+            p_platform_pce_isv_svn: &pce_svn as *const _ as *mut _,
+            p_encrypted_ppid: ptr::null_mut(),
+            encrypted_ppid_size: 0,
+            crypto_suite: 1,
+            pce_id: 3,
+        };
+
+        let id = unsafe { pck_cert_id(&ffi_id) }.unwrap();
+        assert_eq!(
+            id,
+            PckCertId::new(qe_id, cpu_svn.into(), pce_svn.into(), 3, None)
+        );
+    }
+
+    #[test]
+    fn pck_cert_id_without_qe_id_is_none() {
+        let cpu_svn = sgx_cpu_svn_t::default();
+        let pce_svn = 0;
+        let ffi_id = sgx_ql_pck_cert_id_t {
+            p_qe3_id: ptr::null_mut(),
+            qe3_id_size: 16,
+            p_platform_cpu_svn: &cpu_svn as *const _ as *mut _,
+            p_platform_pce_isv_svn: &pce_svn as *const _ as *mut _,
+            p_encrypted_ppid: ptr::null_mut(),
+            encrypted_ppid_size: 0,
+            crypto_suite: 1,
+            pce_id: 0,
+        };
+
+        assert_eq!(unsafe { pck_cert_id(&ffi_id) }, None);
+        assert_eq!(unsafe { pck_cert_id(ptr::null()) }, None);
+    }
+
+    #[test]
+    fn null_out_pointers_are_invalid() {
+        let error = unsafe { sgx_ql_get_root_ca_crl(ptr::null_mut(), ptr::null_mut()) };
+        assert_eq!(error, quote3_error_t::from(QlError::InvalidParameter));
+
+        let error = unsafe { sgx_ql_get_quote_config(ptr::null(), ptr::null_mut()) };
+        assert_eq!(error, quote3_error_t::from(QlError::InvalidParameter));
+    }
+
+    #[test]
+    fn lent_buffers_are_reclaimed() {
+        let (pointer, size) = lend(nul_terminated("identity".into()));
+        assert_eq!(size, 9);
+        assert_eq!(
+            unsafe { CStr::from_ptr(pointer.cast()) }.to_str(),
+            Ok("identity")
+        );
+
+        reclaim(pointer);
+        assert!(!BUFFERS.lock().unwrap().contains_key(&(pointer as usize)));
+        // Freeing again, or freeing null, is harmless
+        reclaim(pointer);
+        reclaim(ptr::null_mut());
+    }
+
+    /// The only test using [`SOURCE`], which is fixed on first use
+    #[test]
+    fn served_from_the_cache_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        env::set_var(CACHE_DIR_VAR, dir.path());
+        let cache = DirectoryCache::new(dir.path());
+        let qe_id = [1u8; 16];
+        let cpu_svn = sgx_cpu_svn_t { svn: [4; 16] };
+        let pce_svn = 12;
+        let id = PckCertId::new(qe_id, cpu_svn.into(), pce_svn.into(), 0, None);
+        let chain = PckCertChain {
+            chain: "leaf\nroot\n".into(),
+            cpu_svn: sgx_cpu_svn_t { svn: [3; 16] }.into(),
+            pce_svn: 11.into(),
+        };
+        cache.store_pck_cert_chain(&id, &chain).unwrap();
+        let fmspc = [0, 0x90, 0x6E, 0xA1, 0, 0];
+        let collateral = Collateral {
+            root_ca_crl: vec![0x30, 0, 1],
+            tcb_info: "{\"tcbInfo\"}".into(),
+            ..Default::default()
+        };
+        cache
+            .store_collateral(&fmspc, PckCa::Processor, &collateral)
+            .unwrap();
+
+        let ffi_id = sgx_ql_pck_cert_id_t {
+            p_qe3_id: qe_id.as_ptr() as *mut u8,
+            qe3_id_size: qe_id.len() as u32,
+            p_platform_cpu_svn: &cpu_svn as *const _ as *mut _,
+            p_platform_pce_isv_svn: &pce_svn as *const _ as *mut _,
+            p_encrypted_ppid: ptr::null_mut(),
+            encrypted_ppid_size: 0,
+            crypto_suite: 1,
+            pce_id: 0,
+        };
+        let mut config = ptr::null_mut();
+        let error = unsafe { sgx_ql_get_quote_config(&ffi_id, &mut config) };
+        assert_eq!(error, quote3_error_t::SGX_QL_SUCCESS);
+        let config_ref = unsafe { &*config };
+        assert_eq!(config_ref.cert_cpu_svn, sgx_cpu_svn_t { svn: [3; 16] });
+        assert_eq!(config_ref.cert_pce_isv_svn, 11);
+        let cert_data = unsafe {
+            slice::from_raw_parts(config_ref.p_cert_data, config_ref.cert_data_size as usize)
+        };
+        assert_eq!(cert_data, b"leaf\nroot\n");
+        unsafe { sgx_ql_free_quote_config(config) };
+
+        let mut ffi_collateral = ptr::null_mut();
+        let error = unsafe {
+            sgx_ql_get_quote_verification_collateral(
+                fmspc.as_ptr(),
+                fmspc.len() as u16,
+                b"processor\0".as_ptr().cast(),
+                &mut ffi_collateral,
+            )
+        };
+        assert_eq!(error, quote3_error_t::SGX_QL_SUCCESS);
+        let collateral_ref = unsafe { &*ffi_collateral };
+        assert_eq!(
+            unsafe { collateral_ref.__bindgen_anon_1.version },
+            COLLATERAL_VERSION
+        );
+        assert_eq!(collateral_ref.root_ca_crl_size, 3);
+        assert_eq!(
+            unsafe { CStr::from_ptr(collateral_ref.tcb_info) }.to_str(),
+            Ok("{\"tcbInfo\"}")
+        );
+        assert_eq!(collateral_ref.tcb_info_size, 12);
+        unsafe { sgx_ql_free_quote_verification_collateral(ffi_collateral) };
+
+        let mut crl = ptr::null_mut();
+        let mut crl_size = 0;
+        let error = unsafe { sgx_ql_get_root_ca_crl(&mut crl, &mut crl_size) };
+        assert_eq!(error, quote3_error_t::SGX_QL_SUCCESS);
+        assert_eq!(
+            unsafe { slice::from_raw_parts(crl, crl_size.into()) },
+            [0x30, 0, 1]
+        );
+        unsafe { sgx_ql_free_root_ca_crl(crl) };
+
+        let (mut identity, mut identity_size) = (ptr::null_mut(), 0);
+        let (mut issuer_chain, mut issuer_chain_size) = (ptr::null_mut(), 0);
+        let error = unsafe {
+            sgx_ql_get_qve_identity(
+                &mut identity,
+                &mut identity_size,
+                &mut issuer_chain,
+                &mut issuer_chain_size,
+            )
+        };
+        assert_eq!(error, quote3_error_t::from(QlError::NoQuoteCollateralData));
+    }
+}
@@ -0,0 +1,316 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+#![doc = include_str!("../README.md")]
+#![deny(missing_docs, missing_debug_implementations)]
+
+mod directory;
+mod ffi;
+#[cfg(feature = "pccs")]
+mod pccs;
+
+pub use directory::{CachingSource, DirectoryCache};
+pub use ffi::{
+    sgx_ql_free_quote_config, sgx_ql_free_quote_verification_collateral, sgx_ql_free_qve_identity,
+    sgx_ql_free_root_ca_crl, sgx_ql_get_quote_config, sgx_ql_get_quote_verification_collateral,
+    sgx_ql_get_qve_identity, sgx_ql_get_root_ca_crl, sgx_ql_set_logging_callback,
+};
+#[cfg(feature = "pccs")]
+pub use pccs::PccsClient;
+
+use core::fmt::Debug;
+use mc_sgx_core_types::{CpuSvn, IsvSvn};
+use mc_sgx_dcap_types::QlError;
+use std::{env, path::PathBuf};
+
+/// The environment variable with the directory of cached collateral
+pub const CACHE_DIR_VAR: &str = "MC_SGX_QPL_CACHE_DIR";
+
+/// The directory of cached collateral when [`CACHE_DIR_VAR`] isn't set
+pub const DEFAULT_CACHE_DIR: &str = "/var/cache/mc-sgx-qpl";
+
+/// The environment variable with the URL of the PCCS to fetch collateral
+/// missing from the cache
+#[cfg(feature = "pccs")]
+pub const PCCS_URL_VAR: &str = "MC_SGX_QPL_PCCS_URL";
+
+/// Errors getting PCK certificates and collateral
+#[derive(Clone, Debug, displaydoc::Display, Eq, Hash, PartialEq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum Error {
+    /// There is no {0}
+    NotFound(String),
+    /// Failed to access {0}: {1}
+    Io(String, String),
+    /// The {0} is invalid: {1}
+    Invalid(&'static str, String),
+    /// Request to the PCCS failed: {0}
+    Network(String),
+}
+
+impl Error {
+    /// The quote library error to return to DCAP
+    ///
+    /// # Arguments
+    /// * `not_found` - The error for [`Error::NotFound`], which depends on
+    ///   what was requested.
+    pub(crate) fn ql_error(&self, not_found: QlError) -> QlError {
+        match self {
+            Self::NotFound(_) => not_found,
+            Self::Io(_, _) => QlError::FileAccess,
+            Self::Invalid(_, _) => QlError::Message,
+            Self::Network(_) => QlError::Network,
+        }
+    }
+}
+
+/// The identity of a platform's PCK certificate, as DCAP requests it
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct PckCertId {
+    qe_id: Vec<u8>,
+    cpu_svn: CpuSvn,
+    pce_svn: IsvSvn,
+    pce_id: u16,
+    encrypted_ppid: Option<Vec<u8>>,
+}
+
+impl PckCertId {
+    /// A new PCK certificate ID
+    ///
+    /// # Arguments
+    /// * `qe_id` - The ID of the quoting enclave
+    /// * `cpu_svn` - The raw CPU SVN of the platform
+    /// * `pce_svn` - The ISV SVN of the provisioning certification enclave
+    /// * `pce_id` - The ID of the provisioning certification enclave
+    /// * `encrypted_ppid` - The PPID encrypted with RSA-3072 OAEP, needed to
+    ///   fetch the certificate from Intel's PCS
+    pub fn new(
+        qe_id: impl Into<Vec<u8>>,
+        cpu_svn: CpuSvn,
+        pce_svn: IsvSvn,
+        pce_id: u16,
+        encrypted_ppid: Option<Vec<u8>>,
+    ) -> Self {
+        Self {
+            qe_id: qe_id.into(),
+            cpu_svn,
+            pce_svn,
+            pce_id,
+            encrypted_ppid,
+        }
+    }
+
+    /// The ID of the quoting enclave
+    pub fn qe_id(&self) -> &[u8] {
+        &self.qe_id
+    }
+
+    /// The raw CPU SVN of the platform
+    pub fn cpu_svn(&self) -> &CpuSvn {
+        &self.cpu_svn
+    }
+
+    /// The ISV SVN of the provisioning certification enclave
+    pub fn pce_svn(&self) -> IsvSvn {
+        self.pce_svn
+    }
+
+    /// The ID of the provisioning certification enclave
+    pub fn pce_id(&self) -> u16 {
+        self.pce_id
+    }
+
+    /// The encrypted PPID, when DCAP provided one
+    pub fn encrypted_ppid(&self) -> Option<&[u8]> {
+        self.encrypted_ppid.as_deref()
+    }
+
+    /// The hex encoded fields identifying the certificate
+    ///
+    /// The PCE ID and SVN are encoded as little endian bytes, as the PCS
+    /// API expects.
+    pub(crate) fn hex_fields(&self) -> [String; 4] {
+        [
+            hex::encode_upper(&self.qe_id),
+            hex::encode_upper(self.pce_id.to_le_bytes()),
+            hex::encode_upper(&self.cpu_svn),
+            hex::encode_upper(u16::from(self.pce_svn).to_le_bytes()),
+        ]
+    }
+}
+
+/// A PCK certificate along with its issuer chain, and the TCB it was issued
+/// for
+///
+/// The TCB of the certificate, the TCBm, can be lower than the raw TCB of the
+/// platform when the platform is ahead of the latest TCB recovery.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct PckCertChain {
+    /// The PEM encoded PCK certificate followed by its issuer chain
+    pub chain: String,
+    /// The CPU SVN the certificate was issued for
+    pub cpu_svn: CpuSvn,
+    /// The PCE SVN the certificate was issued for
+    pub pce_svn: IsvSvn,
+}
+
+/// The CA which issued a PCK certificate
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub enum PckCa {
+    /// The processor CA, for single-package platforms
+    Processor,
+    /// The platform CA, for multi-package platforms
+    Platform,
+}
+
+impl PckCa {
+    /// The name of the CA, as used by the PCS API and DCAP
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Processor => "processor",
+            Self::Platform => "platform",
+        }
+    }
+}
+
+impl TryFrom<&str> for PckCa {
+    type Error = Error;
+
+    fn try_from(name: &str) -> Result<Self, Error> {
+        match name {
+            "processor" => Ok(Self::Processor),
+            "platform" => Ok(Self::Platform),
+            name => Err(Error::Invalid("PCK CA", name.to_owned())),
+        }
+    }
+}
+
+/// The collateral to verify quotes from platforms with one FMSPC
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Collateral {
+    /// The PEM encoded issuer chain of the PCK CRL
+    pub pck_crl_issuer_chain: String,
+    /// The DER encoded CRL of the Intel SGX root CA
+    pub root_ca_crl: Vec<u8>,
+    /// The DER encoded CRL of the PCK CA
+    pub pck_crl: Vec<u8>,
+    /// The PEM encoded issuer chain of the TCB info
+    pub tcb_info_issuer_chain: String,
+    /// The signed TCB info JSON
+    pub tcb_info: String,
+    /// The PEM encoded issuer chain of the QE identity
+    pub qe_identity_issuer_chain: String,
+    /// The signed QE identity JSON
+    pub qe_identity: String,
+}
+
+/// The signed identity of an enclave, along with its issuer chain
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct EnclaveIdentity {
+    /// The signed enclave identity JSON
+    pub identity: String,
+    /// The PEM encoded issuer chain of the identity
+    pub issuer_chain: String,
+}
+
+/// Where the quote provider library gets PCK certificates and collateral
+pub trait CollateralSource: Debug + Send + Sync {
+    /// The PCK certificate chain of a platform
+    ///
+    /// # Arguments
+    /// * `id` - The identity of the platform's PCK certificate
+    ///
+    /// # Errors
+    /// [`Error::NotFound`] if the source has no certificate for the platform.
+    fn pck_cert_chain(&self, id: &PckCertId) -> Result<PckCertChain, Error>;
+
+    /// The collateral to verify quotes of platforms with `fmspc`
+    ///
+    /// # Arguments
+    /// * `fmspc` - The FMSPC of the platforms, from their PCK certificates
+    /// * `ca` - The CA which issued the PCK certificates
+    ///
+    /// # Errors
+    /// [`Error::NotFound`] if the source has no collateral for `fmspc`.
+    fn collateral(&self, fmspc: &[u8], ca: PckCa) -> Result<Collateral, Error>;
+
+    /// The identity of the quote verification enclave (QvE)
+    ///
+    /// # Errors
+    /// [`Error::NotFound`] if the source has no QvE identity.
+    fn qve_identity(&self) -> Result<EnclaveIdentity, Error>;
+
+    /// The DER encoded CRL of the Intel SGX root CA
+    ///
+    /// # Errors
+    /// [`Error::NotFound`] if the source has no root CA CRL.
+    fn root_ca_crl(&self) -> Result<Vec<u8>, Error>;
+}
+
+/// The source the exported library functions use
+///
+/// The directory in [`CACHE_DIR_VAR`], or [`DEFAULT_CACHE_DIR`]. With the
+/// `pccs` feature, when [`PCCS_URL_VAR`] is set, collateral missing from the
+/// directory is fetched from that PCCS and cached.
+pub fn source_from_env() -> Box<dyn CollateralSource> {
+    let dir = env::var_os(CACHE_DIR_VAR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CACHE_DIR));
+    let cache = DirectoryCache::new(dir);
+
+    #[cfg(feature = "pccs")]
+    if let Ok(url) = env::var(PCCS_URL_VAR) {
+        return Box::new(CachingSource::new(cache, PccsClient::new(url)));
+    }
+
+    Box::new(cache)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mc_sgx_core_sys_types::sgx_cpu_svn_t;
+
+    #[test]
+    fn pck_cert_id_hex_fields() {
+        let id = PckCertId::new(
+            [0xAB; 16],
+            sgx_cpu_svn_t { svn: [1; 16] }.into(),
+            14.into(),
+            0x0102,
+            None,
+        );
+        assert_eq!(
+            id.hex_fields(),
+            [
+                "AB".repeat(16),
+                "0201".to_owned(),
+                "01".repeat(16),
+                "0E00".to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn pck_ca_names_round_trip() {
+        for ca in [PckCa::Processor, PckCa::Platform] {
+            assert_eq!(PckCa::try_from(ca.name()), Ok(ca));
+        }
+        assert!(matches!(
+            PckCa::try_from("intermediate"),
+            Err(Error::Invalid(_, _))
+        ));
+    }
+
+    #[test]
+    fn not_found_maps_to_the_requested_error() {
+        let error = Error::NotFound("PCK certificate".to_owned());
+        assert_eq!(
+            error.ql_error(QlError::NoPlatformCertData),
+            QlError::NoPlatformCertData
+        );
+        assert_eq!(
+            Error::Network("timeout".to_owned()).ql_error(QlError::NoPlatformCertData),
+            QlError::Network
+        );
+    }
+}
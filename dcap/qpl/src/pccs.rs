@@ -0,0 +1,337 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! PCK certificates and collateral from a provisioning certification caching
+//! service (PCCS)
+
+use crate::{Collateral, CollateralSource, EnclaveIdentity, Error, PckCa, PckCertChain, PckCertId};
+use mc_sgx_core_types::CpuSvn;
+use std::{io::Read, time::Duration};
+
+/// The path of the certification API, version 4
+const API_PATH: &str = "sgx/certification/v4";
+
+/// How long to wait for the PCCS to respond
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// PCK certificates and collateral from a PCCS, or Intel's provisioning
+/// certification service (PCS) which has the same API
+///
+/// Fetching a PCK certificate from the PCS needs the encrypted PPID, which
+/// DCAP only provides when the quote library is configured to.
+#[derive(Debug)]
+pub struct PccsClient {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl PccsClient {
+    /// A client of the PCCS at `url`
+    ///
+    /// # Arguments
+    /// * `url` - The base URL of the PCCS, like `https://localhost:8081`
+    pub fn new(url: impl Into<String>) -> Self {
+        let mut url = url.into();
+        if !url.ends_with('/') {
+            url.push('/');
+        }
+        url.push_str(API_PATH);
+        Self {
+            url,
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// The base URL of the certification API
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// GET `endpoint` with `query`
+    fn get(&self, what: &str, endpoint: &str, query: &[(&str, &str)]) -> Result<Response, Error> {
+        let mut request = self.agent.get(&format!("{}/{endpoint}", self.url));
+        for (name, value) in query {
+            request = request.query(name, value);
+        }
+        match request.call() {
+            Ok(response) => Ok(Response(response)),
+            Err(ureq::Error::Status(404, _)) => Err(Error::NotFound(what.to_owned())),
+            Err(e) => Err(Error::Network(e.to_string())),
+        }
+    }
+}
+
+impl CollateralSource for PccsClient {
+    fn pck_cert_chain(&self, id: &PckCertId) -> Result<PckCertChain, Error> {
+        let [qe_id, pce_id, cpu_svn, pce_svn] = id.hex_fields();
+        let encrypted_ppid = id.encrypted_ppid().map(hex::encode_upper);
+        let mut query = vec![
+            ("qeid", qe_id.as_str()),
+            ("cpusvn", cpu_svn.as_str()),
+            ("pcesvn", pce_svn.as_str()),
+            ("pceid", pce_id.as_str()),
+        ];
+        if let Some(encrypted_ppid) = &encrypted_ppid {
+            query.push(("encrypted_ppid", encrypted_ppid));
+        }
+        let response = self.get("PCK certificate", "pckcert", &query)?;
+
+        let issuer_chain = response.header("SGX-PCK-Certificate-Issuer-Chain")?;
+        let tcbm = response.raw_header("SGX-TCBm")?;
+        let (cpu_svn, pce_svn) = parse_tcbm(&tcbm)?;
+        let mut chain = response.text()?;
+        if !chain.ends_with('\n') {
+            chain.push('\n');
+        }
+        chain.push_str(&issuer_chain);
+
+        Ok(PckCertChain {
+            chain,
+            cpu_svn,
+            pce_svn: pce_svn.into(),
+        })
+    }
+
+    fn collateral(&self, fmspc: &[u8], ca: PckCa) -> Result<Collateral, Error> {
+        let fmspc = hex::encode_upper(fmspc);
+        let pck_crl = self.get(
+            "PCK CRL",
+            "pckcrl",
+            &[("ca", ca.name()), ("encoding", "der")],
+        )?;
+        let tcb_info = self.get("TCB info", "tcb", &[("fmspc", &fmspc)])?;
+        let qe_identity = self.get("QE identity", "qe/identity", &[])?;
+
+        // Version 4 of the API renamed the TCB info issuer chain header
+        let tcb_info_issuer_chain = tcb_info
+            .header("TCB-Info-Issuer-Chain")
+            .or_else(|_| tcb_info.header("SGX-TCB-Info-Issuer-Chain"))?;
+        Ok(Collateral {
+            pck_crl_issuer_chain: pck_crl.header("SGX-PCK-CRL-Issuer-Chain")?,
+            root_ca_crl: self.root_ca_crl()?,
+            pck_crl: pck_crl.bytes()?,
+            tcb_info_issuer_chain,
+            tcb_info: tcb_info.text()?,
+            qe_identity_issuer_chain: qe_identity.header("SGX-Enclave-Identity-Issuer-Chain")?,
+            qe_identity: qe_identity.text()?,
+        })
+    }
+
+    fn qve_identity(&self) -> Result<EnclaveIdentity, Error> {
+        let response = self.get("QvE identity", "qve/identity", &[])?;
+        Ok(EnclaveIdentity {
+            issuer_chain: response.header("SGX-Enclave-Identity-Issuer-Chain")?,
+            identity: response.text()?,
+        })
+    }
+
+    fn root_ca_crl(&self) -> Result<Vec<u8>, Error> {
+        let crl = self.get("root CA CRL", "rootcacrl", &[])?.bytes()?;
+        // Some versions of the PCCS hex encode the CRL
+        Ok(hex::decode(&crl).unwrap_or(crl))
+    }
+}
+
+/// A successful response from the PCCS
+struct Response(ureq::Response);
+
+impl Response {
+    /// The header `name` as it was sent
+    fn raw_header(&self, name: &str) -> Result<String, Error> {
+        self.0
+            .header(name)
+            .map(str::to_owned)
+            .ok_or_else(|| Error::NotFound(format!("{name} header")))
+    }
+
+    /// The percent encoded header `name`, decoded
+    fn header(&self, name: &str) -> Result<String, Error> {
+        percent_decode(&self.raw_header(name)?)
+    }
+
+    fn text(self) -> Result<String, Error> {
+        String::from_utf8(self.bytes()?)
+            .map_err(|_| Error::Invalid("PCCS response", "not UTF-8".to_owned()))
+    }
+
+    fn bytes(self) -> Result<Vec<u8>, Error> {
+        let mut body = vec![];
+        self.0
+            .into_reader()
+            .read_to_end(&mut body)
+            .map_err(|e| Error::Network(e.to_string()))?;
+        Ok(body)
+    }
+}
+
+/// Parse the hex encoded TCBm, the CPU SVN followed by the little endian PCE
+/// SVN
+fn parse_tcbm(tcbm: &str) -> Result<(CpuSvn, u16), Error> {
+    let bytes = hex::decode(tcbm)
+        .ok()
+        .filter(|bytes| bytes.len() == CpuSvn::SIZE + 2)
+        .ok_or_else(|| Error::Invalid("TCBm", tcbm.to_owned()))?;
+    let mut cpu_svn = [0; CpuSvn::SIZE];
+    cpu_svn.copy_from_slice(&bytes[..CpuSvn::SIZE]);
+    let pce_svn = u16::from_le_bytes([bytes[CpuSvn::SIZE], bytes[CpuSvn::SIZE + 1]]);
+    Ok((cpu_svn.into(), pce_svn))
+}
+
+/// Decode the `%XX` escapes of a header
+fn percent_decode(encoded: &str) -> Result<String, Error> {
+    let invalid = || Error::Invalid("percent encoded header", encoded.to_owned());
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let escape = [
+                bytes.next().ok_or_else(invalid)?,
+                bytes.next().ok_or_else(invalid)?,
+            ];
+            let escape = core::str::from_utf8(&escape).map_err(|_| invalid())?;
+            decoded.push(u8::from_str_radix(escape, 16).map_err(|_| invalid())?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use yare::parameterized;
+
+    #[parameterized(
+        plain = { "abc", "abc" },
+        pem = {
+            "-----BEGIN%20CERTIFICATE-----%0AMIIC%2Bz%3D%3D%0A-----END%20CERTIFICATE-----%0A",
+            "-----BEGIN CERTIFICATE-----\nMIIC+z==\n-----END CERTIFICATE-----\n"
+        },
+        lower_case = { "%2b%2F", "+/" },
+    )]
+    fn percent_decoding(encoded: &str, decoded: &str) {
+        assert_eq!(percent_decode(encoded), Ok(decoded.to_owned()));
+    }
+
+    #[parameterized(
+        truncated = { "abc%2" },
+        not_hex = { "%ZZ" },
+    )]
+    fn invalid_percent_encoding(encoded: &str) {
+        assert!(matches!(percent_decode(encoded), Err(Error::Invalid(_, _))));
+    }
+
+    #[test]
+    fn tcbm_is_cpu_svn_and_pce_svn() {
+        let tcbm = format!("{}0D00", "0F".repeat(16));
+        let (cpu_svn, pce_svn) = parse_tcbm(&tcbm).unwrap();
+        assert_eq!(cpu_svn, CpuSvn::from([0x0F; CpuSvn::SIZE]));
+        assert_eq!(pce_svn, 13);
+
+        assert!(matches!(parse_tcbm("0F0F"), Err(Error::Invalid(_, _))));
+    }
+
+    #[test]
+    fn url_has_the_api_path() {
+        assert_eq!(
+            PccsClient::new("https://localhost:8081").url(),
+            "https://localhost:8081/sgx/certification/v4"
+        );
+        assert_eq!(
+            PccsClient::new("https://localhost:8081/").url(),
+            "https://localhost:8081/sgx/certification/v4"
+        );
+    }
+
+    /// Serve one HTTP request with `response`, returning the URL to request
+    /// and a handle to the request line the server received
+    fn serve_once(response: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            request_line
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn pck_cert_chain_from_pccs() {
+        let (url, server) = serve_once(concat!(
+            "HTTP/1.1 200 OK\r\n",
+            "SGX-TCBm: 020202020202020202020202020202020900\r\n",
+            "SGX-PCK-Certificate-Issuer-Chain: intermediate%0Aroot%0A\r\n",
+            "Content-Length: 4\r\n",
+            "\r\n",
+            "leaf",
+        ));
+        let client = PccsClient::new(url);
+        let id = PckCertId::new(
+            [0xAB; 16],
+            CpuSvn::from([3; CpuSvn::SIZE]),
+            14.into(),
+            0,
+            None,
+        );
+
+        let result = client.pck_cert_chain(&id);
+        let request_line = server.join().unwrap();
+        assert!(request_line.starts_with(&format!(
+            "GET /sgx/certification/v4/pckcert?qeid={}&cpusvn={}&pcesvn=0E00&pceid=0000 ",
+            "AB".repeat(16),
+            "03".repeat(16)
+        )));
+        assert_eq!(
+            result,
+            Ok(PckCertChain {
+                chain: "leaf\nintermediate\nroot\n".to_owned(),
+                cpu_svn: CpuSvn::from([2; CpuSvn::SIZE]),
+                pce_svn: 9.into(),
+            })
+        );
+    }
+
+    #[test]
+    fn missing_pck_cert_is_not_found() {
+        let (url, server) = serve_once("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n");
+        let client = PccsClient::new(url);
+
+        assert_eq!(
+            client.qve_identity(),
+            Err(Error::NotFound("QvE identity".to_owned()))
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn qve_identity_from_pccs() {
+        let (url, server) = serve_once(concat!(
+            "HTTP/1.1 200 OK\r\n",
+            "SGX-Enclave-Identity-Issuer-Chain: signing%0Aroot%0A\r\n",
+            "Content-Length: 19\r\n",
+            "\r\n",
+            "{\"enclaveIdentity\"}",
+        ));
+        let client = PccsClient::new(url);
+
+        assert_eq!(
+            client.qve_identity(),
+            Ok(EnclaveIdentity {
+                identity: "{\"enclaveIdentity\"}".to_owned(),
+                issuer_chain: "signing\nroot\n".to_owned(),
+            })
+        );
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! An explicit configuration of the quote verification library, owned by the
+//! caller instead of being set once for the process.
+
+use crate::{
+    quote_enclave::{c_path, validate_path},
+    Error,
+};
+use mc_sgx_dcap_quoteverify_types::PathKind;
+use mc_sgx_dcap_types::RequestPolicy;
+use mc_sgx_util::ResultInto;
+use once_cell::sync::Lazy;
+use std::{
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct Settings {
+    quote_verification_enclave: Option<PathBuf>,
+    quote_provider_library: Option<PathBuf>,
+    load_policy: RequestPolicy,
+}
+
+/// The settings last applied to the quote verification library
+///
+/// The library keeps its paths and load policy in process global state, so
+/// each context re-applies its settings when another one was used since.
+static APPLIED: Lazy<RwLock<Option<Settings>>> = Lazy::new(|| RwLock::new(None));
+
+/// Builds a [`VerificationContext`]
+///
+/// Paths which aren't set are left to the quote verification library, it will
+/// use the path a previous context set or its own default.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VerificationContextBuilder {
+    settings: Settings,
+}
+
+impl VerificationContextBuilder {
+    /// The QVE(Quote Verification Enclave) to use
+    ///
+    /// Without one the quote verification enclave embedded in the quote
+    /// verification library is used.
+    ///
+    /// # Arguments
+    /// * `path` - The full file path to the quote verification enclave
+    #[must_use]
+    pub fn quote_verification_enclave<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.settings.quote_verification_enclave = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// The QPL(Quote Provider Library) to use
+    ///
+    /// Without one the quote provider library found in the system path is
+    /// used.
+    ///
+    /// # Arguments
+    /// * `path` - The full file path to the quote provider library
+    #[must_use]
+    pub fn quote_provider_library<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.settings.quote_provider_library = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// The load policy of the quote verification enclave
+    ///
+    /// Defaults to [`RequestPolicy::Persistent`].
+    ///
+    /// # Arguments
+    /// * `policy` - The policy to use for loading the quote verification
+    ///   enclave
+    #[must_use]
+    pub fn load_policy(mut self, policy: RequestPolicy) -> Self {
+        self.settings.load_policy = policy;
+        self
+    }
+
+    /// Build the context, validating the paths
+    ///
+    /// # Errors
+    /// * [`Error::PathStringConversion`] if one of the paths contains a null
+    ///   (0) byte.
+    /// * [`Error::PathDoesNotExist`] if one of the paths does not point to a
+    ///   file.
+    /// * [`Error::PathLengthTooLong`] if one of the paths is longer than 259
+    ///   (bytes).
+    pub fn build(self) -> Result<VerificationContext, Error> {
+        let settings = self.settings;
+        for path in [
+            &settings.quote_verification_enclave,
+            &settings.quote_provider_library,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            validate_path(path)?;
+        }
+        Ok(VerificationContext { settings })
+    }
+}
+
+/// The paths and load policy the quote verification library uses
+///
+/// Calls to the quote verification library go through a context, which
+/// applies its settings to the library first. Contexts with different
+/// settings can be used in the same process.
+///
+/// ```no_run
+/// # use mc_sgx_dcap_quoteverify::VerificationContext;
+/// let context = VerificationContext::builder()
+///     .quote_provider_library("/opt/dcap/libdcap_quoteprov.so.1")
+///     .build()?;
+/// let size = context.supplemental_data_size()?;
+/// # Ok::<(), mc_sgx_dcap_quoteverify::Error>(())
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VerificationContext {
+    settings: Settings,
+}
+
+impl VerificationContext {
+    /// Start building a context
+    pub fn builder() -> VerificationContextBuilder {
+        VerificationContextBuilder::default()
+    }
+
+    /// The path to the QVE(Quote Verification Enclave), if set
+    pub fn quote_verification_enclave(&self) -> Option<&Path> {
+        self.settings.quote_verification_enclave.as_deref()
+    }
+
+    /// The path to the QPL(Quote Provider Library), if set
+    pub fn quote_provider_library(&self) -> Option<&Path> {
+        self.settings.quote_provider_library.as_deref()
+    }
+
+    /// This context with its load policy replaced by `policy`
+    pub(crate) fn with_load_policy(mut self, policy: RequestPolicy) -> Self {
+        self.settings.load_policy = policy;
+        self
+    }
+
+    /// The load policy of the quote verification enclave
+    pub fn load_policy(&self) -> RequestPolicy {
+        self.settings.load_policy
+    }
+
+    /// Get the supplemental data size
+    ///
+    /// # Errors
+    /// [`Error::QuoteLibrary`] if applying the settings or retrieving the
+    /// supplemental size fails.
+    pub fn supplemental_data_size(&self) -> Result<usize, Error> {
+        self.call(|| {
+            let mut size: u32 = 0;
+            dcap_quoteverify!(sgx_qv_get_quote_supplemental_data_size(&mut size))?.into_result()?;
+            Ok(size as usize)
+        })
+    }
+
+    /// Call `function` with the settings of this context applied
+    ///
+    /// Other contexts may be used concurrently, but only with the same
+    /// settings.
+    fn call<T>(&self, function: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        loop {
+            {
+                let applied = APPLIED.read().expect("RwLock has been poisoned");
+                if applied.as_ref() == Some(&self.settings) {
+                    return function();
+                }
+            }
+            let mut applied = APPLIED.write().expect("RwLock has been poisoned");
+            if applied.as_ref() != Some(&self.settings) {
+                // Clear first, a partially applied context matches none
+                *applied = None;
+                self.apply()?;
+                *applied = Some(self.settings.clone());
+            }
+        }
+    }
+
+    fn apply(&self) -> Result<(), Error> {
+        let settings = &self.settings;
+        for (kind, path) in [
+            (
+                PathKind::QuoteVerificationEnclave,
+                &settings.quote_verification_enclave,
+            ),
+            (
+                PathKind::QuoteProviderLibrary,
+                &settings.quote_provider_library,
+            ),
+        ] {
+            if let Some(path) = path {
+                let c_path = c_path(path)?;
+                dcap_quoteverify!(sgx_qv_set_path(kind.into(), c_path.as_ptr()))?.into_result()?;
+            }
+        }
+        dcap_quoteverify!(sgx_qv_set_enclave_load_policy(settings.load_policy.into()))?
+            .into_result()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::mem;
+    use mc_sgx_dcap_sys_types::sgx_ql_qv_supplemental_t;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn missing_path_fails_to_build() {
+        let dir = tempdir().unwrap();
+        let file_name = dir.path().join("missing.so");
+        assert_eq!(
+            VerificationContext::builder()
+                .quote_verification_enclave(&file_name)
+                .build(),
+            Err(Error::PathDoesNotExist(
+                file_name.to_string_lossy().into_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn context_reports_its_settings() {
+        let dir = tempdir().unwrap();
+        let qve = dir.path().join("qve");
+        let qpl = dir.path().join("qpl");
+        fs::write(&qve, "qve").unwrap();
+        fs::write(&qpl, "qpl").unwrap();
+
+        let context = VerificationContext::builder()
+            .quote_verification_enclave(&qve)
+            .quote_provider_library(&qpl)
+            .load_policy(RequestPolicy::Ephemeral)
+            .build()
+            .unwrap();
+
+        assert_eq!(context.quote_verification_enclave(), Some(qve.as_path()));
+        assert_eq!(context.quote_provider_library(), Some(qpl.as_path()));
+        assert_eq!(context.load_policy(), RequestPolicy::Ephemeral);
+    }
+
+    #[test]
+    fn contexts_with_different_policies_take_turns() {
+        let persistent = VerificationContext::default();
+        let ephemeral = VerificationContext::builder()
+            .load_policy(RequestPolicy::Ephemeral)
+            .build()
+            .unwrap();
+
+        for _ in 0..2 {
+            for context in [&persistent, &ephemeral] {
+                assert_eq!(
+                    context.supplemental_data_size(),
+                    Ok(mem::size_of::<sgx_ql_qv_supplemental_t>())
+                );
+            }
+        }
+    }
+}
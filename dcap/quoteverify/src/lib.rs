@@ -3,9 +3,28 @@
 #![doc = include_str!("../README.md")]
 #![deny(missing_docs, missing_debug_implementations)]
 
+/// Call a function of the `sgx_dcap_quoteverify` library
+///
+/// With the `dlopen` feature the library is loaded on first use and this
+/// evaluates to [`Error::LibraryUnavailable`] when it can't be loaded.
+macro_rules! dcap_quoteverify {
+    ($function:ident($($arg:expr),* $(,)?)) => {{
+        #[cfg(feature = "dlopen")]
+        let result = mc_sgx_dcap_quoteverify_sys::dlopen::library()
+            .map(|library| unsafe { (library.$function)($($arg),*) })
+            .map_err(|e| $crate::Error::LibraryUnavailable(e.into()));
+        #[cfg(not(feature = "dlopen"))]
+        let result =
+            Ok::<_, $crate::Error>(unsafe { mc_sgx_dcap_quoteverify_sys::$function($($arg),*) });
+        result
+    }};
+}
+
+mod context;
 mod quote_enclave;
 mod verify;
 
+pub use context::{VerificationContext, VerificationContextBuilder};
 use mc_sgx_dcap_types::QlError;
 pub use quote_enclave::{LoadPolicyInitializer, PathInitializer};
 pub use verify::supplemental_data_size;
@@ -26,6 +45,8 @@ pub enum Error {
     PathLengthTooLong(String),
     /// The quote verification enclave load policy has already been initialized
     LoadPolicyInitialized,
+    /// The quote verification library could not be loaded: {0}
+    LibraryUnavailable(String),
 }
 
 impl From<QlError> for Error {
@@ -7,10 +7,8 @@
 //! has a mix up.  It uses the *verification* description for `sgx_ql_set_path`
 //! and the "generation" description for `sgx_qv_set_path`
 
-use crate::Error;
-use mc_sgx_dcap_quoteverify_types::PathKind;
+use crate::{Error, VerificationContext, VerificationContextBuilder};
 use mc_sgx_dcap_types::RequestPolicy;
-use mc_sgx_util::ResultInto;
 use once_cell::sync::Lazy;
 use std::{ffi::CString, os::unix::ffi::OsStrExt, path::Path, sync::Mutex};
 
@@ -32,10 +30,15 @@ pub type Result<T> = core::result::Result<T, Error>;
 /// If a consumer of this crate does not explicitly initialize the paths, then
 /// they will be defaulted on the first call to an SGX function that needs the
 /// paths set.
+///
+/// These paths, and the [`LoadPolicyInitializer`] policy, make up the default
+/// [`VerificationContext`] used by
+/// [`supplemental_data_size()`](crate::supplemental_data_size). Build a
+/// [`VerificationContext`] to choose the paths per call instead.
 #[derive(Debug)]
 pub struct PathInitializer;
 
-static PATH_INITIALIZER: Lazy<Mutex<Option<PathInitializer>>> = Lazy::new(|| Mutex::new(None));
+static PATH_INITIALIZER: Lazy<Mutex<Option<VerificationContext>>> = Lazy::new(|| Mutex::new(None));
 
 impl PathInitializer {
     /// Try to initialize the paths to the default for the system
@@ -43,7 +46,6 @@ impl PathInitializer {
     /// # Errors
     /// * [`Error::PathsInitialized`] if the paths have been previously
     ///   initialized.
-    /// * [`Error::QuoteLibrary`] if any of the default paths don't exist on the system.
     pub fn try_default() -> Result<()> {
         // SGX has internal defaults with fallbacks, so we pass `None` for both
         // paths.
@@ -53,6 +55,9 @@ impl PathInitializer {
     /// Initialize the DCAP quote verification library paths with provided
     /// values
     ///
+    /// The paths are validated now and set in the quote verification library
+    /// on the first call which needs them.
+    ///
     /// # Arguments
     /// * `quote_verification_enclave` - The full file path to the quote
     ///   verification enclave. If `None` then the default quote verification
@@ -67,10 +72,10 @@ impl PathInitializer {
     ///   initialized.
     /// * [`Error::PathStringConversion`] if one of the paths cannot be
     ///   converted to a [`CString`]
-    /// * [`Error::QuoteLibrary`] if
-    ///     * one of the paths does not point to a file
-    ///     * one of the paths is longer than 259 (bytes)
-    ///     * one of the paths contains a null (0) byte.
+    /// * [`Error::PathDoesNotExist`] if one of the paths does not point to a
+    ///   file
+    /// * [`Error::PathLengthTooLong`] if one of the paths is longer than 259
+    ///   (bytes)
     pub fn with_paths<P1, P2>(
         quote_verification_enclave: Option<P1>,
         quote_provider_library: Option<P2>,
@@ -79,15 +84,20 @@ impl PathInitializer {
         P1: AsRef<Path>,
         P2: AsRef<Path>,
     {
+        let mut builder = VerificationContext::builder();
+        if let Some(path) = quote_verification_enclave {
+            builder = builder.quote_verification_enclave(path);
+        }
+        if let Some(path) = quote_provider_library {
+            builder = builder.quote_provider_library(path);
+        }
+        Self::initialize(builder)
+    }
+
+    fn initialize(builder: VerificationContextBuilder) -> Result<()> {
         let mut value = PATH_INITIALIZER.lock().expect("Mutex has been poisoned");
         if value.is_none() {
-            quote_verification_enclave.map_or(Ok(()), |path| {
-                Self::set_path(PathKind::QuoteVerificationEnclave, path)
-            })?;
-            quote_provider_library.map_or(Ok(()), |path| {
-                Self::set_path(PathKind::QuoteProviderLibrary, path)
-            })?;
-            *value = Some(PathInitializer);
+            *value = Some(builder.build()?);
             Ok(())
         } else {
             Err(Error::PathsInitialized)
@@ -99,9 +109,6 @@ impl PathInitializer {
     /// If the paths have not been initialized will initialize to the default.
     ///
     /// # Errors
-    /// Will return [`Error::QuoteLibrary`] if the paths have not been initialized and
-    /// the default paths don't exist.
-    ///
     /// Will *not* return an error if the paths were previously initialized.
     pub(crate) fn ensure_initialized() -> Result<()> {
         match Self::try_default() {
@@ -109,44 +116,56 @@ impl PathInitializer {
             Err(e) => Err(e),
         }
     }
+}
 
-    /// Set path for QVE(Quoting Verification Enclave) or
-    /// QPL(Quote Provider Library)
-    ///
-    /// This allows one to override the path that will be searched for each
-    /// `path_kind`.  When this isn't called then the local path and dlopen
-    /// search path will be utilized.
-    ///
-    /// # Arguments
-    /// * `path_kind` - Which path to set
-    /// * `path` - The path value to use.  This is the full path to a file.
-    ///
-    /// # Errors
-    /// * [`Error::PathStringConversion`] if `path_kind` cannot be converted to
-    ///   a [`CString`]
-    /// * [`Error::QuoteLibrary`] if
-    ///     * `path` does not point to a file
-    ///     * `path` is longer than 259 (bytes)
-    ///     * `path` contains a null (0) byte.
-    fn set_path<P: AsRef<Path>>(path_kind: PathKind, path: P) -> Result<()> {
-        let path = path.as_ref();
-        let c_path = CString::new(path.as_os_str().as_bytes())
-            .map_err(|_| Error::PathStringConversion(path.to_string_lossy().into_owned()))?;
-
-        path.is_file()
-            .then_some(true)
-            .ok_or_else(|| Error::PathDoesNotExist(path.to_string_lossy().into_owned()))?;
-
-        if c_path.as_bytes_with_nul().len() > MAX_PATH_LENGTH {
-            return Err(Error::PathLengthTooLong(
-                path.to_string_lossy().into_owned(),
-            ));
-        }
-
-        unsafe { mc_sgx_dcap_quoteverify_sys::sgx_qv_set_path(path_kind.into(), c_path.as_ptr()) }
-            .into_result()?;
-        Ok(())
+/// Validate a path for the quote verification library
+///
+/// # Errors
+/// * [`Error::PathStringConversion`] if `path` cannot be converted to a
+///   [`CString`]
+/// * [`Error::PathDoesNotExist`] if `path` does not point to a file
+/// * [`Error::PathLengthTooLong`] if `path` is longer than 259 (bytes)
+pub(crate) fn validate_path(path: &Path) -> Result<CString> {
+    let c_path = c_path(path)?;
+
+    path.is_file()
+        .then_some(true)
+        .ok_or_else(|| Error::PathDoesNotExist(path.to_string_lossy().into_owned()))?;
+
+    if c_path.as_bytes_with_nul().len() > MAX_PATH_LENGTH {
+        return Err(Error::PathLengthTooLong(
+            path.to_string_lossy().into_owned(),
+        ));
     }
+
+    Ok(c_path)
+}
+
+/// Convert a path for the quote verification library
+///
+/// # Errors
+/// [`Error::PathStringConversion`] if `path` cannot be converted to a
+/// [`CString`]
+pub(crate) fn c_path(path: &Path) -> Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| Error::PathStringConversion(path.to_string_lossy().into_owned()))
+}
+
+/// The [`VerificationContext`] of the [`PathInitializer`] and
+/// [`LoadPolicyInitializer`], initializing them to the defaults if needed
+pub(crate) fn default_context() -> Result<VerificationContext> {
+    PathInitializer::ensure_initialized()?;
+    LoadPolicyInitializer::ensure_initialized()?;
+    let context = PATH_INITIALIZER
+        .lock()
+        .expect("Mutex has been poisoned")
+        .clone()
+        .expect("Paths were initialized");
+    let policy = LOAD_POLICY_INITIALIZER
+        .lock()
+        .expect("Mutex has been poisoned")
+        .expect("Load policy was initialized");
+    Ok(context.with_load_policy(policy))
 }
 
 /// Initialization of the load policy for the quote verification enclave
@@ -158,8 +177,7 @@ impl PathInitializer {
 /// of [`RequestPolicy::Persistent`].
 #[derive(Debug)]
 pub struct LoadPolicyInitializer;
-static LOAD_POLICY_INITIALIZER: Lazy<Mutex<Option<LoadPolicyInitializer>>> =
-    Lazy::new(|| Mutex::new(None));
+static LOAD_POLICY_INITIALIZER: Lazy<Mutex<Option<RequestPolicy>>> = Lazy::new(|| Mutex::new(None));
 
 impl LoadPolicyInitializer {
     /// Try to initialize the quote verification enclave load policy to the
@@ -168,31 +186,30 @@ impl LoadPolicyInitializer {
     /// The default is [`RequestPolicy::Persistent`].
     ///
     /// # Errors
-    /// * [`Error::LoadPolicyInitialized`] if the policy has been previously
-    ///   initialized.
-    /// * [`Error::QuoteLibrary`] for any errors setting the policy in the SGX SDK.
+    /// [`Error::LoadPolicyInitialized`] if the policy has been previously
+    /// initialized.
     pub fn try_default() -> Result<()> {
         Self::policy(RequestPolicy::Persistent)
     }
 
     /// Set the load policy to use for the quote verification enclave
     ///
+    /// The policy is set in the quote verification library on the first call
+    /// which needs it.
+    ///
     /// # Arguments
     /// * `policy` - The policy to use for loading the quote verification
     ///   enclave
     ///
     /// # Errors
-    /// * [`Error::LoadPolicyInitialized`] if the policy has been previously
-    ///   initialized.
-    /// * [`Error::QuoteLibrary`] for any errors setting the policy in the SGX SDK.
+    /// [`Error::LoadPolicyInitialized`] if the policy has been previously
+    /// initialized.
     pub fn policy(policy: RequestPolicy) -> Result<()> {
         let mut value = LOAD_POLICY_INITIALIZER
             .lock()
             .expect("Mutex has been poisoned");
         if value.is_none() {
-            unsafe { mc_sgx_dcap_quoteverify_sys::sgx_qv_set_enclave_load_policy(policy.into()) }
-                .into_result()?;
-            *value = Some(LoadPolicyInitializer);
+            *value = Some(policy);
             Ok(())
         } else {
             Err(Error::LoadPolicyInitialized)
@@ -204,9 +221,6 @@ impl LoadPolicyInitializer {
     /// If the load policy has already been set does nothing
     ///
     /// # Errors
-    /// Will return [`Error::QuoteLibrary`] if the load policy has not been initialized
-    /// and there is an error setting the policy
-    ///
     /// Will *not* return an error if the load policy as previously initialized.
     pub(crate) fn ensure_initialized() -> Result<()> {
         match Self::try_default() {
@@ -219,7 +233,6 @@ impl LoadPolicyInitializer {
 #[cfg(test)]
 mod test {
     use super::*;
-    use mc_sgx_dcap_quoteverify_types::PathKind::{QuoteProviderLibrary, QuoteVerificationEnclave};
     use serial_test::serial;
     use std::fs;
     use tempfile::tempdir;
@@ -250,7 +263,7 @@ mod test {
         let dir = tempdir().unwrap();
         let file_name = dir.path().join("fake.txt");
         fs::write(&file_name, "stuff").unwrap();
-        assert!(PathInitializer::set_path(QuoteVerificationEnclave, file_name).is_ok());
+        assert!(validate_path(&file_name).is_ok());
     }
 
     #[test]
@@ -258,13 +271,13 @@ mod test {
         let dir = tempdir().unwrap();
         let file_name = dir.path().join("fake.txt");
         fs::write(&file_name, "stuff").unwrap();
-        assert!(PathInitializer::set_path(QuoteProviderLibrary, file_name).is_ok());
+        assert!(validate_path(&file_name).is_ok());
     }
 
     #[test]
     fn path_as_directory_fails() {
         let dir = tempdir().unwrap();
-        assert!(PathInitializer::set_path(QuoteVerificationEnclave, dir.path()).is_err());
+        assert!(validate_path(dir.path()).is_err());
     }
 
     #[test]
@@ -272,8 +285,8 @@ mod test {
         let dir = tempdir().unwrap();
         let file_name = dir.path().join("fake\0.txt");
         // fs::write() will fail to create the file with a null byte in the path
-        // so we pass the path as non existent to `set_path`.
-        assert!(PathInitializer::set_path(QuoteVerificationEnclave, file_name).is_err());
+        // so we pass the path as non existent to `validate_path`.
+        assert!(validate_path(&file_name).is_err());
     }
 
     #[test]
@@ -287,7 +300,7 @@ mod test {
         let file_name = dir.path().join(long_name);
         fs::write(&file_name, "stuff").unwrap();
 
-        assert!(PathInitializer::set_path(QuoteProviderLibrary, file_name).is_ok());
+        assert!(validate_path(&file_name).is_ok());
     }
 
     #[test]
@@ -301,7 +314,7 @@ mod test {
         let file_name = dir.path().join(long_name);
         fs::write(&file_name, "stuff").unwrap();
 
-        assert!(PathInitializer::set_path(QuoteProviderLibrary, file_name).is_err());
+        assert!(validate_path(&file_name).is_err());
     }
 
     #[test]
@@ -2,8 +2,7 @@
 
 //! This module contains logic to assist in verifying a DCAP quote
 
-use crate::{quote_enclave::LoadPolicyInitializer, Error, PathInitializer};
-use mc_sgx_util::ResultInto;
+use crate::{quote_enclave::default_context, Error};
 
 /// Get the supplemental data size
 ///
@@ -13,17 +12,19 @@ use mc_sgx_util::ResultInto;
 ///   [`LoadPolicyInitializer`] after calling this function will result in
 ///   an error.
 ///
+/// Use [`VerificationContext::supplemental_data_size()`] to choose the paths
+/// and load policy instead.
+///
 /// # Errors
 ///
 /// [`Error::QuoteLibrary`] if there is any error retrieving the supplemental size from
 /// SGX.
+///
+/// [`PathInitializer`]: crate::PathInitializer
+/// [`LoadPolicyInitializer`]: crate::LoadPolicyInitializer
+/// [`VerificationContext::supplemental_data_size()`]: crate::VerificationContext::supplemental_data_size
 pub fn supplemental_data_size() -> Result<usize, Error> {
-    PathInitializer::ensure_initialized()?;
-    LoadPolicyInitializer::ensure_initialized()?;
-    let mut size: u32 = 0;
-    unsafe { mc_sgx_dcap_quoteverify_sys::sgx_qv_get_quote_supplemental_data_size(&mut size) }
-        .into_result()?;
-    Ok(size as usize)
+    default_context()?.supplemental_data_size()
 }
 
 #[cfg(test)]
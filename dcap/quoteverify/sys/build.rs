@@ -2,6 +2,8 @@
 //! Builds the FFI function bindings for dcap quoteverify library of the Intel
 //! SGX SDK
 
+use std::env;
+
 const DCAP_QL_FUNCTIONS: &[&str] = &[
     "sgx_qv_free_qve_identity",
     "sgx_qv_get_quote_supplemental_data_size",
@@ -12,7 +14,11 @@ const DCAP_QL_FUNCTIONS: &[&str] = &[
 ];
 
 fn main() {
-    cargo_emit::rustc_link_lib!("dylib=sgx_dcap_quoteverify");
+    // With `dlopen` the library is loaded at runtime instead
+    if env::var_os("CARGO_FEATURE_DLOPEN").is_none() {
+        cargo_emit::rustc_link_lib!("dylib=sgx_dcap_quoteverify");
+    }
+    mc_sgx_core_build::emit_dcap_cfgs();
 
     let mut builder = mc_sgx_core_build::sgx_builder()
         .header("wrapper.h")
@@ -22,10 +28,5 @@ fn main() {
         builder = builder.allowlist_function(f);
     }
 
-    let out_path = mc_sgx_core_build::build_output_dir();
-    builder
-        .generate()
-        .expect("Unable to generate bindings")
-        .write_to_file(out_path.join("bindings.rs"))
-        .expect("Couldn't write bindings!");
+    mc_sgx_core_build::write_bindings(builder);
 }
@@ -0,0 +1,157 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Loading of the `sgx_dcap_quoteverify` library at runtime
+
+use libloading::Library;
+use mc_sgx_dcap_quoteverify_sys_types::sgx_qv_path_type_t;
+use mc_sgx_dcap_sys_types::{
+    quote3_error_t, sgx_ql_qe_report_info_t, sgx_ql_qv_result_t, sgx_ql_qve_collateral_t,
+    sgx_ql_request_policy_t, time_t,
+};
+use once_cell::sync::OnceCell;
+use std::{ffi::OsStr, os::raw::c_char};
+
+/// The name of the library loaded by [`library()`]
+pub const LIBRARY_NAME: &str = "libsgx_dcap_quoteverify.so.1";
+
+/// The functions of the `sgx_dcap_quoteverify` library
+#[derive(Debug)]
+pub struct DcapQuoteVerify {
+    /// `sgx_qv_free_qve_identity()`
+    pub sgx_qv_free_qve_identity: unsafe extern "C" fn(*mut u8, *mut u8, *mut u8) -> quote3_error_t,
+    /// `sgx_qv_get_quote_supplemental_data_size()`
+    pub sgx_qv_get_quote_supplemental_data_size: unsafe extern "C" fn(*mut u32) -> quote3_error_t,
+    /// `sgx_qv_get_qve_identity()`
+    pub sgx_qv_get_qve_identity: unsafe extern "C" fn(
+        *mut *mut u8,
+        *mut u32,
+        *mut *mut u8,
+        *mut u32,
+        *mut *mut u8,
+        *mut u16,
+    ) -> quote3_error_t,
+    /// `sgx_qv_set_enclave_load_policy()`
+    pub sgx_qv_set_enclave_load_policy:
+        unsafe extern "C" fn(sgx_ql_request_policy_t) -> quote3_error_t,
+    /// `sgx_qv_set_path()`
+    pub sgx_qv_set_path: unsafe extern "C" fn(sgx_qv_path_type_t, *const c_char) -> quote3_error_t,
+    /// `sgx_qv_verify_quote()`
+    pub sgx_qv_verify_quote: unsafe extern "C" fn(
+        *const u8,
+        u32,
+        *const sgx_ql_qve_collateral_t,
+        time_t,
+        *mut u32,
+        *mut sgx_ql_qv_result_t,
+        *mut sgx_ql_qe_report_info_t,
+        u32,
+        *mut u8,
+    ) -> quote3_error_t,
+    // Must outlive the function pointers above
+    _library: Library,
+}
+
+impl DcapQuoteVerify {
+    /// Load the library and resolve all of its functions
+    ///
+    /// # Arguments
+    /// * `path` - The library to load, either a file name to search for in the
+    ///   standard library locations or a path.
+    ///
+    /// # Safety
+    /// Loading a library runs its initialization code, and the functions are
+    /// assumed to have the signatures of the `sgx_dcap_quoteverify` library.
+    pub unsafe fn load(path: impl AsRef<OsStr>) -> Result<Self, libloading::Error> {
+        let library = Library::new(path.as_ref())?;
+        Ok(Self {
+            sgx_qv_free_qve_identity: *library.get(b"sgx_qv_free_qve_identity\0")?,
+            sgx_qv_get_quote_supplemental_data_size: *library
+                .get(b"sgx_qv_get_quote_supplemental_data_size\0")?,
+            sgx_qv_get_qve_identity: *library.get(b"sgx_qv_get_qve_identity\0")?,
+            sgx_qv_set_enclave_load_policy: *library.get(b"sgx_qv_set_enclave_load_policy\0")?,
+            sgx_qv_set_path: *library.get(b"sgx_qv_set_path\0")?,
+            sgx_qv_verify_quote: *library.get(b"sgx_qv_verify_quote\0")?,
+            _library: library,
+        })
+    }
+}
+
+static LIBRARY: OnceCell<Result<DcapQuoteVerify, String>> = OnceCell::new();
+
+/// The `sgx_dcap_quoteverify` library of the process, loaded on first use
+///
+/// Loading is only attempted once, later calls return the same result.
+///
+/// # Errors
+/// The reason [`LIBRARY_NAME`] could not be loaded, or a function could not be
+/// found in it.
+pub fn library() -> Result<&'static DcapQuoteVerify, &'static str> {
+    LIBRARY
+        .get_or_init(|| unsafe { DcapQuoteVerify::load(LIBRARY_NAME) }.map_err(|e| e.to_string()))
+        .as_ref()
+        .map_err(String::as_str)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{fs, path::PathBuf, process::Command};
+    use tempfile::TempDir;
+
+    /// Build a stand in for the library whose functions return `status`.
+    fn stub_library(dir: &TempDir, functions: &[&str], status: u32) -> PathBuf {
+        let source = dir.path().join("stub.c");
+        let library = dir.path().join("libstub.so");
+        let contents = functions
+            .iter()
+            .map(|f| format!("unsigned int {f}() {{ return {status}; }}\n"))
+            .collect::<String>();
+        fs::write(&source, contents).expect("Failed to write stub source");
+        let status = Command::new("cc")
+            .args(["-shared", "-fPIC", "-o"])
+            .arg(&library)
+            .arg(&source)
+            .status()
+            .expect("Failed to run the C compiler");
+        assert!(status.success(), "Failed to compile stub library");
+        library
+    }
+
+    const FUNCTIONS: &[&str] = &[
+        "sgx_qv_free_qve_identity",
+        "sgx_qv_get_quote_supplemental_data_size",
+        "sgx_qv_get_qve_identity",
+        "sgx_qv_set_enclave_load_policy",
+        "sgx_qv_set_path",
+        "sgx_qv_verify_quote",
+    ];
+
+    #[test]
+    fn calls_into_loaded_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = stub_library(&dir, FUNCTIONS, 0xE03A);
+
+        let library = unsafe { DcapQuoteVerify::load(&path) }.unwrap();
+        let mut size = 0;
+        let result = unsafe { (library.sgx_qv_get_quote_supplemental_data_size)(&mut size) };
+
+        assert_eq!(result.0, 0xE03A);
+    }
+
+    #[test]
+    fn missing_function_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = stub_library(&dir, &FUNCTIONS[1..], 0);
+
+        let error = unsafe { DcapQuoteVerify::load(path) }.unwrap_err();
+
+        assert!(error.to_string().contains("sgx_qv_free_qve_identity"));
+    }
+
+    #[test]
+    fn missing_library_fails() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert!(unsafe { DcapQuoteVerify::load(dir.path().join("libmissing.so")) }.is_err());
+    }
+}
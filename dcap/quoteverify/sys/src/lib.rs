@@ -9,3 +9,6 @@ use mc_sgx_dcap_sys_types::{
     sgx_ql_request_policy_t, time_t,
 };
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+#[cfg(feature = "dlopen")]
+pub mod dlopen;
@@ -17,10 +17,5 @@ fn main() {
         builder = builder.allowlist_type(t);
     }
 
-    let out_path = mc_sgx_core_build::build_output_dir();
-    builder
-        .generate()
-        .expect("Unable to generate bindings")
-        .write_to_file(out_path.join("bindings.rs"))
-        .expect("Couldn't write bindings!");
+    mc_sgx_core_build::write_bindings(builder);
 }
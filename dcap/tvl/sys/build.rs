@@ -8,6 +8,7 @@ fn main() {
     cargo_emit::rustc_link_search!(link_path);
     cargo_emit::rerun_if_changed!(link_path);
     cargo_emit::rustc_link_lib!("static=sgx_dcap_tvl");
+    mc_sgx_core_build::emit_sgx_sdk_cfgs();
 
     let mut builder = mc_sgx_core_build::sgx_builder()
         .header("wrapper.h")
@@ -17,10 +18,5 @@ fn main() {
         builder = builder.allowlist_function(f);
     }
 
-    let out_path = mc_sgx_core_build::build_output_dir();
-    builder
-        .generate()
-        .expect("Unable to generate bindings")
-        .write_to_file(out_path.join("bindings.rs"))
-        .expect("Couldn't write bindings!");
+    mc_sgx_core_build::write_bindings(builder);
 }
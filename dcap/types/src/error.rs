@@ -3,7 +3,7 @@
 //! This module provides the error type related to Quote v3
 
 use displaydoc::Display;
-use mc_sgx_dcap_sys_types::quote3_error_t;
+use mc_sgx_dcap_sys_types::{quote3_error_t, sgx_pce_error_t};
 use mc_sgx_util::{ResultFrom, ResultInto};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
@@ -436,6 +436,64 @@ impl From<QlError> for quote3_error_t {
 impl ResultFrom<quote3_error_t> for QlError {}
 impl ResultInto<QlError> for quote3_error_t {}
 
+/// An enumeration of errors which occur when using the provisioning
+/// certification enclave (PCE).
+///
+/// These errors correspond to error elements of
+/// [`sgx_pce_error_t`](mc_sgx_dcap_sys_types::sgx_pce_error_t).
+#[derive(Copy, Clone, Debug, Display, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[non_exhaustive]
+#[repr(u32)]
+pub enum PceError {
+    /// An unexpected internal error occurred
+    Unexpected = sgx_pce_error_t::SGX_PCE_UNEXPECTED.0,
+    /// One of the parameters passed to an SGX FFI method was invalid
+    InvalidParameter = sgx_pce_error_t::SGX_PCE_INVALID_PARAMETER.0,
+    /// Not enough memory in the EPC to load the PCE
+    OutOfEpc = sgx_pce_error_t::SGX_PCE_OUT_OF_EPC.0,
+    /// Unable to load the PCE
+    InterfaceUnavailable = sgx_pce_error_t::SGX_PCE_INTERFACE_UNAVAILABLE.0,
+    /// The report from the calling enclave is invalid
+    InvalidReport = sgx_pce_error_t::SGX_PCE_INVALID_REPORT.0,
+    /// Cryptographic failure in the PCE
+    Crypto = sgx_pce_error_t::SGX_PCE_CRYPTO_ERROR.0,
+    /// The calling enclave isn't privileged to use the PCE
+    InvalidPrivilege = sgx_pce_error_t::SGX_PCE_INVALID_PRIVILEGE.0,
+    /// The requested TCB is invalid
+    InvalidTcb = sgx_pce_error_t::SGX_PCE_INVALID_TCB.0,
+}
+
+impl TryFrom<sgx_pce_error_t> for PceError {
+    type Error = ();
+
+    fn try_from(value: sgx_pce_error_t) -> Result<Self, Self::Error> {
+        match value {
+            sgx_pce_error_t::SGX_PCE_SUCCESS => Err(()),
+
+            sgx_pce_error_t::SGX_PCE_UNEXPECTED => Ok(PceError::Unexpected),
+            sgx_pce_error_t::SGX_PCE_INVALID_PARAMETER => Ok(PceError::InvalidParameter),
+            sgx_pce_error_t::SGX_PCE_OUT_OF_EPC => Ok(PceError::OutOfEpc),
+            sgx_pce_error_t::SGX_PCE_INTERFACE_UNAVAILABLE => Ok(PceError::InterfaceUnavailable),
+            sgx_pce_error_t::SGX_PCE_INVALID_REPORT => Ok(PceError::InvalidReport),
+            sgx_pce_error_t::SGX_PCE_CRYPTO_ERROR => Ok(PceError::Crypto),
+            sgx_pce_error_t::SGX_PCE_INVALID_PRIVILEGE => Ok(PceError::InvalidPrivilege),
+            sgx_pce_error_t::SGX_PCE_INVALID_TCB => Ok(PceError::InvalidTcb),
+            // Map all unknowns to the unexpected error
+            _ => Ok(PceError::Unexpected),
+        }
+    }
+}
+
+impl From<PceError> for sgx_pce_error_t {
+    fn from(src: PceError) -> sgx_pce_error_t {
+        sgx_pce_error_t(src as u32)
+    }
+}
+
+impl ResultFrom<sgx_pce_error_t> for PceError {}
+impl ResultInto<PceError> for sgx_pce_error_t {}
+
 #[cfg(test)]
 mod test {
     extern crate std;
@@ -549,4 +607,36 @@ mod test {
             QlError::Unexpected
         );
     }
+
+    #[parameterized(
+    unexpected = { sgx_pce_error_t::SGX_PCE_UNEXPECTED, PceError::Unexpected },
+    invalid_parameter = { sgx_pce_error_t::SGX_PCE_INVALID_PARAMETER, PceError::InvalidParameter },
+    out_of_epc = { sgx_pce_error_t::SGX_PCE_OUT_OF_EPC, PceError::OutOfEpc },
+    interface_unavailable = { sgx_pce_error_t::SGX_PCE_INTERFACE_UNAVAILABLE, PceError::InterfaceUnavailable },
+    invalid_report = { sgx_pce_error_t::SGX_PCE_INVALID_REPORT, PceError::InvalidReport },
+    crypto = { sgx_pce_error_t::SGX_PCE_CRYPTO_ERROR, PceError::Crypto },
+    invalid_privilege = { sgx_pce_error_t::SGX_PCE_INVALID_PRIVILEGE, PceError::InvalidPrivilege },
+    invalid_tcb = { sgx_pce_error_t::SGX_PCE_INVALID_TCB, PceError::InvalidTcb },
+    )]
+    fn pce_error_from_ffi(ffi: sgx_pce_error_t, expected: PceError) {
+        assert_eq!(
+            expected,
+            PceError::try_from(ffi).expect("Could not create error from ffi type")
+        );
+        assert_eq!(sgx_pce_error_t::from(expected), ffi);
+    }
+
+    #[test]
+    fn pce_success_is_not_an_error() {
+        assert!(PceError::try_from(sgx_pce_error_t::SGX_PCE_SUCCESS).is_err())
+    }
+
+    #[test]
+    fn unknown_pce_error_maps_to_unexpected() {
+        let unknown = sgx_pce_error_t(sgx_pce_error_t::SGX_PCE_INVALID_TCB.0 + 1);
+        assert_eq!(
+            PceError::try_from(unknown).expect("Could not parse an unknown PCE error"),
+            PceError::Unexpected
+        );
+    }
 }
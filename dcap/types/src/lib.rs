@@ -21,7 +21,7 @@ pub use crate::{
         CertificationData, EcdsaSignatureAuxData, Pck, PckCertificateChain, PlatformManifest, Ppid,
         PpidEncryptedRsa2048, PpidEncryptedRsa3072,
     },
-    error::{QlError, Quote3Error},
+    error::{PceError, QlError, Quote3Error},
     quote3::{Quote3, SignatureData},
     quoting_enclave::ReportInfo,
     request_policy::RequestPolicy,
@@ -0,0 +1,404 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+#![doc = include_str!("../README.md")]
+#![deny(missing_docs, missing_debug_implementations)]
+
+use core::{mem, ptr, result::Result as CoreResult};
+use mc_sgx_core_sys_types::{
+    sgx_att_key_id_ext_t, sgx_att_key_id_t, sgx_qe_report_info_t, sgx_quote_nonce_t, sgx_report_t,
+    sgx_target_info_t,
+};
+use mc_sgx_core_types::{
+    Algorithm, Error, ExtendedAttestationKeyId, QuoteNonce, QuotingEnclaveReportInfo, Report,
+    TargetInfo,
+};
+use mc_sgx_util::ResultInto;
+
+/// Convenience type for handling SGX quoting results
+pub type Result<T> = CoreResult<T, Error>;
+
+/// The size of `sgx_ql_att_key_id_list_header_t`, which precedes the
+/// attestation key IDs in the list given to `sgx_select_att_key_id()`
+const KEY_ID_LIST_HEADER_SIZE: usize = 8;
+
+/// The attestation keys the platform supports
+///
+/// # Errors
+/// [`Error`] if the platform quoting infrastructure fails to list its keys.
+pub fn supported_attestation_keys() -> Result<Vec<ExtendedAttestationKeyId>> {
+    let mut count = 0;
+    unsafe { mc_sgx_quote_ex_sys::sgx_get_supported_att_key_id_num(&mut count) }.into_result()?;
+
+    let mut keys = vec![sgx_att_key_id_ext_t::default(); count as usize];
+    unsafe { mc_sgx_quote_ex_sys::sgx_get_supported_att_key_ids(keys.as_mut_ptr(), count) }
+        .into_result()?;
+
+    Ok(keys.into_iter().map(Into::into).collect())
+}
+
+/// Select the attestation key to quote with
+///
+/// # Arguments
+/// * `candidates` - The attestation keys the quote verifier supports. When
+///   empty the platform picks its default attestation key.
+///
+/// # Errors
+/// * [`Error::UnsupportedAttKeyId`] if the platform supports none of the
+///   `candidates`.
+/// * [`Error::InvalidParameter`] if there are more `candidates` than the
+///   platform accepts.
+pub fn select_attestation_key(
+    candidates: &[ExtendedAttestationKeyId],
+) -> Result<ExtendedAttestationKeyId> {
+    let list = key_id_list(candidates)?;
+    let (list_ptr, list_size) = match candidates {
+        [] => (ptr::null(), 0),
+        _ => (
+            list.as_ptr(),
+            u32::try_from(list.len()).map_err(|_| Error::InvalidParameter)?,
+        ),
+    };
+
+    let mut selected = sgx_att_key_id_t {
+        att_key_id: [0; mem::size_of::<sgx_att_key_id_t>()],
+    };
+    unsafe { mc_sgx_quote_ex_sys::sgx_select_att_key_id(list_ptr, list_size, &mut selected) }
+        .into_result()?;
+
+    Ok(from_att_key_id(selected))
+}
+
+/// Select the supported attestation key with the most preferred algorithm
+///
+/// For example `&[Algorithm::EcdsaP256, Algorithm::Epid]` quotes with ECDSA
+/// when the platform can and falls back to EPID otherwise.
+///
+/// # Arguments
+/// * `preference` - The acceptable algorithms, most preferred first.
+///
+/// # Errors
+/// * [`Error::UnsupportedAttKeyId`] if the platform supports none of the
+///   algorithms in `preference`.
+/// * [`Error`] if the platform quoting infrastructure fails to list or select
+///   its keys.
+pub fn select_attestation_key_by_algorithm(
+    preference: &[Algorithm],
+) -> Result<ExtendedAttestationKeyId> {
+    let supported = supported_attestation_keys()?;
+    for algorithm in preference {
+        let candidates = keys_with_algorithm(&supported, algorithm);
+        if candidates.is_empty() {
+            continue;
+        }
+        match select_attestation_key(&candidates) {
+            Err(Error::UnsupportedAttKeyId) => continue,
+            result => return result,
+        }
+    }
+    Err(Error::UnsupportedAttKeyId)
+}
+
+/// An attestation key which has been initialized for quoting
+///
+/// Reports to be quoted must target the [`QuotingKey::target_info()`] of the
+/// quoting enclave owning the key.
+///
+/// ```no_run
+/// # use mc_sgx_core_types::{Algorithm, Report};
+/// # use mc_sgx_quote_ex::{select_attestation_key_by_algorithm, QuotingKey};
+/// let key_id = select_attestation_key_by_algorithm(&[Algorithm::EcdsaP256, Algorithm::Epid])?;
+/// let key = QuotingKey::init(key_id)?;
+/// // The application enclave creates a report for `key.target_info()`
+/// # let report = Report::default();
+/// let quote = key.quote(&report)?;
+/// # Ok::<(), mc_sgx_core_types::Error>(())
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct QuotingKey {
+    id: ExtendedAttestationKeyId,
+    target_info: TargetInfo,
+    public_key_id: Vec<u8>,
+}
+
+impl QuotingKey {
+    /// Initialize quoting with an attestation key
+    ///
+    /// The owner of the key generates or retrieves, and certifies, the key
+    /// as needed. When quoting fails with [`Error::AttKeyUninitialized`] the
+    /// key needs to be initialized again.
+    ///
+    /// # Arguments
+    /// * `id` - The attestation key to quote with, usually from
+    ///   [`select_attestation_key()`].
+    ///
+    /// # Errors
+    /// * [`Error::UnsupportedAttKeyId`] if the platform doesn't support the
+    ///   key.
+    /// * [`Error::AttKeyCertificationFailure`] if generating or certifying the
+    ///   key failed.
+    pub fn init(id: ExtendedAttestationKeyId) -> Result<Self> {
+        let att_key_id = to_att_key_id(&id);
+        let mut target_info = sgx_target_info_t::default();
+
+        // Without a buffer for the public key ID only its size is returned
+        let mut size = 0;
+        unsafe {
+            mc_sgx_quote_ex_sys::sgx_init_quote_ex(
+                &att_key_id,
+                &mut target_info,
+                &mut size,
+                ptr::null_mut(),
+            )
+        }
+        .into_result()?;
+
+        let mut public_key_id = vec![0; size];
+        unsafe {
+            mc_sgx_quote_ex_sys::sgx_init_quote_ex(
+                &att_key_id,
+                &mut target_info,
+                &mut size,
+                public_key_id.as_mut_ptr(),
+            )
+        }
+        .into_result()?;
+        public_key_id.truncate(size);
+
+        Ok(Self {
+            id,
+            target_info: target_info.into(),
+            public_key_id,
+        })
+    }
+
+    /// The attestation key
+    pub fn id(&self) -> &ExtendedAttestationKeyId {
+        &self.id
+    }
+
+    /// The target info of the quoting enclave owning the key
+    pub fn target_info(&self) -> &TargetInfo {
+        &self.target_info
+    }
+
+    /// The public identifier of the attestation key
+    pub fn public_key_id(&self) -> &[u8] {
+        &self.public_key_id
+    }
+
+    /// The size, in bytes, of the quotes this key produces
+    ///
+    /// # Errors
+    /// * [`Error::AttKeyUninitialized`] if the key needs to be initialized
+    ///   again.
+    /// * [`Error::UnsupportedAttKeyId`] if the platform no longer supports the
+    ///   key.
+    pub fn quote_size(&self) -> Result<usize> {
+        let att_key_id = to_att_key_id(&self.id);
+        let mut size = 0;
+        unsafe { mc_sgx_quote_ex_sys::sgx_get_quote_size_ex(&att_key_id, &mut size) }
+            .into_result()?;
+        Ok(size as usize)
+    }
+
+    /// Quote an application enclave's report
+    ///
+    /// The quote format depends on the key's algorithm, ECDSA keys produce
+    /// version 3 quotes while EPID keys produce `sgx_quote_t` quotes.
+    ///
+    /// # Arguments
+    /// * `report` - The report to quote, it must target
+    ///   [`QuotingKey::target_info()`].
+    ///
+    /// # Errors
+    /// * [`Error::MacMismatch`] if `report` doesn't target the quoting
+    ///   enclave.
+    /// * [`Error::AttKeyUninitialized`] if the key needs to be initialized
+    ///   again.
+    pub fn quote(&self, report: &Report) -> Result<Vec<u8>> {
+        self.get_quote(report, None)
+    }
+
+    /// Quote an application enclave's report, also getting a report of the
+    /// quoting enclave for the application enclave
+    ///
+    /// The application enclave can verify the returned quoting enclave report
+    /// to detect a spoofed quoting enclave early. The report data of the
+    /// quoting enclave report is `SHA256(nonce || quote) || 32 zero bytes`.
+    ///
+    /// # Arguments
+    /// * `report` - The report to quote, it must target
+    ///   [`QuotingKey::target_info()`].
+    /// * `app_enclave_target_info` - The target info of the application
+    ///   enclave, the quoting enclave report targets it.
+    /// * `nonce` - A nonce from the application enclave.
+    ///
+    /// # Errors
+    /// * [`Error::MacMismatch`] if `report` doesn't target the quoting
+    ///   enclave.
+    /// * [`Error::AttKeyUninitialized`] if the key needs to be initialized
+    ///   again.
+    pub fn quote_with_qe_report(
+        &self,
+        report: &Report,
+        app_enclave_target_info: &TargetInfo,
+        nonce: &QuoteNonce,
+    ) -> Result<(Vec<u8>, QuotingEnclaveReportInfo)> {
+        let mut report_info = sgx_qe_report_info_t {
+            nonce: sgx_quote_nonce_t::from(nonce.clone()),
+            app_enclave_target_info: app_enclave_target_info.clone().into(),
+            qe_report: sgx_report_t::default(),
+        };
+        let quote = self.get_quote(report, Some(&mut report_info))?;
+        Ok((quote, report_info.into()))
+    }
+
+    fn get_quote(
+        &self,
+        report: &Report,
+        report_info: Option<&mut sgx_qe_report_info_t>,
+    ) -> Result<Vec<u8>> {
+        let att_key_id = to_att_key_id(&self.id);
+        let mut quote = vec![0; self.quote_size()?];
+        let size = u32::try_from(quote.len()).map_err(|_| Error::InvalidParameter)?;
+        let report_info = report_info.map_or(ptr::null_mut(), |info| info as *mut _);
+        unsafe {
+            mc_sgx_quote_ex_sys::sgx_get_quote_ex(
+                report.as_ref(),
+                &att_key_id,
+                report_info,
+                quote.as_mut_ptr(),
+                size,
+            )
+        }
+        .into_result()?;
+        Ok(quote)
+    }
+}
+
+/// The keys in `keys` which use `algorithm`
+fn keys_with_algorithm(
+    keys: &[ExtendedAttestationKeyId],
+    algorithm: &Algorithm,
+) -> Vec<ExtendedAttestationKeyId> {
+    keys.iter()
+        .filter(|key| key.base_key_id().algorithm_id().as_ref() == Ok(algorithm))
+        .cloned()
+        .collect()
+}
+
+/// The `sgx_ql_att_key_id_list_t` for `candidates`
+fn key_id_list(candidates: &[ExtendedAttestationKeyId]) -> Result<Vec<u8>> {
+    let count = u32::try_from(candidates.len()).map_err(|_| Error::InvalidParameter)?;
+    let mut list = Vec::with_capacity(
+        KEY_ID_LIST_HEADER_SIZE + candidates.len() * mem::size_of::<sgx_att_key_id_t>(),
+    );
+    // The header's structure ID and version, both 0
+    list.extend_from_slice(&[0; 4]);
+    list.extend_from_slice(&count.to_le_bytes());
+    for candidate in candidates {
+        list.extend_from_slice(&to_att_key_id(candidate).att_key_id);
+    }
+    Ok(list)
+}
+
+/// The opaque form of an extended attestation key ID, which the quoting
+/// functions take
+fn to_att_key_id(id: &ExtendedAttestationKeyId) -> sgx_att_key_id_t {
+    let id: sgx_att_key_id_ext_t = id.clone().into();
+    // SAFETY: Both are plain, packed, 256 byte structures and
+    // `sgx_att_key_id_t` is the SDK's opaque form of `sgx_att_key_id_ext_t`.
+    unsafe { mem::transmute(id) }
+}
+
+fn from_att_key_id(id: sgx_att_key_id_t) -> ExtendedAttestationKeyId {
+    // SAFETY: See `to_att_key_id()`
+    let id: sgx_att_key_id_ext_t = unsafe { mem::transmute(id) };
+    id.into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mc_sgx_core_sys_types::sgx_ql_att_key_id_t;
+    use yare::parameterized;
+
+    fn key(algorithm_id: u32, prod_id: u32) -> ExtendedAttestationKeyId {
+        sgx_att_key_id_ext_t {
+            base: sgx_ql_att_key_id_t {
+                algorithm_id,
+                prod_id,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn empty_key_id_list_has_only_a_header() {
+        assert_eq!(key_id_list(&[]), Ok(vec![0; KEY_ID_LIST_HEADER_SIZE]));
+    }
+
+    #[test]
+    fn key_id_list_counts_its_keys() {
+        let keys = [key(2, 1), key(0, 2)];
+        let list = key_id_list(&keys).unwrap();
+
+        assert_eq!(
+            list.len(),
+            KEY_ID_LIST_HEADER_SIZE + 2 * mem::size_of::<sgx_att_key_id_t>()
+        );
+        assert_eq!(list[..KEY_ID_LIST_HEADER_SIZE], [0, 0, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(
+            list[KEY_ID_LIST_HEADER_SIZE..KEY_ID_LIST_HEADER_SIZE + 256],
+            to_att_key_id(&keys[0]).att_key_id
+        );
+    }
+
+    #[test]
+    fn att_key_id_round_trips() {
+        let id = key(2, 7);
+        assert_eq!(from_att_key_id(to_att_key_id(&id)), id);
+    }
+
+    #[parameterized(
+        epid = { Algorithm::Epid, &[1, 3] },
+        ecdsa_p256 = { Algorithm::EcdsaP256, &[2] },
+        ecdsa_p384 = { Algorithm::EcdsaP384, &[] },
+    )]
+    fn filter_keys_by_algorithm(algorithm: Algorithm, product_ids: &[u32]) {
+        let keys = [key(0, 1), key(2, 2), key(0, 3), key(7, 4)];
+        let filtered = keys_with_algorithm(&keys, &algorithm);
+        let ids = filtered
+            .iter()
+            .map(|key| key.base_key_id().as_ref().prod_id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, product_ids);
+    }
+}
+
+#[cfg(all(test, not(feature = "sim")))]
+mod hw_test {
+    use super::*;
+
+    #[test]
+    fn platform_supports_a_key() {
+        assert!(!supported_attestation_keys().unwrap().is_empty());
+    }
+
+    #[test]
+    fn default_key_can_quote() {
+        let key = QuotingKey::init(select_attestation_key(&[]).unwrap()).unwrap();
+        assert!(key.quote_size().unwrap() > 0);
+        assert_eq!(key.quote(&Report::default()), Err(Error::MacMismatch));
+    }
+
+    #[test]
+    fn no_supported_algorithm_fails_to_select() {
+        assert_eq!(
+            select_attestation_key_by_algorithm(&[Algorithm::EcdsaP384]),
+            Err(Error::UnsupportedAttKeyId)
+        );
+    }
+}
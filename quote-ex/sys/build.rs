@@ -0,0 +1,28 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Builds the FFI bindings for the quoting functions of libsgx_quote_ex.so
+
+const QUOTE_EX_FUNCTIONS: &[&str] = &[
+    "sgx_get_supported_att_key_id_num",
+    "sgx_get_supported_att_key_ids",
+    "sgx_select_att_key_id",
+    "sgx_init_quote_ex",
+    "sgx_get_quote_size_ex",
+    "sgx_get_quote_ex",
+];
+
+fn main() {
+    let link_path = mc_sgx_core_build::sgx_library_string();
+    cargo_emit::rustc_link_search!(link_path);
+    let sgx_suffix = mc_sgx_core_build::sgx_library_suffix();
+    cargo_emit::rustc_link_lib!(&format!("sgx_quote_ex{sgx_suffix}"));
+    mc_sgx_core_build::emit_sgx_sdk_cfgs();
+
+    let mut builder = mc_sgx_core_build::sgx_builder().header("wrapper.h");
+
+    for f in QUOTE_EX_FUNCTIONS {
+        builder = builder.allowlist_function(f);
+    }
+
+    mc_sgx_core_build::write_bindings(builder);
+}
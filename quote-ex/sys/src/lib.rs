@@ -0,0 +1,11 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+#![doc = include_str!("../README.md")]
+#![allow(non_camel_case_types)]
+
+use mc_sgx_core_sys_types::{
+    sgx_att_key_id_ext_t, sgx_att_key_id_t, sgx_qe_report_info_t, sgx_report_t, sgx_status_t,
+    sgx_target_info_t,
+};
+
+include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
@@ -0,0 +1 @@
+#include <sgx_uae_quote_ex.h>
@@ -0,0 +1,506 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+#![doc = include_str!("../README.md")]
+#![deny(missing_docs, missing_debug_implementations, unsafe_code)]
+
+mod manifest;
+mod metadata;
+
+use crate::{
+    manifest::BuildManifest,
+    metadata::{CargoMetadata, Package, SgxMetadata, Signing},
+};
+use clap::{Args, Parser, Subcommand};
+use displaydoc::Display;
+use mc_sgx_core_types::{FfiError, Sigstruct};
+use mc_sgx_sdk_tools::{
+    lint_file, EdgeRoutines, EdgeRoutinesError, Edger8r, Edger8rError, EnclaveLayout,
+    EnclaveLinker, Finding, LayoutError, LinkError, LintError, LintReport, OutputKind, SgxSign,
+};
+use std::{
+    env, fs,
+    io::Error as IoError,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+
+/// The environment variable the edge routines directory is provided in when
+/// building the enclave crate.
+const EDGE_ROUTINES_DIR_VAR: &str = "SGX_EDGE_ROUTINES_DIR";
+
+/// The only target SGX enclaves can be built for.
+const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+/// Errors which can occur when building an enclave.
+#[derive(Debug, Display)]
+pub enum Error {
+    /// There was an error running a command: {0}
+    Io(IoError),
+    /// Failed to read the cargo metadata: {0}
+    Metadata(String),
+    /// No package with `[package.metadata.sgx]` found, package name: {0:?}
+    NoEnclavePackage(Option<String>),
+    /// Multiple packages with `[package.metadata.sgx]`, use `--package`
+    MultipleEnclavePackages,
+    /// The package `{0}` does not have a `[package.metadata.sgx]` section
+    NoSgxMetadata(String),
+    /// The package `{0}` does not have a library target
+    NoLibTarget(String),
+    /**
+     * Either `private-key` or `public-key` with an optional `signature` must
+     * be provided
+     */
+    SigningConfiguration,
+    /// {0}
+    Edger8r(Edger8rError),
+    /// {0}
+    EdgeRoutines(EdgeRoutinesError),
+    /// {0}
+    Link(LinkError),
+    /// Failed to lint the enclave: {0}
+    Lint(LintError),
+    /// The enclave has errors:\n{0}
+    LintFindings(LintReport),
+    /// Failed to read the enclave memory layout: {0}
+    Layout(LayoutError),
+    /// Failed to read the enclave SIGSTRUCT: {0}
+    Sigstruct(FfiError),
+    /**
+     * The command failed,
+     * command:\n{0}\nstdout:\n{1}\n\nstderr:\n{2}
+     */
+    Command(String, String, String),
+}
+
+impl From<IoError> for Error {
+    fn from(src: IoError) -> Error {
+        Error::Io(src)
+    }
+}
+
+impl From<Edger8rError> for Error {
+    fn from(src: Edger8rError) -> Error {
+        Error::Edger8r(src)
+    }
+}
+
+impl From<EdgeRoutinesError> for Error {
+    fn from(src: EdgeRoutinesError) -> Error {
+        Error::EdgeRoutines(src)
+    }
+}
+
+impl From<LinkError> for Error {
+    fn from(src: LinkError) -> Error {
+        Error::Link(src)
+    }
+}
+
+impl From<LintError> for Error {
+    fn from(src: LintError) -> Error {
+        Error::Lint(src)
+    }
+}
+
+impl From<LayoutError> for Error {
+    fn from(src: LayoutError) -> Error {
+        Error::Layout(src)
+    }
+}
+
+impl From<FfiError> for Error {
+    fn from(src: FfiError) -> Error {
+        Error::Sigstruct(src)
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "cargo", bin_name = "cargo")]
+enum Cargo {
+    /// Build signed SGX enclaves
+    Sgx(SgxArgs),
+}
+
+#[derive(Debug, Args)]
+struct SgxArgs {
+    #[command(subcommand)]
+    command: SgxCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum SgxCommand {
+    /// Build, link and sign an enclave
+    Build(BuildArgs),
+}
+
+#[derive(Debug, Args)]
+struct BuildArgs {
+    /// Path to the Cargo.toml of the enclave crate or its workspace
+    #[arg(long)]
+    manifest_path: Option<PathBuf>,
+    /// The enclave package to build
+    #[arg(short, long)]
+    package: Option<String>,
+    /// Build the enclave in release mode
+    #[arg(long)]
+    release: bool,
+    /// Link against the simulation libraries, overriding the package metadata
+    #[arg(long)]
+    sim: bool,
+    /// Where to write the JSON build manifest
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+    /// Warn when the enclave would not fit in an EPC of this many bytes
+    #[arg(long)]
+    epc_size: Option<u64>,
+}
+
+fn main() -> ExitCode {
+    let Cargo::Sgx(args) = Cargo::parse();
+    let result = match args.command {
+        SgxCommand::Build(args) => build(&args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Run the whole enclave pipeline
+fn build(args: &BuildArgs) -> Result<(), Error> {
+    let cargo = PathBuf::from(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
+    let metadata = CargoMetadata::load(&cargo, args.manifest_path.as_deref())?;
+    let package = metadata.enclave_package(args.package.as_deref())?;
+    let sgx = package
+        .sgx()
+        .ok_or_else(|| Error::NoSgxMetadata(package.name.clone()))?;
+    let signing = sgx.signing()?;
+
+    let profile = if args.release { "release" } else { "debug" };
+    let work_dir = metadata
+        .target_directory
+        .join("sgx")
+        .join(profile)
+        .join(&package.name);
+    fs::create_dir_all(&work_dir)?;
+
+    let edge_routines = edge_routines(package, sgx, &work_dir)?;
+
+    let sim = args.sim || sgx.sim;
+    let lib_name = package.lib_name()?;
+    run(staticlib_command(
+        &cargo,
+        package,
+        args.release,
+        sim,
+        &work_dir,
+    ))?;
+    let staticlib = metadata
+        .target_directory
+        .join(TARGET)
+        .join(profile)
+        .join(format!("lib{lib_name}.a"));
+
+    let mut linker = EnclaveLinker::default().sim(sim);
+    linker.static_lib(&staticlib);
+    if let Some(edge_routines) = &edge_routines {
+        linker.static_lib(edge_routines);
+    }
+    for library in &sgx.libraries {
+        linker.library(library);
+    }
+    let unsigned = linker.link(work_dir.join(format!("{lib_name}.so")))?;
+    lint(unsigned.path(), sgx)?;
+
+    let config = package.dir().join(&sgx.config);
+    let signed = work_dir.join(format!("{lib_name}.signed.so"));
+    let mut sgx_sign = SgxSign::default()
+        .allow_relocations(sgx.allow_relocations)
+        .allow_init_sections(sgx.allow_init_sections);
+    match signing {
+        Signing::OneStep { private_key } => {
+            run(unsigned.sign(
+                &mut sgx_sign,
+                &config,
+                package.dir().join(private_key),
+                &signed,
+            ))?;
+        }
+        Signing::TwoStep {
+            public_key,
+            signature,
+        } => {
+            let signing_data = work_dir.join(format!("{lib_name}.sigdata"));
+            run(unsigned.gendata(&mut sgx_sign, &config, &signing_data))?;
+            let Some(signature) = signature else {
+                println!(
+                    "Signing data written to {}, sign it and set `signature` in `[package.metadata.sgx]`",
+                    signing_data.display()
+                );
+                return Ok(());
+            };
+            run(sgx_sign.catsig(
+                &unsigned,
+                &config,
+                package.dir().join(public_key),
+                &signing_data,
+                package.dir().join(signature),
+                &signed,
+            ))?;
+        }
+    }
+
+    let layout = EnclaveLayout::from_file(&signed)?;
+    print!("{layout}");
+    if let Some(warning) = args.epc_size.and_then(|size| layout.check_epc(size)) {
+        eprintln!("warning: {warning}");
+    }
+
+    let css_file = work_dir.join(format!("{lib_name}.css"));
+    run(sgx_sign.dump(
+        &signed,
+        &css_file,
+        work_dir.join(format!("{lib_name}.dump")),
+    ))?;
+    let sigstruct = Sigstruct::try_from(fs::read(&css_file)?.as_slice())?;
+
+    let manifest = BuildManifest::new(
+        &sigstruct,
+        &package.name,
+        &package.version,
+        profile,
+        sim,
+        unsigned.path(),
+        &signed,
+    );
+    let manifest_path = args
+        .manifest
+        .clone()
+        .unwrap_or_else(|| work_dir.join(format!("{lib_name}.manifest.json")));
+    let json = serde_json::to_string_pretty(&manifest)
+        .expect("Serializing the build manifest should not fail");
+    fs::write(&manifest_path, json)?;
+
+    println!("Signed enclave: {}", signed.display());
+    println!("MRENCLAVE: {}", manifest.mr_enclave());
+    println!("MRSIGNER: {}", manifest.mr_signer());
+    println!("Build manifest: {}", manifest_path.display());
+
+    Ok(())
+}
+
+/// Generate and compile the trusted edge routines
+///
+/// # Returns
+/// The path to the static library of the trusted edge routines, `None` when
+/// the package has no EDL file.
+fn edge_routines(
+    package: &Package,
+    sgx: &SgxMetadata,
+    work_dir: &Path,
+) -> Result<Option<PathBuf>, Error> {
+    let Some(edl) = &sgx.edl else {
+        return Ok(None);
+    };
+    let edl = package.dir().join(edl);
+    let out_dir = work_dir.join("edl");
+    fs::create_dir_all(&out_dir)?;
+
+    let mut edger8r = Edger8r::new(&edl)
+        .output_kind(OutputKind::Trusted)
+        .out_dir(&out_dir);
+    for path in &sgx.edl_search_paths {
+        edger8r.search_path(package.dir().join(path));
+    }
+    let files = edger8r.generate()?;
+
+    let mut routines = EdgeRoutines::new(files);
+    routines
+        .out_dir(&out_dir)
+        .target(TARGET)
+        .host(TARGET)
+        .opt_level(2);
+    if let Some(edl_dir) = edl.parent() {
+        routines.include_dir(edl_dir);
+    }
+    for dir in &sgx.include_dirs {
+        routines.include_dir(package.dir().join(dir));
+    }
+    routines.build()?;
+
+    let stem = edl
+        .file_stem()
+        .expect("EDL path should be a file")
+        .to_string_lossy();
+    Ok(Some(out_dir.join(format!("lib{stem}_t.a"))))
+}
+
+/// Lint the unsigned enclave, failing on any errors
+///
+/// Findings which the package metadata explicitly allows are skipped.
+fn lint(unsigned: &Path, sgx: &SgxMetadata) -> Result<(), Error> {
+    let report = lint_file(unsigned)?.filter(|finding| match finding {
+        Finding::TextRelocations => !sgx.allow_relocations,
+        Finding::InitSection(_) => !sgx.allow_init_sections,
+        _ => true,
+    });
+    if report.has_errors() {
+        return Err(Error::LintFindings(report));
+    }
+    eprint!("{report}");
+    Ok(())
+}
+
+/// The command to build the enclave crate as a `staticlib`
+///
+/// When linking against the simulation libraries the enclave crate's `sim`
+/// feature is enabled, if it has one, so its dependencies can do the same.
+fn staticlib_command(
+    cargo: &Path,
+    package: &Package,
+    release: bool,
+    sim: bool,
+    work_dir: &Path,
+) -> Command {
+    let mut command = Command::new(cargo);
+    command
+        .args(["rustc", "--lib", "--crate-type", "staticlib"])
+        .args(["--target", TARGET])
+        .arg("--manifest-path")
+        .arg(&package.manifest_path)
+        .env(EDGE_ROUTINES_DIR_VAR, work_dir.join("edl"));
+    if release {
+        command.arg("--release");
+    }
+    if sim && package.has_feature("sim") {
+        command.args(["--features", "sim"]);
+    }
+    command
+}
+
+/// Run the command, failing if it doesn't succeed
+fn run(command: Command) -> Result<(), Error> {
+    output(command).map(|_| ())
+}
+
+/// Run the command, returning its stdout
+pub(crate) fn output(mut command: Command) -> Result<Vec<u8>, Error> {
+    let output = command.output()?;
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(Error::Command(
+            format!("{command:?}"),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn verify_cli() {
+        Cargo::command().debug_assert();
+    }
+
+    #[test]
+    fn parses_build_args() {
+        let Cargo::Sgx(args) =
+            Cargo::parse_from(["cargo", "sgx", "build", "--release", "-p", "my-enclave"]);
+        let SgxCommand::Build(build) = args.command;
+        assert!(build.release);
+        assert!(!build.sim);
+        assert_eq!(build.package.as_deref(), Some("my-enclave"));
+    }
+
+    fn package(features: &str) -> Package {
+        let json = format!(
+            r#"{{
+                "name": "my-enclave",
+                "version": "1.2.3",
+                "manifest_path": "/work/enclave/Cargo.toml",
+                "targets": [{{"name": "my-enclave", "kind": ["staticlib"]}}],
+                "features": {features}
+            }}"#
+        );
+        serde_json::from_str(&json).expect("Failed to parse package")
+    }
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn staticlib_built_for_sgx_target() {
+        let package = package(r#"{"sim": []}"#);
+        let command = staticlib_command(
+            Path::new("cargo"),
+            &package,
+            true,
+            false,
+            Path::new("/work/target/sgx"),
+        );
+        assert_eq!(
+            args(&command),
+            [
+                "rustc",
+                "--lib",
+                "--crate-type",
+                "staticlib",
+                "--target",
+                TARGET,
+                "--manifest-path",
+                "/work/enclave/Cargo.toml",
+                "--release"
+            ]
+        );
+    }
+
+    #[test]
+    fn sim_enables_sim_feature() {
+        let package = package(r#"{"sim": []}"#);
+        let command = staticlib_command(
+            Path::new("cargo"),
+            &package,
+            false,
+            true,
+            Path::new("/work/target/sgx"),
+        );
+        assert!(args(&command).ends_with(&["--features".to_string(), "sim".to_string()]));
+    }
+
+    #[test]
+    fn sim_without_sim_feature_adds_no_features() {
+        let package = package("{}");
+        let command = staticlib_command(
+            Path::new("cargo"),
+            &package,
+            false,
+            true,
+            Path::new("/work/target/sgx"),
+        );
+        assert!(!args(&command).contains(&"--features".to_string()));
+    }
+
+    #[test]
+    fn failed_command_reports_output() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo out; echo err >&2; exit 1"]);
+        let error = output(command).unwrap_err();
+        let Error::Command(_, stdout, stderr) = error else {
+            panic!("Expected a command error");
+        };
+        assert_eq!(stdout, "out\n");
+        assert_eq!(stderr, "err\n");
+    }
+}
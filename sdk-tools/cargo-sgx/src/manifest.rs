@@ -0,0 +1,122 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! The JSON build manifest describing a signed enclave
+
+use mc_sgx_core_types::Sigstruct;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Describes a signed enclave produced by `cargo sgx build`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BuildManifest {
+    /// The name of the enclave package
+    package: String,
+    /// The version of the enclave package
+    version: String,
+    /// The cargo profile the enclave was built with
+    profile: String,
+    /// Whether the enclave was linked against the simulation libraries
+    sim: bool,
+    /// The path to the unsigned enclave
+    unsigned_enclave: PathBuf,
+    /// The path to the signed enclave
+    signed_enclave: PathBuf,
+    /// The MRENCLAVE of the signed enclave, hex encoded
+    mr_enclave: String,
+    /// The MRSIGNER of the signed enclave, hex encoded
+    mr_signer: String,
+    /// The ISV product ID of the enclave
+    isv_prod_id: u16,
+    /// The ISV security version of the enclave
+    isv_svn: u16,
+    /// Whether the enclave may be launched in debug mode
+    debug: bool,
+}
+
+impl BuildManifest {
+    /// A manifest for a signed enclave
+    ///
+    /// # Arguments
+    /// * `sigstruct` - The SIGSTRUCT of the signed enclave, the measurements
+    ///   and ISV values come from it
+    /// * `package` - The name of the enclave package
+    /// * `version` - The version of the enclave package
+    /// * `profile` - The cargo profile the enclave was built with
+    /// * `sim` - Whether the enclave was linked against the simulation
+    ///   libraries
+    /// * `unsigned_enclave` - The path to the unsigned enclave
+    /// * `signed_enclave` - The path to the signed enclave
+    pub fn new(
+        sigstruct: &Sigstruct,
+        package: impl Into<String>,
+        version: impl Into<String>,
+        profile: impl Into<String>,
+        sim: bool,
+        unsigned_enclave: impl Into<PathBuf>,
+        signed_enclave: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            package: package.into(),
+            version: version.into(),
+            profile: profile.into(),
+            sim,
+            unsigned_enclave: unsigned_enclave.into(),
+            signed_enclave: signed_enclave.into(),
+            mr_enclave: hex(sigstruct.mr_enclave().as_ref()),
+            mr_signer: hex(sigstruct.mr_signer().as_ref()),
+            isv_prod_id: sigstruct.isv_prod_id().into(),
+            isv_svn: sigstruct.isv_svn().into(),
+            debug: sigstruct.debug(),
+        }
+    }
+
+    /// The MRENCLAVE of the signed enclave, hex encoded
+    pub fn mr_enclave(&self) -> &str {
+        &self.mr_enclave
+    }
+
+    /// The MRSIGNER of the signed enclave, hex encoded
+    pub fn mr_signer(&self) -> &str {
+        &self.mr_signer
+    }
+}
+
+/// Lower case hex encoding of `bytes`
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_with_sigstruct_values() {
+        let mut bytes = vec![0; Sigstruct::SIZE];
+        bytes[960] = 0xAB;
+        bytes[1024] = 7;
+        bytes[1026] = 3;
+        let sigstruct = Sigstruct::try_from(bytes.as_slice()).unwrap();
+
+        let manifest = BuildManifest::new(
+            &sigstruct,
+            "my-enclave",
+            "1.2.3",
+            "release",
+            false,
+            "/t/my_enclave.so",
+            "/t/my_enclave.signed.so",
+        );
+
+        let json: serde_json::Value = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(
+            json["mr_enclave"],
+            "ab00000000000000000000000000000000000000000000000000000000000000"
+        );
+        assert_eq!(json["mr_signer"], hex(sigstruct.mr_signer().as_ref()));
+        assert_eq!(json["isv_prod_id"], 7);
+        assert_eq!(json["isv_svn"], 3);
+        assert_eq!(json["debug"], false);
+        assert_eq!(json["signed_enclave"], "/t/my_enclave.signed.so");
+    }
+}
@@ -0,0 +1,326 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! The cargo metadata for the enclave crate
+
+use crate::Error;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The subset of `cargo metadata` output needed to build an enclave.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct CargoMetadata {
+    /// The packages in the workspace
+    pub packages: Vec<Package>,
+    /// The directory cargo places build artifacts in
+    pub target_directory: PathBuf,
+}
+
+impl CargoMetadata {
+    /// Run `cargo metadata` for the given manifest
+    ///
+    /// # Arguments
+    /// * `cargo` - The cargo executable to use.
+    /// * `manifest_path` - The `Cargo.toml` to get the metadata for. When
+    ///   `None` cargo will look in the current directory.
+    pub fn load(cargo: &Path, manifest_path: Option<&Path>) -> Result<Self, Error> {
+        let mut command = Command::new(cargo);
+        command.args(["metadata", "--format-version", "1", "--no-deps"]);
+        if let Some(manifest_path) = manifest_path {
+            command.arg("--manifest-path").arg(manifest_path);
+        }
+        let output = crate::output(command)?;
+        serde_json::from_slice(&output).map_err(|e| Error::Metadata(e.to_string()))
+    }
+
+    /// Find the enclave package
+    ///
+    /// # Arguments
+    /// * `name` - The name of the package. When `None` the workspace must
+    ///   contain only one package with SGX metadata.
+    pub fn enclave_package(&self, name: Option<&str>) -> Result<&Package, Error> {
+        let mut candidates = self.packages.iter().filter(|package| match name {
+            Some(name) => package.name == name,
+            None => package.sgx().is_some(),
+        });
+        match (candidates.next(), candidates.next()) {
+            (Some(package), None) => Ok(package),
+            (None, _) => Err(Error::NoEnclavePackage(name.map(str::to_owned))),
+            (Some(_), Some(_)) => Err(Error::MultipleEnclavePackages),
+        }
+    }
+}
+
+/// A package from `cargo metadata`
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct Package {
+    /// The package name
+    pub name: String,
+    /// The package version
+    pub version: String,
+    /// The path to the package's `Cargo.toml`
+    pub manifest_path: PathBuf,
+    /// The targets of the package
+    pub targets: Vec<Target>,
+    /// The features of the package
+    #[serde(default)]
+    pub features: BTreeMap<String, Vec<String>>,
+    /// The `[package.metadata]` table
+    #[serde(default)]
+    metadata: Option<PackageMetadata>,
+}
+
+impl Package {
+    /// The `[package.metadata.sgx]` table, if present
+    pub fn sgx(&self) -> Option<&SgxMetadata> {
+        self.metadata.as_ref().and_then(|m| m.sgx.as_ref())
+    }
+
+    /// The directory containing the package's `Cargo.toml`
+    pub fn dir(&self) -> &Path {
+        self.manifest_path
+            .parent()
+            .expect("Manifest path should always be a file in a directory")
+    }
+
+    /// Whether the package declares the feature `name`
+    pub fn has_feature(&self, name: &str) -> bool {
+        self.features.contains_key(name)
+    }
+
+    /// The name of the library target as it appears in the built artifacts
+    pub fn lib_name(&self) -> Result<String, Error> {
+        self.targets
+            .iter()
+            .find(|target| {
+                target
+                    .kind
+                    .iter()
+                    .any(|kind| matches!(kind.as_str(), "lib" | "staticlib" | "rlib"))
+            })
+            .map(|target| target.name.replace('-', "_"))
+            .ok_or_else(|| Error::NoLibTarget(self.name.clone()))
+    }
+}
+
+/// A build target of a package
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct Target {
+    /// The name of the target
+    pub name: String,
+    /// The kinds of the target, i.e. "lib", "bin"
+    pub kind: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+struct PackageMetadata {
+    sgx: Option<SgxMetadata>,
+}
+
+/// The `[package.metadata.sgx]` table
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SgxMetadata {
+    /// The EDL file for the enclave
+    pub edl: Option<PathBuf>,
+    /// Search paths for EDL files imported by `edl`
+    #[serde(default)]
+    pub edl_search_paths: Vec<PathBuf>,
+    /// Directories containing headers needed to build the edge routines
+    #[serde(default)]
+    pub include_dirs: Vec<PathBuf>,
+    /// The enclave configuration file
+    pub config: PathBuf,
+    /// The private key for one step signing
+    pub private_key: Option<PathBuf>,
+    /// The public key for two step signing
+    pub public_key: Option<PathBuf>,
+    /// The signature for two step signing
+    pub signature: Option<PathBuf>,
+    /// Additional libraries to link into the enclave
+    #[serde(default)]
+    pub libraries: Vec<String>,
+    /// Whether to link against the simulation libraries
+    #[serde(default)]
+    pub sim: bool,
+    /// Allow relocations in the enclave, see
+    /// [`SgxSign::allow_relocations()`](mc_sgx_sdk_tools::SgxSign::allow_relocations)
+    #[serde(default)]
+    pub allow_relocations: bool,
+    /// Allow .init sections in the enclave, see
+    /// [`SgxSign::allow_init_sections()`](mc_sgx_sdk_tools::SgxSign::allow_init_sections)
+    #[serde(default)]
+    pub allow_init_sections: bool,
+}
+
+/// How the enclave is to be signed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Signing<'a> {
+    /// Sign in one step with the private key
+    OneStep {
+        /// The private key
+        private_key: &'a Path,
+    },
+    /// Generate the signing data, and sign with an externally produced
+    /// signature when available.
+    TwoStep {
+        /// The public key
+        public_key: &'a Path,
+        /// The signature, `None` until it has been produced
+        signature: Option<&'a Path>,
+    },
+}
+
+impl SgxMetadata {
+    /// How the enclave is to be signed
+    pub fn signing(&self) -> Result<Signing<'_>, Error> {
+        match (&self.private_key, &self.public_key, &self.signature) {
+            (Some(private_key), None, None) => Ok(Signing::OneStep { private_key }),
+            (None, Some(public_key), signature) => Ok(Signing::TwoStep {
+                public_key,
+                signature: signature.as_deref(),
+            }),
+            _ => Err(Error::SigningConfiguration),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(packages: &str) -> CargoMetadata {
+        let json = format!(r#"{{"packages": [{packages}], "target_directory": "/work/target"}}"#);
+        serde_json::from_str(&json).expect("Failed to parse metadata")
+    }
+
+    const ENCLAVE_PACKAGE: &str = r#"{
+        "name": "my-enclave",
+        "version": "1.2.3",
+        "manifest_path": "/work/enclave/Cargo.toml",
+        "targets": [{"name": "my-enclave", "kind": ["staticlib"]}],
+        "features": {"sim": ["mc-sgx-trts/sim"]},
+        "metadata": {
+            "sgx": {
+                "edl": "enclave.edl",
+                "edl-search-paths": ["edl"],
+                "config": "Enclave.config.xml",
+                "private-key": "private.pem",
+                "sim": true
+            }
+        }
+    }"#;
+
+    const HOST_PACKAGE: &str = r#"{
+        "name": "host",
+        "version": "1.2.3",
+        "manifest_path": "/work/host/Cargo.toml",
+        "targets": [{"name": "host", "kind": ["bin"]}],
+        "metadata": null
+    }"#;
+
+    #[test]
+    fn finds_enclave_package_by_metadata() {
+        let metadata = metadata(&format!("{HOST_PACKAGE}, {ENCLAVE_PACKAGE}"));
+        let package = metadata.enclave_package(None).unwrap();
+
+        assert_eq!(package.name, "my-enclave");
+        assert_eq!(package.dir(), Path::new("/work/enclave"));
+        assert_eq!(package.lib_name().unwrap(), "my_enclave");
+        assert!(package.has_feature("sim"));
+        assert_eq!(
+            package.sgx(),
+            Some(&SgxMetadata {
+                edl: Some("enclave.edl".into()),
+                edl_search_paths: vec!["edl".into()],
+                config: "Enclave.config.xml".into(),
+                private_key: Some("private.pem".into()),
+                sim: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn finds_enclave_package_by_name() {
+        let metadata = metadata(&format!("{HOST_PACKAGE}, {ENCLAVE_PACKAGE}"));
+        let package = metadata.enclave_package(Some("host")).unwrap();
+        assert_eq!(package.name, "host");
+        assert!(package.sgx().is_none());
+        assert!(!package.has_feature("sim"));
+        assert!(matches!(package.lib_name(), Err(Error::NoLibTarget(_))));
+    }
+
+    #[test]
+    fn no_enclave_package() {
+        let metadata = metadata(HOST_PACKAGE);
+        assert!(matches!(
+            metadata.enclave_package(None),
+            Err(Error::NoEnclavePackage(None))
+        ));
+    }
+
+    #[test]
+    fn multiple_enclave_packages() {
+        let other = ENCLAVE_PACKAGE.replace("my-enclave", "other-enclave");
+        let metadata = metadata(&format!("{ENCLAVE_PACKAGE}, {other}"));
+        assert!(matches!(
+            metadata.enclave_package(None),
+            Err(Error::MultipleEnclavePackages)
+        ));
+    }
+
+    #[test]
+    fn unknown_sgx_metadata_fails() {
+        let package = ENCLAVE_PACKAGE.replace("\"sim\"", "\"simulation\"");
+        let json = format!(r#"{{"packages": [{package}], "target_directory": "/t"}}"#);
+        assert!(serde_json::from_str::<CargoMetadata>(&json).is_err());
+    }
+
+    #[test]
+    fn one_step_signing() {
+        let sgx = SgxMetadata {
+            private_key: Some("private.pem".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            sgx.signing().unwrap(),
+            Signing::OneStep {
+                private_key: Path::new("private.pem")
+            }
+        );
+    }
+
+    #[test]
+    fn two_step_signing() {
+        let sgx = SgxMetadata {
+            public_key: Some("public.pem".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            sgx.signing().unwrap(),
+            Signing::TwoStep {
+                public_key: Path::new("public.pem"),
+                signature: None,
+            }
+        );
+    }
+
+    #[test]
+    fn conflicting_signing_fails() {
+        let sgx = SgxMetadata {
+            private_key: Some("private.pem".into()),
+            public_key: Some("public.pem".into()),
+            ..Default::default()
+        };
+        assert!(matches!(sgx.signing(), Err(Error::SigningConfiguration)));
+
+        assert!(matches!(
+            SgxMetadata::default().signing(),
+            Err(Error::SigningConfiguration)
+        ));
+    }
+}
@@ -0,0 +1,415 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Compilation of the edge routines generated by the edger8r tool
+
+use crate::edger8r::Edger8rFiles;
+use displaydoc::Display;
+use mc_sgx_core_build::SgxParseCallbacks;
+use std::{
+    env,
+    ffi::OsStr,
+    io::Error as IoError,
+    path::{Path, PathBuf},
+};
+
+/// Flags used when compiling the trusted edge routines.
+///
+/// These mirror the flags the Intel SGX SDK sample enclaves use for trusted
+/// code. The system C headers must not be used, the code has to be position
+/// independent and stack protection is expected by `sgx_sign`.
+const TRUSTED_FLAGS: &[&str] = &[
+    "-nostdinc",
+    "-fvisibility=hidden",
+    "-fpie",
+    "-ffunction-sections",
+    "-fdata-sections",
+    "-fstack-protector-strong",
+];
+
+/// Flags used when compiling the untrusted edge routines.
+const UNTRUSTED_FLAGS: &[&str] = &["-fstack-protector-strong"];
+
+/// Errors which can occur when building the edge routines.
+#[derive(Debug, Display)]
+pub enum Error {
+    /// There was an error writing the bindings: {0}
+    Io(IoError),
+    /// Failed to compile the edge routines: {0}
+    Compile(String),
+    /// Failed to generate the bindings for {0}: {1}
+    Bindings(PathBuf, String),
+}
+
+impl From<IoError> for Error {
+    fn from(src: IoError) -> Error {
+        Error::Io(src)
+    }
+}
+
+impl From<cc::Error> for Error {
+    fn from(src: cc::Error) -> Error {
+        Error::Compile(src.to_string())
+    }
+}
+
+/// The Rust bindings generated for the edge routines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EdgeBindings {
+    /// The bindings for the trusted edge routines. Will be `None` when no
+    /// trusted files were generated.
+    pub trusted: Option<PathBuf>,
+    /// The bindings for the untrusted edge routines. Will be `None` when no
+    /// untrusted files were generated.
+    pub untrusted: Option<PathBuf>,
+}
+
+/// Builds the C files generated by the [`Edger8r`](crate::Edger8r) tool.
+///
+/// The trusted (`*_t.c`) and untrusted (`*_u.c`) files are compiled into
+/// static libraries named after the EDL file, e.g. `foo_t` and `foo_u` for
+/// `foo.edl`. The cargo link directives for the libraries are emitted, so this
+/// is meant to be called from a build.rs.
+///
+/// Rust bindings are generated for the ECALLs and OCALLs declared in the
+/// generated headers. The bindings only contain the items declared in the
+/// generated headers, the SGX types they reference, like `sgx_status_t` and
+/// `sgx_enclave_id_t`, need to be brought into scope where the bindings are
+/// included, for example:
+///
+/// ```ignore
+/// use mc_sgx_core_sys_types::sgx_status_t;
+/// use mc_sgx_urts_sys_types::sgx_enclave_id_t;
+///
+/// include!(concat!(env!("OUT_DIR"), "/foo_u.rs"));
+/// ```
+#[derive(Debug)]
+pub struct EdgeRoutines {
+    /// The files generated by the edger8r tool.
+    files: Edger8rFiles,
+    /// The output directory for the libraries and bindings.
+    out_dir: Option<PathBuf>,
+    /// Additional include directories needed to compile the generated files.
+    include_dirs: Vec<PathBuf>,
+    /// The callbacks to use when generating the bindings.
+    parse_callbacks: Option<SgxParseCallbacks>,
+    /// The target triple to compile for, `TARGET` when not set.
+    target: Option<String>,
+    /// The host triple, `HOST` when not set.
+    host: Option<String>,
+    /// The optimization level, `OPT_LEVEL` when not set.
+    opt_level: Option<u32>,
+}
+
+impl EdgeRoutines {
+    /// Create a new builder for the edge routines.
+    ///
+    /// # Arguments
+    /// * `files` - The files generated by [`Edger8r::generate()`](crate::Edger8r::generate)
+    pub fn new(files: Edger8rFiles) -> Self {
+        Self {
+            files,
+            out_dir: None,
+            include_dirs: vec![],
+            parse_callbacks: None,
+            target: None,
+            host: None,
+            opt_level: None,
+        }
+    }
+
+    /// Specify the output directory
+    ///
+    /// # Arguments
+    /// * `out_dir` - the output directory to place the libraries and bindings
+    ///   in.
+    pub fn out_dir(&mut self, out_dir: impl AsRef<Path>) -> &mut Self {
+        self.out_dir = Some(out_dir.as_ref().to_owned());
+        self
+    }
+
+    /// Add an include directory needed by the generated files
+    ///
+    /// Usually needed when the EDL file includes headers defining the types
+    /// used in the ECALLs or OCALLs.
+    ///
+    /// # Arguments
+    /// * `include_dir` - A directory to search for included headers.
+    pub fn include_dir(&mut self, include_dir: impl AsRef<Path>) -> &mut Self {
+        self.include_dirs.push(include_dir.as_ref().to_owned());
+        self
+    }
+
+    /// The callbacks to use when generating the bindings
+    ///
+    /// Defaults to [`SgxParseCallbacks::default()`].
+    ///
+    /// # Arguments
+    /// * `callbacks` - The callbacks for bindgen to use.
+    pub fn parse_callbacks(&mut self, callbacks: SgxParseCallbacks) -> &mut Self {
+        self.parse_callbacks = Some(callbacks);
+        self
+    }
+
+    /// The target triple to compile the edge routines for
+    ///
+    /// Defaults to the `TARGET` environment variable cargo sets for build
+    /// scripts.
+    ///
+    /// # Arguments
+    /// * `target` - The target triple, e.g. `x86_64-unknown-linux-gnu`.
+    pub fn target(&mut self, target: impl Into<String>) -> &mut Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// The host triple the compiler runs on
+    ///
+    /// Defaults to the `HOST` environment variable cargo sets for build
+    /// scripts.
+    ///
+    /// # Arguments
+    /// * `host` - The host triple, e.g. `x86_64-unknown-linux-gnu`.
+    pub fn host(&mut self, host: impl Into<String>) -> &mut Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// The optimization level to compile the edge routines with
+    ///
+    /// Defaults to the `OPT_LEVEL` environment variable cargo sets for build
+    /// scripts.
+    ///
+    /// # Arguments
+    /// * `opt_level` - The optimization level, 0 to 3.
+    pub fn opt_level(&mut self, opt_level: u32) -> &mut Self {
+        self.opt_level = Some(opt_level);
+        self
+    }
+
+    /// Compile the edge routines and generate their bindings
+    ///
+    /// # Returns:
+    /// The paths to the generated bindings.
+    pub fn build(&self) -> Result<EdgeBindings, Error> {
+        let out_dir = match self.out_dir.as_ref() {
+            None => Self::default_out_dir(),
+            Some(dir) => dir.clone(),
+        };
+
+        let trusted = self.build_side(&self.files.trusted, &out_dir, true)?;
+        let untrusted = self.build_side(&self.files.untrusted, &out_dir, false)?;
+
+        Ok(EdgeBindings { trusted, untrusted })
+    }
+
+    /// Compile and generate bindings for one side of the edge routines
+    ///
+    /// # Arguments
+    /// * `files` - The header and C file for this side.
+    /// * `out_dir` - The directory to place the library and bindings in.
+    /// * `trusted` - `true` when building the trusted side.
+    fn build_side(
+        &self,
+        files: &[PathBuf],
+        out_dir: &Path,
+        trusted: bool,
+    ) -> Result<Option<PathBuf>, Error> {
+        let (Some(header), Some(module)) = (
+            Self::file_with_extension(files, "h"),
+            Self::file_with_extension(files, "c"),
+        ) else {
+            return Ok(None);
+        };
+
+        let stem = module
+            .file_stem()
+            .expect("Corrupted edge routine path")
+            .to_str()
+            .expect("Edge routine path contains invalid UTF-8");
+
+        let mut build = cc::Build::new();
+        build
+            .file(module)
+            .out_dir(out_dir)
+            .include(mc_sgx_core_build::sgx_include_dir())
+            .includes(&self.include_dirs);
+        if let Some(target) = &self.target {
+            build.target(target);
+        }
+        if let Some(host) = &self.host {
+            build.host(host);
+        }
+        if let Some(opt_level) = self.opt_level {
+            build.opt_level(opt_level);
+        }
+        if trusted {
+            build.include(mc_sgx_core_build::sgx_tlibc_include_dir());
+            for flag in TRUSTED_FLAGS {
+                build.flag(flag);
+            }
+        } else {
+            for flag in UNTRUSTED_FLAGS {
+                build.flag(flag);
+            }
+        }
+        build.try_compile(stem)?;
+
+        let mut builder = mc_sgx_core_build::sgx_builder()
+            .header(header.to_string_lossy())
+            .allowlist_file(Self::file_regex(header))
+            .parse_callbacks(Box::new(
+                self.parse_callbacks
+                    .as_ref()
+                    .map_or_else(SgxParseCallbacks::default, Clone::clone),
+            ));
+        for dir in &self.include_dirs {
+            builder = builder.clang_arg(format!("-I{}", dir.display()));
+        }
+        if trusted {
+            builder = builder.clang_args([
+                "-nostdinc".to_string(),
+                format!("-I{}", mc_sgx_core_build::sgx_tlibc_include_dir().display()),
+            ]);
+        }
+
+        let bindings = out_dir.join(stem).with_extension("rs");
+        builder
+            .generate()
+            .map_err(|e| Error::Bindings(header.to_owned(), e.to_string()))?
+            .write_to_file(&bindings)?;
+
+        cargo_emit::rerun_if_changed!(header.display(), module.display());
+
+        Ok(Some(bindings))
+    }
+
+    /// Find the file with the given extension in `files`
+    fn file_with_extension<'a>(files: &'a [PathBuf], extension: &str) -> Option<&'a Path> {
+        files
+            .iter()
+            .find(|f| f.extension() == Some(OsStr::new(extension)))
+            .map(PathBuf::as_path)
+    }
+
+    /// Regex for bindgen's `allowlist_file()` which matches only `path`.
+    fn file_regex(path: &Path) -> String {
+        let path = path.to_string_lossy();
+        let mut regex = String::with_capacity(path.len());
+        for c in path.chars() {
+            if !c.is_alphanumeric() && !matches!(c, '/' | '_' | '-') {
+                regex.push('\\');
+            }
+            regex.push(c);
+        }
+        regex
+    }
+
+    /// The default out directory to use.
+    ///
+    /// # Panics
+    /// If the environment variable `OUT_DIR` is not set. This assumes it is
+    /// being run as part of a build.rs as such it looks for the `OUT_DIR`.
+    fn default_out_dir() -> PathBuf {
+        let target_dir = env::var("OUT_DIR").expect(
+            "`OUT_DIR` environment variable is not set. Consider using `EdgeRoutines::out_dir()`.",
+        );
+        target_dir.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Edger8r;
+    use std::fs;
+
+    /// Edge routines for `files` with what `cc` would otherwise read from the
+    /// build script environment
+    fn edge_routines(files: Edger8rFiles) -> EdgeRoutines {
+        let mut routines = EdgeRoutines::new(files);
+        routines
+            .target("x86_64-unknown-linux-gnu")
+            .host("x86_64-unknown-linux-gnu")
+            .opt_level(0);
+        routines
+    }
+
+    static EDL: &str = r#"
+        enclave {
+            trusted {
+                public int ecall_add(int a, int b);
+            };
+            untrusted {
+                void ocall_print([in, string] const char* message);
+            };
+        };"#;
+
+    /// Generate the edge routines for [`EDL`] into `dir`
+    fn generated_files(dir: impl AsRef<Path>) -> Edger8rFiles {
+        let edl_file = dir.as_ref().join("math.edl");
+        fs::write(&edl_file, EDL).expect("Failed to write out EDL file");
+        Edger8r::new(edl_file)
+            .out_dir(dir)
+            .generate()
+            .expect("Failed to generate code from EDL file")
+    }
+
+    #[test]
+    fn builds_both_sides() {
+        let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let files = generated_files(&dir);
+
+        let bindings = edge_routines(files)
+            .out_dir(&dir)
+            .build()
+            .expect("Failed to build edge routines");
+
+        assert_eq!(
+            bindings,
+            EdgeBindings {
+                trusted: Some(dir.path().join("math_t.rs")),
+                untrusted: Some(dir.path().join("math_u.rs")),
+            }
+        );
+        for lib in ["libmath_t.a", "libmath_u.a"] {
+            assert!(dir.path().join(lib).exists());
+        }
+
+        let trusted = fs::read_to_string(dir.path().join("math_t.rs")).unwrap();
+        assert!(trusted.contains("pub fn ocall_print"));
+        assert!(trusted.contains("pub fn ecall_add"));
+
+        let untrusted = fs::read_to_string(dir.path().join("math_u.rs")).unwrap();
+        assert!(untrusted.contains("pub fn ecall_add"));
+        assert!(untrusted.contains("pub fn ocall_print"));
+    }
+
+    #[test]
+    fn only_untrusted_side() {
+        let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let mut files = generated_files(&dir);
+        files.trusted.clear();
+
+        let bindings = edge_routines(files)
+            .out_dir(&dir)
+            .build()
+            .expect("Failed to build edge routines");
+
+        assert_eq!(
+            bindings,
+            EdgeBindings {
+                trusted: None,
+                untrusted: Some(dir.path().join("math_u.rs")),
+            }
+        );
+        assert!(!dir.path().join("libmath_t.a").exists());
+    }
+
+    #[test]
+    fn file_regex_escapes_dots() {
+        assert_eq!(
+            EdgeRoutines::file_regex(Path::new("/tmp/out.dir/foo_t.h")),
+            r"/tmp/out\.dir/foo_t\.h"
+        );
+    }
+}
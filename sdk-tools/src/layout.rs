@@ -0,0 +1,579 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! The memory layout `sgx_sign` records in a signed enclave
+
+use displaydoc::Display;
+use goblin::elf::{
+    program_header::{PF_X, PT_LOAD},
+    Elf, ProgramHeader,
+};
+use std::{collections::BTreeMap, fmt, fs, io::Error as IoError, ops::Range, path::Path};
+
+/// The size of an enclave page in bytes
+pub const PAGE_SIZE: u64 = 0x1000;
+
+/// The section `sgx_sign` writes the enclave metadata to
+const METADATA_SECTION: &str = ".note.sgxmeta";
+
+/// The magic number the `metadata_t` starts with
+const METADATA_MAGIC: [u8; 8] = 0x86A8_0294_635D_0E4C_u64.to_le_bytes();
+
+/// `metadata_t.enclave_size`, the size of the ELRANGE
+const ENCLAVE_SIZE: Range<usize> = 40..48;
+
+/// `metadata_t.dirs[DIR_LAYOUT]`, the offset and size of the layout table
+/// relative to the metadata
+const LAYOUT_DIR_OFFSET: Range<usize> = 1880..1884;
+const LAYOUT_DIR_SIZE: Range<usize> = 1884..1888;
+
+/// The size of a `layout_t`, either a `layout_entry_t` or `layout_group_t`
+const LAYOUT_SIZE: usize = 32;
+
+/// Set in the ID of a `layout_group_t`
+const GROUP_FLAG: u16 = 1 << 12;
+
+/// `PAGE_ATTR_EADD`, the pages are added when the enclave is loaded
+const PAGE_ATTR_EADD: u16 = 1 << 0;
+
+/// The `LAYOUT_ID_*`s of the SGX SDK's `metadata.h`, the `_DYN` IDs are for
+/// the threads EDMM adds
+const LAYOUT_ID_HEAP_MIN: u16 = 1;
+const LAYOUT_ID_HEAP_INIT: u16 = 2;
+const LAYOUT_ID_HEAP_MAX: u16 = 3;
+const LAYOUT_ID_TCS: u16 = 4;
+const LAYOUT_ID_TD: u16 = 5;
+const LAYOUT_ID_SSA: u16 = 6;
+const LAYOUT_ID_STACK_MAX: u16 = 7;
+const LAYOUT_ID_STACK_MIN: u16 = 8;
+const LAYOUT_ID_GUARD: u16 = 10;
+const LAYOUT_ID_HEAP_DYN_MIN: u16 = 11;
+const LAYOUT_ID_HEAP_DYN_INIT: u16 = 12;
+const LAYOUT_ID_HEAP_DYN_MAX: u16 = 13;
+const LAYOUT_ID_TCS_DYN: u16 = 14;
+const LAYOUT_ID_TD_DYN: u16 = 15;
+const LAYOUT_ID_SSA_DYN: u16 = 16;
+const LAYOUT_ID_STACK_DYN_MAX: u16 = 17;
+const LAYOUT_ID_STACK_DYN_MIN: u16 = 18;
+const LAYOUT_ID_RSRV_MIN: u16 = 20;
+const LAYOUT_ID_RSRV_INIT: u16 = 21;
+const LAYOUT_ID_RSRV_MAX: u16 = 22;
+const LAYOUT_ID_USER_REGION: u16 = 23;
+
+/// Errors which can occur when reading an enclave's memory layout.
+#[derive(Debug, Display)]
+pub enum Error {
+    /// There was an error reading the enclave: {0}
+    Io(IoError),
+    /// The enclave is not a valid ELF file: {0}
+    Parse(String),
+    /// The enclave has no `sgx_sign` metadata, it must be signed first
+    NotSigned,
+    /// The enclave metadata is malformed: {0}
+    Metadata(String),
+}
+
+impl From<IoError> for Error {
+    fn from(src: IoError) -> Error {
+        Error::Io(src)
+    }
+}
+
+impl From<goblin::error::Error> for Error {
+    fn from(src: goblin::error::Error) -> Error {
+        Error::Parse(src.to_string())
+    }
+}
+
+/// What a [`Region`] of the enclave is used for
+#[derive(Copy, Clone, Debug, Display, Hash, PartialEq, Eq)]
+pub enum RegionKind {
+    /// code
+    Code,
+    /// data
+    Data,
+    /// heap
+    Heap,
+    /// reserved memory
+    Reserved,
+    /// user region
+    UserRegion,
+    /// guard
+    Guard,
+    /// stack
+    Stack,
+    /// TCS
+    Tcs,
+    /// SSA
+    Ssa,
+    /// thread data and TLS
+    ThreadData,
+    /// layout entry {0}
+    Other(u16),
+}
+
+impl From<u16> for RegionKind {
+    /// The kind of a `LAYOUT_ID_*`
+    fn from(id: u16) -> Self {
+        match id {
+            LAYOUT_ID_HEAP_MIN
+            | LAYOUT_ID_HEAP_INIT
+            | LAYOUT_ID_HEAP_MAX
+            | LAYOUT_ID_HEAP_DYN_MIN
+            | LAYOUT_ID_HEAP_DYN_INIT
+            | LAYOUT_ID_HEAP_DYN_MAX => RegionKind::Heap,
+            LAYOUT_ID_TCS | LAYOUT_ID_TCS_DYN => RegionKind::Tcs,
+            LAYOUT_ID_TD | LAYOUT_ID_TD_DYN => RegionKind::ThreadData,
+            LAYOUT_ID_SSA | LAYOUT_ID_SSA_DYN => RegionKind::Ssa,
+            LAYOUT_ID_STACK_MAX
+            | LAYOUT_ID_STACK_MIN
+            | LAYOUT_ID_STACK_DYN_MAX
+            | LAYOUT_ID_STACK_DYN_MIN => RegionKind::Stack,
+            LAYOUT_ID_GUARD => RegionKind::Guard,
+            LAYOUT_ID_RSRV_MIN | LAYOUT_ID_RSRV_INIT | LAYOUT_ID_RSRV_MAX => RegionKind::Reserved,
+            LAYOUT_ID_USER_REGION => RegionKind::UserRegion,
+            _ => RegionKind::Other(id),
+        }
+    }
+}
+
+/// A region of the enclave's address range
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Region {
+    /// What the region is used for
+    pub kind: RegionKind,
+    /// The pages in one instance of the region
+    pub pages: u64,
+    /// How many instances of the region there are, per thread regions are
+    /// repeated for each TCS.
+    pub count: u64,
+    /// Whether the pages are added to the EPC when the enclave is loaded.
+    /// Guard pages only reserve address space and with dynamic memory
+    /// management (EDMM) pages can be added after the enclave is loaded.
+    pub committed: bool,
+}
+
+impl Region {
+    fn image(kind: RegionKind, pages: u64) -> Self {
+        Self {
+            kind,
+            pages,
+            count: 1,
+            committed: true,
+        }
+    }
+
+    /// The pages in all instances of the region
+    pub fn total_pages(&self) -> u64 {
+        self.pages * self.count
+    }
+}
+
+/// The enclave commits {committed} bytes which exceeds the EPC size of {epc_size} bytes, it will cause EPC paging
+#[derive(Copy, Clone, Debug, Display, Hash, PartialEq, Eq)]
+pub struct EpcWarning {
+    /// The bytes of EPC the enclave uses once loaded
+    pub committed: u64,
+    /// The EPC size that was checked against
+    pub epc_size: u64,
+}
+
+/// The memory layout of a signed enclave
+///
+/// `sgx_sign` records the layout of everything it adds after the ELF image in
+/// the enclave metadata: the heap, reserved memory, and then the stack, TCS,
+/// SSA frames and thread data for each TCS, separated by guard pages. The
+/// uRTS loads the enclave from this layout, so it accounts for the SSA frame
+/// size `sgx_sign` derived from the enclave's XFRM and for the pages EDMM only
+/// adds once the enclave is running.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct EnclaveLayout {
+    regions: Vec<Region>,
+    enclave_size: u64,
+}
+
+impl EnclaveLayout {
+    /// Read the layout of a signed enclave
+    ///
+    /// # Arguments
+    /// * `enclave` - The path to the signed enclave.
+    ///
+    /// # Errors
+    /// [`Error::NotSigned`] if the enclave has no `sgx_sign` metadata.
+    pub fn from_file(enclave: impl AsRef<Path>) -> Result<Self, Error> {
+        let bytes = fs::read(enclave)?;
+        Self::new(&bytes)
+    }
+
+    /// Read the layout of a signed enclave
+    ///
+    /// # Arguments
+    /// * `enclave` - The contents of the signed enclave shared object.
+    ///
+    /// # Errors
+    /// [`Error::NotSigned`] if the enclave has no `sgx_sign` metadata.
+    pub fn new(enclave: &[u8]) -> Result<Self, Error> {
+        let elf = Elf::parse(enclave)?;
+        let section = elf
+            .section_headers
+            .iter()
+            .find(|sh| elf.shdr_strtab.get_at(sh.sh_name) == Some(METADATA_SECTION))
+            .ok_or(Error::NotSigned)?;
+        let metadata = enclave
+            .get(section.file_range().unwrap_or_default())
+            .ok_or_else(|| Error::Parse(format!("`{METADATA_SECTION}` is truncated")))?;
+        let metadata = metadata
+            .windows(METADATA_MAGIC.len())
+            .position(|window| window == METADATA_MAGIC)
+            .map(|offset| &metadata[offset..])
+            .ok_or(Error::NotSigned)?;
+
+        let (code_pages, data_pages) = image_pages(&elf.program_headers);
+        let mut regions = vec![
+            Region::image(RegionKind::Code, code_pages),
+            Region::image(RegionKind::Data, data_pages),
+        ];
+        regions.extend(layout_regions(metadata)?);
+
+        Ok(Self {
+            regions,
+            enclave_size: read_u64(metadata, ENCLAVE_SIZE)?,
+        })
+    }
+
+    /// The regions of the enclave
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// The pages of the regions of `kind`
+    pub fn pages(&self, kind: RegionKind) -> u64 {
+        self.regions
+            .iter()
+            .filter(|r| r.kind == kind)
+            .map(Region::total_pages)
+            .sum()
+    }
+
+    /// The size of the enclave's address range, its ELRANGE, in bytes
+    pub fn enclave_size(&self) -> u64 {
+        self.enclave_size
+    }
+
+    /// The bytes of EPC the enclave uses once loaded
+    pub fn committed_size(&self) -> u64 {
+        self.regions
+            .iter()
+            .filter(|r| r.committed)
+            .map(Region::total_pages)
+            .sum::<u64>()
+            * PAGE_SIZE
+    }
+
+    /// Check whether the enclave fits in the EPC
+    ///
+    /// # Arguments
+    /// * `epc_size` - The size of the EPC on the target platform in bytes.
+    ///
+    /// # Returns
+    /// A warning when the committed size of the enclave exceeds `epc_size`.
+    pub fn check_epc(&self, epc_size: u64) -> Option<EpcWarning> {
+        let committed = self.committed_size();
+        (committed > epc_size).then_some(EpcWarning {
+            committed,
+            epc_size,
+        })
+    }
+}
+
+impl fmt::Display for EnclaveLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for region in &self.regions {
+            let kind = region.kind.to_string();
+            write!(f, "{kind:<20}{:>10} pages", region.total_pages())?;
+            if region.count > 1 {
+                write!(f, " ({} x {})", region.count, region.pages)?;
+            }
+            if !region.committed && region.kind != RegionKind::Guard {
+                write!(f, " added at runtime")?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "{:<20}{:>10} bytes", "committed", self.committed_size())?;
+        writeln!(f, "{:<20}{:>#10x}", "enclave size", self.enclave_size())
+    }
+}
+
+/// The regions in the layout table of the `metadata`
+///
+/// A `layout_group_t` repeats the `entry_count` entries before it
+/// `load_times` more times, this is how the per thread entries are repeated
+/// for each TCS.
+fn layout_regions(metadata: &[u8]) -> Result<Vec<Region>, Error> {
+    let offset = read_u32(metadata, LAYOUT_DIR_OFFSET)? as usize;
+    let size = read_u32(metadata, LAYOUT_DIR_SIZE)? as usize;
+    let table = offset
+        .checked_add(size)
+        .and_then(|end| metadata.get(offset..end))
+        .ok_or_else(|| Error::Metadata("the layout table is out of bounds".into()))?;
+
+    let mut regions: Vec<Region> = vec![];
+    for layout in table.chunks_exact(LAYOUT_SIZE) {
+        let id = read_u16(layout, 0..2)?;
+        if id & GROUP_FLAG != 0 {
+            let entry_count = usize::from(read_u16(layout, 2..4)?);
+            let load_times = u64::from(read_u32(layout, 4..8)?);
+            let start = regions.len().checked_sub(entry_count).ok_or_else(|| {
+                Error::Metadata(format!("layout group {id:#x} has too many entries"))
+            })?;
+            for region in &mut regions[start..] {
+                region.count += load_times;
+            }
+        } else {
+            let attributes = read_u16(layout, 2..4)?;
+            regions.push(Region {
+                kind: id.into(),
+                pages: u64::from(read_u32(layout, 4..8)?),
+                count: 1,
+                committed: attributes & PAGE_ATTR_EADD != 0,
+            });
+        }
+    }
+    Ok(regions)
+}
+
+fn read_u16(bytes: &[u8], range: Range<usize>) -> Result<u16, Error> {
+    read(bytes, range).map(u16::from_le_bytes)
+}
+
+fn read_u32(bytes: &[u8], range: Range<usize>) -> Result<u32, Error> {
+    read(bytes, range).map(u32::from_le_bytes)
+}
+
+fn read_u64(bytes: &[u8], range: Range<usize>) -> Result<u64, Error> {
+    read(bytes, range).map(u64::from_le_bytes)
+}
+
+fn read<const N: usize>(bytes: &[u8], range: Range<usize>) -> Result<[u8; N], Error> {
+    bytes
+        .get(range)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| Error::Metadata("the metadata is truncated".into()))
+}
+
+/// The code and data pages of the ELF image
+///
+/// Segments can share pages, each page is counted once and is code when any
+/// segment on it is executable.
+fn image_pages(headers: &[ProgramHeader]) -> (u64, u64) {
+    let mut image = BTreeMap::new();
+    for header in headers.iter().filter(|header| header.p_type == PT_LOAD) {
+        let start = header.p_vaddr / PAGE_SIZE;
+        let end = pages(header.p_vaddr + header.p_memsz);
+        let executable = header.p_flags & PF_X != 0;
+        for page in start..end {
+            *image.entry(page).or_insert(false) |= executable;
+        }
+    }
+    let code = image.values().filter(|executable| **executable).count() as u64;
+    (code, image.len() as u64 - code)
+}
+
+/// The number of pages needed to hold `bytes`
+fn pages(bytes: u64) -> u64 {
+    (bytes + PAGE_SIZE - 1) / PAGE_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use goblin::elf::program_header::{PF_R, PF_W};
+    use std::process::Command;
+
+    /// `LAYOUT_ID_THREAD_GROUP`
+    const THREAD_GROUP: u16 = GROUP_FLAG | 9;
+
+    /// `PAGE_ATTR_POST_ADD`, the pages are added with EDMM
+    const PAGE_ATTR_POST_ADD: u16 = 1 << 3;
+
+    fn entry(id: u16, attributes: u16, pages: u32) -> [u8; LAYOUT_SIZE] {
+        let mut entry = [0; LAYOUT_SIZE];
+        entry[0..2].copy_from_slice(&id.to_le_bytes());
+        entry[2..4].copy_from_slice(&attributes.to_le_bytes());
+        entry[4..8].copy_from_slice(&pages.to_le_bytes());
+        entry
+    }
+
+    fn group(entry_count: u16, load_times: u32) -> [u8; LAYOUT_SIZE] {
+        let mut group = [0; LAYOUT_SIZE];
+        group[0..2].copy_from_slice(&THREAD_GROUP.to_le_bytes());
+        group[2..4].copy_from_slice(&entry_count.to_le_bytes());
+        group[4..8].copy_from_slice(&load_times.to_le_bytes());
+        group
+    }
+
+    /// A `metadata_t` with the layout table at the end of the fixed fields
+    fn metadata(layout: &[[u8; LAYOUT_SIZE]]) -> Vec<u8> {
+        let mut metadata = vec![0; LAYOUT_DIR_SIZE.end];
+        metadata[..8].copy_from_slice(&METADATA_MAGIC);
+        metadata[ENCLAVE_SIZE].copy_from_slice(&0x100_0000_u64.to_le_bytes());
+        metadata[LAYOUT_DIR_OFFSET].copy_from_slice(&(LAYOUT_DIR_SIZE.end as u32).to_le_bytes());
+        metadata[LAYOUT_DIR_SIZE]
+            .copy_from_slice(&((layout.len() * LAYOUT_SIZE) as u32).to_le_bytes());
+        metadata.extend(layout.iter().flatten());
+        metadata
+    }
+
+    /// The layout of 4 threads with 2 SSA frames of 2 pages each
+    fn thread_layout() -> Vec<[u8; LAYOUT_SIZE]> {
+        vec![
+            entry(LAYOUT_ID_HEAP_MIN, PAGE_ATTR_EADD, 16),
+            entry(LAYOUT_ID_GUARD, 0, 16),
+            entry(LAYOUT_ID_STACK_MAX, PAGE_ATTR_EADD, 8),
+            entry(LAYOUT_ID_TCS, PAGE_ATTR_EADD, 1),
+            entry(LAYOUT_ID_SSA, PAGE_ATTR_EADD, 4),
+            entry(LAYOUT_ID_GUARD, 0, 16),
+            entry(LAYOUT_ID_TD, PAGE_ATTR_EADD, 4),
+            group(5, 3),
+            entry(LAYOUT_ID_GUARD, 0, 16),
+        ]
+    }
+
+    /// Build a small enclave like position independent executable with
+    /// `metadata` in the section `sgx_sign` would write it to.
+    fn enclave(metadata: &[u8]) -> Vec<u8> {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("enclave.c");
+        let output = dir.path().join("enclave.so");
+        let bytes = metadata
+            .iter()
+            .map(|byte| byte.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        fs::write(
+            &source,
+            format!(
+                "__attribute__((section(\"{METADATA_SECTION}\"), used)) \
+                 static const unsigned char metadata[] = {{{bytes}}};\n\
+                 int enclave_entry(void) {{ return 0; }}"
+            ),
+        )
+        .unwrap();
+        let status = Command::new("cc")
+            .args(["-fPIE", "-pie", "-nostdlib", "-Wl,-eenclave_entry", "-o"])
+            .arg(&output)
+            .arg(&source)
+            .status()
+            .expect("Failed to run the C compiler");
+        assert!(status.success(), "Failed to compile test enclave");
+        fs::read(output).unwrap()
+    }
+
+    #[test]
+    fn thread_group_repeats_per_thread_regions() {
+        let regions = layout_regions(&metadata(&thread_layout())).unwrap();
+
+        let count = |kind| {
+            regions
+                .iter()
+                .filter(|r| r.kind == kind)
+                .map(|r| r.count)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(count(RegionKind::Heap), [1]);
+        assert_eq!(count(RegionKind::Stack), [4]);
+        assert_eq!(count(RegionKind::Tcs), [4]);
+        assert_eq!(count(RegionKind::Ssa), [4]);
+        assert_eq!(count(RegionKind::ThreadData), [4]);
+        // Only the guard between the SSA frames and thread data is in the group
+        assert_eq!(count(RegionKind::Guard), [1, 4, 1]);
+    }
+
+    #[test]
+    fn reads_signed_enclave() {
+        let layout = EnclaveLayout::new(&enclave(&metadata(&thread_layout()))).unwrap();
+
+        assert!(layout.pages(RegionKind::Code) > 0);
+        assert_eq!(layout.pages(RegionKind::Heap), 16);
+        assert_eq!(layout.pages(RegionKind::Ssa), 4 * 4);
+        assert_eq!(layout.pages(RegionKind::Guard), 6 * 16);
+        assert_eq!(layout.enclave_size(), 0x100_0000);
+
+        let image = layout.pages(RegionKind::Code) + layout.pages(RegionKind::Data);
+        let threads = 4 * (8 + 1 + 4 + 4);
+        assert_eq!(layout.committed_size(), (image + 16 + threads) * PAGE_SIZE);
+    }
+
+    #[test]
+    fn post_added_pages_are_not_committed() {
+        let layout = [
+            entry(LAYOUT_ID_HEAP_MIN, PAGE_ATTR_EADD, 4),
+            entry(LAYOUT_ID_HEAP_MAX, PAGE_ATTR_POST_ADD, 12),
+        ];
+        let layout = EnclaveLayout::new(&enclave(&metadata(&layout))).unwrap();
+
+        assert_eq!(layout.pages(RegionKind::Heap), 16);
+        let image = layout.pages(RegionKind::Code) + layout.pages(RegionKind::Data);
+        assert_eq!(layout.committed_size(), (image + 4) * PAGE_SIZE);
+        assert!(layout.to_string().contains("added at runtime"));
+    }
+
+    #[test]
+    fn unsigned_enclave_fails() {
+        assert!(matches!(
+            EnclaveLayout::new(&enclave(&[0; 8])),
+            Err(Error::NotSigned)
+        ));
+    }
+
+    #[test]
+    fn group_larger_than_layout_fails() {
+        let layout = [entry(LAYOUT_ID_TCS, PAGE_ATTR_EADD, 1), group(2, 3)];
+        assert!(matches!(
+            layout_regions(&metadata(&layout)),
+            Err(Error::Metadata(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_layout_table_fails() {
+        let mut metadata = metadata(&thread_layout());
+        metadata.truncate(metadata.len() - 1);
+        assert!(matches!(layout_regions(&metadata), Err(Error::Metadata(_))));
+    }
+
+    fn segment(p_vaddr: u64, p_memsz: u64, p_flags: u32) -> ProgramHeader {
+        ProgramHeader {
+            p_type: PT_LOAD,
+            p_flags,
+            p_vaddr,
+            p_memsz,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn overlapping_segments_share_pages() {
+        let headers = [
+            // Code in pages 0 to 2, ending part way into page 2
+            segment(0, 0x2800, PF_R | PF_X),
+            // Read only data from the middle of page 2 into page 3
+            segment(0x2800, 0x1000, PF_R),
+            // Data in pages 5 and 6, leaving a gap
+            segment(0x5000, 0x2000, PF_R | PF_W),
+        ];
+
+        // Page 2 is shared, it's code as it's executable
+        assert_eq!(image_pages(&headers), (3, 3));
+    }
+
+    #[test]
+    fn warns_when_larger_than_epc() {
+        let layout = [entry(LAYOUT_ID_HEAP_MIN, PAGE_ATTR_EADD, 0x4000)];
+        let layout = EnclaveLayout::new(&enclave(&metadata(&layout))).unwrap();
+
+        assert_eq!(layout.check_epc(0x1000_0000), None);
+        let warning = layout.check_epc(0x200_0000).unwrap();
+        assert_eq!(warning.committed, layout.committed_size());
+        assert_eq!(warning.epc_size, 0x200_0000);
+    }
+}
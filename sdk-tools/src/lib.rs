@@ -2,8 +2,23 @@
 
 #![doc = include_str!("../README.md")]
 #![deny(missing_docs, missing_debug_implementations, unsafe_code)]
+mod edge_routines;
 mod edger8r;
+mod layout;
+mod link;
+mod lint;
+mod pcl;
 mod sign;
 
-pub use crate::edger8r::Edger8r;
+pub use crate::edge_routines::{EdgeBindings, EdgeRoutines, Error as EdgeRoutinesError};
+pub use crate::edger8r::{Edger8r, Edger8rFiles, Error as Edger8rError, OutputKind};
+pub use crate::layout::{
+    EnclaveLayout, EpcWarning, Error as LayoutError, Region, RegionKind, PAGE_SIZE,
+};
+pub use crate::link::{EnclaveLinker, Error as LinkError, UnsignedEnclave};
+pub use crate::lint::{lint, lint_file, Error as LintError, Finding, LintReport, Severity};
+pub use crate::pcl::{
+    verify_encryption, verify_encryption_files, Error as PclError, PclKey, PclReport, SealedPclKey,
+    SgxEncrypt, PCL_GUID_SIZE, PCL_KEY_SIZE, SEALED_PCL_KEY_SIZE,
+};
 pub use crate::sign::SgxSign;
@@ -0,0 +1,432 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Linking of an enclave shared object from static libraries
+
+use crate::{pcl, PclReport, SgxEncrypt, SgxSign};
+use displaydoc::Display;
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    io::Error as IoError,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The symbols an enclave must export for the uRTS to load it.
+///
+/// Everything else is made local to avoid exposing symbols outside of the
+/// enclave.
+const VERSION_SCRIPT: &str = r#"enclave.so
+{
+    global:
+        g_global_data_sim;
+        g_global_data;
+        enclave_entry;
+        g_peak_heap_used;
+        g_peak_rsrv_mem_committed;
+    local:
+        *;
+};
+"#;
+
+/// Errors which can occur when linking an enclave.
+#[derive(Debug, Display)]
+pub enum Error {
+    /// There was an error running the command: {0}
+    Io(IoError),
+    /**
+     * There was an error linking the enclave,
+     * command:\n{0}\nstdout:\n{1}\n\nstderr:\n{2}
+     */
+    Link(String, String, String),
+}
+
+impl From<IoError> for Error {
+    fn from(src: IoError) -> Error {
+        Error::Io(src)
+    }
+}
+
+/// Links an unsigned enclave shared object.
+///
+/// The enclave code is expected to be provided as a Rust `staticlib` along with
+/// any other static libraries, like the trusted edge routines built by
+/// [`EdgeRoutines`](crate::EdgeRoutines). The SGX trusted runtime libraries
+/// are linked in with `--whole-archive` so that the enclave entry point and
+/// the global data the loader looks for are always present.
+///
+/// The simulation versions of the trusted runtime libraries are used when the
+/// `sim` feature is enabled for the crate whose build.rs is running, this can
+/// be overridden with [`EnclaveLinker::sim()`].
+///
+/// The resulting enclave must be signed before it can be loaded, see
+/// [`UnsignedEnclave::sign()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnclaveLinker {
+    /// The path to the compiler driver used for linking.
+    linker_path: PathBuf,
+    /// The static libraries which make up the enclave.
+    static_libs: Vec<PathBuf>,
+    /// Additional SGX, or other, libraries to link against.
+    libraries: Vec<String>,
+    /// Additional library search paths.
+    search_paths: Vec<PathBuf>,
+    /// The version script to use. When `None` the default version script is
+    /// used.
+    version_script: Option<PathBuf>,
+    /// Whether to link against the simulation libraries.
+    sim: bool,
+    /// Whether to link in the Protected Code Loader.
+    pcl: bool,
+}
+
+impl Default for EnclaveLinker {
+    /// Create a new linker from the current environment.
+    ///
+    /// The linker will be the `CC` environment variable, falling back to `cc`.
+    fn default() -> Self {
+        let linker_path = env::var_os("CC").unwrap_or_else(|| OsString::from("cc"));
+        EnclaveLinker::from(PathBuf::from(linker_path))
+    }
+}
+
+impl EnclaveLinker {
+    /// Add a static library to the enclave
+    ///
+    /// The library is linked in the same group as the SGX trusted libraries,
+    /// only the objects the enclave references are included.
+    ///
+    /// # Arguments
+    /// * `static_lib` - The path to the static library, usually a Rust
+    ///   `staticlib` or the trusted edge routines.
+    pub fn static_lib(&mut self, static_lib: impl AsRef<Path>) -> &mut Self {
+        self.static_libs.push(static_lib.as_ref().to_owned());
+        self
+    }
+
+    /// Add a library to link against
+    ///
+    /// # Arguments
+    /// * `library` - The name of the library, without the `lib` prefix or
+    ///   file extension, e.g. `sgx_tcxx`.
+    pub fn library(&mut self, library: impl AsRef<str>) -> &mut Self {
+        self.libraries.push(library.as_ref().to_owned());
+        self
+    }
+
+    /// Add a search path for libraries
+    ///
+    /// The SGX library directory is always searched.
+    ///
+    /// # Arguments
+    /// * `search_path` - A directory to search for libraries in.
+    pub fn search_path(&mut self, search_path: impl AsRef<Path>) -> &mut Self {
+        self.search_paths.push(search_path.as_ref().to_owned());
+        self
+    }
+
+    /// Use the provided version script instead of the default
+    ///
+    /// The version script must export `enclave_entry` and the global data,
+    /// `g_global_data` or `g_global_data_sim`, for the enclave to load.
+    ///
+    /// # Arguments
+    /// * `version_script` - The path to the linker version script.
+    #[must_use]
+    pub fn version_script(mut self, version_script: impl AsRef<Path>) -> Self {
+        self.version_script = Some(version_script.as_ref().to_owned());
+        self
+    }
+
+    /// Whether to link against the simulation libraries
+    #[must_use]
+    pub fn sim(mut self, sim: bool) -> Self {
+        self.sim = sim;
+        self
+    }
+
+    /// Whether to link in the Protected Code Loader
+    ///
+    /// An enclave must be linked with the PCL to be encrypted, see
+    /// [`UnsignedEnclave::encrypt()`].
+    #[must_use]
+    pub fn pcl(mut self, pcl: bool) -> Self {
+        self.pcl = pcl;
+        self
+    }
+
+    /// Generate the command to link the enclave
+    ///
+    /// # Arguments
+    /// * `version_script` - The linker version script to use.
+    /// * `output_enclave` - The path to write the unsigned enclave to.
+    pub fn command(
+        &self,
+        version_script: impl AsRef<Path>,
+        output_enclave: impl AsRef<Path>,
+    ) -> Command {
+        let suffix = if self.sim { "_sim" } else { "" };
+        let mut cmd = Command::new(&self.linker_path);
+        cmd.arg("-o")
+            .arg(output_enclave.as_ref())
+            .args(["-nostdlib", "-nodefaultlibs", "-nostartfiles"])
+            .arg("-fstack-protector-strong")
+            .arg(format!("-L{}", mc_sgx_core_build::sgx_library_string()));
+
+        for path in &self.search_paths {
+            cmd.arg(format!("-L{}", path.display()));
+        }
+
+        // Only the trusted runtime, and the PCL which hooks into it, are whole
+        // archives. The enclave's own libraries join the group below so unused
+        // objects can be dropped.
+        cmd.arg("-Wl,--no-undefined").arg("-Wl,--whole-archive");
+        if self.pcl {
+            cmd.arg(if self.sim {
+                "-lsgx_pclsim"
+            } else {
+                "-lsgx_pcl"
+            });
+        }
+        cmd.arg(format!("-lsgx_trts{suffix}"))
+            .arg("-Wl,--no-whole-archive")
+            .arg("-Wl,--start-group")
+            .args(["-lsgx_tstdc", "-lsgx_tcrypto"])
+            .arg(format!("-lsgx_tservice{suffix}"));
+        for lib in &self.static_libs {
+            cmd.arg(lib);
+        }
+        for lib in &self.libraries {
+            cmd.arg(format!("-l{lib}"));
+        }
+        cmd.arg("-Wl,--end-group")
+            .args([
+                "-Wl,-Bstatic",
+                "-Wl,-Bsymbolic",
+                "-Wl,-pie,-eenclave_entry",
+                "-Wl,--export-dynamic",
+                "-Wl,--defsym,__ImageBase=0",
+                "-Wl,--gc-sections",
+            ])
+            .arg(format!(
+                "-Wl,--version-script={}",
+                version_script.as_ref().display()
+            ));
+
+        cmd
+    }
+
+    /// Link the enclave
+    ///
+    /// When no version script was provided the default one will be written
+    /// next to the `output_enclave`.
+    ///
+    /// # Arguments
+    /// * `output_enclave` - The path to write the unsigned enclave to.
+    pub fn link(self, output_enclave: impl AsRef<Path>) -> Result<UnsignedEnclave, Error> {
+        let output_enclave = output_enclave.as_ref();
+        let version_script = match self.version_script.as_ref() {
+            Some(script) => script.clone(),
+            None => {
+                let script = output_enclave.with_extension("lds");
+                fs::write(&script, VERSION_SCRIPT)?;
+                script
+            }
+        };
+
+        let mut command = self.command(version_script, output_enclave);
+        let output = command.output()?;
+
+        if output.status.success() {
+            Ok(UnsignedEnclave(output_enclave.to_owned()))
+        } else {
+            Err(Error::Link(
+                format!("{command:?}"),
+                String::from_utf8_lossy(&output.stdout).into_owned(),
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ))
+        }
+    }
+}
+
+/// Construct a new linker around the given compiler driver path
+impl From<PathBuf> for EnclaveLinker {
+    fn from(linker_path: PathBuf) -> Self {
+        Self {
+            linker_path,
+            static_libs: vec![],
+            libraries: vec![],
+            search_paths: vec![],
+            version_script: None,
+            sim: !mc_sgx_core_build::sgx_library_suffix().is_empty(),
+            pcl: false,
+        }
+    }
+}
+
+/// An enclave shared object which has been linked but not yet signed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnsignedEnclave(PathBuf);
+
+impl UnsignedEnclave {
+    /// The path to the unsigned enclave
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Generate the command to sign the enclave in one step
+    ///
+    /// See [`SgxSign::sign()`].
+    pub fn sign(
+        &self,
+        sgx_sign: &mut SgxSign,
+        config_file: impl AsRef<Path>,
+        private_key: impl AsRef<Path>,
+        output_enclave: impl AsRef<Path>,
+    ) -> Command {
+        sgx_sign.sign(&self.0, config_file, private_key, output_enclave)
+    }
+
+    /// Generate the command to create the data for signing the enclave offline
+    ///
+    /// See [`SgxSign::gendata()`].
+    pub fn gendata(
+        &self,
+        sgx_sign: &mut SgxSign,
+        config_file: impl AsRef<Path>,
+        output_datafile: impl AsRef<Path>,
+    ) -> Command {
+        sgx_sign.gendata(&self.0, config_file, output_datafile)
+    }
+
+    /// Encrypt the enclave with the Protected Code Loader
+    ///
+    /// The encrypted enclave is verified against this one with
+    /// [`verify_encryption()`](crate::verify_encryption) before it is
+    /// returned, ready to be signed.
+    ///
+    /// # Arguments
+    /// * `sgx_encrypt` - The encryption tool to use.
+    /// * `key_file` - The key to encrypt with, see
+    ///   [`PclKey::write_file()`](crate::PclKey::write_file).
+    /// * `output_enclave` - The path to write the encrypted enclave to.
+    ///
+    /// # Returns
+    /// The encrypted enclave and the sections which were encrypted.
+    pub fn encrypt(
+        &self,
+        sgx_encrypt: &mut SgxEncrypt,
+        key_file: impl AsRef<Path>,
+        output_enclave: impl AsRef<Path>,
+    ) -> Result<(UnsignedEnclave, PclReport), pcl::Error> {
+        let output_enclave = output_enclave.as_ref();
+        let mut command = sgx_encrypt.encrypt(&self.0, key_file, output_enclave);
+        let output = command.output()?;
+
+        if !output.status.success() {
+            return Err(pcl::Error::Encrypt(
+                format!("{command:?}"),
+                String::from_utf8_lossy(&output.stdout).into_owned(),
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+
+        let report = pcl::verify_encryption_files(&self.0, output_enclave)?;
+        Ok((UnsignedEnclave(output_enclave.to_owned()), report))
+    }
+}
+
+impl From<PathBuf> for UnsignedEnclave {
+    fn from(path: PathBuf) -> Self {
+        Self(path)
+    }
+}
+
+impl AsRef<Path> for UnsignedEnclave {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_hw_link_command() {
+        let mut linker = EnclaveLinker::from(PathBuf::from("cc")).sim(false);
+        linker
+            .static_lib("path_to_staticlib/libenclave.a")
+            .static_lib("path_to_edge/libenclave_t.a");
+        let cmd = format!(
+            "{:?}",
+            linker.command("path_to_version_script/file", "path_to_output/file")
+        );
+        let expected_cmd = "\"cc\" \"-o\" \"path_to_output/file\" \"-nostdlib\" \"-nodefaultlibs\" \"-nostartfiles\" \"-fstack-protector-strong\" \"-L/opt/intel/sgxsdk/lib64\" \"-Wl,--no-undefined\" \"-Wl,--whole-archive\" \"-lsgx_trts\" \"-Wl,--no-whole-archive\" \"-Wl,--start-group\" \"-lsgx_tstdc\" \"-lsgx_tcrypto\" \"-lsgx_tservice\" \"path_to_staticlib/libenclave.a\" \"path_to_edge/libenclave_t.a\" \"-Wl,--end-group\" \"-Wl,-Bstatic\" \"-Wl,-Bsymbolic\" \"-Wl,-pie,-eenclave_entry\" \"-Wl,--export-dynamic\" \"-Wl,--defsym,__ImageBase=0\" \"-Wl,--gc-sections\" \"-Wl,--version-script=path_to_version_script/file\"";
+
+        assert_eq!(expected_cmd, cmd);
+    }
+
+    #[test]
+    fn generate_sim_link_command_with_extra_libraries() {
+        let mut linker = EnclaveLinker::from(PathBuf::from("cc")).sim(true);
+        linker
+            .static_lib("path_to_staticlib/libenclave.a")
+            .search_path("path_to_libs")
+            .library("sgx_tcxx");
+        let cmd = format!(
+            "{:?}",
+            linker.command("path_to_version_script/file", "path_to_output/file")
+        );
+        let expected_cmd = "\"cc\" \"-o\" \"path_to_output/file\" \"-nostdlib\" \"-nodefaultlibs\" \"-nostartfiles\" \"-fstack-protector-strong\" \"-L/opt/intel/sgxsdk/lib64\" \"-Lpath_to_libs\" \"-Wl,--no-undefined\" \"-Wl,--whole-archive\" \"-lsgx_trts_sim\" \"-Wl,--no-whole-archive\" \"-Wl,--start-group\" \"-lsgx_tstdc\" \"-lsgx_tcrypto\" \"-lsgx_tservice_sim\" \"path_to_staticlib/libenclave.a\" \"-lsgx_tcxx\" \"-Wl,--end-group\" \"-Wl,-Bstatic\" \"-Wl,-Bsymbolic\" \"-Wl,-pie,-eenclave_entry\" \"-Wl,--export-dynamic\" \"-Wl,--defsym,__ImageBase=0\" \"-Wl,--gc-sections\" \"-Wl,--version-script=path_to_version_script/file\"";
+
+        assert_eq!(expected_cmd, cmd);
+    }
+
+    #[test]
+    fn generate_pcl_link_command() {
+        let mut linker = EnclaveLinker::from(PathBuf::from("cc"))
+            .sim(false)
+            .pcl(true);
+        linker.static_lib("path_to_staticlib/libenclave.a");
+        let cmd = format!(
+            "{:?}",
+            linker.command("path_to_version_script/file", "path_to_output/file")
+        );
+        let expected_cmd = "\"cc\" \"-o\" \"path_to_output/file\" \"-nostdlib\" \"-nodefaultlibs\" \"-nostartfiles\" \"-fstack-protector-strong\" \"-L/opt/intel/sgxsdk/lib64\" \"-Wl,--no-undefined\" \"-Wl,--whole-archive\" \"-lsgx_pcl\" \"-lsgx_trts\" \"-Wl,--no-whole-archive\" \"-Wl,--start-group\" \"-lsgx_tstdc\" \"-lsgx_tcrypto\" \"-lsgx_tservice\" \"path_to_staticlib/libenclave.a\" \"-Wl,--end-group\" \"-Wl,-Bstatic\" \"-Wl,-Bsymbolic\" \"-Wl,-pie,-eenclave_entry\" \"-Wl,--export-dynamic\" \"-Wl,--defsym,__ImageBase=0\" \"-Wl,--gc-sections\" \"-Wl,--version-script=path_to_version_script/file\"";
+
+        assert_eq!(expected_cmd, cmd);
+    }
+
+    #[test]
+    fn unsigned_enclave_sign_command() {
+        let enclave = UnsignedEnclave::from(PathBuf::from("path_to_unsigned_enclave/file"));
+        let cmd = format!(
+            "{:?}",
+            enclave.sign(
+                &mut SgxSign::default(),
+                "path_to_config/file",
+                "path_to_private_key/file",
+                "path_to_output_enclave/file",
+            )
+        );
+        let expected_cmd = "\"/opt/intel/sgxsdk/bin/x64/sgx_sign\" \"sign\" \"-enclave\" \"path_to_unsigned_enclave/file\" \"-config\" \"path_to_config/file\" \"-key\" \"path_to_private_key/file\" \"-out\" \"path_to_output_enclave/file\"";
+
+        assert_eq!(expected_cmd, cmd);
+    }
+
+    #[test]
+    fn failed_link_reports_command() {
+        let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let mut linker = EnclaveLinker::from(PathBuf::from("cc")).sim(false);
+        linker.static_lib(dir.path().join("does_not_exist.a"));
+
+        let error = linker
+            .link(dir.path().join("enclave.so"))
+            .expect_err("Linking a non existent library should fail");
+
+        assert!(matches!(error, Error::Link(_, _, _)));
+        assert!(dir.path().join("enclave.lds").exists());
+    }
+}
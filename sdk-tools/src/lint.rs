@@ -0,0 +1,435 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Static analysis of an unsigned enclave shared object
+
+use displaydoc::Display;
+use goblin::elf::{
+    dynamic::{DF_TEXTREL, DT_FLAGS, DT_INIT, DT_TEXTREL},
+    header::{EM_X86_64, ET_DYN},
+    program_header::{PF_W, PF_X, PT_LOAD},
+    reloc::{R_X86_64_DTPMOD64, R_X86_64_DTPOFF64},
+    section_header::SHN_UNDEF,
+    sym::STB_WEAK,
+    Elf,
+};
+use std::{fmt, fs, io::Error as IoError, path::Path};
+
+/// Sections whose code the trusted runtime will never run.
+///
+/// The trusted runtime only runs the constructors in `.init_array`.
+const INIT_SECTIONS: &[&str] = &[".init", ".ctors", ".dtors"];
+
+/// The symbol used to enter the enclave.
+const ENCLAVE_ENTRY: &str = "enclave_entry";
+
+/// The global data the uRTS looks for, one of these must be exported.
+const GLOBAL_DATA: &[&str] = &["g_global_data", "g_global_data_sim"];
+
+/// Errors which prevent an enclave from being linted.
+#[derive(Debug, Display)]
+pub enum Error {
+    /// There was an error reading the enclave: {0}
+    Io(IoError),
+    /// The enclave is not a valid ELF file: {0}
+    Parse(String),
+    /// The enclave must be a 64 bit x86 ELF file
+    UnsupportedArchitecture,
+}
+
+impl From<IoError> for Error {
+    fn from(src: IoError) -> Error {
+        Error::Io(src)
+    }
+}
+
+impl From<goblin::error::Error> for Error {
+    fn from(src: goblin::error::Error) -> Error {
+        Error::Parse(src.to_string())
+    }
+}
+
+/// How serious a [`Finding`] is.
+#[derive(Copy, Clone, Debug, Display, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// warning
+    Warning,
+    /// error
+    Error,
+}
+
+/// An issue found in an enclave.
+#[derive(Clone, Debug, Display, Hash, PartialEq, Eq)]
+pub enum Finding {
+    /// The enclave is not a position independent shared object, ELF type {0}. Link with `-shared` or `-pie`.
+    NotPie(u16),
+    /// The enclave contains text relocations, usually from code which was not compiled with `-fPIC`.
+    TextRelocations,
+    /// The enclave contains a `{0}` section, the trusted runtime only runs the constructors in `.init_array`.
+    InitSection(String),
+    /// The enclave does not export `{0}`, it will fail to load.
+    MissingSymbol(String),
+    /// The enclave imports `{0}`, enclaves can not resolve symbols at load time. Was a library like libc linked in?
+    UndefinedSymbol(String),
+    /// The enclave has a weak import of `{0}`, it will always be null.
+    WeakUndefinedSymbol(String),
+    /// The enclave depends on `{0}`. Enclaves can not load shared libraries.
+    SharedLibrary(String),
+    /// Loadable segment {0} is both writable and executable. Link with `-Wl,-z,noexecstack` and don't use `-N` or `--omagic`.
+    WritableExecutableSegment(usize),
+    /// The enclave uses a dynamic TLS model, relocation type {0}. Compile with `-ftls-model=local-exec`.
+    DynamicTls(u32),
+}
+
+impl Finding {
+    /// The severity of the finding
+    ///
+    /// Findings which are [`Severity::Error`] will prevent the enclave from
+    /// being signed or loaded, or undermine its security.
+    pub fn severity(&self) -> Severity {
+        match self {
+            Finding::WeakUndefinedSymbol(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/// The findings from linting an enclave
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LintReport {
+    findings: Vec<Finding>,
+}
+
+impl LintReport {
+    /// All of the findings
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+
+    /// `true` when any finding is a [`Severity::Error`]
+    pub fn has_errors(&self) -> bool {
+        self.findings
+            .iter()
+            .any(|f| f.severity() == Severity::Error)
+    }
+
+    /// Keep only the findings for which `keep` returns `true`
+    #[must_use]
+    pub fn filter(mut self, keep: impl FnMut(&Finding) -> bool) -> Self {
+        self.findings.retain(keep);
+        self
+    }
+
+    /// `true` when there are no findings
+    pub fn is_empty(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+impl fmt::Display for LintReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for finding in &self.findings {
+            writeln!(f, "{}: {finding}", finding.severity())?;
+        }
+        Ok(())
+    }
+}
+
+/// Lint an unsigned enclave shared object
+///
+/// This looks for the problems that `sgx_sign` would reject, or silently
+/// accept with [`SgxSign::allow_relocations()`](crate::SgxSign::allow_relocations)
+/// and [`SgxSign::allow_init_sections()`](crate::SgxSign::allow_init_sections),
+/// as well as common mistakes which would only show up when loading or
+/// running the enclave.
+///
+/// # Arguments
+/// * `path` - The path to the unsigned enclave.
+pub fn lint_file(path: impl AsRef<Path>) -> Result<LintReport, Error> {
+    let bytes = fs::read(path)?;
+    lint(&bytes)
+}
+
+/// Lint the bytes of an unsigned enclave shared object
+///
+/// See [`lint_file()`].
+///
+/// # Arguments
+/// * `bytes` - The contents of the unsigned enclave.
+pub fn lint(bytes: &[u8]) -> Result<LintReport, Error> {
+    let elf = Elf::parse(bytes)?;
+    if !elf.is_64 || elf.header.e_machine != EM_X86_64 {
+        return Err(Error::UnsupportedArchitecture);
+    }
+
+    let mut findings = vec![];
+
+    if elf.header.e_type != ET_DYN {
+        findings.push(Finding::NotPie(elf.header.e_type));
+    }
+
+    findings.extend(text_relocations(&elf));
+    findings.extend(init_sections(&elf));
+    findings.extend(missing_symbols(&elf));
+    findings.extend(
+        elf.libraries
+            .iter()
+            .map(|lib| Finding::SharedLibrary(lib.to_string())),
+    );
+    findings.extend(undefined_symbols(&elf));
+    findings.extend(
+        elf.program_headers
+            .iter()
+            .enumerate()
+            .filter(|(_, ph)| {
+                ph.p_type == PT_LOAD && ph.p_flags & PF_W != 0 && ph.p_flags & PF_X != 0
+            })
+            .map(|(index, _)| Finding::WritableExecutableSegment(index)),
+    );
+    findings.extend(dynamic_tls(&elf));
+
+    Ok(LintReport { findings })
+}
+
+fn text_relocations(elf: &Elf) -> Option<Finding> {
+    let dynamic = elf.dynamic.as_ref()?;
+    dynamic
+        .dyns
+        .iter()
+        .any(|d| d.d_tag == DT_TEXTREL || (d.d_tag == DT_FLAGS && d.d_val & DF_TEXTREL != 0))
+        .then_some(Finding::TextRelocations)
+}
+
+fn init_sections(elf: &Elf) -> Vec<Finding> {
+    let mut findings = elf
+        .section_headers
+        .iter()
+        .filter_map(|sh| elf.shdr_strtab.get_at(sh.sh_name))
+        .filter(|name| INIT_SECTIONS.contains(name))
+        .map(|name| Finding::InitSection(name.to_string()))
+        .collect::<Vec<_>>();
+
+    // Stripped enclaves may not have section names, but the dynamic entry
+    // will still be there.
+    let has_dt_init = elf
+        .dynamic
+        .as_ref()
+        .map_or(false, |d| d.dyns.iter().any(|d| d.d_tag == DT_INIT));
+    let init = Finding::InitSection(".init".to_string());
+    if has_dt_init && !findings.contains(&init) {
+        findings.push(init);
+    }
+    findings
+}
+
+fn exported_symbols<'a>(elf: &'a Elf) -> impl Iterator<Item = &'a str> {
+    elf.dynsyms
+        .iter()
+        .filter(|sym| sym.st_shndx != SHN_UNDEF as usize)
+        .filter_map(|sym| elf.dynstrtab.get_at(sym.st_name))
+}
+
+fn missing_symbols(elf: &Elf) -> Vec<Finding> {
+    let mut findings = vec![];
+    if !exported_symbols(elf).any(|name| name == ENCLAVE_ENTRY) {
+        findings.push(Finding::MissingSymbol(ENCLAVE_ENTRY.to_string()));
+    }
+    if !exported_symbols(elf).any(|name| GLOBAL_DATA.contains(&name)) {
+        findings.push(Finding::MissingSymbol(GLOBAL_DATA[0].to_string()));
+    }
+    findings
+}
+
+fn undefined_symbols(elf: &Elf) -> Vec<Finding> {
+    elf.dynsyms
+        .iter()
+        .filter(|sym| sym.st_shndx == SHN_UNDEF as usize && sym.st_name != 0)
+        .filter_map(|sym| {
+            let name = elf.dynstrtab.get_at(sym.st_name)?.to_string();
+            Some(if sym.st_bind() == STB_WEAK {
+                Finding::WeakUndefinedSymbol(name)
+            } else {
+                Finding::UndefinedSymbol(name)
+            })
+        })
+        .collect()
+}
+
+fn dynamic_tls(elf: &Elf) -> Option<Finding> {
+    elf.dynrelas
+        .iter()
+        .chain(elf.dynrels.iter())
+        .map(|reloc| reloc.r_type)
+        .find(|r_type| matches!(*r_type, R_X86_64_DTPMOD64 | R_X86_64_DTPOFF64))
+        .map(Finding::DynamicTls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    /// A shared object with everything the linter looks for in an enclave.
+    static ENCLAVE: &str = r#"
+        char g_global_data[64] = {1};
+        int enclave_entry(char *buffer) {
+            char local[64];
+            for (int i = 0; i < 64; i++) local[i] = buffer[i];
+            return local[3];
+        }
+    "#;
+
+    /// Compile `source` into `dir` with the system C compiler.
+    ///
+    /// # Returns
+    /// The contents of the compiled binary.
+    fn compile(dir: &TempDir, source: &str, args: &[&str]) -> Vec<u8> {
+        let source_file = dir.path().join("enclave.c");
+        let output = dir.path().join("enclave.so");
+        fs::write(&source_file, source).expect("Failed to write source file");
+        let status = Command::new("cc")
+            .args(args)
+            .arg("-o")
+            .arg(&output)
+            .arg(&source_file)
+            .status()
+            .expect("Failed to run the C compiler");
+        assert!(status.success(), "Failed to compile test enclave");
+        fs::read(output).expect("Failed to read compiled enclave")
+    }
+
+    fn enclave_args<'a>(extra: &[&'a str]) -> Vec<&'a str> {
+        let mut args = vec!["-shared", "-fPIC", "-nostdlib", "-Wl,-z,noexecstack"];
+        args.extend(extra);
+        args
+    }
+
+    #[test]
+    fn clean_enclave_has_no_findings() {
+        let dir = tempfile::tempdir().unwrap();
+        let bytes = compile(&dir, ENCLAVE, &enclave_args(&[]));
+
+        let report = lint(&bytes).unwrap();
+
+        assert_eq!(report, LintReport::default());
+        assert!(report.is_empty());
+        assert!(!report.has_errors());
+    }
+
+    #[test]
+    fn libc_imports_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = format!("#include <stdio.h>\n{ENCLAVE}\nvoid hello(void) {{ puts(\"hi\"); }}");
+        let mut args = enclave_args(&[]);
+        args.retain(|a| *a != "-nostdlib");
+        let bytes = compile(&dir, &source, &args);
+
+        let report = lint(&bytes).unwrap();
+
+        assert!(report.has_errors());
+        assert!(report
+            .findings()
+            .contains(&Finding::UndefinedSymbol("puts".into())));
+        assert!(report
+            .findings()
+            .contains(&Finding::SharedLibrary("libc.so.6".into())));
+    }
+
+    #[test]
+    fn missing_entry_and_global_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let bytes = compile(&dir, "int nothing(void) { return 0; }", &enclave_args(&[]));
+
+        let report = lint(&bytes).unwrap();
+
+        assert_eq!(
+            report.findings(),
+            &[
+                Finding::MissingSymbol("enclave_entry".into()),
+                Finding::MissingSymbol("g_global_data".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn init_section_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = format!(
+            "{ENCLAVE}\n__attribute__((section(\".init\"), used)) static void init(void) {{}}"
+        );
+        let bytes = compile(&dir, &source, &enclave_args(&[]));
+
+        let report = lint(&bytes).unwrap();
+
+        assert_eq!(report.findings(), &[Finding::InitSection(".init".into())]);
+    }
+
+    #[test]
+    fn writable_executable_segment_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let bytes = compile(&dir, ENCLAVE, &enclave_args(&["-Wl,--omagic"]));
+
+        let report = lint(&bytes).unwrap();
+
+        assert!(report
+            .findings()
+            .iter()
+            .any(|f| matches!(f, Finding::WritableExecutableSegment(_))));
+    }
+
+    #[test]
+    fn global_dynamic_tls_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = format!(
+            "{ENCLAVE}\n__thread int counter;\nvoid *__tls_get_addr(void *p) {{ return p; }}\nint count(void) {{ return counter++; }}"
+        );
+        let bytes = compile(
+            &dir,
+            &source,
+            &enclave_args(&["-ftls-model=global-dynamic"]),
+        );
+
+        let report = lint(&bytes).unwrap();
+
+        assert!(report
+            .findings()
+            .contains(&Finding::DynamicTls(R_X86_64_DTPMOD64)));
+    }
+
+    #[test]
+    fn weak_import_is_a_warning() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = format!(
+            "{ENCLAVE}\nextern void optional(void) __attribute__((weak));\nvoid call(void) {{ if (optional) optional(); }}"
+        );
+        let bytes = compile(&dir, &source, &enclave_args(&[]));
+
+        let report = lint(&bytes).unwrap();
+
+        assert_eq!(
+            report.findings(),
+            &[Finding::WeakUndefinedSymbol("optional".into())]
+        );
+        assert!(!report.has_errors());
+        assert_eq!(
+            report.to_string(),
+            "warning: The enclave has a weak import of `optional`, it will always be null.\n"
+        );
+    }
+
+    #[test]
+    fn executable_is_not_pie() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = format!("{ENCLAVE}\nvoid _start(void) {{}}");
+        let bytes = compile(&dir, &source, &["-static", "-no-pie", "-nostdlib"]);
+
+        let report = lint(&bytes).unwrap();
+
+        assert!(report.findings().contains(&Finding::NotPie(2)));
+    }
+
+    #[test]
+    fn not_an_elf_file() {
+        assert!(matches!(lint(b"not an elf"), Err(Error::Parse(_))));
+    }
+}
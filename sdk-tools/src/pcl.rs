@@ -0,0 +1,592 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Protected Code Loader (PCL) encryption of an enclave shared object
+
+use displaydoc::Display;
+use goblin::elf::{
+    header::EM_X86_64,
+    section_header::{SectionHeader, SHF_ALLOC, SHT_PROGBITS},
+    Elf,
+};
+use std::{
+    fmt, fs,
+    io::Error as IoError,
+    ops::Range,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The size of a PCL key in bytes, an AES-GCM 128 bit key
+pub const PCL_KEY_SIZE: usize = 16;
+
+/// The size of the GUID which ties a sealed PCL key to an encrypted enclave
+pub const PCL_GUID_SIZE: usize = 16;
+
+/// The size of a sealed PCL key in bytes
+///
+/// This is the `sgx_sealed_data_t` header followed by the encrypted key and
+/// the GUID, which is the additional authenticated data.
+pub const SEALED_PCL_KEY_SIZE: usize = SEALED_DATA_HEADER_SIZE + PCL_KEY_SIZE + PCL_GUID_SIZE;
+
+/// The section the PCL library reserves for the decryption table.
+const PCL_TABLE_SECTION: &str = ".pcltbl";
+
+/// The size of `sgx_sealed_data_t` without the payload
+const SEALED_DATA_HEADER_SIZE: usize = 560;
+const KEY_NAME: Range<usize> = 0..2;
+const PLAINTEXT_OFFSET: Range<usize> = 512..516;
+const PAYLOAD_SIZE: Range<usize> = 528..532;
+const GUID: Range<usize> = SEALED_DATA_HEADER_SIZE + PCL_KEY_SIZE..SEALED_PCL_KEY_SIZE;
+
+/// The `key_name` of a seal key, `SGX_KEYSELECT_SEAL`
+const KEYSELECT_SEAL: u16 = 0x0004;
+
+/// Errors which can occur when encrypting an enclave.
+#[derive(Debug, Display)]
+pub enum Error {
+    /// There was an error reading or writing a file: {0}
+    Io(IoError),
+    /// The enclave is not a valid ELF file: {0}
+    Parse(String),
+    /// The enclave must be a 64 bit x86 ELF file
+    UnsupportedArchitecture,
+    /// The PCL key must be 16 bytes, got {0}
+    InvalidKeySize(usize),
+    /// The sealed PCL key is malformed: {0}
+    InvalidSealedKey(String),
+    /**
+     * The enclave has no `.pcltbl` section, link it with
+     * `EnclaveLinker::pcl()`
+     */
+    MissingPclTable,
+    /// The {0} of the encrypted enclave differs from the plaintext enclave
+    LayoutChanged(String),
+    /// None of the sections of the enclave were encrypted
+    NotEncrypted,
+    /**
+     * There was an error encrypting the enclave,
+     * command:\n{0}\nstdout:\n{1}\n\nstderr:\n{2}
+     */
+    Encrypt(String, String, String),
+}
+
+impl From<IoError> for Error {
+    fn from(src: IoError) -> Error {
+        Error::Io(src)
+    }
+}
+
+impl From<goblin::error::Error> for Error {
+    fn from(src: goblin::error::Error) -> Error {
+        Error::Parse(src.to_string())
+    }
+}
+
+/// The key the code and data sections of an enclave are encrypted with.
+///
+/// The key file `sgx_encrypt` takes is the raw 16 bytes of the key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PclKey([u8; PCL_KEY_SIZE]);
+
+impl PclKey {
+    /// Read the key from a key file
+    ///
+    /// # Arguments
+    /// * `path` - The path to the key file.
+    ///
+    /// # Errors
+    /// If the file can't be read or isn't 16 bytes.
+    pub fn read_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::try_from(fs::read(path)?.as_slice())
+    }
+
+    /// Write the key file for `sgx_encrypt`
+    ///
+    /// # Arguments
+    /// * `path` - The path to write the key file to.
+    pub fn write_file(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        Ok(fs::write(path, self.0)?)
+    }
+}
+
+impl From<[u8; PCL_KEY_SIZE]> for PclKey {
+    fn from(key: [u8; PCL_KEY_SIZE]) -> Self {
+        Self(key)
+    }
+}
+
+impl TryFrom<&[u8]> for PclKey {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+        let key = bytes
+            .try_into()
+            .map_err(|_| Error::InvalidKeySize(bytes.len()))?;
+        Ok(Self(key))
+    }
+}
+
+impl AsRef<[u8]> for PclKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for PclKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("PclKey(..)")
+    }
+}
+
+/// A PCL key sealed to the enclave, as passed to
+/// `mc_sgx_urts::EnclaveBuilder::pcl()`.
+///
+/// Sealing needs the seal key of the CPU, so the blob is produced by a key
+/// provisioning enclave with `sgx_seal_data()`. The key is the encrypted text
+/// and the GUID of the encrypted enclave is the additional MAC text, the PCL
+/// refuses to decrypt an enclave with a different GUID.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SealedPclKey(Vec<u8>);
+
+impl SealedPclKey {
+    /// Read and validate a sealed key blob
+    ///
+    /// # Arguments
+    /// * `path` - The path to the sealed key.
+    ///
+    /// # Errors
+    /// If the file can't be read or isn't a sealed PCL key.
+    pub fn read_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::try_from(fs::read(path)?)
+    }
+
+    /// Write the sealed key blob
+    ///
+    /// # Arguments
+    /// * `path` - The path to write the sealed key to.
+    pub fn write_file(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        Ok(fs::write(path, &self.0)?)
+    }
+
+    /// The GUID of the encrypted enclave the key was sealed for
+    pub fn guid(&self) -> [u8; PCL_GUID_SIZE] {
+        self.0[GUID]
+            .try_into()
+            .expect("Sealed key size was validated")
+    }
+
+    /// Returns the sealed key blob
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl TryFrom<Vec<u8>> for SealedPclKey {
+    type Error = Error;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Error> {
+        if bytes.len() != SEALED_PCL_KEY_SIZE {
+            return Err(Error::InvalidSealedKey(format!(
+                "expected {SEALED_PCL_KEY_SIZE} bytes, got {}",
+                bytes.len()
+            )));
+        }
+        let key_name = u16::from_le_bytes(bytes[KEY_NAME].try_into().expect("Range is 2 bytes"));
+        if key_name != KEYSELECT_SEAL {
+            return Err(Error::InvalidSealedKey(format!(
+                "not sealed with a seal key, key name {key_name:#06x}"
+            )));
+        }
+        let plaintext_offset = read_u32(&bytes, PLAINTEXT_OFFSET);
+        if plaintext_offset != PCL_KEY_SIZE as u32 {
+            return Err(Error::InvalidSealedKey(format!(
+                "the encrypted text must be the {PCL_KEY_SIZE} byte key, got {plaintext_offset} bytes"
+            )));
+        }
+        let payload_size = read_u32(&bytes, PAYLOAD_SIZE);
+        if payload_size != (PCL_KEY_SIZE + PCL_GUID_SIZE) as u32 {
+            return Err(Error::InvalidSealedKey(format!(
+                "the payload must be the key and GUID, got {payload_size} bytes"
+            )));
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl AsRef<[u8]> for SealedPclKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SealedPclKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SealedPclKey")
+            .field("guid", &self.guid())
+            .finish_non_exhaustive()
+    }
+}
+
+fn read_u32(bytes: &[u8], range: Range<usize>) -> u32 {
+    u32::from_le_bytes(bytes[range].try_into().expect("Range is 4 bytes"))
+}
+
+/// Wrapper for the enclave encryption tool (sgx_encrypt).
+///
+/// The encryption tool ships as part of the Intel® Software Guard Extensions
+/// SDK. It encrypts the code and data sections of an enclave linked with the
+/// Protected Code Loader, so the intellectual property of the enclave isn't
+/// exposed in the enclave file. The loader decrypts the sections inside the
+/// enclave, with the key from a [`SealedPclKey`].
+///
+/// The enclave must be encrypted before it is signed, the measurement covers
+/// the encrypted sections.
+#[derive(Clone, Debug)]
+pub struct SgxEncrypt {
+    /// The path to the sgx_encrypt executable.
+    sgx_encrypt_path: PathBuf,
+}
+
+impl Default for SgxEncrypt {
+    /// Create a new SGX encryption utility from the current environment.
+    fn default() -> Self {
+        let bin_dir = mc_sgx_core_build::sgx_bin_x64_dir();
+
+        SgxEncrypt::from(bin_dir.join("sgx_encrypt"))
+    }
+}
+
+impl SgxEncrypt {
+    /// Encrypt an unsigned enclave
+    ///
+    /// # Arguments
+    /// * `unsigned_enclave` - The enclave to encrypt, linked with the PCL.
+    /// * `key_file` - The key to encrypt with, see [`PclKey::write_file()`].
+    /// * `output_enclave` - The path to write the encrypted enclave to.
+    pub fn encrypt(
+        &mut self,
+        unsigned_enclave: impl AsRef<Path>,
+        key_file: impl AsRef<Path>,
+        output_enclave: impl AsRef<Path>,
+    ) -> Command {
+        let mut cmd = Command::new(&self.sgx_encrypt_path);
+        cmd.arg("-i")
+            .arg(unsigned_enclave.as_ref())
+            .arg("-o")
+            .arg(output_enclave.as_ref())
+            .arg("-k")
+            .arg(key_file.as_ref());
+
+        cmd
+    }
+}
+
+/// Construct a new SgxEncrypt utility around the given executable path
+impl From<PathBuf> for SgxEncrypt {
+    fn from(sgx_encrypt_path: PathBuf) -> Self {
+        Self { sgx_encrypt_path }
+    }
+}
+
+/// The result of verifying an encrypted enclave
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PclReport {
+    encrypted_sections: Vec<String>,
+}
+
+impl PclReport {
+    /// The names of the sections which were encrypted
+    pub fn encrypted_sections(&self) -> &[String] {
+        &self.encrypted_sections
+    }
+}
+
+/// Verify an encrypted enclave against the plaintext enclave
+///
+/// See [`verify_encryption()`].
+///
+/// # Arguments
+/// * `plaintext` - The path to the enclave before encryption.
+/// * `encrypted` - The path to the enclave after encryption.
+pub fn verify_encryption_files(
+    plaintext: impl AsRef<Path>,
+    encrypted: impl AsRef<Path>,
+) -> Result<PclReport, Error> {
+    let plaintext = fs::read(plaintext)?;
+    let encrypted = fs::read(encrypted)?;
+    verify_encryption(&plaintext, &encrypted)
+}
+
+/// Verify an encrypted enclave against the plaintext enclave
+///
+/// `sgx_encrypt` encrypts sections in place, so the encrypted enclave must
+/// have the same layout as the plaintext one, a PCL table and at least one
+/// encrypted section. Run this before signing, a mistake found after signing
+/// means a new signature.
+///
+/// # Arguments
+/// * `plaintext` - The contents of the enclave before encryption.
+/// * `encrypted` - The contents of the enclave after encryption.
+///
+/// # Errors
+/// [`Error::MissingPclTable`] if the enclave wasn't linked with the PCL,
+/// [`Error::LayoutChanged`] if the layout differs and
+/// [`Error::NotEncrypted`] if no section was encrypted.
+pub fn verify_encryption(plaintext: &[u8], encrypted: &[u8]) -> Result<PclReport, Error> {
+    let plain_elf = parse(plaintext)?;
+    let encrypted_elf = parse(encrypted)?;
+
+    if plain_elf.program_headers.len() != encrypted_elf.program_headers.len()
+        || plain_elf
+            .program_headers
+            .iter()
+            .zip(&encrypted_elf.program_headers)
+            .any(|(plain, encrypted)| plain != encrypted)
+    {
+        return Err(Error::LayoutChanged("program headers".into()));
+    }
+    if plain_elf.section_headers.len() != encrypted_elf.section_headers.len() {
+        return Err(Error::LayoutChanged("number of sections".into()));
+    }
+
+    let mut has_pcl_table = false;
+    let mut encrypted_sections = vec![];
+    for (plain, encrypted_section) in plain_elf
+        .section_headers
+        .iter()
+        .zip(&encrypted_elf.section_headers)
+    {
+        let name = plain_elf
+            .shdr_strtab
+            .get_at(plain.sh_name)
+            .unwrap_or_default();
+        if !same_layout(plain, encrypted_section)
+            || encrypted_elf.shdr_strtab.get_at(encrypted_section.sh_name) != Some(name)
+        {
+            return Err(Error::LayoutChanged(format!("section `{name}`")));
+        }
+        if name == PCL_TABLE_SECTION {
+            has_pcl_table = true;
+            continue;
+        }
+        if plain.sh_type != SHT_PROGBITS || plain.sh_flags & u64::from(SHF_ALLOC) == 0 {
+            continue;
+        }
+        if contents(plaintext, plain) != contents(encrypted, encrypted_section) {
+            encrypted_sections.push(name.to_owned());
+        }
+    }
+
+    if !has_pcl_table {
+        return Err(Error::MissingPclTable);
+    }
+    if encrypted_sections.is_empty() {
+        return Err(Error::NotEncrypted);
+    }
+    Ok(PclReport { encrypted_sections })
+}
+
+fn parse(bytes: &[u8]) -> Result<Elf<'_>, Error> {
+    let elf = Elf::parse(bytes)?;
+    if !elf.is_64 || elf.header.e_machine != EM_X86_64 {
+        return Err(Error::UnsupportedArchitecture);
+    }
+    Ok(elf)
+}
+
+fn same_layout(a: &SectionHeader, b: &SectionHeader) -> bool {
+    a.sh_type == b.sh_type
+        && a.sh_flags == b.sh_flags
+        && a.sh_addr == b.sh_addr
+        && a.sh_offset == b.sh_offset
+        && a.sh_size == b.sh_size
+}
+
+fn contents<'a>(bytes: &'a [u8], section: &SectionHeader) -> Option<&'a [u8]> {
+    let range = section.file_range()?;
+    bytes.get(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A shared object with a PCL table, standing in for an enclave linked
+    /// with the PCL.
+    static ENCLAVE: &str = r#"
+        __attribute__((section(".pcltbl"))) char pcl_table[64] = {1};
+        char secret[64] = {1, 2, 3, 4};
+        int enclave_entry(int a) {
+            return a * 42 + secret[a];
+        }
+    "#;
+
+    fn compile(dir: &TempDir, source: &str) -> Vec<u8> {
+        let source_file = dir.path().join("enclave.c");
+        let output = dir.path().join("enclave.so");
+        fs::write(&source_file, source).expect("Failed to write source file");
+        let status = Command::new("cc")
+            .args(["-shared", "-fPIC", "-nostdlib", "-o"])
+            .arg(&output)
+            .arg(&source_file)
+            .status()
+            .expect("Failed to run the C compiler");
+        assert!(status.success(), "Failed to compile test enclave");
+        fs::read(output).expect("Failed to read compiled enclave")
+    }
+
+    /// Flip the bits of the named sections, like `sgx_encrypt` would
+    /// encrypt them in place.
+    fn encrypt(bytes: &[u8], sections: &[&str]) -> Vec<u8> {
+        let elf = Elf::parse(bytes).unwrap();
+        let mut encrypted = bytes.to_vec();
+        for section in &elf.section_headers {
+            let name = elf.shdr_strtab.get_at(section.sh_name).unwrap();
+            if sections.contains(&name) {
+                for byte in &mut encrypted[section.file_range().unwrap()] {
+                    *byte = !*byte;
+                }
+            }
+        }
+        encrypted
+    }
+
+    fn sealed_key() -> Vec<u8> {
+        let mut bytes = vec![0; SEALED_PCL_KEY_SIZE];
+        bytes[KEY_NAME].copy_from_slice(&KEYSELECT_SEAL.to_le_bytes());
+        bytes[PLAINTEXT_OFFSET].copy_from_slice(&16u32.to_le_bytes());
+        bytes[PAYLOAD_SIZE].copy_from_slice(&32u32.to_le_bytes());
+        bytes[GUID].copy_from_slice(&[7; 16]);
+        bytes
+    }
+
+    #[test]
+    fn generate_encrypt_command() {
+        let cmd = format!(
+            "{:?}",
+            SgxEncrypt::default().encrypt(
+                "path_to_unsigned_enclave/file",
+                "path_to_key/file",
+                "path_to_output_enclave/file",
+            )
+        );
+        let expected_cmd = "\"/opt/intel/sgxsdk/bin/x64/sgx_encrypt\" \"-i\" \"path_to_unsigned_enclave/file\" \"-o\" \"path_to_output_enclave/file\" \"-k\" \"path_to_key/file\"";
+
+        assert_eq!(expected_cmd, cmd);
+    }
+
+    #[test]
+    fn key_file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("key.bin");
+        let key = PclKey::from([9; 16]);
+
+        key.write_file(&path).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), [9; 16]);
+        assert_eq!(PclKey::read_file(&path).unwrap(), key);
+        assert_eq!(format!("{key:?}"), "PclKey(..)");
+    }
+
+    #[test]
+    fn key_of_wrong_size_is_rejected() {
+        assert!(matches!(
+            PclKey::try_from([0u8; 32].as_slice()),
+            Err(Error::InvalidKeySize(32))
+        ));
+    }
+
+    #[test]
+    fn sealed_key_is_validated() {
+        let sealed = SealedPclKey::try_from(sealed_key()).unwrap();
+        assert_eq!(sealed.guid(), [7; 16]);
+        assert_eq!(sealed.into_bytes(), sealed_key());
+
+        let mut short = sealed_key();
+        short.pop();
+        assert!(matches!(
+            SealedPclKey::try_from(short),
+            Err(Error::InvalidSealedKey(_))
+        ));
+
+        let mut wrong_key = sealed_key();
+        wrong_key[KEY_NAME].copy_from_slice(&1u16.to_le_bytes());
+        assert!(matches!(
+            SealedPclKey::try_from(wrong_key),
+            Err(Error::InvalidSealedKey(_))
+        ));
+
+        let mut no_guid = sealed_key();
+        no_guid[PAYLOAD_SIZE].copy_from_slice(&16u32.to_le_bytes());
+        assert!(matches!(
+            SealedPclKey::try_from(no_guid),
+            Err(Error::InvalidSealedKey(_))
+        ));
+    }
+
+    #[test]
+    fn sealed_key_file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sealed_key.bin");
+        let sealed = SealedPclKey::try_from(sealed_key()).unwrap();
+
+        sealed.write_file(&path).unwrap();
+
+        assert_eq!(SealedPclKey::read_file(&path).unwrap(), sealed);
+    }
+
+    #[test]
+    fn encrypted_sections_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let plaintext = compile(&dir, ENCLAVE);
+        let encrypted = encrypt(&plaintext, &[".text", ".data", ".pcltbl"]);
+
+        let report = verify_encryption(&plaintext, &encrypted).unwrap();
+
+        assert_eq!(report.encrypted_sections(), [".text", ".data"]);
+    }
+
+    #[test]
+    fn unencrypted_enclave_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let plaintext = compile(&dir, ENCLAVE);
+
+        assert!(matches!(
+            verify_encryption(&plaintext, &plaintext),
+            Err(Error::NotEncrypted)
+        ));
+    }
+
+    #[test]
+    fn enclave_without_pcl_table_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let plaintext = compile(&dir, "int enclave_entry(int a) { return a; }");
+        let encrypted = encrypt(&plaintext, &[".text"]);
+
+        assert!(matches!(
+            verify_encryption(&plaintext, &encrypted),
+            Err(Error::MissingPclTable)
+        ));
+    }
+
+    #[test]
+    fn changed_layout_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let plaintext = compile(&dir, ENCLAVE);
+        let other = compile(&dir, &format!("{ENCLAVE} int more(void) {{ return 1; }}"));
+
+        assert!(matches!(
+            verify_encryption(&plaintext, &other),
+            Err(Error::LayoutChanged(_))
+        ));
+    }
+
+    #[test]
+    fn non_elf_is_a_parse_error() {
+        assert!(matches!(
+            verify_encryption(b"not an enclave", b"not an enclave"),
+            Err(Error::Parse(_))
+        ));
+    }
+}
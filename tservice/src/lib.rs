@@ -6,10 +6,12 @@
 
 extern crate alloc;
 
+mod qe_report;
 mod report;
 mod seal;
 
 pub use crate::{
+    qe_report::{QeReportError, VerifyQeReport},
     report::Report,
     seal::{SealedBuilder, Unseal},
 };
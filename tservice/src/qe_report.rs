@@ -0,0 +1,45 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+//! Functions used for verifying the quoting enclave inside of an enclave
+
+use crate::Report;
+use core::result::Result as CoreResult;
+use mc_sgx_core_types::QuoteNonce;
+use mc_sgx_dcap_types::{Quote3, ReportInfo};
+pub use mc_sgx_tservice_types::QeReportError;
+
+type Result<T> = CoreResult<T, QeReportError>;
+
+/// Verification of the quoting enclave report returned with a quote
+pub trait VerifyQeReport {
+    /// Verify the quoting enclave report was generated for `quote` and
+    /// `nonce` by an enclave on this platform
+    ///
+    /// The quoting enclave report must target the enclave making this call.
+    /// The identity of the quoting enclave, [`ReportInfo::report()`], is left
+    /// for the caller to check.
+    ///
+    /// # Arguments
+    /// * `nonce` - The nonce this enclave provided for the quote, see
+    ///   [`QuoteNonce::new()`].
+    /// * `quote` - The quote returned with the quoting enclave report.
+    ///
+    /// # Errors
+    /// * [`QeReportError::NonceMismatch`] if the quoting enclave report was
+    ///   generated for a different nonce.
+    /// * [`QeReportError::Sgx`] if the quoting enclave report doesn't target
+    ///   this enclave.
+    /// * [`QeReportError::QuoteMismatch`] if the quoting enclave report was
+    ///   generated for a different quote.
+    fn verify_qe_report<T: AsRef<[u8]>>(&self, nonce: &QuoteNonce, quote: &Quote3<T>)
+        -> Result<()>;
+}
+
+impl VerifyQeReport for ReportInfo {
+    fn verify_qe_report<T: AsRef<[u8]>>(
+        &self,
+        nonce: &QuoteNonce,
+        quote: &Quote3<T>,
+    ) -> Result<()> {
+        mc_sgx_tservice_types::verify_qe_report(self, nonce, quote, Report::verify)
+    }
+}
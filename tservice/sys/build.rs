@@ -38,6 +38,7 @@ fn main() {
 
     let sgx_suffix = mc_sgx_core_build::sgx_library_suffix();
     cargo_emit::rustc_link_lib!(&format!("static=sgx_tservice{sgx_suffix}"));
+    mc_sgx_core_build::emit_sgx_sdk_cfgs();
 
     let mut builder = mc_sgx_core_build::sgx_builder()
         .header("wrapper.h")
@@ -47,10 +48,5 @@ fn main() {
         builder = builder.allowlist_function(f);
     }
 
-    let out_path = mc_sgx_core_build::build_output_dir();
-    builder
-        .generate()
-        .expect("Unable to generate bindings")
-        .write_to_file(out_path.join("bindings.rs"))
-        .expect("Couldn't write bindings!");
+    mc_sgx_core_build::write_bindings(builder);
 }
@@ -11,6 +11,10 @@ extern crate alloc;
 #[cfg(any(test, feature = "test-utils"))]
 pub mod test_utils;
 
+mod qe_report;
 mod seal;
 
-pub use crate::seal::Sealed;
+pub use crate::{
+    qe_report::{verify_qe_report, QeReportError},
+    seal::Sealed,
+};
@@ -0,0 +1,160 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Verification of the quoting enclave report returned with a quote
+
+use mc_sgx_core_types::{QuoteNonce, Report};
+use mc_sgx_dcap_types::{Quote3, ReportInfo};
+
+/// Errors verifying the report of a quoting enclave
+#[derive(Clone, Debug, displaydoc::Display, Eq, Hash, PartialEq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum QeReportError {
+    /// Error from SGX function {0}
+    Sgx(mc_sgx_core_types::Error),
+    /// The quoting enclave report was generated for a different nonce
+    NonceMismatch,
+    /// The quoting enclave report was generated for a different quote
+    QuoteMismatch,
+}
+
+impl From<mc_sgx_core_types::Error> for QeReportError {
+    fn from(src: mc_sgx_core_types::Error) -> Self {
+        QeReportError::Sgx(src)
+    }
+}
+
+/// Verify the quoting enclave report in `report_info` was generated for
+/// `quote` and `nonce`
+///
+/// These are the checks of `mc_sgx_tservice::VerifyQeReport`. Only the enclave
+/// the report targets can check its MAC, that is left to `verify_report`.
+///
+/// # Arguments
+/// * `report_info` - The report info returned with the quote.
+/// * `nonce` - The nonce the enclave provided for the quote.
+/// * `quote` - The quote returned with `report_info`.
+/// * `verify_report` - Verifies the quoting enclave report targets the
+///   calling enclave, `mc_sgx_tservice::Report::verify()` inside an enclave.
+///
+/// # Errors
+/// * [`QeReportError::NonceMismatch`] if the quoting enclave report was
+///   generated for a different nonce.
+/// * [`QeReportError::Sgx`] with the error of `verify_report`.
+/// * [`QeReportError::QuoteMismatch`] if the quoting enclave report was
+///   generated for a different quote.
+pub fn verify_qe_report<T, F>(
+    report_info: &ReportInfo,
+    nonce: &QuoteNonce,
+    quote: &Quote3<T>,
+    verify_report: F,
+) -> Result<(), QeReportError>
+where
+    T: AsRef<[u8]>,
+    F: FnOnce(&Report) -> Result<(), mc_sgx_core_types::Error>,
+{
+    if &report_info.nonce() != nonce {
+        return Err(QeReportError::NonceMismatch);
+    }
+
+    let report = report_info.report();
+    verify_report(&report)?;
+
+    if !quote.verify_nonce(nonce, &report.body().report_data()) {
+        return Err(QeReportError::QuoteMismatch);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mc_sgx_core_sys_types::sgx_report_t;
+    use mc_sgx_core_types::Error as SgxError;
+    use mc_sgx_dcap_sys_types::sgx_ql_qe_report_info_t;
+    use sha2::{Digest, Sha256};
+
+    const QUOTE: &[u8] = include_bytes!("../../../dcap/types/data/tests/hw_quote.dat");
+
+    fn quote() -> Quote3<&'static [u8]> {
+        Quote3::try_from(QUOTE).expect("Test quote should be valid")
+    }
+
+    /// The report info the quoting enclave returns with `quote` for `nonce`
+    fn report_info(nonce: &QuoteNonce, quote: &[u8]) -> ReportInfo {
+        let mut report = sgx_report_t::default();
+        let hash = Sha256::new()
+            .chain_update(nonce)
+            .chain_update(quote)
+            .finalize();
+        report.body.report_data.d[..hash.len()].copy_from_slice(&hash);
+        sgx_ql_qe_report_info_t {
+            nonce: nonce.clone().into(),
+            qe_report: report,
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn verified_report_for_quote_and_nonce() {
+        let nonce = QuoteNonce::from([3; QuoteNonce::SIZE]);
+        let report_info = report_info(&nonce, QUOTE);
+        let mut verified = None;
+
+        let result = verify_qe_report(&report_info, &nonce, &quote(), |report| {
+            verified = Some(report.clone());
+            Ok(())
+        });
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(verified, Some(report_info.report()));
+    }
+
+    #[test]
+    fn different_nonce_is_a_nonce_mismatch() {
+        let report_info = report_info(&QuoteNonce::from([3; QuoteNonce::SIZE]), QUOTE);
+        let nonce = QuoteNonce::from([4; QuoteNonce::SIZE]);
+
+        let result = verify_qe_report(&report_info, &nonce, &quote(), |_| {
+            panic!("The report shouldn't be verified for the wrong nonce")
+        });
+
+        assert_eq!(result, Err(QeReportError::NonceMismatch));
+    }
+
+    #[test]
+    fn report_failing_verification_is_an_sgx_error() {
+        let nonce = QuoteNonce::from([3; QuoteNonce::SIZE]);
+        let report_info = report_info(&nonce, QUOTE);
+
+        let result = verify_qe_report(&report_info, &nonce, &quote(), |_| {
+            Err(SgxError::MacMismatch)
+        });
+
+        assert_eq!(result, Err(QeReportError::Sgx(SgxError::MacMismatch)));
+    }
+
+    #[test]
+    fn report_for_another_quote_is_a_quote_mismatch() {
+        let nonce = QuoteNonce::from([3; QuoteNonce::SIZE]);
+        let report_info = report_info(&nonce, b"another quote");
+
+        let result = verify_qe_report(&report_info, &nonce, &quote(), |_| Ok(()));
+
+        assert_eq!(result, Err(QeReportError::QuoteMismatch));
+    }
+
+    #[test]
+    fn report_without_quote_hash_is_a_quote_mismatch() {
+        let nonce = QuoteNonce::from([3; QuoteNonce::SIZE]);
+        let report_info: ReportInfo = sgx_ql_qe_report_info_t {
+            nonce: nonce.clone().into(),
+            ..Default::default()
+        }
+        .into();
+
+        let result = verify_qe_report(&report_info, &nonce, &quote(), |_| Ok(()));
+
+        assert_eq!(result, Err(QeReportError::QuoteMismatch));
+    }
+}
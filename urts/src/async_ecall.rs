@@ -0,0 +1,556 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Making ECALLs from async code without blocking the executor
+
+use crate::{Ecall, Enclave, EnclavePool, EnclaveRuntime, Error};
+use core::{
+    fmt,
+    future::Future,
+    mem,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+use mc_sgx_core_sys_types::sgx_status_t;
+use mc_sgx_urts_sys_types::sgx_enclave_id_t;
+use std::{
+    collections::VecDeque,
+    io,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+};
+
+/// A call waiting for a worker thread
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Makes the ECALLs of an [`EnclavePool`] on dedicated threads, returning
+/// futures
+///
+/// There is one worker thread per TCS of the pool, so ECALLs never block the
+/// async executor and never wait for a TCS. A queue, as deep as the TCS count,
+/// holds the calls waiting for a worker. When it's full the returned futures
+/// wait for room before queueing their call, which gives callers backpressure
+/// instead of an ever growing backlog.
+///
+/// The futures work with any executor, like tokio's, and need to be polled to
+/// queue their call. Like the [`EnclavePool`] it works with any
+/// [`EnclaveRuntime`].
+///
+/// # Cancellation
+///
+/// Dropping an [`EcallFuture`] cancels its call if a worker hasn't started it.
+/// Once started the ECALL runs to completion, its result is discarded. The
+/// enclave is never destroyed while a call is in it, the workers keep the pool
+/// alive until the calls queued before the [`AsyncEnclave`] was dropped are
+/// done.
+///
+/// ```ignore
+/// let enclave = AsyncEnclave::new(pool)?;
+/// let value: u32 = enclave
+///     .with_enclave(|enclave| enclave.ecall_returning(|eid, value| unsafe { ecall_get(eid, value) }))
+///     .await?;
+/// ```
+pub struct AsyncEnclave<R: EnclaveRuntime = Enclave> {
+    pool: Arc<EnclavePool<R>>,
+    queue: Arc<Queue>,
+}
+
+impl<R: EnclaveRuntime + Send + Sync + 'static> AsyncEnclave<R> {
+    /// Start the worker threads of `pool`, one per TCS
+    ///
+    /// # Errors
+    /// If a worker thread can't be spawned.
+    pub fn new(pool: EnclavePool<R>) -> io::Result<Self> {
+        let workers = pool.tcs_count().get();
+        let queue = Arc::new(Queue::new(workers));
+        for worker in 0..workers {
+            let worker_queue = queue.clone();
+            let spawned = thread::Builder::new()
+                .name(format!("mc-sgx-ecall-{worker}"))
+                .spawn(move || worker_queue.run());
+            if let Err(error) = spawned {
+                queue.close();
+                return Err(error);
+            }
+        }
+        Ok(Self {
+            pool: Arc::new(pool),
+            queue,
+        })
+    }
+
+    /// Call `f` with the enclave on a worker thread
+    ///
+    /// See [`EnclavePool::with_enclave()`], the enclave is recreated if it was
+    /// lost.
+    ///
+    /// # Errors
+    /// The future resolves to the errors of [`EnclavePool::with_enclave()`],
+    /// or [`Error::EcallAborted`] if `f` panics or the [`AsyncEnclave`] was
+    /// dropped before the call was queued.
+    pub fn with_enclave<T, E, F>(&self, f: F) -> EcallFuture<T>
+    where
+        T: Send + 'static,
+        E: Into<Error>,
+        F: FnMut(&R) -> Result<T, E> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        let slot = Arc::new(Slot::default());
+        let job_slot = slot.clone();
+        let job: Job = Box::new(move || {
+            if job_slot.is_cancelled() {
+                return;
+            }
+            let result = panic::catch_unwind(AssertUnwindSafe(|| pool.with_enclave(f)));
+            job_slot.complete(result.unwrap_or(Err(Error::EcallAborted)));
+        });
+        EcallFuture {
+            queue: self.queue.clone(),
+            job: Some(job),
+            slot,
+        }
+    }
+
+    /// Make the ECALL `E` with `input` on a worker thread
+    ///
+    /// See [`EnclavePool::call()`].
+    ///
+    /// # Errors
+    /// See [`AsyncEnclave::with_enclave()`].
+    pub fn call<E>(&self, input: E::Input) -> EcallFuture<E::Output>
+    where
+        E: Ecall,
+        E::Input: Clone + Send,
+        E::Output: Send,
+    {
+        self.with_enclave(move |enclave| enclave.call::<E>(input.clone()))
+    }
+
+    /// Returns the pool the ECALLs are made with
+    pub fn pool(&self) -> &EnclavePool<R> {
+        &self.pool
+    }
+}
+
+impl AsyncEnclave<Enclave> {
+    /// Call an ECALL of the enclave on a worker thread
+    ///
+    /// See [`Enclave::ecall()`].
+    ///
+    /// # Errors
+    /// See [`AsyncEnclave::with_enclave()`].
+    pub fn ecall<F>(&self, mut f: F) -> EcallFuture<()>
+    where
+        F: FnMut(sgx_enclave_id_t) -> sgx_status_t + Send + 'static,
+    {
+        self.with_enclave(move |enclave| enclave.ecall(&mut f))
+    }
+}
+
+impl<R: EnclaveRuntime> Drop for AsyncEnclave<R> {
+    /// Stops the workers once the queued calls are done, without waiting for
+    /// them
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
+
+impl<R: EnclaveRuntime + fmt::Debug> fmt::Debug for AsyncEnclave<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncEnclave")
+            .field("pool", &self.pool)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The result of a call made with an [`AsyncEnclave`]
+///
+/// Dropping the future before the call starts cancels it, see
+/// [`AsyncEnclave`].
+#[must_use = "futures do nothing unless polled"]
+pub struct EcallFuture<T> {
+    queue: Arc<Queue>,
+    // The call until it's queued
+    job: Option<Job>,
+    slot: Arc<Slot<T>>,
+}
+
+impl<T> Future for EcallFuture<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if let Some(job) = this.job.take() {
+            match this.queue.push(job, cx.waker()) {
+                Push::Queued => {}
+                Push::Full(job) => {
+                    this.job = Some(job);
+                    return Poll::Pending;
+                }
+                Push::Closed => return Poll::Ready(Err(Error::EcallAborted)),
+            }
+        }
+        this.slot.poll(cx.waker())
+    }
+}
+
+impl<T> Drop for EcallFuture<T> {
+    fn drop(&mut self) {
+        if self.job.is_none() {
+            self.slot.cancel();
+        }
+    }
+}
+
+impl<T> fmt::Debug for EcallFuture<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EcallFuture")
+            .field("queued", &self.job.is_none())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Where a worker leaves the result of a call for its future
+struct Slot<T> {
+    state: Mutex<SlotState<T>>,
+}
+
+struct SlotState<T> {
+    result: Option<Result<T, Error>>,
+    waker: Option<Waker>,
+    cancelled: bool,
+}
+
+impl<T> Default for Slot<T> {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(SlotState {
+                result: None,
+                waker: None,
+                cancelled: false,
+            }),
+        }
+    }
+}
+
+impl<T> Slot<T> {
+    fn complete(&self, result: Result<T, Error>) {
+        let waker = {
+            let mut state = self.state.lock().expect("Mutex has been poisoned");
+            state.result = Some(result);
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    fn poll(&self, waker: &Waker) -> Poll<Result<T, Error>> {
+        let mut state = self.state.lock().expect("Mutex has been poisoned");
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(waker.clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn cancel(&self) {
+        self.state
+            .lock()
+            .expect("Mutex has been poisoned")
+            .cancelled = true;
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.state
+            .lock()
+            .expect("Mutex has been poisoned")
+            .cancelled
+    }
+}
+
+/// The outcome of queueing a call
+enum Push {
+    Queued,
+    Full(Job),
+    Closed,
+}
+
+/// The bounded queue of calls for the worker threads
+struct Queue {
+    state: Mutex<QueueState>,
+    // Signalled when a job is queued or the queue is closed
+    queued: Condvar,
+}
+
+struct QueueState {
+    jobs: VecDeque<Job>,
+    capacity: usize,
+    // The futures waiting for room in the queue
+    waiting: Vec<Waker>,
+    closed: bool,
+}
+
+impl Queue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                jobs: VecDeque::with_capacity(capacity),
+                capacity,
+                waiting: Vec::new(),
+                closed: false,
+            }),
+            queued: Condvar::new(),
+        }
+    }
+
+    fn push(&self, job: Job, waker: &Waker) -> Push {
+        let mut state = self.state.lock().expect("Mutex has been poisoned");
+        if state.closed {
+            Push::Closed
+        } else if state.jobs.len() < state.capacity {
+            state.jobs.push_back(job);
+            self.queued.notify_one();
+            Push::Queued
+        } else {
+            state.waiting.push(waker.clone());
+            Push::Full(job)
+        }
+    }
+
+    fn close(&self) {
+        let waiting = {
+            let mut state = self.state.lock().expect("Mutex has been poisoned");
+            state.closed = true;
+            mem::take(&mut state.waiting)
+        };
+        self.queued.notify_all();
+        waiting.into_iter().for_each(Waker::wake);
+    }
+
+    /// Run jobs until the queue is closed and empty
+    fn run(&self) {
+        while let Some((job, waiting)) = self.pop() {
+            // Every waiting future is woken, a future may have registered
+            // more than once and waking only one could strand the others
+            waiting.into_iter().for_each(Waker::wake);
+            job();
+        }
+    }
+
+    fn pop(&self) -> Option<(Job, Vec<Waker>)> {
+        let mut state = self.state.lock().expect("Mutex has been poisoned");
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                return Some((job, mem::take(&mut state.waiting)));
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.queued.wait(state).expect("Mutex has been poisoned");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        mock::MockEnclave,
+        pool::test::{pool, Work},
+    };
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use mc_sgx_core_types::Error as SgxError;
+    use std::{
+        sync::{mpsc, Barrier},
+        task::Wake,
+        thread::Thread,
+        time::Duration,
+    };
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    /// Run `f` in the enclave
+    fn ecall<F>(enclave: &AsyncEnclave<MockEnclave>, mut f: F) -> EcallFuture<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        enclave.with_enclave(move |_| {
+            f();
+            Ok::<_, Error>(())
+        })
+    }
+
+    #[test]
+    fn ecall_runs_on_a_worker() {
+        let enclave = AsyncEnclave::new(pool(2)).unwrap();
+        let caller = thread::current().id();
+        let result = block_on(ecall(&enclave, move || {
+            assert_ne!(thread::current().id(), caller);
+        }));
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn results_are_returned() {
+        let enclave = AsyncEnclave::new(pool(1)).unwrap();
+        let id = block_on(enclave.with_enclave(|enclave| Ok::<_, Error>(*enclave.id())));
+        assert_eq!(id, Ok(*enclave.pool().enclave().id()));
+        let result = block_on(enclave.with_enclave(|_| Err::<(), _>(SgxError::InvalidParameter)));
+        assert_eq!(result, Err(Error::Sgx(SgxError::InvalidParameter)));
+    }
+
+    #[test]
+    fn ecalls_are_made_with_the_pool() {
+        let enclave = AsyncEnclave::new(pool(1)).unwrap();
+        let pool_enclave = enclave.pool().enclave();
+        pool_enclave.handle().fail_next(SgxError::EnclaveLost);
+        assert_eq!(block_on(enclave.call::<Work>(())), Ok(()));
+        assert_eq!(enclave.pool().stats().recreations, 1);
+        assert!(!Arc::ptr_eq(&pool_enclave, &enclave.pool().enclave()));
+    }
+
+    #[test]
+    fn panic_is_an_aborted_ecall() {
+        let enclave = AsyncEnclave::new(pool(1)).unwrap();
+        let result = block_on(ecall(&enclave, || panic!("oops")));
+        assert_eq!(result, Err(Error::EcallAborted));
+        assert_eq!(enclave.pool().stats().in_flight, 0);
+        // The worker survives the panic
+        assert_eq!(block_on(ecall(&enclave, || {})), Ok(()));
+    }
+
+    #[test]
+    fn full_queue_makes_callers_wait() {
+        let enclave = AsyncEnclave::new(pool(1)).unwrap();
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        let started = Arc::new(Barrier::new(2));
+        let worker_started = started.clone();
+
+        // Occupy the only worker, then fill the queue
+        let mut running = Box::pin(ecall(&enclave, move || {
+            worker_started.wait();
+            released.lock().unwrap().recv().unwrap();
+        }));
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        assert!(running.as_mut().poll(&mut cx).is_pending());
+        started.wait();
+        let mut queued = Box::pin(ecall(&enclave, || {}));
+        assert!(queued.as_mut().poll(&mut cx).is_pending());
+
+        let mut waiting = Box::pin(ecall(&enclave, || {}));
+        assert!(waiting.as_mut().poll(&mut cx).is_pending());
+        assert!(waiting.job.is_some());
+
+        release.send(()).unwrap();
+        assert_eq!(block_on(running), Ok(()));
+        assert_eq!(block_on(queued), Ok(()));
+        assert_eq!(block_on(waiting), Ok(()));
+    }
+
+    #[test]
+    fn dropped_future_does_not_interrupt_ecall() {
+        let enclave = AsyncEnclave::new(pool(1)).unwrap();
+        let finished = Arc::new(AtomicBool::new(false));
+        let ecall_finished = finished.clone();
+        let started = Arc::new(Barrier::new(2));
+        let worker_started = started.clone();
+
+        let mut future = Box::pin(ecall(&enclave, move || {
+            worker_started.wait();
+            thread::sleep(Duration::from_millis(20));
+            ecall_finished.store(true, Ordering::SeqCst);
+        }));
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        assert!(future
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending());
+        started.wait();
+        drop(future);
+        drop(enclave);
+
+        // The ECALL completes even though nothing waits for it
+        thread::sleep(Duration::from_millis(50));
+        assert!(finished.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn dropped_future_cancels_queued_ecall() {
+        let enclave = AsyncEnclave::new(pool(1)).unwrap();
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cancelled_calls = calls.clone();
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        let mut running = Box::pin(ecall(&enclave, move || {
+            released.lock().unwrap().recv().unwrap();
+        }));
+        assert!(running.as_mut().poll(&mut cx).is_pending());
+        let mut cancelled = Box::pin(ecall(&enclave, move || {
+            cancelled_calls.fetch_add(1, Ordering::SeqCst);
+        }));
+        assert!(cancelled.as_mut().poll(&mut cx).is_pending());
+        drop(cancelled);
+
+        release.send(()).unwrap();
+        assert_eq!(block_on(running), Ok(()));
+        assert_eq!(block_on(ecall(&enclave, || {})), Ok(()));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn dropped_enclave_aborts_unqueued_calls() {
+        let enclave = AsyncEnclave::new(pool(1)).unwrap();
+        let future = ecall(&enclave, || {});
+        drop(enclave);
+        assert_eq!(block_on(future), Err(Error::EcallAborted));
+    }
+
+    #[test]
+    fn concurrency_is_limited_to_tcs_count() {
+        let enclave = AsyncEnclave::new(pool(3)).unwrap();
+        let active = Arc::new(AtomicUsize::new(0));
+        let max_active = Arc::new(AtomicUsize::new(0));
+        let futures = (0..12)
+            .map(|_| {
+                let (active, max_active) = (active.clone(), max_active.clone());
+                ecall(&enclave, move || {
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_active.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(5));
+                    active.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect::<Vec<_>>();
+        for future in futures {
+            assert_eq!(block_on(future), Ok(()));
+        }
+        assert!(max_active.load(Ordering::SeqCst) <= 3);
+        assert_eq!(enclave.pool().stats().queued, 0);
+    }
+}
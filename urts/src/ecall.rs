@@ -0,0 +1,402 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Calling ECALLs of an [`Enclave`] and handling its OCALLs in Rust
+
+use crate::Enclave;
+use core::{
+    any::{Any, TypeId},
+    cell::Cell,
+    fmt,
+};
+use mc_sgx_core_sys_types::sgx_status_t;
+use mc_sgx_core_types::Error as SgxError;
+use mc_sgx_urts_sys_types::sgx_enclave_id_t;
+use mc_sgx_util::ResultInto;
+use std::{collections::HashMap, sync::Arc};
+
+/// Handlers for the OCALLs of an enclave
+///
+/// The `sgx_edger8r` generated code calls a C function for each OCALL, i.e.
+/// `ocall_print()`. Those functions can be implemented in Rust and forward to
+/// a handler registered here, which can be a closure or a trait object, with
+/// [`ocall_handler()`].
+///
+/// Handlers are looked up by their type, so closures need to be registered as
+/// trait objects to be nameable:
+///
+/// ```ignore
+/// type Print = Box<dyn Fn(&str) + Send + Sync>;
+///
+/// let mut ocalls = OcallTable::default();
+/// ocalls.insert::<Print>(Box::new(|message| println!("{message}")));
+/// let enclave = EnclaveBuilder::new("enclave.signed.so")?
+///     .ocalls(ocalls)
+///     .create()?;
+///
+/// #[no_mangle]
+/// extern "C" fn ocall_print(message: *const c_char) {
+///     let message = unsafe { CStr::from_ptr(message) }.to_string_lossy();
+///     ocall_handler(|print: &Print| print(&message));
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct OcallTable {
+    handlers: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl OcallTable {
+    /// Register a handler, replacing any previous handler of the same type
+    ///
+    /// # Arguments
+    /// * `handler` - The handler, a closure or trait object needs to be boxed
+    ///   as a `dyn` type to be looked up with [`ocall_handler()`].
+    pub fn insert<H: Any + Send + Sync>(&mut self, handler: H) -> &mut Self {
+        self.handlers.insert(TypeId::of::<H>(), Arc::new(handler));
+        self
+    }
+
+    /// Returns the handler of type `H`, if one has been registered
+    pub fn get<H: Any + Send + Sync>(&self) -> Option<&H> {
+        self.handlers
+            .get(&TypeId::of::<H>())
+            .and_then(|handler| handler.downcast_ref())
+    }
+
+    /// Returns the number of registered handlers
+    pub fn len(&self) -> usize {
+        self.handlers.len()
+    }
+
+    /// Returns `true` if no handlers have been registered
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+}
+
+impl fmt::Debug for OcallTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OcallTable")
+            .field("handlers", &self.handlers.len())
+            .finish()
+    }
+}
+
+thread_local! {
+    // The OCALL handlers of the enclave the current thread is in an ECALL of
+    static CURRENT_OCALLS: Cell<*const OcallTable> = const { Cell::new(core::ptr::null()) };
+}
+
+/// Makes an enclave's OCALL handlers current for the duration of an ECALL
+///
+/// Restores the previous handlers when dropped, so an OCALL may ECALL into
+/// another enclave.
+struct CurrentOcalls {
+    previous: *const OcallTable,
+}
+
+impl CurrentOcalls {
+    fn enter(ocalls: &OcallTable) -> Self {
+        let previous = CURRENT_OCALLS.with(|current| current.replace(ocalls));
+        Self { previous }
+    }
+}
+
+impl Drop for CurrentOcalls {
+    fn drop(&mut self) {
+        CURRENT_OCALLS.with(|current| current.set(self.previous));
+    }
+}
+
+/// Call `f` with the OCALL handler of type `H` of the enclave being called
+///
+/// This is meant to be called from the OCALL functions the `sgx_edger8r`
+/// generated code calls. OCALLs happen on the thread which made the ECALL, the
+/// handlers are those of the enclave in the innermost ECALL made with the
+/// [`Enclave`] methods on the current thread.
+///
+/// Returns `None` when not in an ECALL or the enclave has no handler of type
+/// `H`.
+///
+/// Note: a panic in `f` must not unwind into the enclave, OCALL functions
+///   should handle `None` and any errors without panicking.
+pub fn ocall_handler<H: Any + Send + Sync, R>(f: impl FnOnce(&H) -> R) -> Option<R> {
+    let ocalls = CURRENT_OCALLS.with(Cell::get);
+    // SAFETY: The pointer is only set while the `Enclave` owning the table is
+    // borrowed for an ECALL on this thread, see `CurrentOcalls`.
+    let ocalls = unsafe { ocalls.as_ref() }?;
+    ocalls.get().map(f)
+}
+
+impl Enclave {
+    /// Call an ECALL which has no return value
+    ///
+    /// The ECALL is made by `ecall` with the enclave's ID, usually by calling
+    /// one of the functions generated by `sgx_edger8r`:
+    ///
+    /// ```ignore
+    /// enclave.ecall(|eid| unsafe { ecall_print(eid, message.as_ptr()) })?;
+    /// ```
+    ///
+    /// The enclave's [`OcallTable`] is available to [`ocall_handler()`] for
+    /// the duration of the call.
+    ///
+    /// # Errors
+    /// The error from the SGX status `ecall` returns.
+    pub fn ecall<F>(&self, ecall: F) -> Result<(), SgxError>
+    where
+        F: FnOnce(sgx_enclave_id_t) -> sgx_status_t,
+    {
+        self.context().ecall(ecall)
+    }
+
+    /// Call an ECALL which returns a value
+    ///
+    /// `ecall` is given the enclave's ID and where to write the return value:
+    ///
+    /// ```ignore
+    /// let sum: u32 = enclave.ecall_returning(|eid, sum| unsafe { ecall_add(eid, sum, 1, 2) })?;
+    /// ```
+    ///
+    /// # Errors
+    /// The error from the SGX status `ecall` returns, in which case the return
+    /// value was not written.
+    pub fn ecall_returning<T, F>(&self, ecall: F) -> Result<T, SgxError>
+    where
+        T: Default,
+        F: FnOnce(sgx_enclave_id_t, &mut T) -> sgx_status_t,
+    {
+        self.context().ecall_returning(ecall)
+    }
+
+    /// Call an ECALL which returns an `sgx_status_t`
+    ///
+    /// Both the status of the SGX call and the status returned by the enclave
+    /// function are checked:
+    ///
+    /// ```ignore
+    /// enclave.ecall_status(|eid, status| unsafe { ecall_seal(eid, status, data.as_ptr(), data.len()) })?;
+    /// ```
+    ///
+    /// # Errors
+    /// The error from the SGX status `ecall` returns, or if that succeeded the
+    /// error the enclave function returned.
+    pub fn ecall_status<F>(&self, ecall: F) -> Result<(), SgxError>
+    where
+        F: FnOnce(sgx_enclave_id_t, &mut sgx_status_t) -> sgx_status_t,
+    {
+        self.context().ecall_status(ecall)
+    }
+
+    fn context(&self) -> EcallContext<'_> {
+        EcallContext {
+            id: self.id,
+            ocalls: &self.ocalls,
+        }
+    }
+}
+
+/// What an ECALL needs of its enclave, the [`Enclave`] methods forward here
+struct EcallContext<'a> {
+    id: sgx_enclave_id_t,
+    ocalls: &'a OcallTable,
+}
+
+impl EcallContext<'_> {
+    fn ecall<F>(&self, ecall: F) -> Result<(), SgxError>
+    where
+        F: FnOnce(sgx_enclave_id_t) -> sgx_status_t,
+    {
+        let _current = CurrentOcalls::enter(self.ocalls);
+        ecall(self.id).into_result()
+    }
+
+    fn ecall_returning<T, F>(&self, ecall: F) -> Result<T, SgxError>
+    where
+        T: Default,
+        F: FnOnce(sgx_enclave_id_t, &mut T) -> sgx_status_t,
+    {
+        let mut retval = T::default();
+        self.ecall(|eid| ecall(eid, &mut retval))?;
+        Ok(retval)
+    }
+
+    fn ecall_status<F>(&self, ecall: F) -> Result<(), SgxError>
+    where
+        F: FnOnce(sgx_enclave_id_t, &mut sgx_status_t) -> sgx_status_t,
+    {
+        let status =
+            self.ecall_returning(|eid, status: &mut StatusRetval| ecall(eid, &mut status.0))?;
+        status.0.into_result()
+    }
+}
+
+// `sgx_status_t` has no `Default`, the enclave function's status is only read
+// when the ECALL succeeded and the enclave wrote it.
+struct StatusRetval(sgx_status_t);
+
+impl Default for StatusRetval {
+    fn default() -> Self {
+        Self(sgx_status_t::SGX_ERROR_UNEXPECTED)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn enclave(ocalls: &OcallTable) -> EcallContext<'_> {
+        EcallContext { id: 0, ocalls }
+    }
+
+    #[test]
+    fn ecall_success() {
+        let ocalls = OcallTable::default();
+        let enclave = enclave(&ocalls);
+        assert_eq!(enclave.ecall(|_| sgx_status_t::SGX_SUCCESS), Ok(()));
+    }
+
+    #[test]
+    fn ecall_passes_enclave_id() {
+        let ocalls = OcallTable::default();
+        let mut enclave = enclave(&ocalls);
+        enclave.id = 42;
+        let mut seen = 0;
+        enclave
+            .ecall(|eid| {
+                seen = eid;
+                sgx_status_t::SGX_SUCCESS
+            })
+            .unwrap();
+        assert_eq!(seen, 42);
+    }
+
+    #[test]
+    fn ecall_failure() {
+        let ocalls = OcallTable::default();
+        let enclave = enclave(&ocalls);
+        assert_eq!(
+            enclave.ecall(|_| sgx_status_t::SGX_ERROR_OUT_OF_TCS),
+            Err(SgxError::OutOfTcs)
+        );
+    }
+
+    #[test]
+    fn ecall_returning_value() {
+        let ocalls = OcallTable::default();
+        let enclave = enclave(&ocalls);
+        let sum = enclave.ecall_returning(|_, sum: &mut u32| {
+            *sum = 3;
+            sgx_status_t::SGX_SUCCESS
+        });
+        assert_eq!(sum, Ok(3));
+    }
+
+    #[test]
+    fn ecall_status_of_enclave_function() {
+        let ocalls = OcallTable::default();
+        let enclave = enclave(&ocalls);
+        let result = enclave.ecall_status(|_, status| {
+            *status = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+            sgx_status_t::SGX_SUCCESS
+        });
+        assert_eq!(result, Err(SgxError::InvalidParameter));
+    }
+
+    #[test]
+    fn ecall_status_prefers_sgx_failure() {
+        let ocalls = OcallTable::default();
+        let enclave = enclave(&ocalls);
+        let result = enclave.ecall_status(|_, status| {
+            *status = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+            sgx_status_t::SGX_ERROR_ENCLAVE_LOST
+        });
+        assert_eq!(result, Err(SgxError::EnclaveLost));
+    }
+
+    #[test]
+    fn ecall_status_success() {
+        let ocalls = OcallTable::default();
+        let enclave = enclave(&ocalls);
+        let result = enclave.ecall_status(|_, status| {
+            *status = sgx_status_t::SGX_SUCCESS;
+            sgx_status_t::SGX_SUCCESS
+        });
+        assert_eq!(result, Ok(()));
+    }
+
+    type Double = Box<dyn Fn(u32) -> u32 + Send + Sync>;
+
+    trait Greeter: Send + Sync {
+        fn greet(&self) -> String;
+    }
+
+    struct English;
+
+    impl Greeter for English {
+        fn greet(&self) -> String {
+            "hello".into()
+        }
+    }
+
+    #[test]
+    fn ocall_table_lookup() {
+        let mut ocalls = OcallTable::default();
+        ocalls
+            .insert::<Double>(Box::new(|x| x * 2))
+            .insert::<Box<dyn Greeter>>(Box::new(English));
+
+        assert_eq!(ocalls.len(), 2);
+        assert_eq!(ocalls.get::<Double>().map(|double| double(2)), Some(4));
+        assert_eq!(
+            ocalls.get::<Box<dyn Greeter>>().map(|g| g.greet()),
+            Some("hello".into())
+        );
+        assert!(ocalls.get::<u32>().is_none());
+    }
+
+    #[test]
+    fn ocall_handler_during_ecall() {
+        let mut ocalls = OcallTable::default();
+        ocalls.insert::<Double>(Box::new(|x| x * 2));
+        let enclave = enclave(&ocalls);
+
+        let result = enclave.ecall_returning(|_, doubled: &mut Option<u32>| {
+            *doubled = ocall_handler(|double: &Double| double(21));
+            sgx_status_t::SGX_SUCCESS
+        });
+
+        assert_eq!(result, Ok(Some(42)));
+    }
+
+    #[test]
+    fn no_ocall_handler_outside_of_ecall() {
+        let mut ocalls = OcallTable::default();
+        ocalls.insert::<Double>(Box::new(|x| x * 2));
+        let enclave = enclave(&ocalls);
+        enclave.ecall(|_| sgx_status_t::SGX_SUCCESS).unwrap();
+
+        assert_eq!(ocall_handler(|double: &Double| double(21)), None);
+    }
+
+    #[test]
+    fn nested_ecall_restores_ocall_handlers() {
+        let mut outer_ocalls = OcallTable::default();
+        outer_ocalls.insert::<Double>(Box::new(|x| x * 2));
+        let inner_ocalls = OcallTable::default();
+        let outer = enclave(&outer_ocalls);
+        let inner = enclave(&inner_ocalls);
+
+        let result = outer.ecall_returning(|_, seen: &mut (Option<u32>, Option<u32>)| {
+            inner
+                .ecall(|_| {
+                    seen.0 = ocall_handler(|double: &Double| double(1));
+                    sgx_status_t::SGX_SUCCESS
+                })
+                .unwrap();
+            seen.1 = ocall_handler(|double: &Double| double(1));
+            sgx_status_t::SGX_SUCCESS
+        });
+
+        assert_eq!(result, Ok((None, Some(2))));
+    }
+}
@@ -2,17 +2,135 @@
 
 #![doc = include_str!("../README.md")]
 
-use mc_sgx_core_types::{ConfigId, ConfigSvn, Error, TargetInfo};
-use mc_sgx_urts_sys::{
-    sgx_create_enclave_from_buffer_ex, sgx_destroy_enclave, sgx_get_target_info,
+/// Call a function of the uRTS for an [`SgxMode`]
+///
+/// Evaluates to the `sgx_status_t` of the function. With the `dlopen` feature
+/// the uRTS for the mode is loaded on first use, otherwise only
+/// [`SgxMode::built()`] is available.
+macro_rules! urts {
+    ($mode:expr, $function:ident($($arg:expr),* $(,)?)) => {{
+        let mode: $crate::SgxMode = $mode;
+        #[cfg(feature = "dlopen")]
+        let result = match mode {
+            $crate::SgxMode::Hardware => mc_sgx_urts_sys::dlopen::hardware(),
+            $crate::SgxMode::Simulation => mc_sgx_urts_sys::dlopen::simulation(),
+        }
+        .map(|urts| unsafe { urts.$function($($arg),*) })
+        .map_err(|e| $crate::Error::LibraryUnavailable(mode, e.into()));
+        #[cfg(not(feature = "dlopen"))]
+        let result = if mode == $crate::SgxMode::built() {
+            Ok(unsafe { mc_sgx_urts_sys::$function($($arg),*) })
+        } else {
+            Err($crate::Error::ModeUnavailable(mode))
+        };
+        result
+    }};
+}
+
+#[cfg(feature = "async")]
+mod async_ecall;
+mod ecall;
+mod metadata;
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+mod mode;
+mod pool;
+mod runtime;
+mod sigstruct;
+#[cfg(feature = "switchless")]
+mod switchless;
+
+#[cfg(feature = "async")]
+pub use crate::async_ecall::{AsyncEnclave, EcallFuture};
+#[cfg(feature = "mock")]
+pub use crate::mock::{MockEnclave, MockEnclaveBuilder, MockHandle};
+#[cfg(feature = "switchless")]
+pub use crate::switchless::{
+    switchless_stats, SwitchlessConfig, WorkerCallback, WorkerEvent, WorkerStats, WorkerType,
+};
+pub use crate::{
+    ecall::{ocall_handler, OcallTable},
+    mode::{SgxMode, SGX_MODE_ENV},
+    pool::{EnclavePool, EnclavePoolBuilder, PoolStats},
+    runtime::{Ecall, EnclaveRuntime},
+};
+
+use crate::sigstruct::IdentityPolicy;
+use mc_sgx_core_sys_types::sgx_attributes_t;
+use mc_sgx_core_types::{
+    Attributes, ConfigId, ConfigSvn, Error as SgxError, IsvSvn, MiscellaneousAttribute,
+    MiscellaneousSelect, MrEnclave, MrSigner, Sigstruct, TargetInfo,
 };
 use mc_sgx_urts_sys_types::{
-    sgx_enclave_id_t, sgx_kss_config_t, MAX_EX_FEATURES_COUNT, SGX_CREATE_ENCLAVE_EX_KSS,
-    SGX_CREATE_ENCLAVE_EX_KSS_BIT_IDX, SGX_CREATE_ENCLAVE_EX_PCL,
+    sgx_enclave_id_t, sgx_kss_config_t, sgx_misc_attribute_t, MAX_EX_FEATURES_COUNT,
+    SGX_CREATE_ENCLAVE_EX_KSS, SGX_CREATE_ENCLAVE_EX_KSS_BIT_IDX, SGX_CREATE_ENCLAVE_EX_PCL,
     SGX_CREATE_ENCLAVE_EX_PCL_BIT_IDX,
 };
+#[cfg(feature = "switchless")]
+use mc_sgx_urts_sys_types::{
+    sgx_uswitchless_config_t, SGX_CREATE_ENCLAVE_EX_SWITCHLESS,
+    SGX_CREATE_ENCLAVE_EX_SWITCHLESS_BIT_IDX,
+};
 use mc_sgx_util::ResultInto;
-use std::{ffi::c_void, fs::File, io::Read, os::raw::c_int, path::Path, ptr};
+use std::{
+    ffi::c_void,
+    fmt,
+    fs::File,
+    io::Read,
+    num::NonZeroUsize,
+    os::raw::c_int,
+    path::{Path, PathBuf},
+    ptr,
+};
+
+/// Errors creating and using an [`Enclave`]
+#[derive(Clone, Debug, displaydoc::Display, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// Error from the SGX SDK: {0}
+    Sgx(SgxError),
+    /// The {0} uRTS could not be loaded: {1}
+    LibraryUnavailable(SgxMode, String),
+    /// The {0} uRTS is only available with the `dlopen` feature
+    ModeUnavailable(SgxMode),
+    /// Invalid SGX mode {0:?}, expected `HW` or `SIM`
+    InvalidMode(String),
+    /// The ECALL was aborted, it panicked or its worker thread stopped
+    EcallAborted,
+    /// The enclave has no SIGSTRUCT, it isn't signed
+    SigstructNotFound,
+    /// The enclave's MRENCLAVE {actual} isn't the expected {expected}
+    MrEnclaveMismatch {
+        /// The MRENCLAVE the enclave was expected to have
+        expected: MrEnclave,
+        /// The MRENCLAVE of the enclave
+        actual: MrEnclave,
+    },
+    /// The enclave's MRSIGNER {actual} isn't the expected {expected}
+    MrSignerMismatch {
+        /// The MRSIGNER the enclave was expected to have
+        expected: MrSigner,
+        /// The MRSIGNER of the enclave
+        actual: MrSigner,
+    },
+    /// The enclave's ISV SVN {actual} is below the minimum {minimum}
+    IsvSvnTooLow {
+        /// The lowest ISV SVN allowed
+        minimum: IsvSvn,
+        /// The ISV SVN of the enclave
+        actual: IsvSvn,
+    },
+    /// The enclave can be debugged, which requires `EnclaveBuilder::debug()`
+    DebugCapable,
+    /// At most {0} enclaves with a switchless worker callback can exist at once
+    TooManyWorkerCallbacks(usize),
+}
+
+impl From<SgxError> for Error {
+    fn from(src: SgxError) -> Self {
+        Self::Sgx(src)
+    }
+}
 
 /// Structure defining configuration for Key Sharing and Separation
 #[repr(transparent)]
@@ -40,19 +158,80 @@ impl From<sgx_kss_config_t> for KssConfig {
     }
 }
 
-/// Struct for interfacing with the SGX SDK.  This should be used in
-/// sgx calls as `ecall_some_function(*enclave.id(), ...)`.
+/// Where the bytes of an enclave came from
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum EnclaveSource {
+    /// Read from the file at the path, see [`EnclaveBuilder::new()`]
+    Path(PathBuf),
+    /// Read from an open file
+    File,
+    /// Provided as bytes
+    Buffer,
+}
+
+impl fmt::Display for EnclaveSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => write!(f, "{}", path.display()),
+            Self::File => write!(f, "file"),
+            Self::Buffer => write!(f, "buffer"),
+        }
+    }
+}
+
+/// Struct for interfacing with the SGX SDK.  ECALLs should be made with
+/// [`Enclave::ecall()`] and friends, which pass the enclave ID to the ECALL
+/// function, i.e. `enclave.ecall(|eid| unsafe { ecall_some_function(eid, ...) })`.
 ///
 /// Avoid storing the de-referenced ID of the enclave.  The de-referenced
 /// ID of the enclave will result in failures to the SGX SDK after the
 /// enclave is dropped.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug)]
 pub struct Enclave {
     // The enclave ID, assigned by the SGX interface
     id: sgx_enclave_id_t,
+
+    // The uRTS the enclave was created with
+    mode: SgxMode,
+
+    // The handlers for the enclave's OCALLs
+    ocalls: OcallTable,
+
+    // The attributes and MISCSELECT the enclave was created with
+    misc_attr: MiscellaneousAttribute,
+
+    // `true` if the enclave was created in debug mode
+    debug: bool,
+
+    // `true` if the enclave was created with Key Separation & Sharing
+    kss: bool,
+
+    // `true` if the enclave was loaded with Intel's Protected Code Loader
+    pcl: bool,
+
+    // Where the bytes of the enclave came from
+    source: EnclaveSource,
+
+    // The TCSs available to ECALLs
+    tcs_count: NonZeroUsize,
+
+    // Keeps the switchless worker callback registered while the enclave lives
+    #[cfg(feature = "switchless")]
+    _switchless: Option<switchless::Registration>,
+}
+
+impl PartialEq for Enclave {
+    fn eq(&self, other: &Self) -> bool {
+        // Enclave IDs are unique for a uRTS, the OCALL handlers aren't
+        // comparable
+        self.id == other.id && self.mode == other.mode
+    }
 }
 
+impl Eq for Enclave {}
+
 /// Build an [Enclave] for use with SGX calls.
+#[derive(Clone)]
 pub struct EnclaveBuilder {
     // The bytes for the enclave.
     bytes: Vec<u8>,
@@ -65,6 +244,23 @@ pub struct EnclaveBuilder {
 
     // Configuration to use with Key Separation & Sharing. None if KSS disabled.
     kss_config: Option<KssConfig>,
+
+    // The uRTS to create the enclave with. None to use the `SGX_MODE`
+    // environment variable or the built mode.
+    mode: Option<SgxMode>,
+
+    // The handlers for the enclave's OCALLs
+    ocalls: OcallTable,
+
+    // Configuration of switchless calls. None if switchless calls disabled.
+    #[cfg(feature = "switchless")]
+    switchless: Option<SwitchlessConfig>,
+
+    // The identity checked before creating the enclave. None if unchecked.
+    identity: Option<IdentityPolicy>,
+
+    // Where the bytes of the enclave came from
+    source: EnclaveSource,
 }
 
 impl EnclaveBuilder {
@@ -75,8 +271,10 @@ impl EnclaveBuilder {
     /// * `path` - The path to the enclave file. This should be a signed
     ///   enclave.
     pub fn new(path: impl AsRef<Path>) -> std::io::Result<EnclaveBuilder> {
-        let file = File::open(path)?;
-        file.try_into()
+        let file = File::open(path.as_ref())?;
+        let mut builder = EnclaveBuilder::try_from(file)?;
+        builder.source = EnclaveSource::Path(path.as_ref().to_path_buf());
+        Ok(builder)
     }
 
     /// Enable debugging of the enclave
@@ -88,6 +286,9 @@ impl EnclaveBuilder {
 
     /// Enable Intel's Protected Code Loader for the enclave
     ///
+    /// The enclave must have been encrypted, see `SgxEncrypt` and
+    /// `SealedPclKey` in `mc-sgx-sdk-tools`.
+    ///
     /// # Arguments
     ///
     /// * `key` - The sealed PCL key to use for loading the enclave
@@ -108,57 +309,243 @@ impl EnclaveBuilder {
         self
     }
 
+    /// Handle the enclave's OCALLs with Rust closures or trait objects
+    ///
+    /// The handlers are available to [`ocall_handler()`] while an ECALL is
+    /// made with [`Enclave::ecall()`] and friends.
+    ///
+    /// # Arguments
+    ///
+    /// * `ocalls` - The OCALL handlers of the enclave
+    #[must_use]
+    pub fn ocalls(mut self, ocalls: OcallTable) -> EnclaveBuilder {
+        self.ocalls = ocalls;
+        self
+    }
+
+    /// Enable switchless ECALLs and OCALLs for the enclave
+    ///
+    /// Only the calls marked `transition_using_threads` in the EDL are
+    /// switchless, the others are unaffected.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The worker threads and retries of the switchless calls
+    #[cfg(feature = "switchless")]
+    #[must_use]
+    pub fn switchless(mut self, config: SwitchlessConfig) -> EnclaveBuilder {
+        self.switchless = Some(config);
+        self
+    }
+
+    /// Check the enclave's SIGSTRUCT before creating it
+    ///
+    /// The enclave fails to be created if it isn't signed, or if it can be
+    /// debugged and [`EnclaveBuilder::debug()`] wasn't requested. Setting an
+    /// expected MRENCLAVE, MRSIGNER or minimum ISV SVN enables the check too.
+    #[must_use]
+    pub fn verify_sigstruct(mut self) -> EnclaveBuilder {
+        self.identity.get_or_insert_with(Default::default);
+        self
+    }
+
+    /// Only create the enclave if it has the MRENCLAVE `mr_enclave`
+    ///
+    /// See [`EnclaveBuilder::verify_sigstruct()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `mr_enclave` - The expected MRENCLAVE of the enclave
+    #[must_use]
+    pub fn expect_mr_enclave(mut self, mr_enclave: MrEnclave) -> EnclaveBuilder {
+        self.identity
+            .get_or_insert_with(Default::default)
+            .mr_enclave = Some(mr_enclave);
+        self
+    }
+
+    /// Only create the enclave if it was signed by `mr_signer`
+    ///
+    /// See [`EnclaveBuilder::verify_sigstruct()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `mr_signer` - The expected MRSIGNER of the enclave
+    #[must_use]
+    pub fn expect_mr_signer(mut self, mr_signer: MrSigner) -> EnclaveBuilder {
+        self.identity.get_or_insert_with(Default::default).mr_signer = Some(mr_signer);
+        self
+    }
+
+    /// Only create the enclave if its ISV SVN is at least `isv_svn`
+    ///
+    /// See [`EnclaveBuilder::verify_sigstruct()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `isv_svn` - The lowest ISV SVN allowed
+    #[must_use]
+    pub fn min_isv_svn(mut self, isv_svn: IsvSvn) -> EnclaveBuilder {
+        self.identity
+            .get_or_insert_with(Default::default)
+            .min_isv_svn = Some(isv_svn);
+        self
+    }
+
+    /// Returns the SIGSTRUCT of the enclave, `None` if it isn't signed
+    pub fn sigstruct(&self) -> Option<Sigstruct> {
+        Sigstruct::from_enclave(&self.bytes)
+    }
+
+    /// Create the enclave with the hardware or simulation uRTS
+    ///
+    /// Selecting a mode other than [`SgxMode::built()`] requires the `dlopen`
+    /// feature, which loads the matching uRTS at runtime.
+    ///
+    /// When no mode is set the `SGX_MODE` environment variable is used with
+    /// the `dlopen` feature, see [`SgxMode::from_env()`], falling back to
+    /// [`SgxMode::built()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The uRTS to create the enclave with
+    #[must_use]
+    pub fn mode(mut self, mode: SgxMode) -> EnclaveBuilder {
+        self.mode = Some(mode);
+        self
+    }
+
     /// Create the enclave
     ///
     /// Will talk to the SGX SDK to create the enclave.  Once the enclave has
     /// been created then calls into the enclave can be made using the enclave
     /// ID.
+    ///
+    /// # Errors
+    /// * [`Error::Sgx`] if the SGX SDK fails to create the enclave.
+    /// * [`Error::LibraryUnavailable`] if the uRTS for the mode can't be
+    ///   loaded.
+    /// * [`Error::ModeUnavailable`] if a mode other than [`SgxMode::built()`]
+    ///   was selected without the `dlopen` feature.
+    /// * [`Error::InvalidMode`] if `SGX_MODE` has an invalid value.
+    /// * [`Error::TooManyWorkerCallbacks`] if the switchless configuration has
+    ///   a worker callback and too many other enclaves have one.
+    /// * [`Error::SigstructNotFound`], [`Error::MrEnclaveMismatch`],
+    ///   [`Error::MrSignerMismatch`], [`Error::IsvSvnTooLow`] or
+    ///   [`Error::DebugCapable`] if the SIGSTRUCT is checked and fails, see
+    ///   [`EnclaveBuilder::verify_sigstruct()`].
     pub fn create(mut self) -> Result<Enclave, Error> {
+        if let Some(identity) = &self.identity {
+            identity.check(&self.bytes, self.debug)?;
+        }
+        let mode = match self.mode {
+            Some(mode) => mode,
+            None if cfg!(feature = "dlopen") => SgxMode::from_env()?.unwrap_or_default(),
+            None => SgxMode::built(),
+        };
         let mut enclave_id: sgx_enclave_id_t = 0;
+        let mut misc_attr = sgx_misc_attribute_t {
+            secs_attr: sgx_attributes_t::default(),
+            misc_select: 0,
+        };
         let mut ex_features = 0;
         let mut ex_features_p: [*const c_void; MAX_EX_FEATURES_COUNT] =
             [ptr::null(); MAX_EX_FEATURES_COUNT];
 
-        if let Some(pcl_key) = self.pcl_key {
+        // The feature structures are only read during creation, but must
+        // outlive the call
+        if let Some(pcl_key) = &self.pcl_key {
             ex_features |= SGX_CREATE_ENCLAVE_EX_PCL;
             ex_features_p[SGX_CREATE_ENCLAVE_EX_PCL_BIT_IDX] = pcl_key.as_ptr() as *const c_void;
         }
 
-        if let Some(kss_config) = self.kss_config {
+        let kss_config = self.kss_config.take().map(sgx_kss_config_t::from);
+        if let Some(kss_config) = &kss_config {
             ex_features |= SGX_CREATE_ENCLAVE_EX_KSS;
             ex_features_p[SGX_CREATE_ENCLAVE_EX_KSS_BIT_IDX] =
-                &kss_config.into() as *const sgx_kss_config_t as *const c_void;
+                kss_config as *const sgx_kss_config_t as *const c_void;
         }
 
-        unsafe {
-            // Per the API reference `buffer` is an input, however the signature
-            // lacks the const qualifier.  Through testing it has been shown
-            // that `sgx_create_enclave_from_buffer_ex()` *will* modify the
-            // `buffer` parameter.  This can be seen by copying the input bytes
-            // and comparing before and after.
-            //
-            //      let mut buffer = self.bytes.to_vec();
-            //      println!("Pre comparing {}", buffer.as_slice() == self.bytes);
-            //      let result = unsafe {sgx_create_enclave_from_buffer_ex(...)};
-            //      println!("Post comparing {}", buffer.as_slice() == self.bytes);
-            //
-            // The modification that `sgx_create_enclave_from_buffer_ex()`
-            // makes to the `buffer` is such that if one were to re-use the
-            // modified buffer in another call to
-            // `sgx_create_enclave_from_buffer_ex()` then
-            // `SGX_ERROR_INVALID_ENCLAVE_ID` would be returned.
+        // Workers start during creation, so the callback is registered first
+        #[cfg(feature = "switchless")]
+        let registration = self
+            .switchless
+            .as_ref()
+            .map(SwitchlessConfig::register)
+            .transpose()?;
+        #[cfg(feature = "switchless")]
+        let switchless_config = self
+            .switchless
+            .as_ref()
+            .zip(registration.as_ref())
+            .map(|(config, registration)| config.to_raw(registration));
+        #[cfg(feature = "switchless")]
+        if let Some(switchless_config) = &switchless_config {
+            ex_features |= SGX_CREATE_ENCLAVE_EX_SWITCHLESS;
+            ex_features_p[SGX_CREATE_ENCLAVE_EX_SWITCHLESS_BIT_IDX] =
+                switchless_config as *const sgx_uswitchless_config_t as *const c_void;
+        }
+
+        // The bytes are read before `sgx_create_enclave_from_buffer_ex()`
+        // modifies them, see below. The uRTS won't load an enclave without a
+        // layout, so falling back to one TCS is only a safeguard.
+        let tcs_count = metadata::tcs_count(&self.bytes).map_or(1, NonZeroUsize::get);
+        // Trusted switchless workers each occupy a TCS for the enclave's
+        // lifetime
+        #[cfg(feature = "switchless")]
+        let tcs_count = match &self.switchless {
+            Some(config) => tcs_count.saturating_sub(config.occupied_tcs()),
+            None => tcs_count,
+        };
+        let tcs_count = NonZeroUsize::new(tcs_count.max(1)).expect("TCS count should be non zero");
+
+        // Per the API reference `buffer` is an input, however the signature
+        // lacks the const qualifier.  Through testing it has been shown
+        // that `sgx_create_enclave_from_buffer_ex()` *will* modify the
+        // `buffer` parameter.  This can be seen by copying the input bytes
+        // and comparing before and after.
+        //
+        //      let mut buffer = self.bytes.to_vec();
+        //      println!("Pre comparing {}", buffer.as_slice() == self.bytes);
+        //      let result = unsafe {sgx_create_enclave_from_buffer_ex(...)};
+        //      println!("Post comparing {}", buffer.as_slice() == self.bytes);
+        //
+        // The modification that `sgx_create_enclave_from_buffer_ex()`
+        // makes to the `buffer` is such that if one were to re-use the
+        // modified buffer in another call to
+        // `sgx_create_enclave_from_buffer_ex()` then
+        // `SGX_ERROR_INVALID_ENCLAVE_ID` would be returned.
+        urts!(
+            mode,
             sgx_create_enclave_from_buffer_ex(
                 self.bytes.as_mut_ptr(),
                 self.bytes.len(),
                 self.debug as c_int,
                 &mut enclave_id,
-                ptr::null_mut(),
+                &mut misc_attr,
                 ex_features,
                 &mut ex_features_p as *mut *const c_void,
             )
-        }
-        .into_result()
-        .map(|_| Enclave { id: enclave_id })
+        )?
+        .into_result()?;
+
+        Ok(Enclave {
+            id: enclave_id,
+            mode,
+            ocalls: self.ocalls,
+            misc_attr: mc_sgx_core_sys_types::sgx_misc_attribute_t {
+                secs_attr: misc_attr.secs_attr,
+                misc_select: misc_attr.misc_select,
+            }
+            .into(),
+            debug: self.debug,
+            kss: kss_config.is_some(),
+            pcl: self.pcl_key.is_some(),
+            source: self.source,
+            tcs_count,
+            #[cfg(feature = "switchless")]
+            _switchless: registration,
+        })
     }
 }
 
@@ -169,6 +556,12 @@ impl From<Vec<u8>> for EnclaveBuilder {
             debug: false,
             pcl_key: None,
             kss_config: None,
+            mode: None,
+            ocalls: OcallTable::default(),
+            #[cfg(feature = "switchless")]
+            switchless: None,
+            identity: None,
+            source: EnclaveSource::Buffer,
         }
     }
 }
@@ -190,12 +583,9 @@ impl TryFrom<File> for EnclaveBuilder {
     fn try_from(mut input: File) -> std::io::Result<EnclaveBuilder> {
         let mut bytes = vec![];
         input.read_to_end(&mut bytes)?;
-        Ok(EnclaveBuilder {
-            bytes,
-            debug: false,
-            pcl_key: None,
-            kss_config: None,
-        })
+        let mut builder = EnclaveBuilder::from(bytes);
+        builder.source = EnclaveSource::File;
+        Ok(builder)
     }
 }
 
@@ -203,16 +593,66 @@ impl Enclave {
     /// Returns the target info for the enclave.
     pub fn target_info(&self) -> Result<TargetInfo, Error> {
         let mut target_info = TargetInfo::default().into();
-        unsafe { sgx_get_target_info(self.id, &mut target_info) }.into_result()?;
+        urts!(self.mode, sgx_get_target_info(self.id, &mut target_info))?.into_result()?;
         Ok(target_info.into())
     }
 
+    /// Returns the uRTS the enclave was created with.
+    pub fn mode(&self) -> SgxMode {
+        self.mode
+    }
+
+    /// Returns the attributes and MISCSELECT the enclave was created with.
+    pub fn misc_attr(&self) -> &MiscellaneousAttribute {
+        &self.misc_attr
+    }
+
+    /// Returns the attributes the enclave was created with, including the XFRM.
+    pub fn attributes(&self) -> Attributes {
+        self.misc_attr.as_ref().secs_attr.into()
+    }
+
+    /// Returns the MISCSELECT the enclave was created with.
+    pub fn misc_select(&self) -> MiscellaneousSelect {
+        self.misc_attr.as_ref().misc_select.into()
+    }
+
+    /// Returns `true` if the enclave was created in debug mode.
+    pub fn debug(&self) -> bool {
+        self.debug
+    }
+
+    /// Returns `true` if the enclave was created with Key Separation &
+    /// Sharing.
+    pub fn kss(&self) -> bool {
+        self.kss
+    }
+
+    /// Returns `true` if the enclave was loaded with Intel's Protected Code
+    /// Loader.
+    pub fn pcl(&self) -> bool {
+        self.pcl
+    }
+
+    /// Returns where the bytes of the enclave came from.
+    pub fn source(&self) -> &EnclaveSource {
+        &self.source
+    }
+
     /// Returns a reference to the enclave ID.
     /// Returns by reference because enclave ID will not be valid after the
     /// enclave is dropped.
     pub fn id(&self) -> &sgx_enclave_id_t {
         &self.id
     }
+
+    /// Returns the number of ECALLs the enclave can be in at once.
+    ///
+    /// This is the `TCSNum` `sgx_sign` recorded in the enclave's metadata,
+    /// less the TCSs of any trusted switchless workers.
+    pub fn tcs_count(&self) -> NonZeroUsize {
+        self.tcs_count
+    }
 }
 
 impl Drop for Enclave {
@@ -225,7 +665,7 @@ impl Drop for Enclave {
         // happen when the ID is invalid, the enclave hasn't been loaded,
         // or the enclave has already been destroyed. Any of these cases
         // don't afford corrective action, so ignore the return value
-        unsafe { sgx_destroy_enclave(self.id) };
+        let _ = urts!(self.mode, sgx_destroy_enclave(self.id));
     }
 }
 
@@ -236,7 +676,79 @@ mod tests {
     #[test]
     fn fail_to_create_enclave_with_bogus_bytes() {
         let builder = EnclaveBuilder::from(b"garbage bytes");
-        assert_eq!(builder.create(), Err(Error::InvalidEnclave));
+        assert_eq!(builder.create(), Err(Error::Sgx(SgxError::InvalidEnclave)));
+    }
+
+    #[test]
+    fn mode_is_unset_by_default() {
+        let builder = EnclaveBuilder::from(b"");
+        assert_eq!(builder.mode, None);
+    }
+
+    #[test]
+    fn mode_can_be_selected() {
+        let builder = EnclaveBuilder::from(b"").mode(SgxMode::Simulation);
+        assert_eq!(builder.mode, Some(SgxMode::Simulation));
+    }
+
+    #[cfg(not(feature = "dlopen"))]
+    #[test]
+    fn other_mode_requires_dlopen() {
+        let other = match SgxMode::built() {
+            SgxMode::Hardware => SgxMode::Simulation,
+            SgxMode::Simulation => SgxMode::Hardware,
+        };
+        let builder = EnclaveBuilder::from(b"garbage bytes").mode(other);
+        assert_eq!(builder.create(), Err(Error::ModeUnavailable(other)));
+    }
+
+    #[test]
+    fn unsigned_enclave_fails_sigstruct_check() {
+        let builder = EnclaveBuilder::from(b"garbage bytes").verify_sigstruct();
+        assert_eq!(builder.create(), Err(Error::SigstructNotFound));
+    }
+
+    #[test]
+    fn sigstruct_is_checked_before_creating() {
+        let enclave = sigstruct::test::enclave_bytes(&sigstruct::test::sigstruct_bytes());
+        let builder = EnclaveBuilder::from(enclave).min_isv_svn(9.into());
+        assert_eq!(
+            builder.create(),
+            Err(Error::IsvSvnTooLow {
+                minimum: 9.into(),
+                actual: 5.into(),
+            })
+        );
+    }
+
+    #[test]
+    fn sigstruct_of_builder() {
+        let sigstruct = sigstruct::test::sigstruct_bytes();
+        let builder = EnclaveBuilder::from(sigstruct::test::enclave_bytes(&sigstruct));
+        assert_eq!(builder.sigstruct().unwrap().as_ref(), sigstruct.as_slice());
+        assert_eq!(EnclaveBuilder::from(b"").sigstruct(), None);
+    }
+
+    #[test]
+    fn source_of_bytes_is_buffer() {
+        let builder = EnclaveBuilder::from(b"");
+        assert_eq!(builder.source, EnclaveSource::Buffer);
+    }
+
+    #[test]
+    fn source_of_path_is_recorded() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        let builder = EnclaveBuilder::try_from(File::open(&path).unwrap()).unwrap();
+        assert_eq!(builder.source, EnclaveSource::File);
+        let builder = EnclaveBuilder::new(&path).expect("Failed to read file");
+        assert_eq!(builder.source, EnclaveSource::Path(path));
+    }
+
+    #[test]
+    fn enclave_source_display() {
+        let path = EnclaveSource::Path("/tmp/enclave.signed.so".into());
+        assert_eq!(path.to_string(), "/tmp/enclave.signed.so");
+        assert_eq!(EnclaveSource::Buffer.to_string(), "buffer");
     }
 
     #[test]
@@ -0,0 +1,169 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Reading the layout `sgx_sign` records in the metadata of a signed enclave
+
+use core::{num::NonZeroUsize, ops::Range};
+
+/// The magic number the `metadata_t` starts with
+const METADATA_MAGIC: [u8; 8] = 0x86A8_0294_635D_0E4C_u64.to_le_bytes();
+
+/// `metadata_t.dirs[DIR_LAYOUT]`, the offset and size of the layout table
+/// relative to the metadata
+const LAYOUT_DIR_OFFSET: Range<usize> = 1880..1884;
+const LAYOUT_DIR_SIZE: Range<usize> = 1884..1888;
+
+/// The size of a `layout_t`, either a `layout_entry_t` or `layout_group_t`
+const LAYOUT_SIZE: usize = 32;
+
+/// Set in the ID of a `layout_group_t`
+const GROUP_FLAG: u16 = 1 << 12;
+
+/// `PAGE_ATTR_EADD`, the pages are added when the enclave is loaded
+const PAGE_ATTR_EADD: u16 = 1 << 0;
+
+/// `LAYOUT_ID_TCS` of the SGX SDK's `metadata.h`
+const LAYOUT_ID_TCS: u16 = 4;
+
+/// The number of TCSs the enclave is loaded with, its `TCSNum`
+///
+/// Only the TCSs added when the enclave is loaded are counted, those EDMM adds
+/// up to `TCSMaxNum` aren't guaranteed to be available.
+///
+/// Returns `None` if the enclave isn't signed or its layout has no TCS.
+///
+/// # Arguments
+/// * `enclave` - The bytes of the signed enclave shared object
+pub(crate) fn tcs_count(enclave: &[u8]) -> Option<NonZeroUsize> {
+    enclave
+        .windows(METADATA_MAGIC.len())
+        .enumerate()
+        .filter(|(_, window)| *window == METADATA_MAGIC)
+        .find_map(|(offset, _)| layout_tcs_count(&enclave[offset..]))
+}
+
+/// The TCSs in the layout table of the `metadata`
+///
+/// A `layout_group_t` repeats the `entry_count` entries before it
+/// `load_times` more times, this is how the per thread entries are repeated
+/// for each TCS.
+fn layout_tcs_count(metadata: &[u8]) -> Option<NonZeroUsize> {
+    let offset = read_u32(metadata, LAYOUT_DIR_OFFSET)? as usize;
+    let size = read_u32(metadata, LAYOUT_DIR_SIZE)? as usize;
+    let table = metadata.get(offset..offset.checked_add(size)?)?;
+
+    // The number of times each entry is loaded, 0 for those not in the EPC
+    let mut tcs = vec![];
+    for layout in table.chunks_exact(LAYOUT_SIZE) {
+        let id = read_u16(layout, 0..2)?;
+        if id & GROUP_FLAG != 0 {
+            let entry_count = usize::from(read_u16(layout, 2..4)?);
+            let load_times = read_u32(layout, 4..8)? as usize;
+            let start = tcs.len().checked_sub(entry_count)?;
+            for count in &mut tcs[start..] {
+                *count += *count * load_times;
+            }
+        } else {
+            let attributes = read_u16(layout, 2..4)?;
+            let loaded = id == LAYOUT_ID_TCS && attributes & PAGE_ATTR_EADD != 0;
+            tcs.push(usize::from(loaded));
+        }
+    }
+    NonZeroUsize::new(tcs.iter().sum())
+}
+
+fn read_u16(bytes: &[u8], range: Range<usize>) -> Option<u16> {
+    bytes.get(range)?.try_into().ok().map(u16::from_le_bytes)
+}
+
+fn read_u32(bytes: &[u8], range: Range<usize>) -> Option<u32> {
+    bytes.get(range)?.try_into().ok().map(u32::from_le_bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// `LAYOUT_ID_THREAD_GROUP`
+    const THREAD_GROUP: u16 = GROUP_FLAG | 9;
+    const LAYOUT_ID_GUARD: u16 = 10;
+    const LAYOUT_ID_STACK_MAX: u16 = 7;
+    const LAYOUT_ID_TCS_DYN: u16 = 14;
+
+    /// `PAGE_ATTR_POST_ADD`, the pages are added with EDMM
+    const PAGE_ATTR_POST_ADD: u16 = 1 << 3;
+
+    fn entry(id: u16, attributes: u16) -> [u8; LAYOUT_SIZE] {
+        let mut entry = [0; LAYOUT_SIZE];
+        entry[0..2].copy_from_slice(&id.to_le_bytes());
+        entry[2..4].copy_from_slice(&attributes.to_le_bytes());
+        entry[4..8].copy_from_slice(&1_u32.to_le_bytes());
+        entry
+    }
+
+    fn group(entry_count: u16, load_times: u32) -> [u8; LAYOUT_SIZE] {
+        let mut group = [0; LAYOUT_SIZE];
+        group[0..2].copy_from_slice(&THREAD_GROUP.to_le_bytes());
+        group[2..4].copy_from_slice(&entry_count.to_le_bytes());
+        group[4..8].copy_from_slice(&load_times.to_le_bytes());
+        group
+    }
+
+    /// An enclave with a `metadata_t` whose layout table follows its fixed
+    /// fields
+    fn enclave(layout: &[[u8; LAYOUT_SIZE]]) -> Vec<u8> {
+        let mut metadata = vec![0; LAYOUT_DIR_SIZE.end];
+        metadata[..8].copy_from_slice(&METADATA_MAGIC);
+        metadata[LAYOUT_DIR_OFFSET].copy_from_slice(&(LAYOUT_DIR_SIZE.end as u32).to_le_bytes());
+        metadata[LAYOUT_DIR_SIZE]
+            .copy_from_slice(&((layout.len() * LAYOUT_SIZE) as u32).to_le_bytes());
+        metadata.extend(layout.iter().flatten());
+
+        let mut enclave = vec![0x7F; 100];
+        enclave.extend(metadata);
+        enclave
+    }
+
+    #[test]
+    fn single_tcs() {
+        let enclave = enclave(&[
+            entry(LAYOUT_ID_GUARD, 0),
+            entry(LAYOUT_ID_STACK_MAX, PAGE_ATTR_EADD),
+            entry(LAYOUT_ID_TCS, PAGE_ATTR_EADD),
+        ]);
+        assert_eq!(tcs_count(&enclave), NonZeroUsize::new(1));
+    }
+
+    #[test]
+    fn thread_group_repeats_the_tcs() {
+        let enclave = enclave(&[
+            entry(LAYOUT_ID_GUARD, 0),
+            entry(LAYOUT_ID_STACK_MAX, PAGE_ATTR_EADD),
+            entry(LAYOUT_ID_TCS, PAGE_ATTR_EADD),
+            group(3, 9),
+        ]);
+        assert_eq!(tcs_count(&enclave), NonZeroUsize::new(10));
+    }
+
+    #[test]
+    fn tcs_added_with_edmm_are_not_counted() {
+        let enclave = enclave(&[
+            entry(LAYOUT_ID_TCS, PAGE_ATTR_EADD),
+            group(1, 1),
+            entry(LAYOUT_ID_TCS_DYN, PAGE_ATTR_POST_ADD),
+            group(1, 6),
+        ]);
+        assert_eq!(tcs_count(&enclave), NonZeroUsize::new(2));
+    }
+
+    #[test]
+    fn unsigned_enclave_has_no_tcs_count() {
+        assert_eq!(tcs_count(b"garbage bytes"), None);
+    }
+
+    #[test]
+    fn truncated_layout_has_no_tcs_count() {
+        let mut enclave = enclave(&[entry(LAYOUT_ID_TCS, PAGE_ATTR_EADD)]);
+        enclave.truncate(enclave.len() - 1);
+        assert_eq!(tcs_count(&enclave), None);
+    }
+}
@@ -0,0 +1,424 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! An in-process stand-in for an enclave, for testing host code
+
+use crate::{Ecall, EnclaveRuntime, Error};
+use core::{
+    any::{Any, TypeId},
+    fmt,
+    num::NonZeroUsize,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use mc_sgx_core_sys_types::sgx_target_info_t;
+use mc_sgx_core_types::{Error as SgxError, MrEnclave, TargetInfo};
+use mc_sgx_urts_sys_types::sgx_enclave_id_t;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+// Mock enclave IDs count down from the top, to stand out from real ones
+static NEXT_ID: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// A closure standing in for an ECALL, boxed as `dyn Any` to be looked up by
+/// the ECALL's type
+type Handler<E> =
+    Box<dyn Fn(<E as Ecall>::Input) -> Result<<E as Ecall>::Output, SgxError> + Send + Sync>;
+
+/// Build a [`MockEnclave`]
+///
+/// Clones share the ECALL handlers, so an [`EnclavePool`](crate::EnclavePool)
+/// of mock enclaves recreates them with the same handlers.
+#[derive(Clone, Default)]
+pub struct MockEnclaveBuilder {
+    handlers: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+    mr_enclave: Option<MrEnclave>,
+    target_info: Option<TargetInfo>,
+    tcs_count: Option<NonZeroUsize>,
+    create_error: Option<Error>,
+}
+
+impl MockEnclaveBuilder {
+    /// Handle the ECALL `E` with `handler`
+    ///
+    /// ECALLs without a handler fail with [`SgxError::InvalidFunction`], like
+    /// an ECALL the enclave doesn't export.
+    ///
+    /// # Arguments
+    /// * `handler` - Called with the input of each call of `E`
+    #[must_use]
+    pub fn ecall<E, F>(mut self, handler: F) -> Self
+    where
+        E: Ecall,
+        F: Fn(E::Input) -> Result<E::Output, SgxError> + Send + Sync + 'static,
+    {
+        let handler: Handler<E> = Box::new(handler);
+        self.handlers.insert(TypeId::of::<E>(), Arc::new(handler));
+        self
+    }
+
+    /// The MRENCLAVE of the fabricated target info
+    ///
+    /// # Arguments
+    /// * `mr_enclave` - The measurement the mock enclave reports
+    #[must_use]
+    pub fn mr_enclave(mut self, mr_enclave: MrEnclave) -> Self {
+        self.mr_enclave = Some(mr_enclave);
+        self
+    }
+
+    /// Report `target_info` instead of fabricating one
+    ///
+    /// # Arguments
+    /// * `target_info` - The target info the mock enclave reports
+    #[must_use]
+    pub fn target_info(mut self, target_info: TargetInfo) -> Self {
+        self.target_info = Some(target_info);
+        self
+    }
+
+    /// The number of ECALLs the mock enclave reports it can be in at once,
+    /// 1 by default
+    ///
+    /// Calls aren't limited by the mock, this is for code sizing itself to
+    /// the enclave, like an [`EnclavePool`](crate::EnclavePool).
+    ///
+    /// # Arguments
+    /// * `tcs_count` - The TCS count the mock enclave reports
+    #[must_use]
+    pub fn tcs_count(mut self, tcs_count: NonZeroUsize) -> Self {
+        self.tcs_count = Some(tcs_count);
+        self
+    }
+
+    /// Fail to create the enclave with `error`
+    ///
+    /// # Arguments
+    /// * `error` - The error [`MockEnclaveBuilder::create()`] returns
+    #[must_use]
+    pub fn create_error(mut self, error: impl Into<Error>) -> Self {
+        self.create_error = Some(error.into());
+        self
+    }
+
+    /// Create the mock enclave
+    ///
+    /// # Errors
+    /// The error of [`MockEnclaveBuilder::create_error()`], if set.
+    pub fn create(self) -> Result<MockEnclave, Error> {
+        if let Some(error) = self.create_error {
+            return Err(error);
+        }
+        let target_info = self.target_info.unwrap_or_else(|| {
+            let mut target_info: sgx_target_info_t = TargetInfo::default().into();
+            if let Some(mr_enclave) = self.mr_enclave {
+                target_info.mr_enclave = mr_enclave.into();
+            }
+            target_info.into()
+        });
+        Ok(MockEnclave {
+            id: NEXT_ID.fetch_sub(1, Ordering::Relaxed),
+            handlers: self.handlers,
+            target_info,
+            tcs_count: self
+                .tcs_count
+                .unwrap_or_else(|| NonZeroUsize::new(1).expect("1 is non zero")),
+            state: Arc::new(Mutex::new(MockState::default())),
+        })
+    }
+}
+
+impl fmt::Debug for MockEnclaveBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockEnclaveBuilder")
+            .field("handlers", &self.handlers.len())
+            .field("mr_enclave", &self.mr_enclave)
+            .field("target_info", &self.target_info)
+            .field("tcs_count", &self.tcs_count)
+            .field("create_error", &self.create_error)
+            .finish()
+    }
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    // Errors the next calls fail with, before reaching their handlers
+    failures: VecDeque<SgxError>,
+    calls: HashMap<TypeId, usize>,
+    destroyed: bool,
+}
+
+/// Observes a [`MockEnclave`] after it has been moved or destroyed
+#[derive(Clone, Debug)]
+pub struct MockHandle {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockHandle {
+    /// Fail the next call with `error`, before it reaches its handler
+    ///
+    /// Errors are queued, each call takes one. Use this to inject
+    /// [`SgxError::EnclaveLost`] or [`SgxError::OutOfTcs`].
+    ///
+    /// # Arguments
+    /// * `error` - The error the call fails with
+    pub fn fail_next(&self, error: SgxError) {
+        self.lock().failures.push_back(error);
+    }
+
+    /// Returns the number of calls of `E`, including those failed with
+    /// [`MockHandle::fail_next()`]
+    pub fn calls<E: Ecall>(&self) -> usize {
+        self.lock()
+            .calls
+            .get(&TypeId::of::<E>())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns `true` once the mock enclave has been destroyed
+    pub fn destroyed(&self) -> bool {
+        self.lock().destroyed
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().expect("Mutex has been poisoned")
+    }
+}
+
+/// An enclave which runs its ECALLs as Rust closures
+///
+/// No uRTS or signed enclave is involved, so host code written against
+/// [`EnclaveRuntime`] can be tested deterministically. Combine the `mock`
+/// feature with `dlopen` so test binaries don't link the uRTS either.
+///
+/// ```ignore
+/// let enclave = MockEnclaveBuilder::default()
+///     .ecall::<Add, _>(|(a, b)| Ok(a + b))
+///     .create()?;
+/// let handle = enclave.handle();
+/// handle.fail_next(SgxError::EnclaveLost);
+/// assert_eq!(add(&enclave), Err(Error::Sgx(SgxError::EnclaveLost)));
+/// assert_eq!(add(&enclave), Ok(5));
+/// ```
+pub struct MockEnclave {
+    id: sgx_enclave_id_t,
+    handlers: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+    target_info: TargetInfo,
+    tcs_count: NonZeroUsize,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockEnclave {
+    /// Returns the ID of the mock enclave, unique among mock enclaves
+    pub fn id(&self) -> &sgx_enclave_id_t {
+        &self.id
+    }
+
+    /// Returns a handle to inject failures and observe the calls
+    pub fn handle(&self) -> MockHandle {
+        MockHandle {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl EnclaveRuntime for MockEnclave {
+    type Builder = MockEnclaveBuilder;
+
+    fn create(builder: MockEnclaveBuilder) -> Result<Self, Error> {
+        builder.create()
+    }
+
+    fn target_info(&self) -> Result<TargetInfo, Error> {
+        Ok(self.target_info.clone())
+    }
+
+    fn tcs_count(&self) -> NonZeroUsize {
+        self.tcs_count
+    }
+
+    fn call<E: Ecall>(&self, input: E::Input) -> Result<E::Output, Error> {
+        {
+            let mut state = self.state.lock().expect("Mutex has been poisoned");
+            *state.calls.entry(TypeId::of::<E>()).or_default() += 1;
+            if let Some(error) = state.failures.pop_front() {
+                return Err(error.into());
+            }
+        }
+        let handler = self
+            .handlers
+            .get(&TypeId::of::<E>())
+            .and_then(|handler| handler.downcast_ref::<Handler<E>>())
+            .ok_or(SgxError::InvalidFunction)?;
+        Ok(handler(input)?)
+    }
+}
+
+impl Drop for MockEnclave {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.destroyed = true;
+        }
+    }
+}
+
+impl fmt::Debug for MockEnclave {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockEnclave")
+            .field("id", &self.id)
+            .field("handlers", &self.handlers.len())
+            .field("target_info", &self.target_info)
+            .field("tcs_count", &self.tcs_count)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Enclave;
+
+    struct Add;
+
+    impl Ecall for Add {
+        type Input = (u32, u32);
+        type Output = u32;
+
+        fn ecall(_: &Enclave, _: Self::Input) -> Result<u32, SgxError> {
+            unreachable!("The mock never makes real ECALLs")
+        }
+    }
+
+    struct Missing;
+
+    impl Ecall for Missing {
+        type Input = ();
+        type Output = ();
+
+        fn ecall(_: &Enclave, _: ()) -> Result<(), SgxError> {
+            unreachable!("The mock never makes real ECALLs")
+        }
+    }
+
+    /// Host code under test
+    fn add<R: EnclaveRuntime>(runtime: &R, a: u32, b: u32) -> Result<u32, Error> {
+        runtime.call::<Add>((a, b))
+    }
+
+    fn adder() -> MockEnclave {
+        MockEnclaveBuilder::default()
+            .ecall::<Add, _>(|(a, b)| Ok(a + b))
+            .create()
+            .expect("Mock should be created")
+    }
+
+    #[test]
+    fn ecall_dispatches_to_handler() {
+        let enclave = adder();
+        assert_eq!(add(&enclave, 2, 3), Ok(5));
+        assert_eq!(enclave.handle().calls::<Add>(), 1);
+    }
+
+    #[test]
+    fn missing_handler_is_invalid_function() {
+        let enclave = adder();
+        assert_eq!(
+            enclave.call::<Missing>(()),
+            Err(Error::Sgx(SgxError::InvalidFunction))
+        );
+    }
+
+    #[test]
+    fn injected_failures_are_returned_in_order() {
+        let enclave = adder();
+        let handle = enclave.handle();
+        handle.fail_next(SgxError::EnclaveLost);
+        handle.fail_next(SgxError::OutOfTcs);
+
+        assert_eq!(add(&enclave, 1, 1), Err(Error::Sgx(SgxError::EnclaveLost)));
+        assert_eq!(add(&enclave, 1, 1), Err(Error::Sgx(SgxError::OutOfTcs)));
+        assert_eq!(add(&enclave, 1, 1), Ok(2));
+        assert_eq!(handle.calls::<Add>(), 3);
+    }
+
+    #[test]
+    fn handler_errors_are_returned() {
+        let enclave = MockEnclaveBuilder::default()
+            .ecall::<Add, _>(|_| Err(SgxError::InvalidParameter))
+            .create()
+            .unwrap();
+        assert_eq!(
+            add(&enclave, 1, 1),
+            Err(Error::Sgx(SgxError::InvalidParameter))
+        );
+    }
+
+    #[test]
+    fn create_failure_is_injected() {
+        let builder = MockEnclaveBuilder::default().create_error(SgxError::NoDevice);
+        assert_eq!(
+            MockEnclave::create(builder).unwrap_err(),
+            Error::Sgx(SgxError::NoDevice)
+        );
+    }
+
+    #[test]
+    fn target_info_is_fabricated() {
+        let mr_enclave = MrEnclave::from([3; 32]);
+        let enclave = MockEnclaveBuilder::default()
+            .mr_enclave(mr_enclave.clone())
+            .create()
+            .unwrap();
+        let target_info: sgx_target_info_t = enclave.target_info().unwrap().into();
+        assert_eq!(MrEnclave::from(target_info.mr_enclave), mr_enclave);
+    }
+
+    #[test]
+    fn target_info_is_reported() {
+        let target_info = sgx_target_info_t {
+            mr_enclave: MrEnclave::from([5; 32]).into(),
+            ..Default::default()
+        };
+        let enclave = MockEnclaveBuilder::default()
+            .target_info(target_info.into())
+            .create()
+            .unwrap();
+        assert_eq!(enclave.target_info(), Ok(target_info.into()));
+    }
+
+    #[test]
+    fn destroy_is_observable() {
+        let enclave = adder();
+        let handle = enclave.handle();
+        assert!(!handle.destroyed());
+        enclave.destroy();
+        assert!(handle.destroyed());
+    }
+
+    #[test]
+    fn tcs_count_is_reported() {
+        assert_eq!(adder().tcs_count().get(), 1);
+        let tcs_count = NonZeroUsize::new(4).unwrap();
+        let enclave = MockEnclaveBuilder::default()
+            .tcs_count(tcs_count)
+            .create()
+            .unwrap();
+        assert_eq!(enclave.tcs_count(), tcs_count);
+    }
+
+    #[test]
+    fn clones_share_handlers() {
+        let builder = MockEnclaveBuilder::default().ecall::<Add, _>(|(a, b)| Ok(a + b));
+        let enclave = builder.clone().create().unwrap();
+        let other = builder.create().unwrap();
+        assert_eq!(add(&enclave, 1, 2), Ok(3));
+        assert_eq!(add(&other, 2, 2), Ok(4));
+        assert_ne!(enclave.id(), other.id());
+    }
+
+    #[test]
+    fn mock_ids_are_unique() {
+        assert_ne!(adder().id(), adder().id());
+    }
+}
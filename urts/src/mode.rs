@@ -0,0 +1,109 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Selection of the hardware or simulation uRTS
+
+use crate::Error;
+use core::{fmt, str::FromStr};
+use std::env;
+
+/// The environment variable [`SgxMode::from_env()`] reads
+pub const SGX_MODE_ENV: &str = "SGX_MODE";
+
+/// The uRTS an enclave is created with
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub enum SgxMode {
+    /// The enclave runs on SGX hardware
+    Hardware,
+    /// The enclave is simulated, it has no SGX protections
+    Simulation,
+}
+
+impl SgxMode {
+    /// The mode the crate was built for
+    ///
+    /// [`SgxMode::Simulation`] with the `sim` feature, otherwise
+    /// [`SgxMode::Hardware`]. This is the only mode available without the
+    /// `dlopen` feature.
+    pub const fn built() -> Self {
+        if cfg!(feature = "sim") {
+            Self::Simulation
+        } else {
+            Self::Hardware
+        }
+    }
+
+    /// The mode from the `SGX_MODE` environment variable, if set
+    ///
+    /// The values of the SGX SDK makefiles, `HW` and `SIM`, are accepted as
+    /// well as `hardware` and `simulation`, ignoring case.
+    ///
+    /// # Errors
+    /// [`Error::InvalidMode`] if the variable has any other value.
+    pub fn from_env() -> Result<Option<Self>, Error> {
+        match env::var(SGX_MODE_ENV) {
+            Ok(value) => value.parse().map(Some),
+            Err(env::VarError::NotPresent) => Ok(None),
+            Err(env::VarError::NotUnicode(value)) => {
+                Err(Error::InvalidMode(value.to_string_lossy().into_owned()))
+            }
+        }
+    }
+}
+
+impl Default for SgxMode {
+    fn default() -> Self {
+        Self::built()
+    }
+}
+
+impl fmt::Display for SgxMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hardware => write!(f, "hardware"),
+            Self::Simulation => write!(f, "simulation"),
+        }
+    }
+}
+
+impl FromStr for SgxMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hw" | "hardware" => Ok(Self::Hardware),
+            "sim" | "simulation" => Ok(Self::Simulation),
+            _ => Err(Error::InvalidMode(s.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yare::parameterized;
+
+    #[parameterized(
+        hw = { "HW", SgxMode::Hardware },
+        hardware = { "hardware", SgxMode::Hardware },
+        sim = { "SIM", SgxMode::Simulation },
+        simulation = { "Simulation", SgxMode::Simulation },
+    )]
+    fn parse_mode(value: &str, mode: SgxMode) {
+        assert_eq!(value.parse(), Ok(mode));
+    }
+
+    #[test]
+    fn invalid_mode_fails() {
+        assert_eq!(
+            "emulated".parse::<SgxMode>(),
+            Err(Error::InvalidMode("emulated".into()))
+        );
+    }
+
+    #[test]
+    fn mode_round_trips_through_display() {
+        for mode in [SgxMode::Hardware, SgxMode::Simulation] {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+    }
+}
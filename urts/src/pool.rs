@@ -0,0 +1,543 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Sharing an [`Enclave`] between threads within its TCS limit
+
+use crate::{Ecall, Enclave, EnclaveRuntime, Error};
+use core::{
+    fmt,
+    num::NonZeroUsize,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use mc_sgx_core_sys_types::sgx_status_t;
+use mc_sgx_core_types::Error as SgxError;
+use mc_sgx_urts_sys_types::sgx_enclave_id_t;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+
+/// Creates the enclaves of a pool
+type Factory<R> = Box<dyn Fn() -> Result<R, Error> + Send + Sync>;
+
+/// Initializes an enclave of a pool after it's created
+type Hook<R> = Box<dyn Fn(&R) -> Result<(), Error> + Send + Sync>;
+
+/// Build an [`EnclavePool`]
+pub struct EnclavePoolBuilder<R: EnclaveRuntime = Enclave> {
+    builder: R::Builder,
+    hooks: Vec<Hook<R>>,
+}
+
+impl<R> EnclavePoolBuilder<R>
+where
+    R: EnclaveRuntime,
+    R::Builder: Clone + Send + Sync + 'static,
+{
+    /// Returns an EnclavePoolBuilder for the enclave of `builder`
+    ///
+    /// The pool makes as many calls at once as the enclave has TCSs, see
+    /// [`EnclaveRuntime::tcs_count()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `builder` - Builds the enclave, and rebuilds it when it's lost.
+    pub fn new(builder: R::Builder) -> Self {
+        Self {
+            builder,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Run `hook` after the enclave is created and each time it's recreated
+    ///
+    /// Hooks run in the order they were added, before any other ECALL is made
+    /// to the new enclave. This is where state the enclave loses with its
+    /// memory, like keys or configuration, should be restored.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Initializes the enclave. An error fails the creation.
+    #[must_use]
+    pub fn on_create<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&R) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Create the enclave and the pool sharing it
+    ///
+    /// # Errors
+    /// Any error from [`EnclaveRuntime::create()`] or the hooks.
+    pub fn create(self) -> Result<EnclavePool<R>, Error> {
+        let builder = self.builder;
+        let factory: Factory<R> = Box::new(move || R::create(builder.clone()));
+        EnclavePool::new(factory, self.hooks)
+    }
+}
+
+impl<R: EnclaveRuntime> fmt::Debug for EnclavePoolBuilder<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnclavePoolBuilder")
+            .field("hooks", &self.hooks.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Counters of an [`EnclavePool`]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct PoolStats {
+    /// The calls made to the enclave, including those retried after
+    /// recreating it
+    pub calls: u64,
+    /// The calls which had to wait for a TCS
+    pub queued: u64,
+    /// The calls in progress
+    pub in_flight: u64,
+    /// The times the enclave was lost and recreated
+    pub recreations: u64,
+    /// The times recreating the enclave, or one of its hooks, failed
+    pub recreation_failures: u64,
+    /// The calls which failed with [`SgxError::OutOfTcs`] despite the limit
+    pub out_of_tcs: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    calls: AtomicU64,
+    queued: AtomicU64,
+    in_flight: AtomicU64,
+    recreations: AtomicU64,
+    recreation_failures: AtomicU64,
+    out_of_tcs: AtomicU64,
+}
+
+/// A counting semaphore for the TCSs of the enclave
+#[derive(Debug)]
+struct Tcs {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+impl Tcs {
+    fn acquire<'a>(&'a self, counters: &'a Counters) -> TcsPermit<'a> {
+        let mut available = self.available.lock().expect("Mutex has been poisoned");
+        if *available == 0 {
+            counters.queued.fetch_add(1, Ordering::Relaxed);
+        }
+        while *available == 0 {
+            available = self
+                .released
+                .wait(available)
+                .expect("Mutex has been poisoned");
+        }
+        *available -= 1;
+        counters.in_flight.fetch_add(1, Ordering::Relaxed);
+        TcsPermit {
+            tcs: self,
+            in_flight: &counters.in_flight,
+        }
+    }
+}
+
+/// A TCS held for one call, released when dropped, even if the call panics
+struct TcsPermit<'a> {
+    tcs: &'a Tcs,
+    in_flight: &'a AtomicU64,
+}
+
+impl Drop for TcsPermit<'_> {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+        *self.tcs.available.lock().expect("Mutex has been poisoned") += 1;
+        self.tcs.released.notify_one();
+    }
+}
+
+/// An [`Enclave`] shared by many threads
+///
+/// At most the enclave's TCS count of calls are made at once, other callers
+/// wait for a TCS in the order the OS wakes them. This avoids
+/// [`SgxError::OutOfTcs`] from calling an enclave with more threads than it
+/// has TCSs.
+///
+/// After a power transition, like a suspend or hibernate, the enclave is lost
+/// and every ECALL fails with [`SgxError::EnclaveLost`]. The pool then
+/// recreates the enclave from the builder it was created with, runs the
+/// [`EnclavePoolBuilder::on_create()`] hooks, and retries the call once on
+/// the new enclave.
+///
+/// The pool works with any [`EnclaveRuntime`], so code using it can be tested
+/// with the `MockEnclave` of the `mock` feature.
+///
+/// ```ignore
+/// let builder = EnclaveBuilder::new("enclave.signed.so")?;
+/// let pool = EnclavePoolBuilder::<Enclave>::new(builder)
+///     .on_create(|enclave| Ok(enclave.ecall(|eid| unsafe { ecall_init(eid) })?))
+///     .create()?;
+/// pool.ecall(|eid| unsafe { ecall_do_work(eid) })?;
+/// ```
+pub struct EnclavePool<R: EnclaveRuntime = Enclave> {
+    factory: Factory<R>,
+    hooks: Vec<Hook<R>>,
+    enclave: RwLock<Arc<R>>,
+    tcs_count: NonZeroUsize,
+    tcs: Tcs,
+    counters: Counters,
+}
+
+impl<R: EnclaveRuntime> EnclavePool<R> {
+    fn new(factory: Factory<R>, hooks: Vec<Hook<R>>) -> Result<Self, Error> {
+        let enclave = Self::create_enclave(&factory, &hooks)?;
+        // Recreated enclaves come from the same builder, so have as many TCSs
+        let tcs_count = enclave.tcs_count();
+        Ok(Self {
+            factory,
+            hooks,
+            enclave: RwLock::new(Arc::new(enclave)),
+            tcs_count,
+            tcs: Tcs {
+                available: Mutex::new(tcs_count.get()),
+                released: Condvar::new(),
+            },
+            counters: Counters::default(),
+        })
+    }
+
+    fn create_enclave(factory: &Factory<R>, hooks: &[Hook<R>]) -> Result<R, Error> {
+        let enclave = factory()?;
+        for hook in hooks {
+            hook(&enclave)?;
+        }
+        Ok(enclave)
+    }
+
+    /// Call `f` with the enclave, once a TCS is available
+    ///
+    /// When `f` fails with [`SgxError::EnclaveLost`] the enclave is recreated
+    /// and `f` is called again with the new enclave.
+    ///
+    /// # Errors
+    /// * The error from `f`.
+    /// * Any error recreating the enclave, `f` isn't retried.
+    pub fn with_enclave<T, E>(&self, mut f: impl FnMut(&R) -> Result<T, E>) -> Result<T, Error>
+    where
+        E: Into<Error>,
+    {
+        let _permit = self.tcs.acquire(&self.counters);
+        self.call_with(&mut |enclave| f(enclave).map_err(Into::into))
+    }
+
+    /// Make the ECALL `E` with `input`, once a TCS is available
+    ///
+    /// See [`EnclaveRuntime::call()`], the ECALL is retried once with a clone
+    /// of `input` if the enclave was lost.
+    ///
+    /// # Errors
+    /// See [`EnclavePool::with_enclave()`].
+    pub fn call<E: Ecall>(&self, input: E::Input) -> Result<E::Output, Error>
+    where
+        E::Input: Clone,
+    {
+        self.with_enclave(|enclave| enclave.call::<E>(input.clone()))
+    }
+
+    fn call_with<T>(&self, f: &mut impl FnMut(&R) -> Result<T, Error>) -> Result<T, Error> {
+        let enclave = self.enclave();
+        match self.counted(&enclave, f) {
+            Err(Error::Sgx(SgxError::EnclaveLost)) => {
+                let enclave = self.recreate(&enclave)?;
+                self.counted(&enclave, f)
+            }
+            result => result,
+        }
+    }
+
+    fn counted<T>(
+        &self,
+        enclave: &R,
+        f: &mut impl FnMut(&R) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.counters.calls.fetch_add(1, Ordering::Relaxed);
+        let result = f(enclave);
+        if let Err(Error::Sgx(SgxError::OutOfTcs)) = result {
+            self.counters.out_of_tcs.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Replace the `lost` enclave, unless another call already has
+    fn recreate(&self, lost: &Arc<R>) -> Result<Arc<R>, Error> {
+        // Holding the write lock keeps new calls from using the lost enclave
+        let mut current = self.enclave.write().expect("RwLock has been poisoned");
+        if Arc::ptr_eq(&current, lost) {
+            match Self::create_enclave(&self.factory, &self.hooks) {
+                Ok(enclave) => {
+                    // Calls still in the lost enclave keep it alive until they
+                    // return
+                    *current = Arc::new(enclave);
+                    self.counters.recreations.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(error) => {
+                    self.counters
+                        .recreation_failures
+                        .fetch_add(1, Ordering::Relaxed);
+                    return Err(error);
+                }
+            }
+        }
+        Ok(current.clone())
+    }
+
+    /// Returns the current enclave
+    ///
+    /// Calls made directly on it aren't limited to the TCS count and aren't
+    /// recovered when the enclave is lost.
+    pub fn enclave(&self) -> Arc<R> {
+        self.enclave
+            .read()
+            .expect("RwLock has been poisoned")
+            .clone()
+    }
+
+    /// Returns the number of calls made to the enclave at once
+    pub fn tcs_count(&self) -> NonZeroUsize {
+        self.tcs_count
+    }
+
+    /// Returns the counters of the pool
+    pub fn stats(&self) -> PoolStats {
+        let counters = &self.counters;
+        PoolStats {
+            calls: counters.calls.load(Ordering::Relaxed),
+            queued: counters.queued.load(Ordering::Relaxed),
+            in_flight: counters.in_flight.load(Ordering::Relaxed),
+            recreations: counters.recreations.load(Ordering::Relaxed),
+            recreation_failures: counters.recreation_failures.load(Ordering::Relaxed),
+            out_of_tcs: counters.out_of_tcs.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl EnclavePool<Enclave> {
+    /// Call an ECALL of the enclave, once a TCS is available
+    ///
+    /// See [`Enclave::ecall()`], the ECALL is retried once if the enclave was
+    /// lost.
+    ///
+    /// # Errors
+    /// See [`EnclavePool::with_enclave()`].
+    pub fn ecall(&self, mut f: impl FnMut(sgx_enclave_id_t) -> sgx_status_t) -> Result<(), Error> {
+        self.with_enclave(|enclave| enclave.ecall(&mut f))
+    }
+}
+
+impl<R: EnclaveRuntime + fmt::Debug> fmt::Debug for EnclavePool<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnclavePool")
+            .field("enclave", &self.enclave())
+            .field("tcs_count", &self.tcs_count)
+            .field("stats", &self.stats())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::mock::{MockEnclave, MockEnclaveBuilder};
+    use std::{sync::Barrier, thread, time::Duration};
+
+    /// An ECALL of the mock enclaves
+    pub(crate) struct Work;
+
+    impl Ecall for Work {
+        type Input = ();
+        type Output = ();
+
+        fn ecall(_: &Enclave, _: ()) -> Result<(), SgxError> {
+            unreachable!("The mock never makes real ECALLs")
+        }
+    }
+
+    /// Mock enclaves with `tcs_count` TCSs, which do the [`Work`]
+    pub(crate) fn builder(tcs_count: usize) -> MockEnclaveBuilder {
+        let tcs_count = NonZeroUsize::new(tcs_count).expect("TCS count should be non zero");
+        MockEnclaveBuilder::default()
+            .tcs_count(tcs_count)
+            .ecall::<Work, _>(|()| Ok(()))
+    }
+
+    /// A pool of mock enclaves with `tcs_count` TCSs
+    pub(crate) fn pool(tcs_count: usize) -> EnclavePool<MockEnclave> {
+        EnclavePoolBuilder::new(builder(tcs_count))
+            .create()
+            .expect("Failed to create pool")
+    }
+
+    #[test]
+    fn call_uses_the_enclave() {
+        let pool = pool(1);
+        assert_eq!(pool.call::<Work>(()), Ok(()));
+        assert_eq!(pool.enclave().handle().calls::<Work>(), 1);
+        assert_eq!(pool.stats().calls, 1);
+    }
+
+    #[test]
+    fn tcs_count_is_the_enclaves() {
+        assert_eq!(pool(3).tcs_count().get(), 3);
+    }
+
+    #[test]
+    fn errors_are_returned() {
+        let builder = builder(1).ecall::<Work, _>(|()| Err(SgxError::InvalidParameter));
+        let pool = EnclavePoolBuilder::<MockEnclave>::new(builder)
+            .create()
+            .unwrap();
+        assert_eq!(
+            pool.call::<Work>(()),
+            Err(Error::Sgx(SgxError::InvalidParameter))
+        );
+    }
+
+    #[test]
+    fn creation_errors_are_returned() {
+        let builder = builder(1).create_error(SgxError::NoDevice);
+        let result = EnclavePoolBuilder::<MockEnclave>::new(builder).create();
+        assert_eq!(result.unwrap_err(), Error::Sgx(SgxError::NoDevice));
+    }
+
+    #[test]
+    fn lost_enclave_is_recreated_and_retried() {
+        let pool = pool(2);
+        let lost = pool.enclave().handle();
+        let lost_id = *pool.enclave().id();
+        lost.fail_next(SgxError::EnclaveLost);
+
+        assert_eq!(pool.call::<Work>(()), Ok(()));
+
+        assert_ne!(*pool.enclave().id(), lost_id);
+        assert_eq!(pool.enclave().handle().calls::<Work>(), 1);
+        assert!(lost.destroyed());
+        let stats = pool.stats();
+        assert_eq!(stats.recreations, 1);
+        assert_eq!(stats.calls, 2);
+    }
+
+    #[test]
+    fn enclave_lost_twice_is_returned() {
+        let builder = builder(1).ecall::<Work, _>(|()| Err(SgxError::EnclaveLost));
+        let pool = EnclavePoolBuilder::<MockEnclave>::new(builder)
+            .create()
+            .unwrap();
+        assert_eq!(
+            pool.call::<Work>(()),
+            Err(Error::Sgx(SgxError::EnclaveLost))
+        );
+        assert_eq!(pool.stats().recreations, 1);
+    }
+
+    #[test]
+    fn hooks_run_on_create_and_recreate() {
+        let runs = Arc::new(Mutex::new(vec![]));
+        let hook_runs = runs.clone();
+        let pool = EnclavePoolBuilder::new(builder(1))
+            .on_create(move |enclave: &MockEnclave| {
+                hook_runs.lock().unwrap().push(*enclave.id());
+                Ok(())
+            })
+            .create()
+            .unwrap();
+        let first = *pool.enclave().id();
+        pool.enclave().handle().fail_next(SgxError::EnclaveLost);
+
+        assert_eq!(pool.call::<Work>(()), Ok(()));
+
+        assert_eq!(*runs.lock().unwrap(), [first, *pool.enclave().id()]);
+    }
+
+    #[test]
+    fn failing_hook_fails_recreation() {
+        let runs = AtomicU64::new(0);
+        let pool = EnclavePoolBuilder::new(builder(1))
+            .on_create(
+                move |_: &MockEnclave| match runs.fetch_add(1, Ordering::SeqCst) {
+                    0 => Ok(()),
+                    _ => Err(SgxError::Unexpected.into()),
+                },
+            )
+            .create()
+            .unwrap();
+        let first = *pool.enclave().id();
+        pool.enclave().handle().fail_next(SgxError::EnclaveLost);
+
+        assert_eq!(pool.call::<Work>(()), Err(Error::Sgx(SgxError::Unexpected)));
+
+        let stats = pool.stats();
+        assert_eq!(stats.recreations, 0);
+        assert_eq!(stats.recreation_failures, 1);
+        assert_eq!(*pool.enclave().id(), first);
+    }
+
+    #[test]
+    fn concurrent_losses_recreate_once() {
+        let pool = Arc::new(pool(2));
+        let first = *pool.enclave().id();
+        let barrier = Arc::new(Barrier::new(2));
+        let threads = (0..2)
+            .map(|_| {
+                let (pool, barrier) = (pool.clone(), barrier.clone());
+                thread::spawn(move || {
+                    pool.with_enclave(|enclave| {
+                        if *enclave.id() == first {
+                            barrier.wait();
+                            Err(SgxError::EnclaveLost)
+                        } else {
+                            Ok(())
+                        }
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), Ok(()));
+        }
+        assert_eq!(pool.stats().recreations, 1);
+        assert_ne!(*pool.enclave().id(), first);
+    }
+
+    #[test]
+    fn calls_are_limited_to_tcs_count() {
+        let pool = Arc::new(pool(2));
+        let active = Arc::new(AtomicU64::new(0));
+        let max_active = Arc::new(AtomicU64::new(0));
+        let threads = (0..6)
+            .map(|_| {
+                let (pool, active, max_active) = (pool.clone(), active.clone(), max_active.clone());
+                thread::spawn(move || {
+                    pool.with_enclave(|_| {
+                        let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_active.fetch_max(now, Ordering::SeqCst);
+                        thread::sleep(Duration::from_millis(20));
+                        active.fetch_sub(1, Ordering::SeqCst);
+                        Ok::<_, Error>(())
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), Ok(()));
+        }
+        assert_eq!(max_active.load(Ordering::SeqCst), 2);
+        let stats = pool.stats();
+        assert_eq!(stats.calls, 6);
+        assert_eq!(stats.in_flight, 0);
+        assert!(stats.queued > 0);
+    }
+
+    #[test]
+    fn out_of_tcs_is_counted() {
+        let pool = pool(1);
+        pool.enclave().handle().fail_next(SgxError::OutOfTcs);
+        assert_eq!(pool.call::<Work>(()), Err(Error::Sgx(SgxError::OutOfTcs)));
+        assert_eq!(pool.stats().out_of_tcs, 1);
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! An abstraction of the uRTS, so host code can run against a mock enclave
+
+use crate::{Enclave, EnclaveBuilder, Error};
+use core::num::NonZeroUsize;
+use mc_sgx_core_types::{Error as SgxError, TargetInfo};
+
+/// An ECALL of an enclave, with typed input and output
+///
+/// Implementing this for each ECALL lets host code call it through an
+/// [`EnclaveRuntime`], and lets a mock runtime dispatch it to a Rust closure
+/// instead of the enclave.
+///
+/// ```ignore
+/// struct Add;
+///
+/// impl Ecall for Add {
+///     type Input = (u32, u32);
+///     type Output = u32;
+///
+///     fn ecall(enclave: &Enclave, (a, b): Self::Input) -> Result<u32, SgxError> {
+///         enclave.ecall_returning(|eid, sum| unsafe { ecall_add(eid, sum, a, b) })
+///     }
+/// }
+///
+/// fn add<R: EnclaveRuntime>(runtime: &R) -> Result<u32, Error> {
+///     runtime.call::<Add>((2, 3))
+/// }
+/// ```
+pub trait Ecall: 'static {
+    /// The arguments of the ECALL
+    type Input: 'static;
+    /// The result of the ECALL
+    type Output: 'static;
+
+    /// Make the ECALL in `enclave`
+    ///
+    /// # Arguments
+    /// * `enclave` - The enclave to call
+    /// * `input` - The arguments of the ECALL
+    fn ecall(enclave: &Enclave, input: Self::Input) -> Result<Self::Output, SgxError>;
+}
+
+/// The operations of the uRTS on an enclave
+///
+/// [`Enclave`] implements this with the SGX SDK. Host code written against
+/// this trait can be tested with another implementation, like the
+/// `MockEnclave` of the `mock` feature.
+pub trait EnclaveRuntime: Sized {
+    /// Describes the enclave to create
+    type Builder;
+
+    /// Create the enclave described by `builder`
+    ///
+    /// # Errors
+    /// If the enclave can't be created.
+    fn create(builder: Self::Builder) -> Result<Self, Error>;
+
+    /// Destroy the enclave
+    ///
+    /// The enclave is also destroyed when dropped, this makes it explicit.
+    fn destroy(self) {
+        drop(self)
+    }
+
+    /// Returns the target info of the enclave, for reports targeting it
+    ///
+    /// # Errors
+    /// If the target info can't be retrieved.
+    fn target_info(&self) -> Result<TargetInfo, Error>;
+
+    /// Returns the number of ECALLs the enclave can be in at once
+    fn tcs_count(&self) -> NonZeroUsize;
+
+    /// Make the ECALL `E` with `input`
+    ///
+    /// # Errors
+    /// [`Error::Sgx`] if the ECALL fails.
+    fn call<E: Ecall>(&self, input: E::Input) -> Result<E::Output, Error>;
+}
+
+impl EnclaveRuntime for Enclave {
+    type Builder = EnclaveBuilder;
+
+    fn create(builder: EnclaveBuilder) -> Result<Self, Error> {
+        builder.create()
+    }
+
+    fn target_info(&self) -> Result<TargetInfo, Error> {
+        Enclave::target_info(self)
+    }
+
+    fn tcs_count(&self) -> NonZeroUsize {
+        Enclave::tcs_count(self)
+    }
+
+    fn call<E: Ecall>(&self, input: E::Input) -> Result<E::Output, Error> {
+        Ok(E::ecall(self, input)?)
+    }
+}
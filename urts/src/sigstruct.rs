@@ -0,0 +1,173 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Checking the SIGSTRUCT embedded in a signed enclave
+
+use crate::Error;
+use mc_sgx_core_types::{IsvSvn, MrEnclave, MrSigner, Sigstruct};
+
+/// The identity an enclave is expected to have before it's created
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct IdentityPolicy {
+    pub(crate) mr_enclave: Option<MrEnclave>,
+    pub(crate) mr_signer: Option<MrSigner>,
+    pub(crate) min_isv_svn: Option<IsvSvn>,
+}
+
+impl IdentityPolicy {
+    /// Check the SIGSTRUCT of `enclave` against the policy
+    ///
+    /// # Arguments
+    /// * `enclave` - The bytes of the signed enclave
+    /// * `debug` - `true` if the enclave will be created in debug mode
+    pub(crate) fn check(&self, enclave: &[u8], debug: bool) -> Result<Sigstruct, Error> {
+        let sigstruct = Sigstruct::from_enclave(enclave).ok_or(Error::SigstructNotFound)?;
+        if let Some(expected) = &self.mr_enclave {
+            let actual = sigstruct.mr_enclave();
+            if &actual != expected {
+                return Err(Error::MrEnclaveMismatch {
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+        if let Some(expected) = &self.mr_signer {
+            let actual = sigstruct.mr_signer();
+            if &actual != expected {
+                return Err(Error::MrSignerMismatch {
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+        if let Some(minimum) = self.min_isv_svn {
+            let actual = sigstruct.isv_svn();
+            if actual.as_ref() < minimum.as_ref() {
+                return Err(Error::IsvSvnTooLow { minimum, actual });
+            }
+        }
+        if sigstruct.debug_capable() && !debug {
+            return Err(Error::DebugCapable);
+        }
+        Ok(sigstruct)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use core::ops::Range;
+
+    const METADATA_MAGIC: [u8; 8] = 0x86A8_0294_635D_0E4C_u64.to_le_bytes();
+    const METADATA_SIGSTRUCT_OFFSET: usize = 64;
+    const HEADER: [u8; 16] = [6, 0, 0, 0, 0xE1, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0];
+    const MODULUS: Range<usize> = 128..512;
+    const ATTRIBUTE_FLAGS: Range<usize> = 928..936;
+    const ATTRIBUTE_MASK_FLAGS: Range<usize> = 944..952;
+    const ENCLAVE_HASH: Range<usize> = 960..992;
+    const ISV_PROD_ID: Range<usize> = 1024..1026;
+    const ISV_SVN: Range<usize> = 1026..1028;
+    const DEBUG_FLAG: u8 = 0x2;
+
+    /// A SIGSTRUCT which can't be created in debug mode
+    pub(crate) fn sigstruct_bytes() -> Vec<u8> {
+        let mut bytes = vec![0; Sigstruct::SIZE];
+        bytes[..HEADER.len()].copy_from_slice(&HEADER);
+        bytes[MODULUS].fill(0xA5);
+        bytes[ATTRIBUTE_FLAGS].copy_from_slice(&[0x4, 0, 0, 0, 0, 0, 0, 0]);
+        bytes[ATTRIBUTE_MASK_FLAGS].copy_from_slice(&[0xFF; 8]);
+        bytes[ENCLAVE_HASH].fill(0x11);
+        bytes[ISV_PROD_ID].copy_from_slice(&[0x34, 0x12]);
+        bytes[ISV_SVN].copy_from_slice(&[0x05, 0x00]);
+        bytes
+    }
+
+    /// Enclave bytes with the metadata holding `sigstruct` somewhere inside
+    pub(crate) fn enclave_bytes(sigstruct: &[u8]) -> Vec<u8> {
+        let mut bytes = b"\x7FELF".to_vec();
+        bytes.resize(300, 0);
+        bytes.extend_from_slice(&METADATA_MAGIC);
+        bytes.resize(
+            bytes.len() - METADATA_MAGIC.len() + METADATA_SIGSTRUCT_OFFSET,
+            0,
+        );
+        bytes.extend_from_slice(sigstruct);
+        bytes.resize(bytes.len() + 100, 0);
+        bytes
+    }
+
+    #[test]
+    fn matching_identity_passes() {
+        let enclave = enclave_bytes(&sigstruct_bytes());
+        let sigstruct = Sigstruct::from_enclave(&enclave).unwrap();
+        let policy = IdentityPolicy {
+            mr_enclave: Some(sigstruct.mr_enclave()),
+            mr_signer: Some(sigstruct.mr_signer()),
+            min_isv_svn: Some(5.into()),
+        };
+        assert_eq!(policy.check(&enclave, false), Ok(sigstruct));
+    }
+
+    #[test]
+    fn mr_enclave_mismatch_fails() {
+        let enclave = enclave_bytes(&sigstruct_bytes());
+        let policy = IdentityPolicy {
+            mr_enclave: Some([0x22; 32].into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.check(&enclave, false),
+            Err(Error::MrEnclaveMismatch {
+                expected: [0x22; 32].into(),
+                actual: [0x11; 32].into(),
+            })
+        );
+    }
+
+    #[test]
+    fn mr_signer_mismatch_fails() {
+        let enclave = enclave_bytes(&sigstruct_bytes());
+        let policy = IdentityPolicy {
+            mr_signer: Some([0x22; 32].into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            policy.check(&enclave, false),
+            Err(Error::MrSignerMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn isv_svn_below_minimum_fails() {
+        let enclave = enclave_bytes(&sigstruct_bytes());
+        let policy = IdentityPolicy {
+            min_isv_svn: Some(6.into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.check(&enclave, false),
+            Err(Error::IsvSvnTooLow {
+                minimum: 6.into(),
+                actual: 5.into(),
+            })
+        );
+    }
+
+    #[test]
+    fn debug_capable_enclave_requires_debug() {
+        let mut sigstruct = sigstruct_bytes();
+        sigstruct[ATTRIBUTE_FLAGS][0] |= DEBUG_FLAG;
+        let enclave = enclave_bytes(&sigstruct);
+        let policy = IdentityPolicy::default();
+        assert_eq!(policy.check(&enclave, false), Err(Error::DebugCapable));
+        assert!(policy.check(&enclave, true).is_ok());
+    }
+
+    #[test]
+    fn unsigned_enclave_fails_policy() {
+        let policy = IdentityPolicy::default();
+        assert_eq!(
+            policy.check(b"\x7FELF", true),
+            Err(Error::SigstructNotFound)
+        );
+    }
+}
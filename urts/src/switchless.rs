@@ -0,0 +1,541 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Configuration of switchless ECALLs and OCALLs
+
+use crate::Error;
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use mc_sgx_urts_sys_types::{
+    sgx_uswitchless_config_t, sgx_uswitchless_worker_callback_t, sgx_uswitchless_worker_event_t,
+    sgx_uswitchless_worker_stats_t, sgx_uswitchless_worker_type_t, SL_MAX_TASKS_MAX_QWORDS,
+};
+use once_cell::sync::Lazy;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+};
+
+/// The number of switchless calls tracked by each qword of the calls pool
+const CALLS_PER_QWORD: u64 = 64;
+
+/// The number of worker events, the size of the callback array
+const WORKER_EVENTS: usize = 4;
+
+/// The number of enclaves with a worker callback which can exist at once
+pub(crate) const MAX_WORKER_CALLBACKS: usize = 32;
+
+/// The kind of a switchless worker thread
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub enum WorkerType {
+    /// Runs outside of the enclave, handling switchless OCALLs
+    Untrusted,
+    /// Runs inside of the enclave, handling switchless ECALLs
+    Trusted,
+}
+
+impl TryFrom<sgx_uswitchless_worker_type_t> for WorkerType {
+    type Error = ();
+
+    fn try_from(worker_type: sgx_uswitchless_worker_type_t) -> Result<Self, ()> {
+        match worker_type {
+            sgx_uswitchless_worker_type_t::SGX_USWITCHLESS_WORKER_TYPE_UNTRUSTED => {
+                Ok(Self::Untrusted)
+            }
+            sgx_uswitchless_worker_type_t::SGX_USWITCHLESS_WORKER_TYPE_TRUSTED => Ok(Self::Trusted),
+            _ => Err(()),
+        }
+    }
+}
+
+/// An event of a switchless worker thread
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub enum WorkerEvent {
+    /// The worker thread started
+    Start,
+    /// The worker thread is idle and going to sleep
+    Idle,
+    /// The worker thread missed calls, they fell back to regular ECALLs or
+    /// OCALLs
+    Miss,
+    /// The worker thread exited
+    Exit,
+}
+
+impl TryFrom<sgx_uswitchless_worker_event_t> for WorkerEvent {
+    type Error = ();
+
+    fn try_from(event: sgx_uswitchless_worker_event_t) -> Result<Self, ()> {
+        match event {
+            sgx_uswitchless_worker_event_t::SGX_USWITCHLESS_WORKER_EVENT_START => Ok(Self::Start),
+            sgx_uswitchless_worker_event_t::SGX_USWITCHLESS_WORKER_EVENT_IDLE => Ok(Self::Idle),
+            sgx_uswitchless_worker_event_t::SGX_USWITCHLESS_WORKER_EVENT_MISS => Ok(Self::Miss),
+            sgx_uswitchless_worker_event_t::SGX_USWITCHLESS_WORKER_EVENT_EXIT => Ok(Self::Exit),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Statistics of the switchless worker threads of a [`WorkerType`]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct WorkerStats {
+    /// The number of calls the workers have handled
+    pub processed: u64,
+    /// The number of calls the workers missed, which fell back to regular
+    /// ECALLs or OCALLs
+    pub missed: u64,
+}
+
+impl From<&sgx_uswitchless_worker_stats_t> for WorkerStats {
+    fn from(stats: &sgx_uswitchless_worker_stats_t) -> Self {
+        Self {
+            processed: stats.processed,
+            missed: stats.missed,
+        }
+    }
+}
+
+/// A callback for the events of switchless worker threads
+pub type WorkerCallback = Arc<dyn Fn(WorkerType, WorkerEvent, WorkerStats) + Send + Sync>;
+
+/// Configuration of switchless ECALLs and OCALLs for an enclave
+///
+/// Only the ECALLs and OCALLs marked `transition_using_threads` in the EDL
+/// are switchless. The defaults match `SGX_USWITCHLESS_CONFIG_INITIALIZER`,
+/// one untrusted and one trusted worker with the SGX SDK's default retries.
+///
+/// ```ignore
+/// let config = SwitchlessConfig::default()
+///     .untrusted_workers(2)
+///     .on_worker_event(|worker, event, stats| {
+///         println!("{worker:?} worker {event:?}, missed {} calls", stats.missed)
+///     });
+/// let enclave = EnclaveBuilder::new("enclave.signed.so")?
+///     .switchless(config)
+///     .create()?;
+/// ```
+#[derive(Clone)]
+pub struct SwitchlessConfig {
+    // The number of qwords of the pool tracking pending calls, 0 for the
+    // default
+    calls_pool_size_qwords: u64,
+
+    // Worker threads handling OCALLs
+    untrusted_workers: u64,
+
+    // Worker threads handling ECALLs
+    trusted_workers: u64,
+
+    // Retries waiting for a worker before a call falls back to a regular
+    // ECALL or OCALL, 0 for the default
+    retries_before_fallback: u64,
+
+    // Retries of an idle worker before it sleeps, 0 for the default
+    retries_before_sleep: u64,
+
+    callback: Option<WorkerCallback>,
+}
+
+impl SwitchlessConfig {
+    /// The number of calls which can be pending at once
+    ///
+    /// Rounded up to a multiple of 64 and limited to 512. Defaults to 64.
+    ///
+    /// # Arguments
+    /// * `calls` - The number of pending switchless calls to allow
+    #[must_use]
+    pub fn pending_calls(mut self, calls: u64) -> Self {
+        let qwords = (calls + CALLS_PER_QWORD - 1) / CALLS_PER_QWORD;
+        self.calls_pool_size_qwords = qwords.clamp(1, SL_MAX_TASKS_MAX_QWORDS.into());
+        self
+    }
+
+    /// The number of untrusted worker threads, which handle OCALLs
+    ///
+    /// # Arguments
+    /// * `workers` - The number of workers, 0 for no switchless OCALLs
+    #[must_use]
+    pub fn untrusted_workers(mut self, workers: u64) -> Self {
+        self.untrusted_workers = workers;
+        self
+    }
+
+    /// The number of trusted worker threads, which handle ECALLs
+    ///
+    /// Each trusted worker occupies one of the enclave's TCSs.
+    ///
+    /// # Arguments
+    /// * `workers` - The number of workers, 0 for no switchless ECALLs
+    #[must_use]
+    pub fn trusted_workers(mut self, workers: u64) -> Self {
+        self.trusted_workers = workers;
+        self
+    }
+
+    /// How many times a call waits for a worker to pick it up before falling
+    /// back to a regular ECALL or OCALL
+    ///
+    /// # Arguments
+    /// * `retries` - The number of `pause` instructions to wait, 0 for the
+    ///   SGX SDK default of 20000
+    #[must_use]
+    pub fn retries_before_fallback(mut self, retries: u64) -> Self {
+        self.retries_before_fallback = retries;
+        self
+    }
+
+    /// How many times an idle worker checks for calls before it sleeps
+    ///
+    /// # Arguments
+    /// * `retries` - The number of `pause` instructions to wait, 0 for the
+    ///   SGX SDK default of 20000
+    #[must_use]
+    pub fn retries_before_sleep(mut self, retries: u64) -> Self {
+        self.retries_before_sleep = retries;
+        self
+    }
+
+    /// Call `callback` for the events of the enclave's worker threads
+    ///
+    /// The callback is called on the worker threads, a panic in it is caught
+    /// and ignored. It only receives the events of the enclave created with
+    /// this configuration. At most 32 enclaves with a callback can exist at
+    /// once.
+    ///
+    /// # Arguments
+    /// * `callback` - Called with the worker type, the event and the
+    ///   statistics of the workers of that type
+    #[must_use]
+    pub fn on_worker_event<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(WorkerType, WorkerEvent, WorkerStats) + Send + Sync + 'static,
+    {
+        self.callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Register the callback for an enclave being created with this
+    /// configuration
+    ///
+    /// # Errors
+    /// [`Error::TooManyWorkerCallbacks`] if all of the callback slots are in
+    /// use by other enclaves.
+    pub(crate) fn register(&self) -> Result<Registration, Error> {
+        let slot = match &self.callback {
+            Some(callback) => {
+                let mut callbacks = CALLBACKS.lock().expect("Mutex has been poisoned");
+                let slot = callbacks
+                    .iter()
+                    .position(Option::is_none)
+                    .ok_or(Error::TooManyWorkerCallbacks(MAX_WORKER_CALLBACKS))?;
+                callbacks[slot] = Some(callback.clone());
+                Some(slot)
+            }
+            None => None,
+        };
+        Ok(Registration { slot })
+    }
+
+    /// The TCSs the trusted workers occupy while the enclave exists
+    pub(crate) fn occupied_tcs(&self) -> usize {
+        self.trusted_workers as usize
+    }
+
+    /// The SGX SDK configuration for an enclave registered with `registration`
+    pub(crate) fn to_raw(&self, registration: &Registration) -> sgx_uswitchless_config_t {
+        // The callback is always installed to collect the statistics
+        let callback: sgx_uswitchless_worker_callback_t = match registration.slot {
+            Some(slot) => Some(SLOT_CALLBACKS[slot]),
+            None => Some(worker_callback),
+        };
+        sgx_uswitchless_config_t {
+            switchless_calls_pool_size_qwords: self.calls_pool_size_qwords,
+            num_uworkers: self.untrusted_workers,
+            num_tworkers: self.trusted_workers,
+            retries_before_fallback: self.retries_before_fallback,
+            retries_before_sleep: self.retries_before_sleep,
+            callback_func: [callback; WORKER_EVENTS],
+        }
+    }
+}
+
+impl Default for SwitchlessConfig {
+    fn default() -> Self {
+        Self {
+            calls_pool_size_qwords: 0,
+            untrusted_workers: 1,
+            trusted_workers: 1,
+            retries_before_fallback: 0,
+            retries_before_sleep: 0,
+            callback: None,
+        }
+    }
+}
+
+impl fmt::Debug for SwitchlessConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SwitchlessConfig")
+            .field("calls_pool_size_qwords", &self.calls_pool_size_qwords)
+            .field("untrusted_workers", &self.untrusted_workers)
+            .field("trusted_workers", &self.trusted_workers)
+            .field("retries_before_fallback", &self.retries_before_fallback)
+            .field("retries_before_sleep", &self.retries_before_sleep)
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+/// The most recent statistics of the switchless workers of `worker_type`
+///
+/// The SGX SDK doesn't identify the enclave of a worker, these are the
+/// statistics last reported by any enclave of the process.
+pub fn switchless_stats(worker_type: WorkerType) -> WorkerStats {
+    let stats = match worker_type {
+        WorkerType::Untrusted => &UNTRUSTED_STATS,
+        WorkerType::Trusted => &TRUSTED_STATS,
+    };
+    WorkerStats {
+        processed: stats[0].load(Ordering::Relaxed),
+        missed: stats[1].load(Ordering::Relaxed),
+    }
+}
+
+// The callbacks of the enclaves using switchless calls, by slot
+static CALLBACKS: Lazy<Mutex<Vec<Option<WorkerCallback>>>> =
+    Lazy::new(|| Mutex::new(vec![None; MAX_WORKER_CALLBACKS]));
+
+// The processed and missed counts last reported by the workers
+static UNTRUSTED_STATS: [AtomicU64; 2] = [AtomicU64::new(0), AtomicU64::new(0)];
+static TRUSTED_STATS: [AtomicU64; 2] = [AtomicU64::new(0), AtomicU64::new(0)];
+
+/// Keeps an enclave's worker callback registered until the enclave is dropped
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct Registration {
+    // The callback slot, None when the enclave has no callback
+    slot: Option<usize>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            CALLBACKS.lock().expect("Mutex has been poisoned")[slot] = None;
+        }
+    }
+}
+
+type RawWorkerCallback = unsafe extern "C" fn(
+    sgx_uswitchless_worker_type_t,
+    sgx_uswitchless_worker_event_t,
+    *const sgx_uswitchless_worker_stats_t,
+);
+
+macro_rules! slot_callbacks {
+    ($($slot:literal)*) => {
+        [$(slot_callback::<$slot> as RawWorkerCallback),*]
+    };
+}
+
+// The SGX SDK doesn't say which enclave a worker belongs to, so each callback
+// slot has its own function to tell the enclaves apart
+const SLOT_CALLBACKS: [RawWorkerCallback; MAX_WORKER_CALLBACKS] = slot_callbacks!(
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+);
+
+unsafe extern "C" fn slot_callback<const SLOT: usize>(
+    worker_type: sgx_uswitchless_worker_type_t,
+    event: sgx_uswitchless_worker_event_t,
+    stats: *const sgx_uswitchless_worker_stats_t,
+) {
+    raw_worker_event(Some(SLOT), worker_type, event, stats);
+}
+
+unsafe extern "C" fn worker_callback(
+    worker_type: sgx_uswitchless_worker_type_t,
+    event: sgx_uswitchless_worker_event_t,
+    stats: *const sgx_uswitchless_worker_stats_t,
+) {
+    raw_worker_event(None, worker_type, event, stats);
+}
+
+unsafe fn raw_worker_event(
+    slot: Option<usize>,
+    worker_type: sgx_uswitchless_worker_type_t,
+    event: sgx_uswitchless_worker_event_t,
+    stats: *const sgx_uswitchless_worker_stats_t,
+) {
+    let stats = stats.as_ref().map(WorkerStats::from).unwrap_or_default();
+    if let (Ok(worker_type), Ok(event)) = (worker_type.try_into(), event.try_into()) {
+        handle_worker_event(slot, worker_type, event, stats);
+    }
+}
+
+fn handle_worker_event(
+    slot: Option<usize>,
+    worker_type: WorkerType,
+    event: WorkerEvent,
+    stats: WorkerStats,
+) {
+    let counts = match worker_type {
+        WorkerType::Untrusted => &UNTRUSTED_STATS,
+        WorkerType::Trusted => &TRUSTED_STATS,
+    };
+    counts[0].store(stats.processed, Ordering::Relaxed);
+    counts[1].store(stats.missed, Ordering::Relaxed);
+
+    // The callback is called without the lock so it can create enclaves
+    let callback = match (slot, CALLBACKS.lock()) {
+        (Some(slot), Ok(callbacks)) => callbacks[slot].clone(),
+        _ => None,
+    };
+    if let Some(callback) = callback {
+        // Unwinding into the switchless runtime is undefined behavior
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| callback(worker_type, event, stats)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    fn raw(config: &SwitchlessConfig) -> sgx_uswitchless_config_t {
+        config.to_raw(&Registration { slot: None })
+    }
+
+    #[test]
+    fn default_config_matches_sdk_initializer() {
+        let config = raw(&SwitchlessConfig::default());
+        assert_eq!(config.switchless_calls_pool_size_qwords, 0);
+        assert_eq!(config.num_uworkers, 1);
+        assert_eq!(config.num_tworkers, 1);
+        assert_eq!(config.retries_before_fallback, 0);
+        assert_eq!(config.retries_before_sleep, 0);
+        assert!(config.callback_func.iter().all(Option::is_some));
+    }
+
+    #[test]
+    fn config_values() {
+        let config = SwitchlessConfig::default()
+            .untrusted_workers(3)
+            .trusted_workers(0)
+            .retries_before_fallback(10)
+            .retries_before_sleep(20);
+        let config = raw(&config);
+        assert_eq!(config.num_uworkers, 3);
+        assert_eq!(config.num_tworkers, 0);
+        assert_eq!(config.retries_before_fallback, 10);
+        assert_eq!(config.retries_before_sleep, 20);
+    }
+
+    #[test]
+    fn pending_calls_rounds_up_to_qwords() {
+        let config = SwitchlessConfig::default().pending_calls(65);
+        assert_eq!(config.calls_pool_size_qwords, 2);
+        let config = SwitchlessConfig::default().pending_calls(64);
+        assert_eq!(config.calls_pool_size_qwords, 1);
+    }
+
+    #[test]
+    fn pending_calls_is_limited() {
+        let config = SwitchlessConfig::default().pending_calls(10_000);
+        assert_eq!(config.calls_pool_size_qwords, 8);
+        let config = SwitchlessConfig::default().pending_calls(0);
+        assert_eq!(config.calls_pool_size_qwords, 1);
+    }
+
+    #[test]
+    fn callbacks_are_called_until_unregistered() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let config = SwitchlessConfig::default().on_worker_event(move |worker, event, stats| {
+            if (worker, event, stats.processed) == (WorkerType::Trusted, WorkerEvent::Miss, 7) {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        let stats = WorkerStats {
+            processed: 7,
+            missed: 2,
+        };
+        let registration = config.register().unwrap();
+        let slot = registration.slot;
+        handle_worker_event(slot, WorkerType::Trusted, WorkerEvent::Miss, stats);
+        drop(registration);
+        handle_worker_event(slot, WorkerType::Trusted, WorkerEvent::Miss, stats);
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn callbacks_only_get_their_enclaves_events() {
+        let counter = |calls: &Arc<AtomicUsize>| {
+            let calls = calls.clone();
+            SwitchlessConfig::default().on_worker_event(move |_, _, _| {
+                calls.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+        let first_calls = Arc::new(AtomicUsize::new(0));
+        let second_calls = Arc::new(AtomicUsize::new(0));
+        let first = counter(&first_calls).register().unwrap();
+        let second = counter(&second_calls).register().unwrap();
+        let first_config = counter(&first_calls).to_raw(&first);
+
+        let stats = sgx_uswitchless_worker_stats_t {
+            processed: 1,
+            missed: 0,
+        };
+        let callback = first_config.callback_func[0].unwrap();
+        unsafe {
+            callback(
+                sgx_uswitchless_worker_type_t::SGX_USWITCHLESS_WORKER_TYPE_TRUSTED,
+                sgx_uswitchless_worker_event_t::SGX_USWITCHLESS_WORKER_EVENT_START,
+                &stats,
+            )
+        };
+
+        assert_ne!(first.slot, second.slot);
+        assert_eq!(first_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(second_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn config_without_callback_takes_no_slot() {
+        let registration = SwitchlessConfig::default().register().unwrap();
+        assert_eq!(registration.slot, None);
+    }
+
+    #[test]
+    fn panicking_callback_is_contained() {
+        let config = SwitchlessConfig::default().on_worker_event(|_, _, _| panic!("oops"));
+        let registration = config.register().unwrap();
+        handle_worker_event(
+            registration.slot,
+            WorkerType::Untrusted,
+            WorkerEvent::Idle,
+            WorkerStats::default(),
+        );
+    }
+
+    #[test]
+    fn raw_events_are_converted() {
+        let stats = sgx_uswitchless_worker_stats_t {
+            processed: 11,
+            missed: 5,
+        };
+        unsafe {
+            worker_callback(
+                sgx_uswitchless_worker_type_t::SGX_USWITCHLESS_WORKER_TYPE_UNTRUSTED,
+                sgx_uswitchless_worker_event_t::SGX_USWITCHLESS_WORKER_EVENT_EXIT,
+                &stats,
+            )
+        };
+        assert_eq!(
+            switchless_stats(WorkerType::Untrusted),
+            WorkerStats {
+                processed: 11,
+                missed: 5
+            }
+        );
+    }
+}
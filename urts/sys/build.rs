@@ -2,12 +2,15 @@
 
 //! Builds the FFI bindings for the untrusted side of the Intel SGX SDK
 
+use std::env;
+
 const URTS_FUNCTIONS: &[&str] = &[
     "sgx_create_enclave",
     "sgx_create_enclave_ex",
     "sgx_create_enclave_from_buffer_ex",
     "sgx_create_encrypted_enclave",
     "sgx_destroy_enclave",
+    "sgx_ecall",
     "sgx_get_target_info",
 ];
 
@@ -15,10 +18,21 @@ fn main() {
     let link_path = mc_sgx_core_build::sgx_library_string();
     cargo_emit::rustc_link_search!(link_path);
 
-    let sgx_suffix = mc_sgx_core_build::sgx_library_suffix();
-    cargo_emit::rustc_link_lib!(&format!("sgx_launch{sgx_suffix}"));
-    cargo_emit::rustc_link_lib!(&format!("sgx_urts{sgx_suffix}"));
-    cargo_emit::rustc_link_lib!(&format!("sgx_uae_service{sgx_suffix}"));
+    // With `dlopen` the uRTS is loaded at runtime instead
+    if env::var_os("CARGO_FEATURE_DLOPEN").is_none() {
+        let sgx_suffix = mc_sgx_core_build::sgx_library_suffix();
+        cargo_emit::rustc_link_lib!(&format!("sgx_launch{sgx_suffix}"));
+        cargo_emit::rustc_link_lib!(&format!("sgx_urts{sgx_suffix}"));
+        cargo_emit::rustc_link_lib!(&format!("sgx_uae_service{sgx_suffix}"));
+    }
+
+    // The switchless runtime is only available as a static library. The uRTS
+    // looks its functions up at runtime so they must all be kept.
+    if env::var_os("CARGO_FEATURE_SWITCHLESS").is_some() {
+        cargo_emit::rustc_link_lib!("static:-bundle,+whole-archive=sgx_uswitchless");
+    }
+
+    mc_sgx_core_build::emit_sgx_sdk_cfgs();
 
     let mut builder = mc_sgx_core_build::sgx_builder().header("wrapper.h");
 
@@ -26,11 +40,5 @@ fn main() {
         builder = builder.allowlist_function(f);
     }
 
-    let out_path = mc_sgx_core_build::build_output_dir();
-
-    builder
-        .generate()
-        .expect("Unable to generate bindings")
-        .write_to_file(out_path.join("bindings.rs"))
-        .expect("Couldn't write bindings!");
+    mc_sgx_core_build::write_bindings(builder);
 }
@@ -0,0 +1,436 @@
+// Copyright (c) 2023 The MobileCoin Foundation
+
+//! Loading of the hardware or simulation `sgx_urts` library at runtime
+//!
+//! The `sgx_edger8r` generated untrusted bridges call `sgx_ecall()` and
+//! `sgx_ecall_switchless()` of the uRTS they are linked with. With `dlopen`
+//! no uRTS is linked, instead this module defines both symbols and forwards
+//! each ECALL to the uRTS which created the enclave. Rust callers keep using
+//! the crate's `sgx_ecall()` binding, it resolves to the definition here.
+
+use libloading::Library;
+use mc_sgx_core_sys_types::{sgx_status_t, sgx_target_info_t};
+use mc_sgx_urts_sys_types::{sgx_enclave_id_t, sgx_misc_attribute_t};
+use once_cell::sync::{Lazy, OnceCell};
+use std::{
+    collections::HashMap,
+    env,
+    ffi::{c_void, OsStr},
+    os::raw::c_int,
+    path::PathBuf,
+    sync::RwLock,
+};
+
+/// The name of the hardware uRTS library, installed with the SGX PSW
+pub const HARDWARE_LIBRARY: &str = "libsgx_urts.so.2";
+
+/// The name of the simulation uRTS library, installed with the SGX SDK
+pub const SIMULATION_LIBRARY: &str = "libsgx_urts_sim.so";
+
+/// The SGX SDK location used when `SGX_SDK` isn't set
+const DEFAULT_SGX_SDK_PATH: &str = "/opt/intel/sgxsdk";
+
+/// The signature of `sgx_ecall()` and `sgx_ecall_switchless()`
+type EcallFn =
+    unsafe extern "C" fn(sgx_enclave_id_t, c_int, *const c_void, *mut c_void) -> sgx_status_t;
+
+/// The functions of the `sgx_urts` library
+#[derive(Debug)]
+pub struct Urts {
+    create_enclave_from_buffer_ex: unsafe extern "C" fn(
+        *mut u8,
+        usize,
+        c_int,
+        *mut sgx_enclave_id_t,
+        *mut sgx_misc_attribute_t,
+        u32,
+        *mut *const c_void,
+    ) -> sgx_status_t,
+    destroy_enclave: unsafe extern "C" fn(sgx_enclave_id_t) -> sgx_status_t,
+    get_target_info: unsafe extern "C" fn(sgx_enclave_id_t, *mut sgx_target_info_t) -> sgx_status_t,
+    ecall: EcallFn,
+    ecall_switchless: EcallFn,
+    // Must outlive the function pointers above
+    _library: Library,
+}
+
+/// The uRTS each live enclave was created with
+///
+/// Enclave ids are only unique within one uRTS, so the hardware and
+/// simulation uRTS may hand out the same id. Every ECALL looks up its enclave
+/// so only creating and destroying enclaves takes the write lock.
+static ENCLAVES: Lazy<RwLock<HashMap<sgx_enclave_id_t, &'static Urts>>> =
+    Lazy::new(Default::default);
+
+/// The uRTS which created the enclave `eid`, if it's still alive
+fn enclave_urts(eid: sgx_enclave_id_t) -> Option<&'static Urts> {
+    ENCLAVES
+        .read()
+        .expect("Enclave registry poisoned")
+        .get(&eid)
+        .copied()
+}
+
+impl Urts {
+    /// Load the library and resolve all of its functions
+    ///
+    /// # Arguments
+    /// * `path` - The library to load, either a file name to search for in the
+    ///   standard library locations or a path.
+    ///
+    /// # Safety
+    /// Loading a library runs its initialization code, and the functions are
+    /// assumed to have the signatures of the `sgx_urts` library.
+    pub unsafe fn load(path: impl AsRef<OsStr>) -> Result<Self, libloading::Error> {
+        let library = Library::new(path.as_ref())?;
+        Ok(Self {
+            create_enclave_from_buffer_ex: *library.get(b"sgx_create_enclave_from_buffer_ex\0")?,
+            destroy_enclave: *library.get(b"sgx_destroy_enclave\0")?,
+            get_target_info: *library.get(b"sgx_get_target_info\0")?,
+            ecall: *library.get(b"sgx_ecall\0")?,
+            ecall_switchless: *library.get(b"sgx_ecall_switchless\0")?,
+            _library: library,
+        })
+    }
+
+    /// `sgx_create_enclave_from_buffer_ex()`
+    ///
+    /// The created enclave's ECALLs are forwarded to this uRTS.
+    /// `SGX_ERROR_INVALID_ENCLAVE_ID` is returned, and the enclave destroyed,
+    /// when another uRTS already has a live enclave with the same id.
+    ///
+    /// # Safety
+    /// The arguments must be valid for `sgx_create_enclave_from_buffer_ex()`.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn sgx_create_enclave_from_buffer_ex(
+        &'static self,
+        buffer: *mut u8,
+        buffer_size: usize,
+        debug: c_int,
+        enclave_id: *mut sgx_enclave_id_t,
+        misc_attr: *mut sgx_misc_attribute_t,
+        ex_features: u32,
+        ex_features_p: *mut *const c_void,
+    ) -> sgx_status_t {
+        let status = (self.create_enclave_from_buffer_ex)(
+            buffer,
+            buffer_size,
+            debug,
+            enclave_id,
+            misc_attr,
+            ex_features,
+            ex_features_p,
+        );
+        if status != sgx_status_t::SGX_SUCCESS {
+            return status;
+        }
+
+        let eid = *enclave_id;
+        let mut enclaves = ENCLAVES.write().expect("Enclave registry poisoned");
+        match enclaves.get(&eid) {
+            Some(other) if !std::ptr::eq(*other, self) => {
+                drop(enclaves);
+                (self.destroy_enclave)(eid);
+                sgx_status_t::SGX_ERROR_INVALID_ENCLAVE_ID
+            }
+            _ => {
+                enclaves.insert(eid, self);
+                status
+            }
+        }
+    }
+
+    /// `sgx_destroy_enclave()`
+    ///
+    /// # Safety
+    /// The enclave must not be in use.
+    pub unsafe fn sgx_destroy_enclave(&'static self, enclave_id: sgx_enclave_id_t) -> sgx_status_t {
+        let mut enclaves = ENCLAVES.write().expect("Enclave registry poisoned");
+        if matches!(enclaves.get(&enclave_id), Some(urts) if std::ptr::eq(*urts, self)) {
+            enclaves.remove(&enclave_id);
+        }
+        drop(enclaves);
+        (self.destroy_enclave)(enclave_id)
+    }
+
+    /// `sgx_get_target_info()`
+    ///
+    /// # Safety
+    /// `target_info` must be valid for writes.
+    pub unsafe fn sgx_get_target_info(
+        &'static self,
+        enclave_id: sgx_enclave_id_t,
+        target_info: *mut sgx_target_info_t,
+    ) -> sgx_status_t {
+        (self.get_target_info)(enclave_id, target_info)
+    }
+
+    /// Load the first of `candidates` which can be loaded
+    ///
+    /// The errors of all the candidates are returned when none of them can be
+    /// loaded.
+    fn load_any(candidates: &[PathBuf]) -> Result<Self, String> {
+        let mut errors = Vec::new();
+        for candidate in candidates {
+            match unsafe { Self::load(candidate) } {
+                Ok(urts) => return Ok(urts),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+        Err(errors.join("; "))
+    }
+}
+
+static HARDWARE: OnceCell<Result<Urts, String>> = OnceCell::new();
+static SIMULATION: OnceCell<Result<Urts, String>> = OnceCell::new();
+
+/// The hardware uRTS, loaded on first use
+///
+/// [`HARDWARE_LIBRARY`] is searched for in the standard library locations.
+/// Loading is only attempted once, later calls return the same result.
+///
+/// # Errors
+/// The reason the library could not be loaded, or a function could not be
+/// found in it.
+pub fn hardware() -> Result<&'static Urts, &'static str> {
+    HARDWARE
+        .get_or_init(|| Urts::load_any(&[HARDWARE_LIBRARY.into()]))
+        .as_ref()
+        .map_err(String::as_str)
+}
+
+/// The simulation uRTS, loaded on first use
+///
+/// [`SIMULATION_LIBRARY`] is searched for in the standard library locations
+/// and then in the `lib64` directory of the SGX SDK, `SGX_SDK` or
+/// `/opt/intel/sgxsdk`. Loading is only attempted once, later calls return the
+/// same result.
+///
+/// # Errors
+/// The reason the library could not be loaded, or a function could not be
+/// found in it.
+pub fn simulation() -> Result<&'static Urts, &'static str> {
+    SIMULATION
+        .get_or_init(|| {
+            let sdk = env::var_os("SGX_SDK").unwrap_or_else(|| DEFAULT_SGX_SDK_PATH.into());
+            let sdk_library = PathBuf::from(sdk).join("lib64").join(SIMULATION_LIBRARY);
+            Urts::load_any(&[SIMULATION_LIBRARY.into(), sdk_library])
+        })
+        .as_ref()
+        .map_err(String::as_str)
+}
+
+/// `sgx_ecall()` for the `sgx_edger8r` generated untrusted bridges and the
+/// crate's `sgx_ecall()` binding
+///
+/// Forwards to the uRTS which created the enclave.
+///
+/// # Safety
+/// The arguments must be valid for `sgx_ecall()` of the uRTS.
+#[no_mangle]
+unsafe extern "C" fn sgx_ecall(
+    eid: sgx_enclave_id_t,
+    index: c_int,
+    ocall_table: *const c_void,
+    ms: *mut c_void,
+) -> sgx_status_t {
+    match enclave_urts(eid) {
+        Some(urts) => (urts.ecall)(eid, index, ocall_table, ms),
+        None => sgx_status_t::SGX_ERROR_INVALID_ENCLAVE_ID,
+    }
+}
+
+/// `sgx_ecall_switchless()` for the `sgx_edger8r` generated untrusted bridges
+///
+/// Forwards to the uRTS which created the enclave.
+///
+/// # Safety
+/// The arguments must be valid for `sgx_ecall_switchless()` of the uRTS.
+#[no_mangle]
+unsafe extern "C" fn sgx_ecall_switchless(
+    eid: sgx_enclave_id_t,
+    index: c_int,
+    ocall_table: *const c_void,
+    ms: *mut c_void,
+) -> sgx_status_t {
+    match enclave_urts(eid) {
+        Some(urts) => (urts.ecall_switchless)(eid, index, ocall_table, ms),
+        None => sgx_status_t::SGX_ERROR_INVALID_ENCLAVE_ID,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{fs, process::Command};
+    use tempfile::TempDir;
+
+    /// Build a stand in for the library whose functions return `status`.
+    fn stub_library(dir: &TempDir, functions: &[&str], status: u32) -> PathBuf {
+        let contents = functions
+            .iter()
+            .map(|f| format!("unsigned int {f}() {{ return {status}; }}\n"))
+            .collect::<String>();
+        compile_library(dir, "stub", &contents)
+    }
+
+    /// Build a uRTS stand in which creates enclaves with the id `eid` and
+    /// whose ECALLs return `index + eid`
+    fn ecall_library(dir: &TempDir, name: &str, eid: u64) -> &'static Urts {
+        let contents = format!(
+            r#"
+            typedef unsigned long long eid_t;
+            unsigned int sgx_create_enclave_from_buffer_ex(
+                void *buffer, unsigned long size, int debug, eid_t *eid,
+                void *misc, unsigned int ex, void *ex_p) {{
+                *eid = {eid};
+                return 0;
+            }}
+            unsigned int sgx_destroy_enclave(eid_t eid) {{ return 0; }}
+            unsigned int sgx_get_target_info(eid_t eid, void *info) {{ return 0; }}
+            unsigned int sgx_ecall(eid_t eid, int index, const void *table, void *ms) {{
+                return (unsigned int)(index + eid);
+            }}
+            unsigned int sgx_ecall_switchless(eid_t eid, int index, const void *table, void *ms) {{
+                return (unsigned int)(index + eid + 100);
+            }}
+            "#
+        );
+        let path = compile_library(dir, name, &contents);
+        Box::leak(Box::new(unsafe { Urts::load(path) }.unwrap()))
+    }
+
+    fn compile_library(dir: &TempDir, name: &str, contents: &str) -> PathBuf {
+        let source = dir.path().join(format!("{name}.c"));
+        let library = dir.path().join(format!("lib{name}.so"));
+        fs::write(&source, contents).expect("Failed to write stub source");
+        let status = Command::new("cc")
+            .args(["-shared", "-fPIC", "-o"])
+            .arg(&library)
+            .arg(&source)
+            .status()
+            .expect("Failed to run the C compiler");
+        assert!(status.success(), "Failed to compile stub library");
+        library
+    }
+
+    const FUNCTIONS: &[&str] = &[
+        "sgx_create_enclave_from_buffer_ex",
+        "sgx_destroy_enclave",
+        "sgx_get_target_info",
+        "sgx_ecall",
+        "sgx_ecall_switchless",
+    ];
+
+    /// Create an enclave with `urts`, returning its id
+    fn create(urts: &'static Urts) -> Result<sgx_enclave_id_t, sgx_status_t> {
+        let mut eid = 0;
+        let mut misc_attr = sgx_misc_attribute_t {
+            secs_attr: Default::default(),
+            misc_select: 0,
+        };
+        let status = unsafe {
+            urts.sgx_create_enclave_from_buffer_ex(
+                [0u8; 1].as_mut_ptr(),
+                1,
+                0,
+                &mut eid,
+                &mut misc_attr,
+                0,
+                std::ptr::null_mut(),
+            )
+        };
+        match status {
+            sgx_status_t::SGX_SUCCESS => Ok(eid),
+            error => Err(error),
+        }
+    }
+
+    fn ecall(eid: sgx_enclave_id_t, index: c_int) -> sgx_status_t {
+        unsafe { sgx_ecall(eid, index, std::ptr::null(), std::ptr::null_mut()) }
+    }
+
+    #[test]
+    fn calls_into_loaded_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = stub_library(&dir, FUNCTIONS, 0x2001);
+
+        let urts = Box::leak(Box::new(unsafe { Urts::load(&path) }.unwrap()));
+        let result = unsafe { urts.sgx_destroy_enclave(1) };
+
+        assert_eq!(result.0, 0x2001);
+    }
+
+    #[test]
+    fn ecalls_are_forwarded_to_the_creating_urts() {
+        let dir = tempfile::tempdir().unwrap();
+        let urts = ecall_library(&dir, "forward", 1000);
+
+        let eid = create(urts).unwrap();
+
+        assert_eq!(ecall(eid, 3).0, 1003);
+        let switchless =
+            unsafe { sgx_ecall_switchless(eid, 3, std::ptr::null(), std::ptr::null_mut()) };
+        assert_eq!(switchless.0, 1103);
+        unsafe { urts.sgx_destroy_enclave(eid) };
+    }
+
+    #[test]
+    fn ecall_to_destroyed_enclave_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let urts = ecall_library(&dir, "destroyed", 2000);
+        let eid = create(urts).unwrap();
+
+        unsafe { urts.sgx_destroy_enclave(eid) };
+
+        assert_eq!(ecall(eid, 3), sgx_status_t::SGX_ERROR_INVALID_ENCLAVE_ID);
+    }
+
+    #[test]
+    fn duplicate_id_from_another_urts_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = ecall_library(&dir, "first", 3000);
+        let second = ecall_library(&dir, "second", 3000);
+        let eid = create(first).unwrap();
+
+        assert_eq!(
+            create(second),
+            Err(sgx_status_t::SGX_ERROR_INVALID_ENCLAVE_ID)
+        );
+        assert_eq!(ecall(eid, 3).0, 3003);
+        unsafe { first.sgx_destroy_enclave(eid) };
+    }
+
+    #[test]
+    fn missing_function_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = stub_library(&dir, &FUNCTIONS[1..], 0);
+
+        let error = unsafe { Urts::load(path) }.unwrap_err();
+
+        assert!(error
+            .to_string()
+            .contains("sgx_create_enclave_from_buffer_ex"));
+    }
+
+    #[test]
+    fn loads_first_available_candidate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = stub_library(&dir, FUNCTIONS, 0);
+        let missing = dir.path().join("libmissing.so");
+
+        assert!(Urts::load_any(&[missing, path]).is_ok());
+    }
+
+    #[test]
+    fn reports_all_candidate_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("libfirst.so");
+        let second = dir.path().join("libsecond.so");
+
+        let error = Urts::load_any(&[first, second]).unwrap_err();
+
+        assert!(error.contains("libfirst.so"));
+        assert!(error.contains("libsecond.so"));
+    }
+}
@@ -7,3 +7,6 @@ use mc_sgx_core_sys_types::{sgx_status_t, sgx_target_info_t};
 use mc_sgx_urts_sys_types::{sgx_enclave_id_t, sgx_launch_token_t, sgx_misc_attribute_t};
 
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+#[cfg(feature = "dlopen")]
+pub mod dlopen;
@@ -8,6 +8,11 @@ const URTS_TYPES: &[&str] = &[
     "sgx_launch_token_t",
     "_sgx_misc_attribute_t",
     "_sgx_kss_config_t",
+    "sgx_uswitchless_config_t",
+    "sgx_uswitchless_worker_callback_t",
+    "sgx_uswitchless_worker_event_t",
+    "sgx_uswitchless_worker_stats_t",
+    "sgx_uswitchless_worker_type_t",
 ];
 
 const URTS_CONSTANTS: &[&str] = &[
@@ -15,11 +20,26 @@ const URTS_CONSTANTS: &[&str] = &[
     "SGX_CREATE_ENCLAVE_EX_KSS_BIT_IDX",
     "SGX_CREATE_ENCLAVE_EX_PCL",
     "SGX_CREATE_ENCLAVE_EX_KSS",
+    "SGX_CREATE_ENCLAVE_EX_SWITCHLESS_BIT_IDX",
+    "SGX_CREATE_ENCLAVE_EX_SWITCHLESS",
     "MAX_EX_FEATURES_COUNT",
+    "SL_DEFAULT_FALLBACK_RETRIES",
+    "SL_DEFAULT_SLEEP_RETRIES",
+    "SL_DEFUALT_MAX_TASKS_QWORDS",
+    "SL_MAX_TASKS_MAX_QWORDS",
 ];
 
 fn main() {
-    let callback = SgxParseCallbacks::default().derive_copy(["sgx_kss_config_t"]);
+    let callback = SgxParseCallbacks::default()
+        .derive_copy([
+            "sgx_kss_config_t",
+            "sgx_uswitchless_config_t",
+            "sgx_uswitchless_worker_stats_t",
+        ])
+        .enum_types([
+            "sgx_uswitchless_worker_event_t",
+            "sgx_uswitchless_worker_type_t",
+        ]);
     let mut builder = mc_sgx_core_build::sgx_builder()
         .header("wrapper.h")
         .parse_callbacks(Box::new(callback));
@@ -32,10 +52,5 @@ fn main() {
         builder = builder.allowlist_var(c);
     }
 
-    let out_path = mc_sgx_core_build::build_output_dir();
-    builder
-        .generate()
-        .expect("Unable to generate bindings")
-        .write_to_file(out_path.join("bindings.rs"))
-        .expect("Couldn't write bindings!");
+    mc_sgx_core_build::write_bindings(builder);
 }
@@ -1 +1,2 @@
 #include <sgx_urts.h>
+#include <sgx_uswitchless.h>